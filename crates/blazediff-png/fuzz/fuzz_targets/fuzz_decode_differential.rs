@@ -8,7 +8,7 @@ fn snapshot(result: Result<blazediff_png::Image, blazediff_png::PngError>) -> Op
 fn snapshot_spng(
     result: Result<blazediff::Image, blazediff::DiffError>,
 ) -> Option<(u32, u32, Vec<u8>)> {
-    result.ok().map(|i| (i.width, i.height, i.data))
+    result.ok().map(|i| (i.width, i.height, i.data.into_vec()))
 }
 
 // Full parity: blazediff_png and spng must agree on accept/reject for every
@@ -7,7 +7,7 @@ use blazediff_png::decode;
 fn oracle(bytes: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
     blazediff::decode_spng_reference(bytes)
         .ok()
-        .map(|img| (img.width, img.height, img.data))
+        .map(|img| (img.width, img.height, img.data.into_vec()))
 }
 
 fn snapshot(bytes: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
@@ -9,7 +9,7 @@ use blazediff_png::{
 
 fn spng_pixels(bytes: &[u8]) -> (u32, u32, Vec<u8>) {
     let img = blazediff::decode_spng_reference(bytes).expect("spng must accept our output");
-    (img.width, img.height, img.data)
+    (img.width, img.height, img.data.into_vec())
 }
 
 fn rgba_image(w: u32, h: u32) -> Image {
@@ -7,7 +7,7 @@ use blazediff_png::{decode, encode, ColorMode, EncodeOptions, Filter, FilterSet,
 
 fn oracle(bytes: &[u8]) -> (u32, u32, Vec<u8>) {
     let img = blazediff::decode_spng_reference(bytes).expect("spng must accept our output");
-    (img.width, img.height, img.data)
+    (img.width, img.height, img.data.into_vec())
 }
 
 fn lcg_bytes(n: usize, mut seed: u32) -> Vec<u8> {
@@ -0,0 +1,191 @@
+//! Core diff benchmarks on synthetic images, so they run without fixtures
+//! or the `io` feature.
+//!
+//! ```text
+//! cargo bench --bench diff                  # diff() only
+//! cargo bench --bench diff --features bench # plus the isolated cold pass
+//! ```
+
+use blazediff::output::fill_gray;
+use blazediff::{diff, DiffOptions, Image};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const WIDTH: u32 = 2048;
+const HEIGHT: u32 = 1536;
+/// A 64 x 64 change in the middle, a few blocks out of the whole image.
+const LOCAL_CHANGE: (u32, u32, u32, u32) = (992, 736, 64, 64);
+
+/// Deterministic photo-like noise: a smooth gradient with low-amplitude
+/// hash noise on top, fully opaque.
+fn noise_image(width: u32, height: u32, seed: u32) -> Image {
+    let mut img = Image::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let hash = (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663) ^ seed)
+                .wrapping_mul(2_654_435_761);
+            let r = ((x * 255 / width) as u8).wrapping_add((hash >> 24) as u8 & 0x0F);
+            let g = ((y * 255 / height) as u8).wrapping_add((hash >> 16) as u8 & 0x0F);
+            let b = (((x + y) / 8) as u8).wrapping_add((hash >> 8) as u8 & 0x0F);
+            img.set_pixel(
+                x,
+                y,
+                0xFF00_0000 | (b as u32) << 16 | (g as u32) << 8 | r as u32,
+            );
+        }
+    }
+    img
+}
+
+/// `base` with the colors inverted inside `(x, y, width, height)`.
+fn inverted(base: &Image, (x0, y0, width, height): (u32, u32, u32, u32)) -> Image {
+    let mut img = base.crop(0, 0, base.width, base.height);
+    for y in y0..y0 + height {
+        for x in x0..x0 + width {
+            let pixel = img.get_pixel(x, y);
+            img.set_pixel(x, y, pixel ^ 0x00FF_FFFF);
+        }
+    }
+    img
+}
+
+/// Lines of dark glyph-like strokes on white with anti-aliased edges, drawn
+/// at a sub-pixel horizontal `offset`. Two offsets give a diff that is almost
+/// entirely anti-aliasing, like a font hinting change.
+fn text_image(width: u32, height: u32, offset: f32) -> Image {
+    const LINE_HEIGHT: u32 = 18;
+    const GLYPH_WIDTH: u32 = 9;
+    let mut img = Image::new(width, height);
+    for y in 0..height {
+        let in_line = y % LINE_HEIGHT;
+        for x in 0..width {
+            let glyph = x / GLYPH_WIDTH;
+            // Vertical stems with coverage from the fractional stem edge.
+            let stem_x = (glyph * GLYPH_WIDTH) as f32 + 2.0 + offset + (glyph % 3) as f32;
+            let coverage = if (3..14).contains(&in_line) {
+                (1.5 - (x as f32 + 0.5 - stem_x).abs()).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            // Every other glyph gets a horizontal bar, fully on its row.
+            let bar = glyph.is_multiple_of(2) && in_line == 8 && x % GLYPH_WIDTH < 7;
+            let ink = if bar { 1.0 } else { coverage };
+            let v = (255.0 * (1.0 - ink)).round() as u32;
+            img.set_pixel(x, y, 0xFF00_0000 | (v * 0x0001_0101));
+        }
+    }
+    img
+}
+
+fn bench_diff(c: &mut Criterion) {
+    let base = noise_image(WIDTH, HEIGHT, 1);
+    let cases = [
+        ("identical", base.crop(0, 0, WIDTH, HEIGHT)),
+        ("localized", inverted(&base, LOCAL_CHANGE)),
+        ("different", inverted(&base, (0, 0, WIDTH, HEIGHT))),
+    ];
+    let text_a = text_image(WIDTH, HEIGHT, 0.0);
+    let text_b = text_image(WIDTH, HEIGHT, 0.4);
+
+    let options = DiffOptions::default();
+    let mut group = c.benchmark_group("diff");
+    group.throughput(Throughput::Elements((WIDTH * HEIGHT) as u64));
+    group.sample_size(20);
+
+    let pairs = cases
+        .iter()
+        .map(|(name, candidate)| (*name, &base, candidate))
+        .chain(std::iter::once(("aa_text", &text_a, &text_b)));
+    for (name, a, b) in pairs {
+        group.bench_with_input(
+            BenchmarkId::new("no_output", name),
+            &(a, b),
+            |bench, (a, b)| {
+                bench.iter(|| diff(a, b, None, &options).unwrap());
+            },
+        );
+        let mut output = Image::new(WIDTH, HEIGHT);
+        group.bench_with_input(
+            BenchmarkId::new("output", name),
+            &(a, b),
+            |bench, (a, b)| {
+                bench.iter(|| diff(a, b, Some(&mut output), &options).unwrap());
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Full count against `stop_at_first_diff` when only the bottom-right
+/// block changes, so both scan every block before reaching it, and when
+/// every block does.
+fn bench_first_diff(c: &mut Criterion) {
+    let base = noise_image(WIDTH, HEIGHT, 1);
+    let (block_size, blocks_x, blocks_y) = blazediff::block_grid(WIDTH, HEIGHT);
+    let (x, y) = ((blocks_x - 1) * block_size, (blocks_y - 1) * block_size);
+    let last_block = inverted(&base, (x, y, WIDTH - x, HEIGHT - y));
+    let different = inverted(&base, (0, 0, WIDTH, HEIGHT));
+
+    let count = DiffOptions::default();
+    let boolean = DiffOptions {
+        stop_at_first_diff: true,
+        ..Default::default()
+    };
+    let mut group = c.benchmark_group("first_diff");
+    group.throughput(Throughput::Elements((WIDTH * HEIGHT) as u64));
+    group.sample_size(20);
+    for (name, candidate) in [("last_block", &last_block), ("different", &different)] {
+        for (mode, options) in [("count", &count), ("boolean", &boolean)] {
+            group.bench_with_input(BenchmarkId::new(mode, name), candidate, |bench, b| {
+                bench.iter(|| diff(&base, b, None, options).unwrap());
+            });
+        }
+    }
+    group.finish();
+}
+
+/// The gray background on its own at 1080p, as drawn for an output whose
+/// blocks are all unchanged.
+fn bench_gray_fill(c: &mut Criterion) {
+    let (width, height) = (1920, 1080);
+    let source = noise_image(width, height, 1);
+    let mut output = Image::new(width, height);
+
+    let mut group = c.benchmark_group("gray_fill");
+    group.throughput(Throughput::Elements((width * height) as u64));
+    group.bench_function("fill_gray", |bench| {
+        bench.iter(|| fill_gray(&source, 0.1, &mut output));
+    });
+    group.finish();
+}
+
+#[cfg(feature = "bench")]
+fn bench_cold_pass(c: &mut Criterion) {
+    let base = noise_image(WIDTH, HEIGHT, 1);
+    let localized = inverted(&base, LOCAL_CHANGE);
+    let different = inverted(&base, (0, 0, WIDTH, HEIGHT));
+
+    let mut group = c.benchmark_group("cold_pass");
+    group.throughput(Throughput::Elements((WIDTH * HEIGHT) as u64));
+    for (name, candidate) in [
+        ("identical", &base),
+        ("localized", &localized),
+        ("different", &different),
+    ] {
+        group.bench_function(name, |bench| {
+            bench.iter(|| blazediff::cold_pass(&base, candidate, 0.1));
+        });
+    }
+    group.finish();
+}
+
+#[cfg(not(feature = "bench"))]
+fn bench_cold_pass(_: &mut Criterion) {}
+
+criterion_group!(
+    benches,
+    bench_diff,
+    bench_first_diff,
+    bench_gray_fill,
+    bench_cold_pass
+);
+criterion_main!(benches);
@@ -0,0 +1,239 @@
+//! Global translation estimate by phase correlation, for telling "the whole
+//! page moved a pixel" apart from real changes before diffing.
+
+use crate::types::Image;
+use crate::yiq::luminance_over_white;
+
+#[derive(Clone, Copy, Default)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    #[inline]
+    fn mul(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+
+    #[inline]
+    fn conj(self) -> Complex {
+        Complex {
+            re: self.re,
+            im: -self.im,
+        }
+    }
+}
+
+/// The integer `(dx, dy)` that best maps `a` onto `b`: `b`'s content at
+/// `(x + dx, y + dy)` matches `a`'s at `(x, y)`. `(0, 0)` when nothing lines
+/// up better than the images as they are.
+///
+/// Correlates the luminance (blended over white, as [`diff`](crate::diff)
+/// does) of the top-left region both images cover. The region is
+/// Hann-windowed and zero-padded to power-of-two sides for a radix-2 FFT, so
+/// this costs two complex f32 buffers of 8 bytes per padded pixel. A shift
+/// of more than half the padded size wraps around to the opposite sign.
+pub fn estimate_shift(a: &Image, b: &Image) -> (i32, i32) {
+    let width = a.width.min(b.width) as usize;
+    let height = a.height.min(b.height) as usize;
+    if width == 0 || height == 0 {
+        return (0, 0);
+    }
+    let (fft_w, fft_h) = (width.next_power_of_two(), height.next_power_of_two());
+
+    let mut spectrum_a = windowed_luma(a, width, height, fft_w, fft_h);
+    let mut spectrum_b = windowed_luma(b, width, height, fft_w, fft_h);
+    fft_2d(&mut spectrum_a, fft_w, fft_h, false);
+    fft_2d(&mut spectrum_b, fft_w, fft_h, false);
+
+    // Normalized cross-power spectrum: only the phase difference survives,
+    // and its inverse transform peaks at the shift.
+    for (fa, fb) in spectrum_a.iter_mut().zip(&spectrum_b) {
+        let cross = fa.conj().mul(*fb);
+        let magnitude = cross.re.hypot(cross.im);
+        *fa = if magnitude > f32::EPSILON {
+            Complex {
+                re: cross.re / magnitude,
+                im: cross.im / magnitude,
+            }
+        } else {
+            Complex::default()
+        };
+    }
+    fft_2d(&mut spectrum_a, fft_w, fft_h, true);
+
+    let mut peak = (0, f32::MIN);
+    for (i, value) in spectrum_a.iter().enumerate() {
+        if value.re > peak.1 {
+            peak = (i, value.re);
+        }
+    }
+    let (px, py) = (peak.0 % fft_w, peak.0 / fft_w);
+    let signed = |p: usize, n: usize| {
+        if p > n / 2 {
+            p as i32 - n as i32
+        } else {
+            p as i32
+        }
+    };
+    (signed(px, fft_w), signed(py, fft_h))
+}
+
+/// Mean-subtracted, Hann-windowed luminance of the `width` x `height`
+/// top-left region, zero-padded to `fft_w` x `fft_h`.
+fn windowed_luma(
+    image: &Image,
+    width: usize,
+    height: usize,
+    fft_w: usize,
+    fft_h: usize,
+) -> Vec<Complex> {
+    let hann = |i: usize, n: usize| {
+        if n < 2 {
+            1.0
+        } else {
+            0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / (n - 1) as f32).cos()
+        }
+    };
+    let window_x: Vec<f32> = (0..width).map(|x| hann(x, width)).collect();
+
+    let mut luma = vec![0.0f32; width * height];
+    for y in 0..height {
+        let row = &image.as_u32()[y * image.width as usize..][..width];
+        for (x, &pixel) in row.iter().enumerate() {
+            luma[y * width + x] = luminance_over_white(pixel) as f32;
+        }
+    }
+    let mean = luma.iter().map(|&v| v as f64).sum::<f64>() / luma.len() as f64;
+
+    let mut out = vec![Complex::default(); fft_w * fft_h];
+    for y in 0..height {
+        let window_y = hann(y, height);
+        for x in 0..width {
+            out[y * fft_w + x].re = (luma[y * width + x] - mean as f32) * window_x[x] * window_y;
+        }
+    }
+    out
+}
+
+/// In-place 2D FFT of a row-major `width` x `height` grid, both powers of
+/// two. The inverse is scaled by `1 / (width * height)`.
+fn fft_2d(data: &mut [Complex], width: usize, height: usize, inverse: bool) {
+    for row in data.chunks_exact_mut(width) {
+        fft(row, inverse);
+    }
+    let mut column = vec![Complex::default(); height];
+    for x in 0..width {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = data[y * width + x];
+        }
+        fft(&mut column, inverse);
+        for (y, value) in column.iter().enumerate() {
+            data[y * width + x] = *value;
+        }
+    }
+    if inverse {
+        let scale = 1.0 / (width * height) as f32;
+        for value in data.iter_mut() {
+            value.re *= scale;
+            value.im *= scale;
+        }
+    }
+}
+
+/// Iterative radix-2 FFT; `data.len()` must be a power of two. Unscaled in
+/// both directions.
+fn fft(data: &mut [Complex], inverse: bool) {
+    let n = data.len();
+    if n < 2 {
+        return;
+    }
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let half = len / 2;
+        // Twiddles in f64 so long transforms do not accumulate f32 error.
+        let angle = sign * 2.0 * std::f64::consts::PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..half {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let twiddle = Complex {
+                    re: cos as f32,
+                    im: sin as f32,
+                };
+                let even = data[start + k];
+                let odd = data[start + k + half].mul(twiddle);
+                data[start + k] = Complex {
+                    re: even.re + odd.re,
+                    im: even.im + odd.im,
+                };
+                data[start + k + half] = Complex {
+                    re: even.re - odd.re,
+                    im: even.im - odd.im,
+                };
+            }
+        }
+        len *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::pack_gray_pixel;
+
+    /// Deterministic noise with some large-scale structure, so both fine and
+    /// coarse detail have to line up.
+    fn textured(width: u32, height: u32, dx: i32, dy: i32) -> Image {
+        let mut img = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x as i32 - dx, y as i32 - dy);
+                let hash = (sx.wrapping_mul(73_856_093) ^ sy.wrapping_mul(19_349_663)) as u32;
+                let v =
+                    ((hash >> 8) & 0x7F) as u8 + if (sx / 9 + sy / 7) % 2 == 0 { 0 } else { 120 };
+                img.set_pixel(x, y, pack_gray_pixel(v));
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn test_estimate_shift_finds_translation() {
+        let base = textured(100, 60, 0, 0);
+        for (dx, dy) in [(0, 0), (1, 0), (0, -1), (3, 2), (-5, 4)] {
+            let moved = textured(100, 60, dx, dy);
+            assert_eq!(estimate_shift(&base, &moved), (dx, dy));
+        }
+        assert_eq!(estimate_shift(&Image::new(8, 8), &Image::new(8, 8)), (0, 0));
+        assert_eq!(estimate_shift(&Image::new(0, 4), &base), (0, 0));
+    }
+
+    #[test]
+    fn test_fft_round_trips() {
+        let original: Vec<Complex> = (0..16)
+            .map(|i| Complex {
+                re: (i * 7 % 5) as f32,
+                im: (i % 3) as f32,
+            })
+            .collect();
+        let mut data = original.clone();
+        fft_2d(&mut data, 4, 4, false);
+        fft_2d(&mut data, 4, 4, true);
+        for (a, b) in data.iter().zip(&original) {
+            assert!((a.re - b.re).abs() < 1e-4 && (a.im - b.im).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! A byte buffer whose start is 16-byte aligned.
+//!
+//! `Vec<u8>` only promises 1-byte alignment, but [`Image::as_u32`] views the
+//! pixel bytes as `u32`s and the SIMD kernels load them 16 bytes at a time.
+//! Backing the bytes with 16-byte chunks makes the alignment part of the
+//! type instead of an accident of the allocator.
+//!
+//! [`Image::as_u32`]: crate::Image::as_u32
+
+use bytemuck::{Pod, Zeroable};
+use std::fmt;
+use std::ops::{Deref, DerefMut, Range};
+
+/// Alignment of every [`AlignedBytes`] buffer.
+pub const ALIGNMENT: usize = 16;
+
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+struct Chunk([u8; ALIGNMENT]);
+
+// SAFETY: a byte array with no padding (16 bytes at alignment 16), so every
+// bit pattern is valid and all-zero is a valid value.
+unsafe impl Zeroable for Chunk {}
+unsafe impl Pod for Chunk {}
+
+const ZERO: Chunk = Chunk([0; ALIGNMENT]);
+
+/// Growable bytes, 16-byte aligned, with the parts of the `Vec<u8>` API the
+/// image constructors use. Derefs to `[u8]`.
+///
+/// A `Vec<u8>` whose allocation already starts on a 16-byte boundary (as
+/// the system allocators return for all but tiny buffers) is adopted
+/// without copying, and [`into_vec`](Self::into_vec) hands it back the same
+/// way. Only a misaligned `Vec<u8>` is copied.
+pub struct AlignedBytes {
+    storage: Storage,
+}
+
+enum Storage {
+    /// Bytes past `len` in the last chunk are never exposed.
+    Chunks { chunks: Vec<Chunk>, len: usize },
+    /// A `Vec<u8>` whose pointer is 16-byte aligned. Operations that may
+    /// reallocate it check again and fall back to `Chunks`.
+    Adopted(Vec<u8>),
+}
+
+impl AlignedBytes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn from_chunks(chunks: Vec<Chunk>, len: usize) -> Self {
+        Self {
+            storage: Storage::Chunks { chunks, len },
+        }
+    }
+
+    /// Room for `capacity` bytes before reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::from_chunks(Vec::with_capacity(capacity.div_ceil(ALIGNMENT)), 0)
+    }
+
+    /// `len` zero bytes, allocated through `alloc_zeroed` (`calloc`).
+    pub fn zeroed(len: usize) -> Self {
+        Self::from_chunks(bytemuck::zeroed_vec(len.div_ceil(ALIGNMENT)), len)
+    }
+
+    /// `len` bytes of `value`.
+    pub fn filled(len: usize, value: u8) -> Self {
+        Self::from_chunks(
+            vec![Chunk([value; ALIGNMENT]); len.div_ceil(ALIGNMENT)],
+            len,
+        )
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Self {
+        let mut aligned = Self::with_capacity(bytes.len());
+        aligned.extend_from_slice(bytes);
+        aligned
+    }
+
+    /// Adopt `bytes` if it starts 16-byte aligned, else copy it.
+    pub fn from_vec(bytes: Vec<u8>) -> Self {
+        if is_aligned(&bytes) {
+            Self {
+                storage: Storage::Adopted(bytes),
+            }
+        } else {
+            Self::from_slice(&bytes)
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) {
+        match &mut self.storage {
+            Storage::Chunks { chunks, len } => {
+                if *len == chunks.len() * ALIGNMENT {
+                    chunks.push(ZERO);
+                }
+                *len += 1;
+                let last = *len - 1;
+                self[last] = byte;
+            }
+            Storage::Adopted(vec) => {
+                vec.push(byte);
+                self.realign();
+            }
+        }
+    }
+
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        let start = self.len();
+        self.resize(start + bytes.len(), 0);
+        self[start..].copy_from_slice(bytes);
+    }
+
+    /// Append a copy of the bytes in `range`.
+    pub fn extend_from_within(&mut self, range: Range<usize>) {
+        let start = self.len();
+        self.resize(start + range.len(), 0);
+        self.copy_within(range, start);
+    }
+
+    /// Grow or shrink to `len` bytes, filling new bytes with `value`.
+    pub fn resize(&mut self, new_len: usize, value: u8) {
+        match &mut self.storage {
+            Storage::Chunks { chunks, len } => {
+                let old = *len;
+                chunks.resize(new_len.div_ceil(ALIGNMENT), ZERO);
+                *len = new_len;
+                if new_len > old {
+                    self[old..].fill(value);
+                }
+            }
+            Storage::Adopted(vec) => {
+                vec.resize(new_len, value);
+                self.realign();
+            }
+        }
+    }
+
+    pub fn truncate(&mut self, new_len: usize) {
+        match &mut self.storage {
+            Storage::Chunks { chunks, len } => {
+                if new_len < *len {
+                    chunks.truncate(new_len.div_ceil(ALIGNMENT));
+                    *len = new_len;
+                }
+            }
+            // Truncating never reallocates.
+            Storage::Adopted(vec) => vec.truncate(new_len),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// The bytes as a plain `Vec<u8>`: an adopted `Vec<u8>` is returned as
+    /// is, chunked storage is copied.
+    pub fn into_vec(self) -> Vec<u8> {
+        match self.storage {
+            Storage::Adopted(vec) => vec,
+            Storage::Chunks { .. } => self.to_vec(),
+        }
+    }
+
+    /// After an adopted `Vec<u8>` may have reallocated: copy it into chunks
+    /// if its new allocation is misaligned.
+    fn realign(&mut self) {
+        if let Storage::Adopted(vec) = &self.storage {
+            if !is_aligned(vec) {
+                *self = Self::from_slice(vec);
+            }
+        }
+    }
+}
+
+fn is_aligned(bytes: &[u8]) -> bool {
+    (bytes.as_ptr() as usize).is_multiple_of(ALIGNMENT)
+}
+
+impl Default for AlignedBytes {
+    fn default() -> Self {
+        Self::from_chunks(Vec::new(), 0)
+    }
+}
+
+/// A clone of an adopted `Vec<u8>` is a new allocation, so it is checked
+/// for alignment again.
+impl Clone for AlignedBytes {
+    fn clone(&self) -> Self {
+        match &self.storage {
+            Storage::Chunks { chunks, len } => Self::from_chunks(chunks.clone(), *len),
+            Storage::Adopted(vec) => Self::from_vec(vec.clone()),
+        }
+    }
+}
+
+impl Deref for AlignedBytes {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        match &self.storage {
+            Storage::Chunks { chunks, len } => &bytemuck::cast_slice(chunks)[..*len],
+            Storage::Adopted(vec) => vec,
+        }
+    }
+}
+
+impl DerefMut for AlignedBytes {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match &mut self.storage {
+            Storage::Chunks { chunks, len } => &mut bytemuck::cast_slice_mut(chunks)[..*len],
+            Storage::Adopted(vec) => vec,
+        }
+    }
+}
+
+impl AsRef<[u8]> for AlignedBytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl AsMut<[u8]> for AlignedBytes {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+/// See [`AlignedBytes::from_vec`].
+impl From<Vec<u8>> for AlignedBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::from_vec(bytes)
+    }
+}
+
+impl From<&[u8]> for AlignedBytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self::from_slice(bytes)
+    }
+}
+
+impl From<AlignedBytes> for Vec<u8> {
+    fn from(bytes: AlignedBytes) -> Self {
+        bytes.into_vec()
+    }
+}
+
+impl FromIterator<u8> for AlignedBytes {
+    fn from_iter<I: IntoIterator<Item = u8>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut aligned = Self::with_capacity(iter.size_hint().0);
+        for byte in iter {
+            aligned.push(byte);
+        }
+        aligned
+    }
+}
+
+impl Extend<u8> for AlignedBytes {
+    fn extend<I: IntoIterator<Item = u8>>(&mut self, iter: I) {
+        for byte in iter {
+            self.push(byte);
+        }
+    }
+}
+
+impl PartialEq for AlignedBytes {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for AlignedBytes {}
+
+impl PartialEq<[u8]> for AlignedBytes {
+    fn eq(&self, other: &[u8]) -> bool {
+        **self == *other
+    }
+}
+
+impl PartialEq<Vec<u8>> for AlignedBytes {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        **self == **other
+    }
+}
+
+impl PartialEq<AlignedBytes> for [u8] {
+    fn eq(&self, other: &AlignedBytes) -> bool {
+        *self == **other
+    }
+}
+
+impl PartialEq<AlignedBytes> for Vec<u8> {
+    fn eq(&self, other: &AlignedBytes) -> bool {
+        **self == **other
+    }
+}
+
+impl fmt::Debug for AlignedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aligned_bytes() {
+        let mut bytes = AlignedBytes::new();
+        assert!(is_aligned(&bytes));
+        for b in 0..20 {
+            bytes.push(b);
+        }
+        bytes.extend_from_slice(&[7; 30]);
+        assert_eq!(bytes.len(), 50);
+        assert_eq!(bytes[19], 19);
+        assert_eq!(bytes[49], 7);
+        assert!(is_aligned(&bytes));
+
+        bytes.truncate(17);
+        bytes.resize(18, 9);
+        assert_eq!(bytes, (0..17).chain([9]).collect::<Vec<u8>>());
+        assert_eq!(AlignedBytes::filled(5, 3), vec![3; 5]);
+        assert_eq!(AlignedBytes::zeroed(33).into_vec(), vec![0; 33]);
+    }
+
+    #[test]
+    fn test_aligned_bytes_adopts_aligned_vec() {
+        // Whether the allocator aligned the `Vec` is up to it, so adoption
+        // is only checked when it did.
+        let vec: Vec<u8> = (0..64).collect();
+        let ptr = vec.as_ptr();
+        let aligned_input = is_aligned(&vec);
+        let mut bytes = AlignedBytes::from(vec);
+        assert!(is_aligned(&bytes));
+        assert_eq!(bytes.as_ptr() == ptr, aligned_input);
+
+        bytes.extend_from_slice(&[1; 1000]);
+        bytes.push(2);
+        assert!(is_aligned(&bytes));
+        assert_eq!(bytes.len(), 1065);
+        assert!(is_aligned(&bytes.clone()));
+
+        let ptr = bytes.as_ptr();
+        let vec = bytes.into_vec();
+        if aligned_input {
+            assert_eq!(vec.as_ptr(), ptr);
+        }
+        assert_eq!(vec[..64], (0..64).collect::<Vec<u8>>());
+    }
+}
@@ -1,7 +1,7 @@
 //! Anti-aliasing detection based on "Anti-aliased Pixel and Intensity Slope Detector"
 //! by V. Vysniauskas (2009). Examines 3x3 neighborhood to find gradient patterns.
 
-use crate::types::Image;
+use crate::types::{Image, Pixels};
 
 // f32 YIQ coefficients for Y-only calculation
 const YIQ_Y_F32: [f32; 3] = [0.29889531, 0.58662247, 0.11448223];
@@ -31,7 +31,9 @@ fn has_many_siblings_simd(image_u32: &[u32], x: u32, y: u32, width: u32) -> bool
 
     #[cfg(target_arch = "x86_64")]
     {
-        if is_x86_feature_detected!("sse4.1") {
+        if is_x86_feature_detected!("avx2") {
+            unsafe { has_many_siblings_avx2(image_u32, x, y, width) }
+        } else if is_x86_feature_detected!("sse4.1") {
             unsafe { has_many_siblings_sse(image_u32, x, y, width) }
         } else {
             has_many_siblings_scalar(image_u32, x, y, width, u32::MAX, 0)
@@ -142,6 +144,39 @@ unsafe fn has_many_siblings_sse(image_u32: &[u32], x: u32, y: u32, width: u32) -
     count > 2
 }
 
+/// AVX2 sibling check: the three-pixel rows above and below share one
+/// 256-bit compare. Masked loads read exactly those three pixels, so the row
+/// below never reaches past the last interior pixel.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn has_many_siblings_avx2(image_u32: &[u32], x: u32, y: u32, width: u32) -> bool {
+    use std::arch::x86_64::*;
+
+    let pos = (y * width + x) as usize;
+    let val = image_u32[pos];
+    let center = _mm256_set1_epi32(val as i32);
+    let three = _mm_setr_epi32(-1, -1, -1, 0);
+
+    let above = _mm_maskload_epi32(
+        image_u32.as_ptr().add(pos - width as usize - 1) as *const i32,
+        three,
+    );
+    let below = _mm_maskload_epi32(
+        image_u32.as_ptr().add(pos + width as usize - 1) as *const i32,
+        three,
+    );
+    let rows = _mm256_set_m128i(below, above);
+
+    // Lane 3 of each half is a masked-out zero; drop it from the mask.
+    let mask =
+        _mm256_movemask_ps(_mm256_castsi256_ps(_mm256_cmpeq_epi32(rows, center))) & 0b0111_0111;
+
+    let count =
+        mask.count_ones() + (image_u32[pos - 1] == val) as u32 + (image_u32[pos + 1] == val) as u32;
+    count > 2
+}
+
 /// Scalar fallback with bounds checking
 #[inline]
 fn has_many_siblings_scalar(
@@ -197,7 +232,37 @@ fn brightness_delta_f32(pixel_a: u32, pixel_b: u32) -> f32 {
     dr * YIQ_Y_F32[0] + dg * YIQ_Y_F32[1] + db * YIQ_Y_F32[2]
 }
 
+/// Largest absolute Y (brightness) delta between a pixel and its 3x3
+/// neighbors, 0-255. High values mark edges such as glyph outlines.
+pub fn local_contrast(image: &Image, x: u32, y: u32) -> f32 {
+    local_contrast_pixels(image.pixels(), x, y)
+}
+
+pub(crate) fn local_contrast_pixels(image: Pixels<'_>, x: u32, y: u32) -> f32 {
+    let a32 = image.as_u32();
+    let (width, height) = (image.width, image.height);
+    let center_pixel = a32[(y * width + x) as usize];
+
+    let mut contrast = 0.0f32;
+    for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+        for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+            let adj_pixel = a32[(ny * width + nx) as usize];
+            contrast = contrast.max(brightness_delta_f32(center_pixel, adj_pixel).abs());
+        }
+    }
+    contrast
+}
+
 pub fn is_antialiased(image1: &Image, image2: &Image, x: u32, y: u32) -> bool {
+    is_antialiased_pixels(image1.pixels(), image2.pixels(), x, y)
+}
+
+pub(crate) fn is_antialiased_pixels(
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
+    x: u32,
+    y: u32,
+) -> bool {
     let a32 = image1.as_u32();
     let b32 = image2.as_u32();
 
@@ -227,9 +292,11 @@ pub fn is_antialiased(image1: &Image, image2: &Image, x: u32, y: u32) -> bool {
     let mut max_x = 0u32;
     let mut max_y = 0u32;
 
-    // Examine 8 adjacent pixels
-    for ny in y0..=y1 {
-        for nx in x0..=x1 {
+    // Examine 8 adjacent pixels, column by column as pixelmatch does: the
+    // first darkest/brightest neighbor wins ties, so the order decides which
+    // sibling gets checked below.
+    for nx in x0..=x1 {
+        for ny in y0..=y1 {
             if nx == x && ny == y {
                 continue;
             }
@@ -310,6 +377,28 @@ mod tests {
         assert!(!is_antialiased(&img, &img, 9, 0));
     }
 
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_avx2_siblings_match_scalar() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        // Three colors, so every sibling count from 0 to 8 shows up.
+        let (width, height) = (13u32, 9u32);
+        let pixels: Vec<u32> = (0..width * height)
+            .map(|i| [0u32, 0xFFFF_FFFF, 0x8040_2010][(i * 7 % 11 % 3) as usize])
+            .collect();
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                assert_eq!(
+                    unsafe { has_many_siblings_avx2(&pixels, x, y, width) },
+                    has_many_siblings_scalar(&pixels, x, y, width, height, 0),
+                    "({x}, {y})"
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_gradient_detection() {
         // Create an image with a gradient pattern
@@ -0,0 +1,155 @@
+//! Colormaps for [`OutputMode::Heatmap`](crate::OutputMode::Heatmap): each
+//! maps a normalized delta magnitude through a 256-entry RGB lookup table.
+
+/// Lookup table a heatmap colors counted pixels through.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Colormap {
+    /// Blue for barely-over-threshold deltas through to red for the largest.
+    #[default]
+    BlueRed,
+    /// Perceptually uniform dark purple to yellow (matplotlib's viridis).
+    Viridis,
+    /// Perceptually uniform black to pale yellow (matplotlib's magma).
+    Magma,
+    /// Black to white.
+    Grayscale,
+}
+
+impl Colormap {
+    /// The RGB color at `t` in 0..=1, quantized to the nearest LUT entry;
+    /// out-of-range values clamp.
+    pub fn rgb(self, t: f32) -> [u8; 3] {
+        let lut = match self {
+            Colormap::BlueRed => &BLUE_RED,
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Magma => &MAGMA,
+            Colormap::Grayscale => &GRAYSCALE,
+        };
+        let i = (t.clamp(0.0, 1.0) * 255.0).round() as usize * 3;
+        [lut[i], lut[i + 1], lut[i + 2]]
+    }
+}
+
+/// A linear 256-step ramp between two RGB endpoints.
+const fn ramp(from: [u8; 3], to: [u8; 3]) -> [u8; 768] {
+    let mut lut = [0u8; 768];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = 0;
+        while c < 3 {
+            let (a, b) = (from[c] as u32, to[c] as u32);
+            lut[i * 3 + c] = ((a * (255 - i as u32) + b * i as u32 + 127) / 255) as u8;
+            c += 1;
+        }
+        i += 1;
+    }
+    lut
+}
+
+const BLUE_RED: [u8; 768] = ramp([0, 0, 255], [255, 0, 0]);
+const GRAYSCALE: [u8; 768] = ramp([0, 0, 0], [255, 255, 255]);
+
+/// matplotlib's viridis, sampled from a degree-6 polynomial fit.
+const VIRIDIS: [u8; 768] = [
+    71, 1, 85, 71, 3, 87, 71, 4, 88, 71, 6, 89, 71, 7, 91, 71, 8, 92, 71, 10, 93, 71, 11, 95, 72,
+    13, 96, 72, 14, 97, 72, 15, 99, 72, 17, 100, 72, 18, 101, 72, 20, 103, 72, 21, 104, 72, 22,
+    105, 72, 24, 106, 72, 25, 108, 72, 26, 109, 72, 28, 110, 72, 29, 111, 72, 31, 112, 72, 32, 113,
+    72, 33, 114, 72, 35, 116, 72, 36, 117, 72, 37, 118, 72, 39, 119, 71, 40, 120, 71, 41, 121, 71,
+    42, 121, 71, 44, 122, 71, 45, 123, 71, 46, 124, 71, 48, 125, 70, 49, 126, 70, 50, 127, 70, 51,
+    127, 70, 53, 128, 70, 54, 129, 69, 55, 129, 69, 56, 130, 69, 58, 131, 69, 59, 131, 68, 60, 132,
+    68, 61, 133, 68, 62, 133, 68, 63, 134, 67, 65, 134, 67, 66, 135, 67, 67, 135, 66, 68, 136, 66,
+    69, 136, 65, 70, 136, 65, 72, 137, 65, 73, 137, 64, 74, 138, 64, 75, 138, 63, 76, 138, 63, 77,
+    139, 63, 78, 139, 62, 79, 139, 62, 80, 139, 61, 81, 140, 61, 82, 140, 60, 84, 140, 60, 85, 140,
+    59, 86, 140, 59, 87, 141, 58, 88, 141, 58, 89, 141, 57, 90, 141, 57, 91, 141, 56, 92, 141, 56,
+    93, 141, 55, 94, 142, 54, 95, 142, 54, 96, 142, 53, 97, 142, 53, 98, 142, 52, 99, 142, 52, 100,
+    142, 51, 101, 142, 50, 102, 142, 50, 103, 142, 49, 104, 142, 49, 105, 142, 48, 106, 142, 48,
+    107, 142, 47, 108, 142, 46, 109, 142, 46, 110, 142, 45, 111, 142, 45, 112, 142, 44, 113, 142,
+    44, 114, 142, 43, 115, 142, 43, 116, 142, 42, 116, 142, 41, 117, 142, 41, 118, 142, 40, 119,
+    142, 40, 120, 142, 39, 121, 142, 39, 122, 142, 38, 123, 142, 38, 124, 141, 37, 125, 141, 37,
+    126, 141, 37, 127, 141, 36, 128, 141, 36, 129, 141, 35, 130, 141, 35, 131, 141, 34, 132, 141,
+    34, 133, 141, 34, 134, 141, 33, 134, 141, 33, 135, 140, 33, 136, 140, 33, 137, 140, 32, 138,
+    140, 32, 139, 140, 32, 140, 140, 32, 141, 140, 31, 142, 140, 31, 143, 139, 31, 144, 139, 31,
+    145, 139, 31, 146, 139, 31, 147, 139, 31, 148, 139, 31, 148, 138, 31, 149, 138, 31, 150, 138,
+    31, 151, 138, 31, 152, 137, 31, 153, 137, 31, 154, 137, 31, 155, 137, 32, 156, 136, 32, 157,
+    136, 32, 158, 136, 32, 159, 136, 33, 160, 135, 33, 161, 135, 33, 162, 135, 34, 162, 134, 34,
+    163, 134, 35, 164, 133, 35, 165, 133, 36, 166, 133, 37, 167, 132, 37, 168, 132, 38, 169, 131,
+    39, 170, 131, 39, 171, 130, 40, 172, 130, 41, 172, 129, 42, 173, 128, 43, 174, 128, 43, 175,
+    127, 44, 176, 127, 45, 177, 126, 46, 178, 125, 48, 179, 125, 49, 180, 124, 50, 180, 123, 51,
+    181, 122, 52, 182, 122, 53, 183, 121, 55, 184, 120, 56, 185, 119, 58, 186, 118, 59, 186, 117,
+    60, 187, 116, 62, 188, 115, 63, 189, 114, 65, 190, 113, 67, 191, 112, 68, 191, 111, 70, 192,
+    110, 72, 193, 109, 74, 194, 108, 75, 195, 107, 77, 195, 105, 79, 196, 104, 81, 197, 103, 83,
+    198, 102, 85, 198, 100, 87, 199, 99, 89, 200, 98, 91, 201, 96, 94, 201, 95, 96, 202, 94, 98,
+    203, 92, 100, 204, 91, 103, 204, 89, 105, 205, 88, 107, 206, 86, 110, 206, 85, 112, 207, 83,
+    115, 208, 82, 117, 208, 80, 120, 209, 78, 122, 210, 77, 125, 210, 75, 127, 211, 74, 130, 211,
+    72, 132, 212, 70, 135, 213, 69, 138, 213, 67, 141, 214, 65, 143, 214, 64, 146, 215, 62, 149,
+    215, 61, 152, 216, 59, 154, 217, 57, 157, 217, 56, 160, 218, 54, 163, 218, 52, 166, 219, 51,
+    168, 219, 49, 171, 220, 48, 174, 220, 46, 177, 220, 45, 180, 221, 43, 183, 221, 42, 186, 222,
+    41, 188, 222, 39, 191, 223, 38, 194, 223, 37, 197, 223, 36, 200, 224, 35, 202, 224, 33, 205,
+    225, 32, 208, 225, 32, 210, 225, 31, 213, 226, 30, 216, 226, 29, 218, 226, 29, 221, 227, 28,
+    224, 227, 28, 226, 227, 27, 228, 228, 27, 231, 228, 27, 233, 228, 27, 236, 229, 27, 238, 229,
+    27, 240, 229, 28, 242, 230, 28, 244, 230, 29, 246, 230, 30, 248, 231, 31, 250, 231, 32, 252,
+    231, 33,
+];
+
+/// matplotlib's magma, sampled from a degree-6 polynomial fit.
+const MAGMA: [u8; 768] = [
+    0, 0, 0, 0, 0, 1, 0, 1, 4, 0, 2, 6, 1, 2, 9, 1, 3, 11, 2, 3, 14, 3, 4, 16, 3, 4, 19, 4, 5, 21,
+    5, 5, 23, 6, 6, 26, 7, 6, 28, 7, 7, 31, 8, 7, 33, 9, 7, 36, 10, 8, 38, 11, 8, 40, 12, 9, 43,
+    14, 9, 45, 15, 9, 47, 16, 10, 50, 17, 10, 52, 18, 10, 54, 19, 11, 57, 21, 11, 59, 22, 11, 61,
+    23, 11, 63, 25, 12, 65, 26, 12, 67, 27, 12, 70, 29, 12, 72, 30, 13, 74, 31, 13, 76, 33, 13, 78,
+    34, 14, 80, 36, 14, 82, 37, 14, 83, 39, 14, 85, 40, 15, 87, 42, 15, 89, 43, 15, 91, 45, 15, 93,
+    46, 16, 94, 48, 16, 96, 49, 16, 98, 51, 16, 99, 52, 17, 101, 54, 17, 102, 55, 17, 104, 57, 17,
+    105, 59, 18, 107, 60, 18, 108, 62, 18, 109, 63, 19, 111, 65, 19, 112, 66, 19, 113, 68, 19, 115,
+    70, 20, 116, 71, 20, 117, 73, 20, 118, 74, 21, 119, 76, 21, 120, 78, 21, 121, 79, 22, 122, 81,
+    22, 123, 83, 22, 124, 84, 23, 125, 86, 23, 126, 87, 23, 126, 89, 24, 127, 91, 24, 128, 92, 24,
+    128, 94, 25, 129, 96, 25, 130, 97, 25, 130, 99, 26, 131, 101, 26, 131, 102, 26, 132, 104, 27,
+    132, 105, 27, 132, 107, 28, 133, 109, 28, 133, 110, 28, 133, 112, 29, 134, 114, 29, 134, 115,
+    30, 134, 117, 30, 134, 119, 31, 134, 120, 31, 134, 122, 31, 134, 124, 32, 134, 125, 32, 134,
+    127, 33, 134, 129, 33, 134, 130, 34, 134, 132, 34, 134, 134, 35, 134, 135, 35, 134, 137, 36,
+    134, 139, 36, 133, 140, 37, 133, 142, 37, 133, 144, 38, 132, 145, 38, 132, 147, 39, 132, 149,
+    39, 131, 150, 40, 131, 152, 40, 131, 154, 41, 130, 155, 42, 130, 157, 42, 129, 158, 43, 129,
+    160, 43, 128, 162, 44, 128, 163, 45, 127, 165, 45, 127, 167, 46, 126, 168, 46, 125, 170, 47,
+    125, 172, 48, 124, 173, 48, 124, 175, 49, 123, 176, 50, 122, 178, 51, 122, 180, 51, 121, 181,
+    52, 120, 183, 53, 120, 184, 54, 119, 186, 54, 118, 187, 55, 118, 189, 56, 117, 190, 57, 116,
+    192, 57, 115, 194, 58, 115, 195, 59, 114, 197, 60, 113, 198, 61, 113, 200, 62, 112, 201, 63,
+    111, 203, 63, 111, 204, 64, 110, 205, 65, 109, 207, 66, 109, 208, 67, 108, 210, 68, 107, 211,
+    69, 107, 212, 70, 106, 214, 71, 105, 215, 72, 105, 217, 73, 104, 218, 74, 104, 219, 75, 103,
+    220, 77, 102, 222, 78, 102, 223, 79, 101, 224, 80, 101, 225, 81, 100, 227, 82, 100, 228, 83,
+    99, 229, 85, 99, 230, 86, 98, 231, 87, 98, 232, 88, 98, 233, 90, 97, 234, 91, 97, 235, 92, 97,
+    237, 94, 96, 238, 95, 96, 238, 97, 96, 239, 98, 96, 240, 99, 96, 241, 101, 95, 242, 102, 95,
+    243, 104, 95, 244, 105, 95, 245, 107, 95, 245, 108, 95, 246, 110, 95, 247, 111, 95, 248, 113,
+    95, 248, 115, 95, 249, 116, 96, 249, 118, 96, 250, 120, 96, 251, 121, 96, 251, 123, 97, 252,
+    125, 97, 252, 127, 97, 253, 128, 98, 253, 130, 98, 253, 132, 99, 254, 134, 99, 254, 136, 100,
+    255, 137, 100, 255, 139, 101, 255, 141, 101, 255, 143, 102, 255, 145, 103, 255, 147, 104, 255,
+    149, 104, 255, 151, 105, 255, 153, 106, 255, 155, 107, 255, 157, 108, 255, 159, 109, 255, 161,
+    110, 255, 163, 111, 255, 165, 112, 255, 167, 113, 255, 169, 114, 255, 171, 116, 255, 173, 117,
+    255, 175, 118, 255, 177, 119, 255, 180, 121, 255, 182, 122, 255, 184, 123, 255, 186, 125, 255,
+    188, 126, 255, 190, 128, 254, 192, 129, 254, 194, 131, 254, 196, 132, 254, 198, 134, 253, 200,
+    135, 253, 202, 137, 253, 205, 139, 253, 207, 140, 252, 209, 142, 252, 211, 144, 252, 213, 145,
+    252, 215, 147, 251, 216, 149, 251, 218, 150, 251, 220, 152, 251, 222, 154, 251, 224, 156, 251,
+    226, 157, 250, 228, 159, 250, 229, 161, 250, 231, 163, 250, 233, 164, 250, 234, 166, 250, 236,
+    168, 250, 237, 170, 251, 239, 171, 251, 240, 173, 251, 241, 175, 251, 243, 177, 252, 244, 178,
+    252, 245, 180, 253, 246, 182, 253, 247, 183, 254, 248, 185, 254, 249, 186,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colormap_endpoints() {
+        assert_eq!(Colormap::BlueRed.rgb(0.0), [0, 0, 255]);
+        assert_eq!(Colormap::BlueRed.rgb(1.0), [255, 0, 0]);
+        assert_eq!(Colormap::Grayscale.rgb(0.5), [128, 128, 128]);
+        assert_eq!(Colormap::Grayscale.rgb(7.0), [255, 255, 255]);
+        // Dark purple to yellow, dark to pale: both brighten monotonically
+        // in green, the channel that dominates luminance.
+        for map in [Colormap::Viridis, Colormap::Magma] {
+            let greens: Vec<u8> = (0..=10).map(|i| map.rgb(i as f32 / 10.0)[1]).collect();
+            assert!(
+                greens.windows(2).all(|w| w[0] < w[1]),
+                "{map:?}: {greens:?}"
+            );
+        }
+    }
+}
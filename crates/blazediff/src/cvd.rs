@@ -0,0 +1,130 @@
+//! Color-vision-deficiency simulation, for diffing what a color-blind viewer
+//! would actually tell apart.
+
+use crate::aligned::AlignedBytes;
+use crate::types::{Image, Pixels};
+
+/// Which dichromacy [`Image::simulate_cvd`] models.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CvdType {
+    /// No working L (red) cones.
+    Protanopia,
+    /// No working M (green) cones.
+    Deuteranopia,
+    /// No working S (blue) cones.
+    Tritanopia,
+}
+
+impl CvdType {
+    /// Machado, Oliveira & Fernandes (2009) at full severity: the LMS-space
+    /// projection onto the dichromat's plane, folded into one linear-RGB
+    /// matrix.
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            CvdType::Protanopia => [
+                [0.152_286, 1.052_583, -0.204_868],
+                [0.114_503, 0.786_281, 0.099_216],
+                [-0.003_882, -0.048_116, 1.051_998],
+            ],
+            CvdType::Deuteranopia => [
+                [0.367_322, 0.860_646, -0.227_968],
+                [0.280_085, 0.672_501, 0.047_413],
+                [-0.011_820, 0.042_940, 0.968_881],
+            ],
+            CvdType::Tritanopia => [
+                [1.255_528, -0.076_749, -0.178_779],
+                [-0.078_411, 0.930_809, 0.147_602],
+                [0.004_733, 0.691_367, 0.303_900],
+            ],
+        }
+    }
+}
+
+impl Image {
+    /// How the image looks to a viewer with `kind` of color blindness: each
+    /// pixel is decoded from sRGB, transformed in linear RGB and re-encoded.
+    /// Grays and alpha are unchanged.
+    pub fn simulate_cvd(&self, kind: CvdType) -> Image {
+        self.pixels().simulate_cvd(kind)
+    }
+}
+
+impl Pixels<'_> {
+    pub(crate) fn simulate_cvd(self, kind: CvdType) -> Image {
+        let matrix = kind.matrix();
+        let to_linear: Vec<f32> = (0..=255u8).map(srgb_to_linear).collect();
+
+        let mut data = AlignedBytes::with_capacity(self.data.len() * 4);
+        // Screenshots repeat colors in long runs, so the previous pixel's
+        // result saves most of the re-encoding.
+        let mut last: Option<(u32, [u8; 4])> = None;
+        for &pixel in self.data {
+            let simulated = match last {
+                Some((previous, simulated)) if previous == pixel => simulated,
+                _ => {
+                    let [r, g, b, a] = pixel.to_le_bytes();
+                    let linear = [r, g, b].map(|c| to_linear[c as usize]);
+                    let [r, g, b] = matrix.map(|row| {
+                        linear_to_srgb(row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2])
+                    });
+                    let simulated = [r, g, b, a];
+                    last = Some((pixel, simulated));
+                    simulated
+                }
+            };
+            data.extend_from_slice(&simulated);
+        }
+
+        Image {
+            data,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let c = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grays_and_alpha_unchanged() {
+        let mut img = Image::new(3, 1);
+        img.set_pixel(0, 0, 0xFF00_0000);
+        img.set_pixel(1, 0, 0x8080_8080);
+        img.set_pixel(2, 0, 0x40FF_FFFF);
+        for kind in [
+            CvdType::Protanopia,
+            CvdType::Deuteranopia,
+            CvdType::Tritanopia,
+        ] {
+            assert!(img.simulate_cvd(kind).data == img.data, "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn test_srgb_round_trip() {
+        for value in 0..=255u8 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(value)), value);
+        }
+    }
+}
@@ -2,13 +2,24 @@
 //!
 //! Cold pass: SIMD byte comparison to identify changed blocks, draw unchanged immediately.
 //! Hot pass: YIQ perceptual delta with anti-aliasing detection on changed blocks only.
+//! Both passes share the same delta kernels, so a block is flagged exactly
+//! when the hot pass would count at least one of its pixels (before AA).
 //!
 //! SIMD: NEON (aarch64), AVX-512/AVX2/SSE4.1 (x86_64), scalar fallback (riscv64, others).
 
-use crate::antialiasing::is_antialiased;
-use crate::output::{clear_transparent, fill_block_gray_optimized};
-use crate::types::{DiffError, DiffOptions, DiffResult, Image};
-use crate::yiq::threshold_to_max_delta_f32;
+use crate::antialiasing::{is_antialiased_pixels, local_contrast_pixels};
+use crate::lab::delta_e76;
+use crate::output::{draw_grid, fill_block_gray_u32, posterize_bytes};
+use crate::types::{
+    BackgroundSource, ColorMetric, CompatMode, DiffError, DiffOptions, DiffResult, Image,
+    OutputMode, Pixels, SimdBackend, SizeMismatchPolicy,
+};
+use crate::yiq::{threshold_to_max_delta_f32, MAX_YIQ_DELTA, MAX_YIQ_DELTA_F32};
+use std::borrow::Cow;
+use std::sync::Arc;
+
+mod incremental;
+pub use incremental::{Differ, DifferPool, PooledDiffer};
 
 /// Cached CPU feature detection for x86_64
 #[cfg(target_arch = "x86_64")]
@@ -29,14 +40,92 @@ impl X86Features {
     }
 }
 
+#[cfg(target_arch = "x86_64")]
+impl X86Features {
+    /// Detected features, narrowed to `force` when a backend is forced and
+    /// to the non-FMA kernels under [`CompatMode::Wasm`].
+    fn select(force: Option<SimdBackend>, compat: CompatMode) -> Result<Self, DiffError> {
+        let mut detected = Self::detect();
+        if compat == CompatMode::Wasm {
+            detected.has_avx2_fma = false;
+        }
+        let selected = match force {
+            None => Some(detected),
+            Some(SimdBackend::Scalar) => Some(Self {
+                has_avx2_fma: false,
+                has_sse41: false,
+            }),
+            Some(SimdBackend::Sse41) if detected.has_sse41 => Some(Self {
+                has_avx2_fma: false,
+                has_sse41: true,
+            }),
+            Some(SimdBackend::Avx2) if detected.has_avx2_fma => Some(detected),
+            Some(_) => None,
+        };
+        selected.ok_or_else(|| DiffError::UnsupportedBackend(force.unwrap()))
+    }
+
+    /// The kernels the cold and hot passes dispatch to with these features.
+    fn backend(self) -> SimdBackend {
+        if self.has_avx2_fma {
+            SimdBackend::Avx2
+        } else if self.has_sse41 {
+            SimdBackend::Sse41
+        } else {
+            SimdBackend::Scalar
+        }
+    }
+}
+
+/// The only backend compiled in on targets without runtime dispatch.
+#[cfg(target_arch = "aarch64")]
+fn native_backend() -> SimdBackend {
+    SimdBackend::Neon
+}
+
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+fn native_backend() -> SimdBackend {
+    SimdBackend::Simd128
+}
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    all(target_arch = "wasm32", target_feature = "simd128")
+)))]
+fn native_backend() -> SimdBackend {
+    SimdBackend::Scalar
+}
+
+/// The backend [`diff`] runs on for this CPU and build when
+/// [`DiffOptions::force_backend`] is unset.
+pub fn detected_backend() -> SimdBackend {
+    #[cfg(target_arch = "x86_64")]
+    return X86Features::detect().backend();
+    #[cfg(not(target_arch = "x86_64"))]
+    native_backend()
+}
+
 const YIQ_Y_F32: [f32; 3] = [0.29889531, 0.58662247, 0.11448223];
 const YIQ_I_F32: [f32; 3] = [0.59597799, -0.2741761, -0.32180189];
 const YIQ_Q_F32: [f32; 3] = [0.21147017, -0.52261711, 0.31114694];
 const YIQ_WEIGHTS_F32: [f32; 3] = [0.5053, 0.299, 0.1957];
 const INV_255: f32 = 1.0 / 255.0;
 
+/// Edge length of the square blocks the cold pass scans for a `width` x
+/// `height` image.
+///
+/// The edge grows with the fourth root of the area (16 px at 100x100), so
+/// the block count only grows with the square root of the area: big images
+/// get big blocks and the cold pass stays cheap, while small images keep
+/// blocks fine enough that one changed pixel doesn't drag a large unchanged
+/// area into the hot pass. The result is rounded to a power of two and
+/// clamped to 8..=128; zero-area images get the minimum.
 #[inline]
-fn calculate_block_size(width: u32, height: u32) -> u32 {
+pub fn calculate_block_size(width: u32, height: u32) -> u32 {
+    if width == 0 || height == 0 {
+        return 8;
+    }
     let area = (width as f64) * (height as f64);
     let scale = area.sqrt() / 100.0;
     let raw_size = 16.0 * scale.sqrt();
@@ -44,6 +133,19 @@ fn calculate_block_size(width: u32, height: u32) -> u32 {
     (1u32 << (log2_val.round() as u32)).clamp(8, 128)
 }
 
+/// Block grid [`diff`] uses for a `width` x `height` image, as
+/// `(block_size, blocks_x, blocks_y)`. Edge blocks are clipped to the image,
+/// so `blocks_x * block_size` may exceed `width`.
+#[inline]
+pub fn block_grid(width: u32, height: u32) -> (u32, u32, u32) {
+    let block_size = calculate_block_size(width, height);
+    (
+        block_size,
+        width.div_ceil(block_size),
+        height.div_ceil(block_size),
+    )
+}
+
 // =============================================================================
 // Block perceptual diff detection (cold pass)
 // =============================================================================
@@ -160,7 +262,7 @@ fn block_has_perceptual_diff_neon(
     let row_width = (end_x - start_x) as usize;
 
     for y in start_y..end_y {
-        let row_start = (y * width + start_x) as usize;
+        let row_start = y as usize * width as usize + start_x as usize;
         let mut offset = 0;
 
         unsafe {
@@ -175,7 +277,7 @@ fn block_has_perceptual_diff_neon(
 
                 if vmaxvq_u32(not_cmp) != 0 {
                     // At least one pixel differs - check with SIMD YIQ
-                    let deltas = yiq_delta_4_neon_direct(va, vb);
+                    let deltas = yiq_delta_4_neon_signed(va, vb, vdupq_n_u32(0xFF));
                     let max_vec = vdupq_n_f32(max_delta);
                     let abs_deltas = vabsq_f32(deltas);
                     let exceeds = vcgtq_f32(abs_deltas, max_vec);
@@ -217,7 +319,7 @@ fn block_has_perceptual_diff_wasm(
     let row_width = (end_x - start_x) as usize;
 
     for y in start_y..end_y {
-        let row_start = (y * width + start_x) as usize;
+        let row_start = y as usize * width as usize + start_x as usize;
         let mut offset = 0;
 
         unsafe {
@@ -231,7 +333,7 @@ fn block_has_perceptual_diff_wasm(
                 let not_cmp = v128_not(cmp);
 
                 if v128_any_true(not_cmp) {
-                    let deltas = yiq_delta_4_wasm_direct(va, vb);
+                    let deltas = yiq_delta_4_wasm_signed(va, vb);
                     let max_vec = f32x4_splat(max_delta);
                     let abs_deltas = f32x4_abs(deltas);
                     let exceeds = f32x4_gt(abs_deltas, max_vec);
@@ -272,9 +374,14 @@ unsafe fn block_has_perceptual_diff_avx2(
     use std::arch::x86_64::*;
 
     let row_width = (end_x - start_x) as usize;
+    let mask_ff = _mm256_set1_epi32(0xFF);
+    let v255 = _mm256_set1_ps(255.0);
+    let zero = _mm256_setzero_ps();
+    let mask_ff_128 = _mm_set1_epi32(0xFF);
+    let zero_128 = _mm_setzero_ps();
 
     for y in start_y..end_y {
-        let row_start = (y * width + start_x) as usize;
+        let row_start = y as usize * width as usize + start_x as usize;
         let mut offset = 0;
 
         let a_ptr = a32.as_ptr().add(row_start);
@@ -289,7 +396,7 @@ unsafe fn block_has_perceptual_diff_avx2(
 
             if mask != 0xFF {
                 // At least one pixel differs - compute YIQ deltas
-                let deltas = yiq_delta_8_avx2_direct(va, vb);
+                let deltas = yiq_delta_8_avx2_signed(va, vb, mask_ff, v255, zero);
                 let max_vec = _mm256_set1_ps(max_delta);
                 let abs_mask = _mm256_castsi256_ps(_mm256_set1_epi32(0x7FFFFFFF));
                 let abs_deltas = _mm256_and_ps(deltas, abs_mask);
@@ -309,7 +416,7 @@ unsafe fn block_has_perceptual_diff_avx2(
             let mask = _mm_movemask_epi8(cmp);
 
             if mask != 0xFFFF {
-                let deltas = yiq_delta_4_sse_direct(va, vb);
+                let deltas = yiq_delta_4_sse_signed(va, vb, mask_ff_128, zero_128);
                 let max_vec = _mm_set1_ps(max_delta);
                 let abs_mask = _mm_castsi128_ps(_mm_set1_epi32(0x7FFFFFFF));
                 let abs_deltas = _mm_and_ps(deltas, abs_mask);
@@ -350,9 +457,11 @@ unsafe fn block_has_perceptual_diff_sse(
     use std::arch::x86_64::*;
 
     let row_width = (end_x - start_x) as usize;
+    let mask_ff_128 = _mm_set1_epi32(0xFF);
+    let zero_128 = _mm_setzero_ps();
 
     for y in start_y..end_y {
-        let row_start = (y * width + start_x) as usize;
+        let row_start = y as usize * width as usize + start_x as usize;
         let mut offset = 0;
 
         let a_ptr = a32.as_ptr().add(row_start);
@@ -365,7 +474,7 @@ unsafe fn block_has_perceptual_diff_sse(
             let mask = _mm_movemask_epi8(cmp);
 
             if mask != 0xFFFF {
-                let deltas = yiq_delta_4_sse_direct(va, vb);
+                let deltas = yiq_delta_4_sse_signed(va, vb, mask_ff_128, zero_128);
                 let max_vec = _mm_set1_ps(max_delta);
                 let abs_mask = _mm_castsi128_ps(_mm_set1_epi32(0x7FFFFFFF));
                 let abs_deltas = _mm_and_ps(deltas, abs_mask);
@@ -406,7 +515,7 @@ fn block_has_perceptual_diff_scalar(
 ) -> bool {
     for y in start_y..end_y {
         for x in start_x..end_x {
-            let idx = (y * width + x) as usize;
+            let idx = y as usize * width as usize + x as usize;
             let pa = a32[idx];
             let pb = b32[idx];
             if pa != pb && color_delta_f32(pa, pb).abs() > max_delta {
@@ -417,391 +526,279 @@ fn block_has_perceptual_diff_scalar(
     false
 }
 
-// =============================================================================
-// SIMD YIQ Delta - Pure SIMD RGB extraction (no scalar loops)
-// =============================================================================
-
-/// NEON: Extract RGB and compute YIQ delta for 4 pixels - pure SIMD with alpha handling
-#[cfg(target_arch = "aarch64")]
-#[inline]
-unsafe fn yiq_delta_4_neon_direct(
-    va: std::arch::aarch64::uint32x4_t,
-    vb: std::arch::aarch64::uint32x4_t,
-) -> std::arch::aarch64::float32x4_t {
-    use std::arch::aarch64::*;
-
-    let mask_ff = vdupq_n_u32(0xFF);
-    let v255 = vdupq_n_f32(255.0);
-    let inv255 = vdupq_n_f32(INV_255);
-
-    let r_a = vandq_u32(va, mask_ff);
-    let g_a = vandq_u32(vshrq_n_u32(va, 8), mask_ff);
-    let b_a = vandq_u32(vshrq_n_u32(va, 16), mask_ff);
-    let a_a = vshrq_n_u32(va, 24);
-
-    let r_b = vandq_u32(vb, mask_ff);
-    let g_b = vandq_u32(vshrq_n_u32(vb, 8), mask_ff);
-    let b_b = vandq_u32(vshrq_n_u32(vb, 16), mask_ff);
-    let a_b = vshrq_n_u32(vb, 24);
-
-    let r_a_f = vcvtq_f32_u32(r_a);
-    let g_a_f = vcvtq_f32_u32(g_a);
-    let b_a_f = vcvtq_f32_u32(b_a);
-    let a_a_f = vcvtq_f32_u32(a_a);
-
-    let r_b_f = vcvtq_f32_u32(r_b);
-    let g_b_f = vcvtq_f32_u32(g_b);
-    let b_b_f = vcvtq_f32_u32(b_b);
-    let a_b_f = vcvtq_f32_u32(a_b);
-
-    let alpha_norm_a = vmulq_f32(a_a_f, inv255);
-    let alpha_norm_b = vmulq_f32(a_b_f, inv255);
-
-    let br_a = vfmaq_f32(v255, vsubq_f32(r_a_f, v255), alpha_norm_a);
-    let bg_a = vfmaq_f32(v255, vsubq_f32(g_a_f, v255), alpha_norm_a);
-    let bb_a = vfmaq_f32(v255, vsubq_f32(b_a_f, v255), alpha_norm_a);
+/// `progressive_reject` downscales by this factor per dimension for its
+/// coarse pass, and only runs on images of at least this many pixels.
+const PROGRESSIVE_REJECT_FACTOR: u32 = 8;
+const PROGRESSIVE_REJECT_MIN_PIXELS: u32 = 1 << 16;
+/// Coarse diff percentage above which `progressive_reject` returns early.
+const PROGRESSIVE_REJECT_PERCENTAGE: f64 = 25.0;
+
+/// Images at least this large scan block rows on the rayon pool (with the
+/// `parallel` feature); below it the fork/join overhead outweighs the scan.
+const PARALLEL_COLD_PASS_MIN_PIXELS: u32 = 1 << 20;
+
+/// Cold pass over the whole grid, appending changed blocks in row-major
+/// order. Block rows are independent, so with `parallel` each row is
+/// scanned on the rayon pool and the per-row lists are concatenated in
+/// order, giving exactly the serial result.
+#[allow(clippy::too_many_arguments)]
+#[cfg_attr(not(feature = "parallel"), allow(unused_variables))]
+fn find_changed_blocks(
+    a32: &[u32],
+    b32: &[u32],
+    width: u32,
+    height: u32,
+    block_size: u32,
+    max_delta: f32,
+    #[cfg(target_arch = "x86_64")] features: X86Features,
+    parallel: bool,
+    changed_blocks: &mut Vec<(u32, u32, u32, u32)>,
+) {
+    let blocks_y = height.div_ceil(block_size);
+
+    #[cfg(feature = "parallel")]
+    if parallel && blocks_y > 1 {
+        use rayon::prelude::*;
+
+        let rows: Vec<Vec<(u32, u32, u32, u32)>> = (0..blocks_y)
+            .into_par_iter()
+            .map(|by| {
+                let mut row = Vec::new();
+                scan_block_row(
+                    a32,
+                    b32,
+                    width,
+                    height,
+                    block_size,
+                    by,
+                    max_delta,
+                    #[cfg(target_arch = "x86_64")]
+                    features,
+                    &mut row,
+                );
+                row
+            })
+            .collect();
+        for row in rows {
+            changed_blocks.extend(row);
+        }
+        return;
+    }
 
-    let br_b = vfmaq_f32(v255, vsubq_f32(r_b_f, v255), alpha_norm_b);
-    let bg_b = vfmaq_f32(v255, vsubq_f32(g_b_f, v255), alpha_norm_b);
-    let bb_b = vfmaq_f32(v255, vsubq_f32(b_b_f, v255), alpha_norm_b);
+    for by in 0..blocks_y {
+        scan_block_row(
+            a32,
+            b32,
+            width,
+            height,
+            block_size,
+            by,
+            max_delta,
+            #[cfg(target_arch = "x86_64")]
+            features,
+            changed_blocks,
+        );
+    }
+}
 
-    let dr = vsubq_f32(br_a, br_b);
-    let dg = vsubq_f32(bg_a, bg_b);
-    let db = vsubq_f32(bb_a, bb_b);
+/// Cold pass over block row `by`.
+#[allow(clippy::too_many_arguments)]
+#[inline]
+fn scan_block_row(
+    a32: &[u32],
+    b32: &[u32],
+    width: u32,
+    height: u32,
+    block_size: u32,
+    by: u32,
+    max_delta: f32,
+    #[cfg(target_arch = "x86_64")] features: X86Features,
+    changed_blocks: &mut Vec<(u32, u32, u32, u32)>,
+) {
+    let start_y = by * block_size;
+    let end_y = (start_y + block_size).min(height);
+    for bx in 0..width.div_ceil(block_size) {
+        let start_x = bx * block_size;
+        let end_x = (start_x + block_size).min(width);
+
+        if block_changed(
+            a32,
+            b32,
+            width,
+            start_x,
+            start_y,
+            end_x,
+            end_y,
+            max_delta,
+            #[cfg(target_arch = "x86_64")]
+            features,
+        ) {
+            changed_blocks.push((start_x, start_y, end_x, end_y));
+        }
+    }
+}
 
-    let vy = vfmaq_n_f32(
-        vfmaq_n_f32(vmulq_n_f32(dr, YIQ_Y_F32[0]), dg, YIQ_Y_F32[1]),
-        db,
-        YIQ_Y_F32[2],
-    );
-    let vi = vfmaq_n_f32(
-        vfmaq_n_f32(vmulq_n_f32(dr, YIQ_I_F32[0]), dg, YIQ_I_F32[1]),
-        db,
-        YIQ_I_F32[2],
-    );
-    let vq = vfmaq_n_f32(
-        vfmaq_n_f32(vmulq_n_f32(dr, YIQ_Q_F32[0]), dg, YIQ_Q_F32[1]),
-        db,
-        YIQ_Q_F32[2],
+/// Cold check of one block with the resolved kernels.
+#[allow(clippy::too_many_arguments)]
+#[inline]
+fn block_changed(
+    a32: &[u32],
+    b32: &[u32],
+    width: u32,
+    start_x: u32,
+    start_y: u32,
+    end_x: u32,
+    end_y: u32,
+    max_delta: f32,
+    #[cfg(target_arch = "x86_64")] features: X86Features,
+) -> bool {
+    #[cfg(target_arch = "x86_64")]
+    return block_has_perceptual_diff_with_features(
+        a32, b32, width, start_x, start_y, end_x, end_y, max_delta, features,
     );
+    #[cfg(not(target_arch = "x86_64"))]
+    block_has_perceptual_diff(a32, b32, width, start_x, start_y, end_x, end_y, max_delta)
+}
 
-    let vy2 = vmulq_f32(vy, vy);
-    let vi2 = vmulq_f32(vi, vi);
-    let vq2 = vmulq_f32(vq, vq);
+/// Serial cold pass over same-size images with the auto-detected backend.
+#[cfg(feature = "bench")]
+pub(crate) fn cold_pass(
+    image1: &Image,
+    image2: &Image,
+    threshold: f64,
+) -> Vec<(u32, u32, u32, u32)> {
+    let (block_size, _, _) = block_grid(image1.width, image1.height);
+    let mut changed_blocks = Vec::new();
+    find_changed_blocks(
+        image1.as_u32(),
+        image2.as_u32(),
+        image1.width,
+        image1.height,
+        block_size,
+        threshold_to_max_delta_f32(threshold),
+        #[cfg(target_arch = "x86_64")]
+        X86Features::detect(),
+        false,
+        &mut changed_blocks,
+    );
+    changed_blocks
+}
 
-    vfmaq_n_f32(
-        vfmaq_n_f32(
-            vmulq_n_f32(vy2, YIQ_WEIGHTS_F32[0]),
-            vi2,
-            YIQ_WEIGHTS_F32[1],
-        ),
-        vq2,
-        YIQ_WEIGHTS_F32[2],
-    )
+/// Scalar cold pass for a gate that changes the delta itself, which the SIMD
+/// block checks cannot see: a block changes when any pixel's gated delta
+/// exceeds `max_delta`.
+#[allow(clippy::too_many_arguments)]
+fn find_changed_blocks_gated(
+    a32: &[u32],
+    b32: &[u32],
+    width: u32,
+    height: u32,
+    block_size: u32,
+    max_delta: f32,
+    gate: PixelGate<'_>,
+    changed_blocks: &mut Vec<(u32, u32, u32, u32)>,
+) {
+    for start_y in (0..height).step_by(block_size as usize) {
+        let end_y = (start_y + block_size).min(height);
+        for start_x in (0..width).step_by(block_size as usize) {
+            let end_x = (start_x + block_size).min(width);
+            if block_changed_gated(
+                a32, b32, width, start_x, start_y, end_x, end_y, max_delta, gate,
+            ) {
+                changed_blocks.push((start_x, start_y, end_x, end_y));
+            }
+        }
+    }
 }
 
-/// wasm v128: Extract RGB and compute YIQ delta for 4 pixels - pure SIMD with alpha handling
-#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+/// Scalar cold check of one block: any pixel the gate does not exclude with
+/// a gated delta over `max_delta`.
+#[allow(clippy::too_many_arguments)]
 #[inline]
-#[target_feature(enable = "simd128")]
-unsafe fn yiq_delta_4_wasm_direct(
-    va: std::arch::wasm32::v128,
-    vb: std::arch::wasm32::v128,
-) -> std::arch::wasm32::v128 {
-    use std::arch::wasm32::*;
+fn block_changed_gated(
+    a32: &[u32],
+    b32: &[u32],
+    width: u32,
+    start_x: u32,
+    start_y: u32,
+    end_x: u32,
+    end_y: u32,
+    max_delta: f32,
+    gate: PixelGate<'_>,
+) -> bool {
+    (start_y..end_y).any(|y| {
+        let row = y as usize * width as usize;
+        (start_x..end_x).any(|x| {
+            let i = row + x as usize;
+            let (pa, pb) = (a32[i], b32[i]);
+            pa != pb
+                && !gate.excludes(x, y)
+                && gate.weigh(color_delta_f32(pa, pb), pa, pb).abs() > max_delta
+        })
+    })
+}
 
-    let mask_ff = u32x4_splat(0xFF);
-    let v255 = f32x4_splat(255.0);
-    let inv255 = f32x4_splat(INV_255);
+// =============================================================================
+// YIQ delta with sign - shared by the cold and hot passes
+// =============================================================================
 
-    let r_a = v128_and(va, mask_ff);
-    let g_a = v128_and(u32x4_shr(va, 8), mask_ff);
-    let b_a = v128_and(u32x4_shr(va, 16), mask_ff);
-    let a_a = u32x4_shr(va, 24);
+/// Scalar f32 YIQ delta (handles alpha)
+#[inline(always)]
+fn color_delta_f32(pixel_a: u32, pixel_b: u32) -> f32 {
+    if pixel_a == pixel_b {
+        return 0.0;
+    }
 
-    let r_b = v128_and(vb, mask_ff);
-    let g_b = v128_and(u32x4_shr(vb, 8), mask_ff);
-    let b_b = v128_and(u32x4_shr(vb, 16), mask_ff);
-    let a_b = u32x4_shr(vb, 24);
+    let r1 = (pixel_a & 0xFF) as f32;
+    let g1 = ((pixel_a >> 8) & 0xFF) as f32;
+    let b1 = ((pixel_a >> 16) & 0xFF) as f32;
+    let a1 = ((pixel_a >> 24) & 0xFF) as f32;
 
-    let r_a_f = f32x4_convert_u32x4(r_a);
-    let g_a_f = f32x4_convert_u32x4(g_a);
-    let b_a_f = f32x4_convert_u32x4(b_a);
-    let a_a_f = f32x4_convert_u32x4(a_a);
+    let r2 = (pixel_b & 0xFF) as f32;
+    let g2 = ((pixel_b >> 8) & 0xFF) as f32;
+    let b2 = ((pixel_b >> 16) & 0xFF) as f32;
+    let a2 = ((pixel_b >> 24) & 0xFF) as f32;
 
-    let r_b_f = f32x4_convert_u32x4(r_b);
-    let g_b_f = f32x4_convert_u32x4(g_b);
-    let b_b_f = f32x4_convert_u32x4(b_b);
-    let a_b_f = f32x4_convert_u32x4(a_b);
+    // Same operation order as the non-FMA SIMD kernels (normalize alpha
+    // first, then blend), so a pixel that lands in a scalar remainder gets
+    // the same delta it would get in a SIMD lane. `255.0 * INV_255` is
+    // exactly 1.0, so the opaque shortcut is exact too.
+    let (dr, dg, db) = if a1 >= 255.0 && a2 >= 255.0 {
+        (r1 - r2, g1 - g2, b1 - b2)
+    } else {
+        let alpha_norm1 = a1 * INV_255;
+        let alpha_norm2 = a2 * INV_255;
+        let br1 = 255.0 + (r1 - 255.0) * alpha_norm1;
+        let bg1 = 255.0 + (g1 - 255.0) * alpha_norm1;
+        let bb1 = 255.0 + (b1 - 255.0) * alpha_norm1;
+        let br2 = 255.0 + (r2 - 255.0) * alpha_norm2;
+        let bg2 = 255.0 + (g2 - 255.0) * alpha_norm2;
+        let bb2 = 255.0 + (b2 - 255.0) * alpha_norm2;
+        (br1 - br2, bg1 - bg2, bb1 - bb2)
+    };
 
-    let alpha_norm_a = f32x4_mul(a_a_f, inv255);
-    let alpha_norm_b = f32x4_mul(a_b_f, inv255);
+    let y = dr * YIQ_Y_F32[0] + dg * YIQ_Y_F32[1] + db * YIQ_Y_F32[2];
+    let i = dr * YIQ_I_F32[0] + dg * YIQ_I_F32[1] + db * YIQ_I_F32[2];
+    let q = dr * YIQ_Q_F32[0] + dg * YIQ_Q_F32[1] + db * YIQ_Q_F32[2];
 
-    let br_a = f32x4_add(v255, f32x4_mul(f32x4_sub(r_a_f, v255), alpha_norm_a));
-    let bg_a = f32x4_add(v255, f32x4_mul(f32x4_sub(g_a_f, v255), alpha_norm_a));
-    let bb_a = f32x4_add(v255, f32x4_mul(f32x4_sub(b_a_f, v255), alpha_norm_a));
+    let delta =
+        y * y * YIQ_WEIGHTS_F32[0] + i * i * YIQ_WEIGHTS_F32[1] + q * q * YIQ_WEIGHTS_F32[2];
 
-    let br_b = f32x4_add(v255, f32x4_mul(f32x4_sub(r_b_f, v255), alpha_norm_b));
-    let bg_b = f32x4_add(v255, f32x4_mul(f32x4_sub(g_b_f, v255), alpha_norm_b));
-    let bb_b = f32x4_add(v255, f32x4_mul(f32x4_sub(b_b_f, v255), alpha_norm_b));
+    if y > 0.0 {
+        -delta
+    } else {
+        delta
+    }
+}
 
-    let dr = f32x4_sub(br_a, br_b);
-    let dg = f32x4_sub(bg_a, bg_b);
-    let db = f32x4_sub(bb_a, bb_b);
-
-    let y_r = f32x4_splat(YIQ_Y_F32[0]);
-    let y_g = f32x4_splat(YIQ_Y_F32[1]);
-    let y_b = f32x4_splat(YIQ_Y_F32[2]);
-    let i_r = f32x4_splat(YIQ_I_F32[0]);
-    let i_g = f32x4_splat(YIQ_I_F32[1]);
-    let i_b = f32x4_splat(YIQ_I_F32[2]);
-    let q_r = f32x4_splat(YIQ_Q_F32[0]);
-    let q_g = f32x4_splat(YIQ_Q_F32[1]);
-    let q_b = f32x4_splat(YIQ_Q_F32[2]);
-    let w_y = f32x4_splat(YIQ_WEIGHTS_F32[0]);
-    let w_i = f32x4_splat(YIQ_WEIGHTS_F32[1]);
-    let w_q = f32x4_splat(YIQ_WEIGHTS_F32[2]);
-
-    // No native FMA in baseline simd128; use add(mul, c).
-    let vy = f32x4_add(
-        f32x4_add(f32x4_mul(dr, y_r), f32x4_mul(dg, y_g)),
-        f32x4_mul(db, y_b),
-    );
-    let vi = f32x4_add(
-        f32x4_add(f32x4_mul(dr, i_r), f32x4_mul(dg, i_g)),
-        f32x4_mul(db, i_b),
-    );
-    let vq = f32x4_add(
-        f32x4_add(f32x4_mul(dr, q_r), f32x4_mul(dg, q_g)),
-        f32x4_mul(db, q_b),
-    );
-
-    let vy2 = f32x4_mul(vy, vy);
-    let vi2 = f32x4_mul(vi, vi);
-    let vq2 = f32x4_mul(vq, vq);
-
-    f32x4_add(
-        f32x4_add(f32x4_mul(vy2, w_y), f32x4_mul(vi2, w_i)),
-        f32x4_mul(vq2, w_q),
-    )
-}
-
-/// SSE4.1: Extract RGB and compute YIQ delta for 4 pixels - pure SIMD with alpha handling
-#[cfg(target_arch = "x86_64")]
-#[target_feature(enable = "sse4.1")]
-#[inline]
-unsafe fn yiq_delta_4_sse_direct(
-    va: std::arch::x86_64::__m128i,
-    vb: std::arch::x86_64::__m128i,
-) -> std::arch::x86_64::__m128 {
-    use std::arch::x86_64::*;
-
-    let mask_ff = _mm_set1_epi32(0xFF);
-
-    let r_a = _mm_and_si128(va, mask_ff);
-    let g_a = _mm_and_si128(_mm_srli_epi32(va, 8), mask_ff);
-    let b_a = _mm_and_si128(_mm_srli_epi32(va, 16), mask_ff);
-    let a_a = _mm_srli_epi32(va, 24);
-
-    let r_b = _mm_and_si128(vb, mask_ff);
-    let g_b = _mm_and_si128(_mm_srli_epi32(vb, 8), mask_ff);
-    let b_b = _mm_and_si128(_mm_srli_epi32(vb, 16), mask_ff);
-    let a_b = _mm_srli_epi32(vb, 24);
-
-    let r_a_f = _mm_cvtepi32_ps(r_a);
-    let g_a_f = _mm_cvtepi32_ps(g_a);
-    let b_a_f = _mm_cvtepi32_ps(b_a);
-    let a_a_f = _mm_cvtepi32_ps(a_a);
-
-    let r_b_f = _mm_cvtepi32_ps(r_b);
-    let g_b_f = _mm_cvtepi32_ps(g_b);
-    let b_b_f = _mm_cvtepi32_ps(b_b);
-    let a_b_f = _mm_cvtepi32_ps(a_b);
-
-    let v255 = _mm_set1_ps(255.0);
-    let inv255 = _mm_set1_ps(INV_255);
-
-    let alpha_norm_a = _mm_mul_ps(a_a_f, inv255);
-    let alpha_norm_b = _mm_mul_ps(a_b_f, inv255);
-
-    let br_a = _mm_add_ps(v255, _mm_mul_ps(_mm_sub_ps(r_a_f, v255), alpha_norm_a));
-    let bg_a = _mm_add_ps(v255, _mm_mul_ps(_mm_sub_ps(g_a_f, v255), alpha_norm_a));
-    let bb_a = _mm_add_ps(v255, _mm_mul_ps(_mm_sub_ps(b_a_f, v255), alpha_norm_a));
-
-    let br_b = _mm_add_ps(v255, _mm_mul_ps(_mm_sub_ps(r_b_f, v255), alpha_norm_b));
-    let bg_b = _mm_add_ps(v255, _mm_mul_ps(_mm_sub_ps(g_b_f, v255), alpha_norm_b));
-    let bb_b = _mm_add_ps(v255, _mm_mul_ps(_mm_sub_ps(b_b_f, v255), alpha_norm_b));
-
-    let dr = _mm_sub_ps(br_a, br_b);
-    let dg = _mm_sub_ps(bg_a, bg_b);
-    let db = _mm_sub_ps(bb_a, bb_b);
-
-    let y_r = _mm_set1_ps(YIQ_Y_F32[0]);
-    let y_g = _mm_set1_ps(YIQ_Y_F32[1]);
-    let y_b = _mm_set1_ps(YIQ_Y_F32[2]);
-    let i_r = _mm_set1_ps(YIQ_I_F32[0]);
-    let i_g = _mm_set1_ps(YIQ_I_F32[1]);
-    let i_b = _mm_set1_ps(YIQ_I_F32[2]);
-    let q_r = _mm_set1_ps(YIQ_Q_F32[0]);
-    let q_g = _mm_set1_ps(YIQ_Q_F32[1]);
-    let q_b = _mm_set1_ps(YIQ_Q_F32[2]);
-    let w_y = _mm_set1_ps(YIQ_WEIGHTS_F32[0]);
-    let w_i = _mm_set1_ps(YIQ_WEIGHTS_F32[1]);
-    let w_q = _mm_set1_ps(YIQ_WEIGHTS_F32[2]);
-
-    let vy = _mm_add_ps(
-        _mm_add_ps(_mm_mul_ps(dr, y_r), _mm_mul_ps(dg, y_g)),
-        _mm_mul_ps(db, y_b),
-    );
-    let vi = _mm_add_ps(
-        _mm_add_ps(_mm_mul_ps(dr, i_r), _mm_mul_ps(dg, i_g)),
-        _mm_mul_ps(db, i_b),
-    );
-    let vq = _mm_add_ps(
-        _mm_add_ps(_mm_mul_ps(dr, q_r), _mm_mul_ps(dg, q_g)),
-        _mm_mul_ps(db, q_b),
-    );
-
-    let vy2 = _mm_mul_ps(vy, vy);
-    let vi2 = _mm_mul_ps(vi, vi);
-    let vq2 = _mm_mul_ps(vq, vq);
-
-    _mm_add_ps(
-        _mm_add_ps(_mm_mul_ps(vy2, w_y), _mm_mul_ps(vi2, w_i)),
-        _mm_mul_ps(vq2, w_q),
-    )
-}
-
-/// AVX2+FMA: Extract RGB and compute YIQ delta for 8 pixels - pure SIMD with alpha handling
-#[cfg(target_arch = "x86_64")]
-#[target_feature(enable = "avx2", enable = "fma")]
-#[inline]
-unsafe fn yiq_delta_8_avx2_direct(
-    va: std::arch::x86_64::__m256i,
-    vb: std::arch::x86_64::__m256i,
-) -> std::arch::x86_64::__m256 {
-    use std::arch::x86_64::*;
-
-    let mask_ff = _mm256_set1_epi32(0xFF);
-
-    let r_a = _mm256_and_si256(va, mask_ff);
-    let g_a = _mm256_and_si256(_mm256_srli_epi32(va, 8), mask_ff);
-    let b_a = _mm256_and_si256(_mm256_srli_epi32(va, 16), mask_ff);
-    let a_a = _mm256_srli_epi32(va, 24);
-
-    let r_b = _mm256_and_si256(vb, mask_ff);
-    let g_b = _mm256_and_si256(_mm256_srli_epi32(vb, 8), mask_ff);
-    let b_b = _mm256_and_si256(_mm256_srli_epi32(vb, 16), mask_ff);
-    let a_b = _mm256_srli_epi32(vb, 24);
-
-    let r_a_f = _mm256_cvtepi32_ps(r_a);
-    let g_a_f = _mm256_cvtepi32_ps(g_a);
-    let b_a_f = _mm256_cvtepi32_ps(b_a);
-    let a_a_f = _mm256_cvtepi32_ps(a_a);
-
-    let r_b_f = _mm256_cvtepi32_ps(r_b);
-    let g_b_f = _mm256_cvtepi32_ps(g_b);
-    let b_b_f = _mm256_cvtepi32_ps(b_b);
-    let a_b_f = _mm256_cvtepi32_ps(a_b);
-
-    let v255 = _mm256_set1_ps(255.0);
-    let inv255 = _mm256_set1_ps(INV_255);
-
-    let alpha_norm_a = _mm256_mul_ps(a_a_f, inv255);
-    let alpha_norm_b = _mm256_mul_ps(a_b_f, inv255);
-
-    let br_a = _mm256_fmadd_ps(_mm256_sub_ps(r_a_f, v255), alpha_norm_a, v255);
-    let bg_a = _mm256_fmadd_ps(_mm256_sub_ps(g_a_f, v255), alpha_norm_a, v255);
-    let bb_a = _mm256_fmadd_ps(_mm256_sub_ps(b_a_f, v255), alpha_norm_a, v255);
-
-    let br_b = _mm256_fmadd_ps(_mm256_sub_ps(r_b_f, v255), alpha_norm_b, v255);
-    let bg_b = _mm256_fmadd_ps(_mm256_sub_ps(g_b_f, v255), alpha_norm_b, v255);
-    let bb_b = _mm256_fmadd_ps(_mm256_sub_ps(b_b_f, v255), alpha_norm_b, v255);
-
-    let dr = _mm256_sub_ps(br_a, br_b);
-    let dg = _mm256_sub_ps(bg_a, bg_b);
-    let db = _mm256_sub_ps(bb_a, bb_b);
-
-    let y_r = _mm256_set1_ps(YIQ_Y_F32[0]);
-    let y_g = _mm256_set1_ps(YIQ_Y_F32[1]);
-    let y_b = _mm256_set1_ps(YIQ_Y_F32[2]);
-    let i_r = _mm256_set1_ps(YIQ_I_F32[0]);
-    let i_g = _mm256_set1_ps(YIQ_I_F32[1]);
-    let i_b = _mm256_set1_ps(YIQ_I_F32[2]);
-    let q_r = _mm256_set1_ps(YIQ_Q_F32[0]);
-    let q_g = _mm256_set1_ps(YIQ_Q_F32[1]);
-    let q_b = _mm256_set1_ps(YIQ_Q_F32[2]);
-    let w_y = _mm256_set1_ps(YIQ_WEIGHTS_F32[0]);
-    let w_i = _mm256_set1_ps(YIQ_WEIGHTS_F32[1]);
-    let w_q = _mm256_set1_ps(YIQ_WEIGHTS_F32[2]);
-
-    let vy = _mm256_fmadd_ps(dr, y_r, _mm256_fmadd_ps(dg, y_g, _mm256_mul_ps(db, y_b)));
-    let vi = _mm256_fmadd_ps(dr, i_r, _mm256_fmadd_ps(dg, i_g, _mm256_mul_ps(db, i_b)));
-    let vq = _mm256_fmadd_ps(dr, q_r, _mm256_fmadd_ps(dg, q_g, _mm256_mul_ps(db, q_b)));
-
-    let vy2 = _mm256_mul_ps(vy, vy);
-    let vi2 = _mm256_mul_ps(vi, vi);
-    let vq2 = _mm256_mul_ps(vq, vq);
-
-    _mm256_fmadd_ps(vy2, w_y, _mm256_fmadd_ps(vi2, w_i, _mm256_mul_ps(vq2, w_q)))
-}
-
-// =============================================================================
-// SIMD YIQ Delta with sign (for hot pass) - returns signed delta
-// =============================================================================
-
-/// Scalar f32 YIQ delta (handles alpha)
-#[inline(always)]
-fn color_delta_f32(pixel_a: u32, pixel_b: u32) -> f32 {
-    if pixel_a == pixel_b {
-        return 0.0;
-    }
-
-    let r1 = (pixel_a & 0xFF) as f32;
-    let g1 = ((pixel_a >> 8) & 0xFF) as f32;
-    let b1 = ((pixel_a >> 16) & 0xFF) as f32;
-    let a1 = ((pixel_a >> 24) & 0xFF) as f32;
-
-    let r2 = (pixel_b & 0xFF) as f32;
-    let g2 = ((pixel_b >> 8) & 0xFF) as f32;
-    let b2 = ((pixel_b >> 16) & 0xFF) as f32;
-    let a2 = ((pixel_b >> 24) & 0xFF) as f32;
-
-    let (dr, dg, db) = if a1 >= 255.0 && a2 >= 255.0 {
-        (r1 - r2, g1 - g2, b1 - b2)
-    } else {
-        let inv255 = 1.0 / 255.0;
-        let br1 = 255.0 + (r1 - 255.0) * a1 * inv255;
-        let bg1 = 255.0 + (g1 - 255.0) * a1 * inv255;
-        let bb1 = 255.0 + (b1 - 255.0) * a1 * inv255;
-        let br2 = 255.0 + (r2 - 255.0) * a2 * inv255;
-        let bg2 = 255.0 + (g2 - 255.0) * a2 * inv255;
-        let bb2 = 255.0 + (b2 - 255.0) * a2 * inv255;
-        (br1 - br2, bg1 - bg2, bb1 - bb2)
-    };
-
-    let y = dr * YIQ_Y_F32[0] + dg * YIQ_Y_F32[1] + db * YIQ_Y_F32[2];
-    let i = dr * YIQ_I_F32[0] + dg * YIQ_I_F32[1] + db * YIQ_I_F32[2];
-    let q = dr * YIQ_Q_F32[0] + dg * YIQ_Q_F32[1] + db * YIQ_Q_F32[2];
-
-    let delta =
-        YIQ_WEIGHTS_F32[0] * y * y + YIQ_WEIGHTS_F32[1] * i * i + YIQ_WEIGHTS_F32[2] * q * q;
-
-    if y > 0.0 {
-        -delta
-    } else {
-        delta
-    }
-}
-
-// =============================================================================
-// Hot pass SIMD processing
-// =============================================================================
+// =============================================================================
+// Hot pass SIMD processing
+// =============================================================================
 
 /// Process a block of changed pixels with SIMD (aarch64)
 #[cfg(target_arch = "aarch64")]
 #[inline]
-fn process_hot_block(
+fn process_hot_block<P: Fn(f32, u32, u32) -> u32>(
     a32: &[u32],
     b32: &[u32],
     out32: Option<&mut [u32]>,
@@ -813,13 +810,35 @@ fn process_hot_block(
     max_delta: f32,
     include_aa: bool,
     draw_background: bool,
-    diff_color: u32,
-    diff_color_alt: u32,
+    painter: &P,
     aa_color: u32,
     alpha_f32: f32,
-    image1: &Image,
-    image2: &Image,
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
+    gate: Option<PixelGate<'_>>,
 ) -> u32 {
+    if let Some(gate) = gate {
+        return process_hot_block_scalar(
+            a32,
+            b32,
+            out32,
+            width,
+            start_x,
+            start_y,
+            end_x,
+            end_y,
+            max_delta,
+            include_aa,
+            draw_background,
+            painter,
+            aa_color,
+            alpha_f32,
+            image1,
+            image2,
+            gate,
+            None,
+        );
+    }
     process_hot_block_neon(
         a32,
         b32,
@@ -832,8 +851,7 @@ fn process_hot_block(
         max_delta,
         include_aa,
         draw_background,
-        diff_color,
-        diff_color_alt,
+        painter,
         aa_color,
         alpha_f32,
         image1,
@@ -844,7 +862,7 @@ fn process_hot_block(
 /// Process a block of changed pixels with SIMD (x86_64 with cached features)
 #[cfg(target_arch = "x86_64")]
 #[inline]
-fn process_hot_block_with_features(
+fn process_hot_block_with_features<P: Fn(f32, u32, u32) -> u32>(
     a32: &[u32],
     b32: &[u32],
     out32: Option<&mut [u32]>,
@@ -856,19 +874,41 @@ fn process_hot_block_with_features(
     max_delta: f32,
     include_aa: bool,
     draw_background: bool,
-    diff_color: u32,
-    diff_color_alt: u32,
+    painter: &P,
     aa_color: u32,
     alpha_f32: f32,
-    image1: &Image,
-    image2: &Image,
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
+    gate: Option<PixelGate<'_>>,
     features: X86Features,
 ) -> u32 {
-    if features.has_avx2_fma {
-        unsafe {
-            process_hot_block_avx2(
-                a32,
-                b32,
+    if let Some(gate) = gate {
+        return process_hot_block_scalar(
+            a32,
+            b32,
+            out32,
+            width,
+            start_x,
+            start_y,
+            end_x,
+            end_y,
+            max_delta,
+            include_aa,
+            draw_background,
+            painter,
+            aa_color,
+            alpha_f32,
+            image1,
+            image2,
+            gate,
+            None,
+        );
+    }
+    if features.has_avx2_fma {
+        unsafe {
+            process_hot_block_avx2(
+                a32,
+                b32,
                 out32,
                 width,
                 start_x,
@@ -878,8 +918,7 @@ fn process_hot_block_with_features(
                 max_delta,
                 include_aa,
                 draw_background,
-                diff_color,
-                diff_color_alt,
+                painter,
                 aa_color,
                 alpha_f32,
                 image1,
@@ -900,8 +939,7 @@ fn process_hot_block_with_features(
                 max_delta,
                 include_aa,
                 draw_background,
-                diff_color,
-                diff_color_alt,
+                painter,
                 aa_color,
                 alpha_f32,
                 image1,
@@ -921,12 +959,13 @@ fn process_hot_block_with_features(
             max_delta,
             include_aa,
             draw_background,
-            diff_color,
-            diff_color_alt,
+            painter,
             aa_color,
             alpha_f32,
             image1,
             image2,
+            PixelGate::default(),
+            None,
         )
     }
 }
@@ -934,7 +973,7 @@ fn process_hot_block_with_features(
 /// Process a block of changed pixels with SIMD (wasm32 v128)
 #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
 #[inline]
-fn process_hot_block(
+fn process_hot_block<P: Fn(f32, u32, u32) -> u32>(
     a32: &[u32],
     b32: &[u32],
     out32: Option<&mut [u32]>,
@@ -946,13 +985,35 @@ fn process_hot_block(
     max_delta: f32,
     include_aa: bool,
     draw_background: bool,
-    diff_color: u32,
-    diff_color_alt: u32,
+    painter: &P,
     aa_color: u32,
     alpha_f32: f32,
-    image1: &Image,
-    image2: &Image,
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
+    gate: Option<PixelGate<'_>>,
 ) -> u32 {
+    if let Some(gate) = gate {
+        return process_hot_block_scalar(
+            a32,
+            b32,
+            out32,
+            width,
+            start_x,
+            start_y,
+            end_x,
+            end_y,
+            max_delta,
+            include_aa,
+            draw_background,
+            painter,
+            aa_color,
+            alpha_f32,
+            image1,
+            image2,
+            gate,
+            None,
+        );
+    }
     process_hot_block_wasm(
         a32,
         b32,
@@ -965,8 +1026,7 @@ fn process_hot_block(
         max_delta,
         include_aa,
         draw_background,
-        diff_color,
-        diff_color_alt,
+        painter,
         aa_color,
         alpha_f32,
         image1,
@@ -981,7 +1041,7 @@ fn process_hot_block(
     all(target_arch = "wasm32", target_feature = "simd128"),
 )))]
 #[inline]
-fn process_hot_block(
+fn process_hot_block<P: Fn(f32, u32, u32) -> u32>(
     a32: &[u32],
     b32: &[u32],
     out32: Option<&mut [u32]>,
@@ -993,12 +1053,12 @@ fn process_hot_block(
     max_delta: f32,
     include_aa: bool,
     draw_background: bool,
-    diff_color: u32,
-    diff_color_alt: u32,
+    painter: &P,
     aa_color: u32,
     alpha_f32: f32,
-    image1: &Image,
-    image2: &Image,
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
+    gate: Option<PixelGate<'_>>,
 ) -> u32 {
     process_hot_block_scalar(
         a32,
@@ -1012,19 +1072,20 @@ fn process_hot_block(
         max_delta,
         include_aa,
         draw_background,
-        diff_color,
-        diff_color_alt,
+        painter,
         aa_color,
         alpha_f32,
         image1,
         image2,
+        gate.unwrap_or_default(),
+        None,
     )
 }
 
 /// NEON hot block processing
 #[cfg(target_arch = "aarch64")]
 #[inline]
-fn process_hot_block_neon(
+fn process_hot_block_neon<P: Fn(f32, u32, u32) -> u32>(
     a32: &[u32],
     b32: &[u32],
     mut out32: Option<&mut [u32]>,
@@ -1036,12 +1097,11 @@ fn process_hot_block_neon(
     max_delta: f32,
     include_aa: bool,
     draw_background: bool,
-    diff_color: u32,
-    diff_color_alt: u32,
+    painter: &P,
     aa_color: u32,
     alpha_f32: f32,
-    image1: &Image,
-    image2: &Image,
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
 ) -> u32 {
     use std::arch::aarch64::*;
 
@@ -1050,7 +1110,7 @@ fn process_hot_block_neon(
     let alpha_scaled = alpha_f32 * inv_255;
 
     for y in start_y..end_y {
-        let row_offset = (y * width) as usize;
+        let row_offset = y as usize * width as usize;
         let base_offset = row_offset + start_x as usize;
         let row_width = (end_x - start_x) as usize;
         let mut offset = 0usize;
@@ -1117,8 +1177,7 @@ fn process_hot_block_neon(
                                     delta_arr[i],
                                     include_aa,
                                     draw_background,
-                                    diff_color,
-                                    diff_color_alt,
+                                    painter,
                                     aa_color,
                                     start_x + offset as u32 + i as u32,
                                     y,
@@ -1165,8 +1224,7 @@ fn process_hot_block_neon(
                         delta,
                         include_aa,
                         draw_background,
-                        diff_color,
-                        diff_color_alt,
+                        painter,
                         aa_color,
                         start_x + offset as u32,
                         y,
@@ -1191,7 +1249,7 @@ fn process_hot_block_neon(
 /// wasm v128 hot block processing
 #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
 #[inline]
-fn process_hot_block_wasm(
+fn process_hot_block_wasm<P: Fn(f32, u32, u32) -> u32>(
     a32: &[u32],
     b32: &[u32],
     mut out32: Option<&mut [u32]>,
@@ -1203,12 +1261,11 @@ fn process_hot_block_wasm(
     max_delta: f32,
     include_aa: bool,
     draw_background: bool,
-    diff_color: u32,
-    diff_color_alt: u32,
+    painter: &P,
     aa_color: u32,
     alpha_f32: f32,
-    image1: &Image,
-    image2: &Image,
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
 ) -> u32 {
     use std::arch::wasm32::*;
 
@@ -1217,7 +1274,7 @@ fn process_hot_block_wasm(
     let alpha_scaled = alpha_f32 * inv_255;
 
     for y in start_y..end_y {
-        let row_offset = (y * width) as usize;
+        let row_offset = y as usize * width as usize;
         let base_offset = row_offset + start_x as usize;
         let row_width = (end_x - start_x) as usize;
         let mut offset = 0usize;
@@ -1287,8 +1344,7 @@ fn process_hot_block_wasm(
                                     delta_arr[i],
                                     include_aa,
                                     draw_background,
-                                    diff_color,
-                                    diff_color_alt,
+                                    painter,
                                     aa_color,
                                     start_x + offset as u32 + i as u32,
                                     y,
@@ -1335,8 +1391,7 @@ fn process_hot_block_wasm(
                         delta,
                         include_aa,
                         draw_background,
-                        diff_color,
-                        diff_color_alt,
+                        painter,
                         aa_color,
                         start_x + offset as u32,
                         y,
@@ -1361,7 +1416,7 @@ fn process_hot_block_wasm(
 /// NEON: Compute gray values for 4 pixels
 #[cfg(target_arch = "aarch64")]
 #[inline]
-unsafe fn compute_gray_4_neon(
+pub(crate) unsafe fn compute_gray_4_neon(
     pixels: std::arch::aarch64::uint32x4_t,
     alpha_vec: std::arch::aarch64::float32x4_t,
     mask_ff: std::arch::aarch64::uint32x4_t,
@@ -1397,7 +1452,7 @@ unsafe fn compute_gray_4_neon(
 #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
 #[inline]
 #[target_feature(enable = "simd128")]
-unsafe fn compute_gray_4_wasm(
+pub(crate) unsafe fn compute_gray_4_wasm(
     pixels: std::arch::wasm32::v128,
     alpha_vec: std::arch::wasm32::v128,
     mask_ff: std::arch::wasm32::v128,
@@ -1611,7 +1666,7 @@ unsafe fn yiq_delta_4_wasm_signed(
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2", enable = "fma")]
 #[inline]
-unsafe fn process_hot_block_avx2(
+unsafe fn process_hot_block_avx2<P: Fn(f32, u32, u32) -> u32>(
     a32: &[u32],
     b32: &[u32],
     mut out32: Option<&mut [u32]>,
@@ -1623,12 +1678,11 @@ unsafe fn process_hot_block_avx2(
     max_delta: f32,
     include_aa: bool,
     draw_background: bool,
-    diff_color: u32,
-    diff_color_alt: u32,
+    painter: &P,
     aa_color: u32,
     alpha_f32: f32,
-    image1: &Image,
-    image2: &Image,
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
 ) -> u32 {
     use std::arch::x86_64::*;
 
@@ -1642,7 +1696,7 @@ unsafe fn process_hot_block_avx2(
     let zero = _mm256_setzero_ps();
 
     for y in start_y..end_y {
-        let row_offset = (y * width) as usize;
+        let row_offset = y as usize * width as usize;
         let base_offset = row_offset + start_x as usize;
         let row_width = (end_x - start_x) as usize;
         let mut offset = 0usize;
@@ -1701,8 +1755,7 @@ unsafe fn process_hot_block_avx2(
                             delta_arr[i],
                             include_aa,
                             draw_background,
-                            diff_color,
-                            diff_color_alt,
+                            painter,
                             aa_color,
                             start_x + offset as u32 + i as u32,
                             y,
@@ -1733,8 +1786,7 @@ unsafe fn process_hot_block_avx2(
                 max_delta,
                 include_aa,
                 draw_background,
-                diff_color,
-                diff_color_alt,
+                painter,
                 aa_color,
                 alpha_scaled,
                 start_x,
@@ -1766,8 +1818,7 @@ unsafe fn process_hot_block_avx2(
                         delta,
                         include_aa,
                         draw_background,
-                        diff_color,
-                        diff_color_alt,
+                        painter,
                         aa_color,
                         start_x + offset as u32,
                         y,
@@ -1793,7 +1844,7 @@ unsafe fn process_hot_block_avx2(
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2", enable = "fma")]
 #[inline]
-unsafe fn compute_gray_8_avx2(
+pub(crate) unsafe fn compute_gray_8_avx2(
     pixels: std::arch::x86_64::__m256i,
     alpha_vec: std::arch::x86_64::__m256,
     mask_ff: std::arch::x86_64::__m256i,
@@ -1818,7 +1869,9 @@ unsafe fn compute_gray_8_avx2(
         v255,
     );
     let gray_clamped = _mm256_min_ps(_mm256_max_ps(gray_f, zero), v255);
-    let gray_u32 = _mm256_cvtps_epi32(gray_clamped);
+    // Truncate like the scalar, NEON and wasm kernels, so a pixel's gray
+    // does not depend on which kernel drew it.
+    let gray_u32 = _mm256_cvttps_epi32(gray_clamped);
 
     _mm256_or_si256(
         _mm256_or_si256(
@@ -1912,7 +1965,7 @@ unsafe fn yiq_delta_8_avx2_signed(
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "sse4.1")]
 #[inline]
-unsafe fn process_hot_block_sse(
+unsafe fn process_hot_block_sse<P: Fn(f32, u32, u32) -> u32>(
     a32: &[u32],
     b32: &[u32],
     mut out32: Option<&mut [u32]>,
@@ -1924,18 +1977,17 @@ unsafe fn process_hot_block_sse(
     max_delta: f32,
     include_aa: bool,
     draw_background: bool,
-    diff_color: u32,
-    diff_color_alt: u32,
+    painter: &P,
     aa_color: u32,
     alpha_f32: f32,
-    image1: &Image,
-    image2: &Image,
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
 ) -> u32 {
     let mut diff_count = 0u32;
     let alpha_scaled = alpha_f32 / 255.0;
 
     for y in start_y..end_y {
-        let row_offset = (y * width) as usize;
+        let row_offset = y as usize * width as usize;
         let base_offset = row_offset + start_x as usize;
         let row_width = (end_x - start_x) as usize;
         let mut offset = 0usize;
@@ -1951,8 +2003,7 @@ unsafe fn process_hot_block_sse(
                 max_delta,
                 include_aa,
                 draw_background,
-                diff_color,
-                diff_color_alt,
+                painter,
                 aa_color,
                 alpha_scaled,
                 start_x,
@@ -1984,8 +2035,7 @@ unsafe fn process_hot_block_sse(
                         delta,
                         include_aa,
                         draw_background,
-                        diff_color,
-                        diff_color_alt,
+                        painter,
                         aa_color,
                         start_x + offset as u32,
                         y,
@@ -2011,7 +2061,7 @@ unsafe fn process_hot_block_sse(
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "sse4.1")]
 #[inline]
-unsafe fn process_hot_chunk_sse(
+unsafe fn process_hot_chunk_sse<P: Fn(f32, u32, u32) -> u32>(
     a32: &[u32],
     b32: &[u32],
     mut out32: Option<&mut [u32]>,
@@ -2021,14 +2071,13 @@ unsafe fn process_hot_chunk_sse(
     max_delta: f32,
     include_aa: bool,
     draw_background: bool,
-    diff_color: u32,
-    diff_color_alt: u32,
+    painter: &P,
     aa_color: u32,
     alpha_scaled: f32,
     start_x: u32,
     y: u32,
-    image1: &Image,
-    image2: &Image,
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
 ) -> u32 {
     use std::arch::x86_64::*;
 
@@ -2091,8 +2140,7 @@ unsafe fn process_hot_chunk_sse(
                     delta_arr[i],
                     include_aa,
                     draw_background,
-                    diff_color,
-                    diff_color_alt,
+                    painter,
                     aa_color,
                     start_x + offset as u32 + i as u32,
                     y,
@@ -2116,7 +2164,7 @@ unsafe fn process_hot_chunk_sse(
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "sse4.1")]
 #[inline]
-unsafe fn compute_gray_4_sse(
+pub(crate) unsafe fn compute_gray_4_sse(
     pixels: std::arch::x86_64::__m128i,
     alpha_vec: std::arch::x86_64::__m128,
     mask_ff: std::arch::x86_64::__m128i,
@@ -2143,7 +2191,8 @@ unsafe fn compute_gray_4_sse(
         _mm_mul_ps(_mm_sub_ps(luminance, v255), _mm_mul_ps(alpha_vec, a)),
     );
     let gray_clamped = _mm_min_ps(_mm_max_ps(gray_f, zero), v255);
-    let gray_u32 = _mm_cvtps_epi32(gray_clamped);
+    // Truncate, as in `compute_gray_8_avx2`.
+    let gray_u32 = _mm_cvttps_epi32(gray_clamped);
 
     _mm_or_si128(
         _mm_or_si128(
@@ -2245,12 +2294,9 @@ unsafe fn yiq_delta_4_sse_signed(
     _mm_blendv_ps(delta, neg_delta, y_positive)
 }
 
-/// Scalar hot block processing (fallback for non-SIMD architectures or x86_64 without SSE4.1)
-#[cfg(not(any(
-    target_arch = "aarch64",
-    all(target_arch = "wasm32", target_feature = "simd128"),
-)))]
-fn process_hot_block_scalar(
+/// Scalar hot block processing (fallback for non-SIMD architectures or x86_64
+/// without SSE4.1, and on every target when a [`PixelGate`] is active).
+fn process_hot_block_scalar<P: Fn(f32, u32, u32) -> u32>(
     a32: &[u32],
     b32: &[u32],
     mut out32: Option<&mut [u32]>,
@@ -2262,19 +2308,20 @@ fn process_hot_block_scalar(
     max_delta: f32,
     include_aa: bool,
     draw_background: bool,
-    diff_color: u32,
-    diff_color_alt: u32,
+    painter: &P,
     aa_color: u32,
     alpha_f32: f32,
-    image1: &Image,
-    image2: &Image,
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
+    gate: PixelGate<'_>,
+    mut spatial: Option<&mut SpatialSums>,
 ) -> u32 {
     let mut diff_count = 0u32;
     let alpha_scaled = alpha_f32 / 255.0;
 
     for y in start_y..end_y {
         for x in start_x..end_x {
-            let pixel_index = (y * width + x) as usize;
+            let pixel_index = y as usize * width as usize + x as usize;
             let pa = a32[pixel_index];
             let pb = b32[pixel_index];
 
@@ -2286,15 +2333,14 @@ fn process_hot_block_scalar(
                     }
                 }
             } else {
-                let delta = color_delta_f32(pa, pb);
-                if delta.abs() > max_delta {
-                    diff_count += process_diff_pixel(
+                let delta = gate.weigh(color_delta_f32(pa, pb), pa, pb);
+                if delta.abs() > max_delta && gate.passes(delta, pa, pb, x, y, image1, image2) {
+                    let counted = process_diff_pixel(
                         pixel_index,
                         delta,
                         include_aa,
                         draw_background,
-                        diff_color,
-                        diff_color_alt,
+                        painter,
                         aa_color,
                         x,
                         y,
@@ -2302,6 +2348,12 @@ fn process_hot_block_scalar(
                         image2,
                         out32.as_deref_mut(),
                     );
+                    if counted > 0 {
+                        if let Some(ref mut sums) = spatial {
+                            sums.add(x, y);
+                        }
+                    }
+                    diff_count += counted;
                 } else if draw_background {
                     if let Some(ref mut out) = out32 {
                         let g = compute_gray_pixel_f32(pa, alpha_scaled);
@@ -2315,34 +2367,362 @@ fn process_hot_block_scalar(
     diff_count
 }
 
+// =============================================================================
+// Identical fast path gray fill
+// =============================================================================
+
+/// The output of a diff with no changed block: the posterized gray
+/// background, unless `diff_mask` (cleared up front) or
+/// `skip_output_when_identical` leaves it to the caller.
+fn draw_identical_output(
+    src: &[u32],
+    out: &mut [u32],
+    options: &DiffOptions,
+    #[cfg(target_arch = "x86_64")] features: X86Features,
+) {
+    if options.diff_mask || options.skip_output_when_identical {
+        return;
+    }
+    fill_gray_identical(
+        src,
+        out,
+        options.alpha as f32,
+        #[cfg(target_arch = "x86_64")]
+        features,
+    );
+    if let OutputMode::Posterized { levels } = options.output_mode {
+        posterize_bytes(bytemuck::cast_slice_mut(out), levels);
+    }
+}
+
+/// Fill `out` with the dimmed gray of `src`, treating the image as one
+/// contiguous run of pixels. When the inputs are known identical up front
+/// there are no changed blocks to skip, so the whole buffer goes through the
+/// same SIMD gray kernels the hot pass uses, with no per-block or per-row
+/// bookkeeping.
+#[inline]
+fn fill_gray_identical(
+    src: &[u32],
+    out: &mut [u32],
+    alpha_f32: f32,
+    #[cfg(target_arch = "x86_64")] features: X86Features,
+) {
+    let alpha_scaled = alpha_f32 / 255.0;
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if features.has_avx2_fma {
+            unsafe { fill_gray_run_avx2(src, out, alpha_scaled) }
+        } else if features.has_sse41 {
+            unsafe { fill_gray_run_sse(src, out, alpha_scaled) }
+        } else {
+            fill_gray_run_scalar(src, out, alpha_scaled)
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        fill_gray_run_neon(src, out, alpha_scaled)
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    {
+        fill_gray_run_wasm(src, out, alpha_scaled)
+    }
+
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        all(target_arch = "wasm32", target_feature = "simd128"),
+    )))]
+    {
+        fill_gray_run_scalar(src, out, alpha_scaled)
+    }
+}
+
+/// AVX2: gray-fill a contiguous pixel run, 8 pixels at a time
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2", enable = "fma")]
+#[inline]
+unsafe fn fill_gray_run_avx2(src: &[u32], out: &mut [u32], alpha_scaled: f32) {
+    use std::arch::x86_64::*;
+
+    let len = src.len().min(out.len());
+    let mask_ff = _mm256_set1_epi32(0xFF);
+    let v255 = _mm256_set1_ps(255.0);
+    let zero = _mm256_setzero_ps();
+    let alpha_vec = _mm256_set1_ps(alpha_scaled);
+
+    let mut offset = 0usize;
+    while offset + 8 <= len {
+        let pixels = _mm256_loadu_si256(src.as_ptr().add(offset) as *const __m256i);
+        let grays = compute_gray_8_avx2(pixels, alpha_vec, mask_ff, v255, zero);
+        _mm256_storeu_si256(out.as_mut_ptr().add(offset) as *mut __m256i, grays);
+        offset += 8;
+    }
+
+    fill_gray_run_scalar(&src[offset..len], &mut out[offset..len], alpha_scaled);
+}
+
+/// SSE4.1: gray-fill a contiguous pixel run, 4 pixels at a time
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+#[inline]
+unsafe fn fill_gray_run_sse(src: &[u32], out: &mut [u32], alpha_scaled: f32) {
+    use std::arch::x86_64::*;
+
+    let len = src.len().min(out.len());
+    let mask_ff = _mm_set1_epi32(0xFF);
+    let v255 = _mm_set1_ps(255.0);
+    let zero = _mm_setzero_ps();
+    let alpha_vec = _mm_set1_ps(alpha_scaled);
+
+    let mut offset = 0usize;
+    while offset + 4 <= len {
+        let pixels = _mm_loadu_si128(src.as_ptr().add(offset) as *const __m128i);
+        let grays = compute_gray_4_sse(pixels, alpha_vec, mask_ff, v255, zero);
+        _mm_storeu_si128(out.as_mut_ptr().add(offset) as *mut __m128i, grays);
+        offset += 4;
+    }
+
+    fill_gray_run_scalar(&src[offset..len], &mut out[offset..len], alpha_scaled);
+}
+
+/// NEON: gray-fill a contiguous pixel run, 4 pixels at a time
+#[cfg(target_arch = "aarch64")]
+#[inline]
+fn fill_gray_run_neon(src: &[u32], out: &mut [u32], alpha_scaled: f32) {
+    use std::arch::aarch64::*;
+
+    let len = src.len().min(out.len());
+    let mut offset = 0usize;
+
+    unsafe {
+        let mask_ff = vdupq_n_u32(0xFF);
+        let v255 = vdupq_n_f32(255.0);
+        let alpha_vec = vdupq_n_f32(alpha_scaled);
+
+        while offset + 4 <= len {
+            let pixels = vld1q_u32(src.as_ptr().add(offset));
+            let grays = compute_gray_4_neon(pixels, alpha_vec, mask_ff, v255);
+            vst1q_u32(out.as_mut_ptr().add(offset), grays);
+            offset += 4;
+        }
+    }
+
+    fill_gray_run_scalar(&src[offset..len], &mut out[offset..len], alpha_scaled);
+}
+
+/// wasm v128: gray-fill a contiguous pixel run, 4 pixels at a time
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+#[inline]
+fn fill_gray_run_wasm(src: &[u32], out: &mut [u32], alpha_scaled: f32) {
+    use std::arch::wasm32::*;
+
+    let len = src.len().min(out.len());
+    let mut offset = 0usize;
+
+    unsafe {
+        let mask_ff = u32x4_splat(0xFF);
+        let v255 = f32x4_splat(255.0);
+        let alpha_vec = f32x4_splat(alpha_scaled);
+
+        while offset + 4 <= len {
+            let pixels = v128_load(src.as_ptr().add(offset) as *const v128);
+            let grays = compute_gray_4_wasm(pixels, alpha_vec, mask_ff, v255);
+            v128_store(out.as_mut_ptr().add(offset) as *mut v128, grays);
+            offset += 4;
+        }
+    }
+
+    fill_gray_run_scalar(&src[offset..len], &mut out[offset..len], alpha_scaled);
+}
+
+/// Scalar gray-fill of a contiguous pixel run (SIMD tails and fallback)
+#[inline]
+fn fill_gray_run_scalar(src: &[u32], out: &mut [u32], alpha_scaled: f32) {
+    for (dst, &pixel) in out.iter_mut().zip(src) {
+        *dst = pack_gray_pixel(compute_gray_pixel_f32(pixel, alpha_scaled));
+    }
+}
+
+/// Whether `a` and `b` hold the same pixels, compared a whole vector at a
+/// time with an early exit at the first differing chunk.
+fn are_identical_simd(
+    a: &[u32],
+    b: &[u32],
+    #[cfg(target_arch = "x86_64")] features: X86Features,
+) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if features.has_avx2_fma {
+            unsafe { are_identical_avx2(a, b) }
+        } else if features.has_sse41 {
+            unsafe { are_identical_sse(a, b) }
+        } else {
+            a == b
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        are_identical_neon(a, b)
+    }
+
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    {
+        are_identical_wasm(a, b)
+    }
+
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        target_arch = "aarch64",
+        all(target_arch = "wasm32", target_feature = "simd128"),
+    )))]
+    {
+        a == b
+    }
+}
+
+/// AVX2: compare 32 pixels per step, OR-ing four XORed vectors
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+#[inline]
+unsafe fn are_identical_avx2(a: &[u32], b: &[u32]) -> bool {
+    use std::arch::x86_64::*;
+
+    let len = a.len();
+    let (pa, pb) = (a.as_ptr(), b.as_ptr());
+    let mut offset = 0usize;
+    while offset + 32 <= len {
+        let mut acc = _mm256_setzero_si256();
+        for lane in (0..32).step_by(8) {
+            let va = _mm256_loadu_si256(pa.add(offset + lane) as *const __m256i);
+            let vb = _mm256_loadu_si256(pb.add(offset + lane) as *const __m256i);
+            acc = _mm256_or_si256(acc, _mm256_xor_si256(va, vb));
+        }
+        if _mm256_testz_si256(acc, acc) == 0 {
+            return false;
+        }
+        offset += 32;
+    }
+
+    a[offset..] == b[offset..]
+}
+
+/// SSE4.1: compare 16 pixels per step, OR-ing four XORed vectors
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+#[inline]
+unsafe fn are_identical_sse(a: &[u32], b: &[u32]) -> bool {
+    use std::arch::x86_64::*;
+
+    let len = a.len();
+    let (pa, pb) = (a.as_ptr(), b.as_ptr());
+    let mut offset = 0usize;
+    while offset + 16 <= len {
+        let mut acc = _mm_setzero_si128();
+        for lane in (0..16).step_by(4) {
+            let va = _mm_loadu_si128(pa.add(offset + lane) as *const __m128i);
+            let vb = _mm_loadu_si128(pb.add(offset + lane) as *const __m128i);
+            acc = _mm_or_si128(acc, _mm_xor_si128(va, vb));
+        }
+        if _mm_testz_si128(acc, acc) == 0 {
+            return false;
+        }
+        offset += 16;
+    }
+
+    a[offset..] == b[offset..]
+}
+
+/// NEON: compare 16 pixels per step, OR-ing four XORed vectors
+#[cfg(target_arch = "aarch64")]
+#[inline]
+fn are_identical_neon(a: &[u32], b: &[u32]) -> bool {
+    use std::arch::aarch64::*;
+
+    let len = a.len();
+    let (pa, pb) = (a.as_ptr(), b.as_ptr());
+    let mut offset = 0usize;
+
+    unsafe {
+        while offset + 16 <= len {
+            let mut acc = vdupq_n_u32(0);
+            for lane in (0..16).step_by(4) {
+                let va = vld1q_u32(pa.add(offset + lane));
+                let vb = vld1q_u32(pb.add(offset + lane));
+                acc = vorrq_u32(acc, veorq_u32(va, vb));
+            }
+            if vmaxvq_u32(acc) != 0 {
+                return false;
+            }
+            offset += 16;
+        }
+    }
+
+    a[offset..] == b[offset..]
+}
+
+/// wasm v128: compare 16 pixels per step, OR-ing four XORed vectors
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+#[inline]
+fn are_identical_wasm(a: &[u32], b: &[u32]) -> bool {
+    use std::arch::wasm32::*;
+
+    let len = a.len();
+    let (pa, pb) = (a.as_ptr(), b.as_ptr());
+    let mut offset = 0usize;
+
+    unsafe {
+        while offset + 16 <= len {
+            let mut acc = u32x4_splat(0);
+            for lane in (0..16).step_by(4) {
+                let va = v128_load(pa.add(offset + lane) as *const v128);
+                let vb = v128_load(pb.add(offset + lane) as *const v128);
+                acc = v128_or(acc, v128_xor(va, vb));
+            }
+            if v128_any_true(acc) {
+                return false;
+            }
+            offset += 16;
+        }
+    }
+
+    a[offset..] == b[offset..]
+}
+
 /// Process a single differing pixel (AA check + output)
 #[inline(always)]
-fn process_diff_pixel(
+fn process_diff_pixel<P: Fn(f32, u32, u32) -> u32>(
     pixel_index: usize,
     delta: f32,
     include_aa: bool,
     _draw_background: bool,
-    diff_color: u32,
-    diff_color_alt: u32,
+    painter: &P,
     aa_color: u32,
     x: u32,
     y: u32,
-    image1: &Image,
-    image2: &Image,
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
     out32: Option<&mut [u32]>,
 ) -> u32 {
     if include_aa {
         if let Some(out) = out32 {
-            let color = if delta < 0.0 {
-                diff_color_alt
-            } else {
-                diff_color
-            };
-            out[pixel_index] = color;
+            out[pixel_index] = painter(
+                delta,
+                image1.as_u32()[pixel_index],
+                image2.as_u32()[pixel_index],
+            );
         }
         1
     } else {
-        let is_aa = is_antialiased(image1, image2, x, y) || is_antialiased(image2, image1, x, y);
+        let is_aa = is_antialiased_pixels(image1, image2, x, y)
+            || is_antialiased_pixels(image2, image1, x, y);
         if is_aa {
             if let Some(out) = out32 {
                 out[pixel_index] = aa_color;
@@ -2350,77 +2730,276 @@ fn process_diff_pixel(
             0
         } else {
             if let Some(out) = out32 {
-                let color = if delta < 0.0 {
-                    diff_color_alt
-                } else {
-                    diff_color
-                };
-                out[pixel_index] = color;
+                out[pixel_index] = painter(
+                    delta,
+                    image1.as_u32()[pixel_index],
+                    image2.as_u32()[pixel_index],
+                );
             }
             1
         }
     }
 }
+
+/// Count the pixels that differ between two same-sized images, optionally
+/// drawing the diff into `output`.
+///
+/// Images with a zero width or height have nothing to compare and are
+/// reported identical (`diff_count` 0, `diff_percentage` 0.0) rather than
+/// as an error; `output` is left untouched.
 pub fn diff(
     image1: &Image,
     image2: &Image,
-    mut output: Option<&mut Image>,
+    output: Option<&mut Image>,
     options: &DiffOptions,
 ) -> Result<DiffResult, DiffError> {
-    if image1.width != image2.width || image1.height != image2.height {
-        return Err(DiffError::SizeMismatch {
-            img1_width: image1.width,
-            img1_height: image1.height,
-            img2_width: image2.width,
-            img2_height: image2.height,
-        });
-    }
-
-    let (width, height) = (image1.width, image1.height);
-    let total_pixels = width * height;
+    DiffContext::new().diff_with(image1, image2, output, options)
+}
 
-    if let Some(ref mut out) = output {
-        if options.diff_mask {
-            clear_transparent(out);
-        }
+/// [`diff`] into an output image it allocates itself, sized from the inputs.
+///
+/// The output is `None` when the images come out identical and
+/// `skip_output_when_identical` is set. Otherwise it holds exactly what
+/// [`diff`] draws, which for an identical pair is the grayed background (or
+/// the cleared mask).
+pub fn diff_to_image(
+    image1: &Image,
+    image2: &Image,
+    options: &DiffOptions,
+) -> Result<(DiffResult, Option<Image>), DiffError> {
+    if let Some((overlap1, overlap2, margin)) = overlap(image1, image2, options) {
+        let (mut result, output) = diff_to_image(&overlap1, &overlap2, options)?;
+        result.overlap_margin = Some(margin);
+        count_margin(&mut result, image1, image2, &overlap1, options)?;
+        return Ok((result, output));
     }
-
-    if image1.data.as_ptr() == image2.data.as_ptr() {
-        if let Some(ref mut out) = output {
-            if !options.diff_mask {
-                fill_block_gray_optimized(image1, out, options.alpha, 0, 0, width, height);
-            }
-        }
-        return Ok(DiffResult::new(0, total_pixels));
+    if let Some((padded1, padded2)) = padded(image1, image2, options)? {
+        return diff_to_image(&padded1, &padded2, options);
+    }
+    check_same_size(image1, image2)?;
+
+    // A mask starts cleared, so the pipeline can skip its own clear; every
+    // other mode overwrites the whole buffer whenever a block changes.
+    let mask_options;
+    let options = if options.diff_mask {
+        mask_options = DiffOptions {
+            output_cleared: true,
+            ..options.clone()
+        };
+        &mask_options
+    } else {
+        options
+    };
+    let mut output = Image::new_output(image1, options.diff_mask);
+    let mut changed_blocks = Vec::new();
+    let result = diff_into(
+        image1,
+        image2,
+        Some(&mut output),
+        options,
+        default_painter(options),
+        &mut changed_blocks,
+    )?;
+
+    if result.identical && options.skip_output_when_identical {
+        return Ok((result, None));
     }
+    Ok((result, Some(output)))
+}
 
-    // Fast path on *decoded* RGBA equality (parity with `@blazediff/core`'s
-    // `fastBufferCheck`). Two PNGs that round-trip to the same pixel buffer
-    // — e.g. an artifact and its lossless re-encoding, or a snapshot test
-    // comparing an actual against an expected that happen to differ only
-    // in PNG metadata — finish here with zero per-block work.
-    //
-    // The comparison is `==` on `Vec<u8>`, which lowers to a vectorized
-    // memcmp with early exit on first mismatch. For non-identical images
-    // the cost is ~tens of nanoseconds (mismatch is almost always in the
-    // first cache line); for identical images it walks both buffers once
-    // at memory-bandwidth speed, which is no more work than the cold
-    // block-scan would have done.
-    if image1.data == image2.data {
-        return Ok(DiffResult::new(0, total_pixels));
+/// Largest denominator [`diff_normalized`] tries for a density ratio, so
+/// 1.25x, 1.5x and 1.75x screens are recognized alongside integer ones.
+const MAX_DENSITY_DENOMINATOR: u32 = 4;
+
+/// [`diff`] for screenshots of the same content at different pixel
+/// densities, e.g. from a 1x and a 2x display.
+///
+/// When the larger image is the smaller one scaled by the same `p / q` in
+/// both dimensions (`q` up to 4), it is shrunk to match by box-averaging
+/// the area under each smaller pixel, source pixels on its edges weighted
+/// by the fraction they cover, and the pair is diffed at
+/// the smaller size, with the factor reported in
+/// [`DiffResult::normalized_scale`]. Any other pair goes to [`diff`] as is,
+/// so a size mismatch fails unless `compare_overlap` is set. No diff image
+/// is drawn.
+pub fn diff_normalized(
+    image1: &Image,
+    image2: &Image,
+    options: &DiffOptions,
+) -> Result<DiffResult, DiffError> {
+    let first_larger = image1.width > image2.width;
+    let (large, small) = if first_larger {
+        (image1, image2)
+    } else {
+        (image2, image1)
+    };
+    let Some((p, q)) = density_ratio(large, small) else {
+        return diff(image1, image2, None, options);
+    };
+    if p == q {
+        return diff(image1, image2, None, options);
     }
 
-    let block_size = calculate_block_size(width, height);
-    let blocks_x = (width + block_size - 1) / block_size;
-    let blocks_y = (height + block_size - 1) / block_size;
+    let shrunk = large.pixels().box_resample(p, q);
+    let mut result = if first_larger {
+        diff(&shrunk, image2, None, options)
+    } else {
+        diff(image1, &shrunk, None, options)
+    }?;
+    result.normalized_scale = Some(p as f64 / q as f64);
+    Ok(result)
+}
 
-    let a32 = image1.as_u32();
-    let b32 = image2.as_u32();
-    let max_delta = threshold_to_max_delta_f32(options.threshold);
-    let draw_background = output.is_some() && !options.diff_mask;
-    let include_aa = options.include_aa;
-    let alpha_f32 = options.alpha as f32;
+/// `(p, q)` with the smallest `q` such that `large` is `small` scaled by
+/// `p / q` in both dimensions.
+fn density_ratio(large: &Image, small: &Image) -> Option<(u32, u32)> {
+    if (large.width, large.height) == (small.width, small.height) {
+        return Some((1, 1));
+    }
+    if small.width == 0 || small.height == 0 {
+        return None;
+    }
+    (1..=MAX_DENSITY_DENOMINATOR).find_map(|q| {
+        let scaled = large.width as u64 * q as u64;
+        let p = scaled / small.width as u64;
+        (scaled.is_multiple_of(small.width as u64)
+            && large.height as u64 * q as u64 == small.height as u64 * p)
+            .then_some((p as u32, q))
+    })
+}
+
+/// Lowest-level entry point: [`diff`] over packed pixels (see
+/// [`Image::as_u32`]) that the caller already holds, with a caller-owned
+/// `scratch` list for the changed blocks.
+///
+/// `a`, `b` and `out` must all hold exactly `width * height` pixels. With a
+/// `scratch` reused across calls, a diff without `blur_radius` or
+/// `progressive_reject` allocates nothing once `scratch` has grown to fit.
+///
+/// The options that need an owned output (`compare_overlap`,
+/// `crop_output_to_changes`, `grid_overlay`) are ignored, and `roi`, which
+/// would change the counts, fails with [`DiffError::InvalidOption`].
+pub fn diff_u32(
+    a: &[u32],
+    b: &[u32],
+    width: u32,
+    height: u32,
+    out: Option<&mut [u32]>,
+    scratch: &mut Vec<(u32, u32, u32, u32)>,
+    options: &DiffOptions,
+) -> Result<DiffResult, DiffError> {
+    check_pixel_count(width, height)?;
+    reject_roi(options, "diff_u32")?;
+    let expected = width as usize * height as usize;
+    let lengths = [
+        Some(a.len()),
+        Some(b.len()),
+        out.as_ref().map(|out| out.len()),
+    ];
+    if let Some(actual) = lengths.into_iter().flatten().find(|&len| len != expected) {
+        return Err(DiffError::InvalidDataSize {
+            expected: expected * 4,
+            actual: actual * 4,
+        });
+    }
+    let pixels = |data| Pixels {
+        data,
+        width,
+        height,
+    };
+    let mut result = diff_pixels(
+        pixels(a),
+        pixels(b),
+        out,
+        options,
+        default_painter(options),
+        scratch,
+    )?;
+    record_block_and_row_stats(&mut result, scratch, width, height, options);
+    Ok(result)
+}
+
+/// [`diff_u32`] over RGBA bytes, for buffers the caller does not own as an
+/// [`Image`] (memory maps, GPU readback). Each slice must hold exactly
+/// `width * height * 4` bytes, or this fails with
+/// [`DiffError::InvalidDataSize`].
+///
+/// 4-byte-aligned slices are read and written in place; an unaligned one
+/// is copied into an aligned buffer first. As with [`diff_u32`], the options
+/// that need an owned output (`compare_overlap`, `crop_output_to_changes`,
+/// `grid_overlay`) are ignored, and `roi` fails with
+/// [`DiffError::InvalidOption`].
+pub fn diff_slices(
+    a: &[u8],
+    b: &[u8],
+    out: Option<&mut [u8]>,
+    width: u32,
+    height: u32,
+    options: &DiffOptions,
+) -> Result<DiffResult, DiffError> {
+    check_pixel_count(width, height)?;
+    let expected = width as usize * height as usize * 4;
+    let lengths = [
+        Some(a.len()),
+        Some(b.len()),
+        out.as_ref().map(|out| out.len()),
+    ];
+    if let Some(actual) = lengths.into_iter().flatten().find(|&len| len != expected) {
+        return Err(DiffError::InvalidDataSize { expected, actual });
+    }
+    let (a, b) = (packed_pixels(a), packed_pixels(b));
+    let mut scratch = Vec::new();
+    match out {
+        None => diff_u32(&a, &b, width, height, None, &mut scratch, options),
+        Some(out) => match bytemuck::try_cast_slice_mut::<u8, u32>(out) {
+            Ok(out32) => diff_u32(&a, &b, width, height, Some(out32), &mut scratch, options),
+            Err(_) => {
+                let mut out32 = packed_pixels(out).into_owned();
+                let result = diff_u32(
+                    &a,
+                    &b,
+                    width,
+                    height,
+                    Some(&mut out32),
+                    &mut scratch,
+                    options,
+                );
+                out.copy_from_slice(bytemuck::cast_slice(&out32));
+                result
+            }
+        },
+    }
+}
+
+/// `roi` for the entry points that cannot honor it, which would otherwise
+/// report a full-image count as if it were the rectangle's.
+pub(crate) fn reject_roi(options: &DiffOptions, entry_point: &str) -> Result<(), DiffError> {
+    match options.roi {
+        Some(_) => Err(DiffError::InvalidOption {
+            name: "roi",
+            detail: format!("not supported by {entry_point}; use diff"),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// RGBA bytes as packed pixels, copied only when they are not 4-byte aligned.
+fn packed_pixels(bytes: &[u8]) -> Cow<'_, [u32]> {
+    match bytemuck::try_cast_slice(bytes) {
+        Ok(pixels) => Cow::Borrowed(pixels),
+        Err(_) => Cow::Owned(
+            bytes
+                .chunks_exact(4)
+                .map(|pixel| u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]))
+                .collect(),
+        ),
+    }
+}
 
+/// The painter behind [`diff`]: `diff_color`, or `diff_color_alt` where
+/// `image2` is darker, or the colormap in [`OutputMode::Heatmap`], or the
+/// background in [`OutputMode::AaOnly`].
+fn default_painter(options: &DiffOptions) -> impl Fn(f32, u32, u32) -> u32 {
     let diff_color = pack_color_pixel(&options.diff_color);
     let diff_color_alt = pack_color_pixel(
         options
@@ -2428,320 +3007,3796 @@ pub fn diff(
             .as_ref()
             .unwrap_or(&options.diff_color),
     );
-    let aa_color = pack_color_pixel(&options.aa_color);
+    let heatmap = match options.output_mode {
+        OutputMode::Heatmap { colormap } => Some(colormap),
+        _ => None,
+    };
+    let aa_only = options.output_mode == OutputMode::AaOnly;
+    let alpha_scaled = options.alpha as f32 / 255.0;
+    let (mask, candidate_background) = (
+        options.diff_mask,
+        options.background_source == BackgroundSource::Candidate,
+    );
+    move |delta, a, b| {
+        if aa_only {
+            // Solid changes blend into the background around them.
+            let background = if candidate_background { b } else { a };
+            if mask {
+                0
+            } else {
+                pack_gray_pixel(compute_gray_pixel_f32(background, alpha_scaled))
+            }
+        } else if let Some(colormap) = heatmap {
+            pack_color_pixel(&colormap.rgb((delta.abs() / MAX_YIQ_DELTA_F32).sqrt()))
+        } else if delta < 0.0 {
+            diff_color_alt
+        } else {
+            diff_color
+        }
+    }
+}
 
-    let estimated_changed_blocks = ((blocks_x * blocks_y) as usize / 8).max(16);
-    let mut changed_blocks: Vec<(u32, u32, u32, u32)> =
-        Vec::with_capacity(estimated_changed_blocks);
+/// Like [`diff`], but every counted diff pixel is colored by `painter`.
+///
+/// The painter receives `(signed_delta, pixel_a, pixel_b)`: the YIQ delta
+/// (negative where `image2` is darker, magnitude up to ~35215) and the two
+/// packed RGBA input pixels, and returns the packed RGBA output pixel.
+/// `options.diff_color` and `options.diff_color_alt` are ignored; gray
+/// background, anti-aliasing markers and `diff_mask` behave as in [`diff`].
+pub fn diff_with_painter<P: Fn(f32, u32, u32) -> u32>(
+    image1: &Image,
+    image2: &Image,
+    output: Option<&mut Image>,
+    options: &DiffOptions,
+    painter: P,
+) -> Result<DiffResult, DiffError> {
+    diff_into(image1, image2, output, options, painter, &mut Vec::new())
+}
 
-    // Detect CPU features once (x86_64 only)
-    #[cfg(target_arch = "x86_64")]
-    let features = X86Features::detect();
+/// Owns the changed-block list [`diff`] would otherwise allocate per call,
+/// for batches of comparisons. The list is cleared, not reallocated, between
+/// runs, so once it has grown to fit a diff without `blur_radius`,
+/// `progressive_reject` or the block-recording options allocates nothing.
+#[derive(Debug, Default)]
+pub struct DiffContext {
+    changed_blocks: Vec<(u32, u32, u32, u32)>,
+}
 
-    // Cold pass: identify changed blocks. We deliberately *do not* fill the
-    // output's unchanged blocks with gray here — that fill is purely
-    // cosmetic (it visualizes "this pixel did not change") and is wasted
-    // work whenever the image turns out identical: callers skip saving the
-    // diff PNG in that case. Deferring the fill into a second pass means an
-    // identical-image diff finishes after just the cold-pass equality scan,
-    // with no extra writes to the 4×W×H output buffer.
-    //
-    // Cache cost of the deferred fill on non-identical images: each block's
-    // input data has to be reloaded in pass 2. For full-image-fit-in-L2
-    // inputs that's free; for very large screenshots the extra read is a
-    // small percentage of total bandwidth (and is dwarfed by PNG IO).
-    for by in 0..blocks_y {
-        for bx in 0..blocks_x {
-            let start_x = bx * block_size;
-            let start_y = by * block_size;
-            let end_x = (start_x + block_size).min(width);
-            let end_y = (start_y + block_size).min(height);
+impl DiffContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-            #[cfg(target_arch = "x86_64")]
-            let has_diff = block_has_perceptual_diff_with_features(
-                a32, b32, width, start_x, start_y, end_x, end_y, max_delta, features,
-            );
-            #[cfg(not(target_arch = "x86_64"))]
-            let has_diff = block_has_perceptual_diff(
-                a32, b32, width, start_x, start_y, end_x, end_y, max_delta,
-            );
+    /// [`diff`], reusing this context's buffer.
+    pub fn diff_with(
+        &mut self,
+        image1: &Image,
+        image2: &Image,
+        output: Option<&mut Image>,
+        options: &DiffOptions,
+    ) -> Result<DiffResult, DiffError> {
+        diff_into(
+            image1,
+            image2,
+            output,
+            options,
+            default_painter(options),
+            &mut self.changed_blocks,
+        )
+    }
+}
 
-            if has_diff {
-                changed_blocks.push((start_x, start_y, end_x, end_y));
-            }
-        }
+/// With `compare_overlap` or `CropToSmaller`, copies of the top-left region
+/// two differently sized images share, and the uncompared `(width, height)`
+/// margin.
+fn overlap(
+    image1: &Image,
+    image2: &Image,
+    options: &DiffOptions,
+) -> Option<(Image, Image, (u32, u32))> {
+    let crop = options.compare_overlap
+        || matches!(
+            options.size_mismatch,
+            SizeMismatchPolicy::CropToSmaller { .. }
+        );
+    if !crop || (image1.width, image1.height) == (image2.width, image2.height) {
+        return None;
     }
+    let width = image1.width.min(image2.width);
+    let height = image1.height.min(image2.height);
+    Some((
+        image1.crop(0, 0, width, height),
+        image2.crop(0, 0, width, height),
+        (
+            image1.width.abs_diff(image2.width),
+            image1.height.abs_diff(image2.height),
+        ),
+    ))
+}
 
-    if changed_blocks.is_empty() {
-        // Identical (within threshold) — the output buffer is intentionally
-        // left in its initial state. Callers that care about gray-fill
-        // visualization should also check `result.identical` and either skip
-        // saving or fill explicitly; the napi binding already skips the save
-        // path on identical, which is the case this short-circuit targets.
-        return Ok(DiffResult::new(0, total_pixels));
+/// With `CropToSmaller { count_margin: true }`, counts every pixel of
+/// either image outside the shared `overlap` and rebases the percentage on
+/// the area the two cover together.
+fn count_margin(
+    result: &mut DiffResult,
+    image1: &Image,
+    image2: &Image,
+    overlap: &Image,
+    options: &DiffOptions,
+) -> Result<(), DiffError> {
+    if options.compare_overlap
+        || options.size_mismatch != (SizeMismatchPolicy::CropToSmaller { count_margin: true })
+    {
+        return Ok(());
     }
+    let area = |image: &Image| image.width as u64 * image.height as u64;
+    let shared = area(overlap);
+    let covered = area(image1) + area(image2) - shared;
+    let covered = u32::try_from(covered).map_err(|_| DiffError::TooManyPixels {
+        width: image1.width.max(image2.width),
+        height: image1.height.max(image2.height),
+    })?;
+    let counted = DiffResult::new(result.diff_count + (covered - shared as u32), covered);
+    result.diff_count = counted.diff_count;
+    result.diff_percentage = counted.diff_percentage;
+    result.identical = counted.identical;
+    Ok(())
+}
 
-    // Second pass: fill unchanged blocks with gray. We re-walk every block
-    // in the same row-major order pass 1 used and skip indexes that ended
-    // up in `changed_blocks` (which is naturally row-major), so the skip
-    // check is a single comparison per block — no hash set needed.
-    if let Some(ref mut out) = output {
-        if !options.diff_mask {
-            let mut next_changed = 0usize;
-            for by in 0..blocks_y {
-                for bx in 0..blocks_x {
-                    let start_x = bx * block_size;
-                    let start_y = by * block_size;
-                    let end_x = (start_x + block_size).min(width);
-                    let end_y = (start_y + block_size).min(height);
+/// With `PadToLarger`, copies of two differently sized images grown to
+/// their larger width and height with transparent pixels.
+fn padded(
+    image1: &Image,
+    image2: &Image,
+    options: &DiffOptions,
+) -> Result<Option<(Image, Image)>, DiffError> {
+    if options.size_mismatch != SizeMismatchPolicy::PadToLarger
+        || (image1.width, image1.height) == (image2.width, image2.height)
+    {
+        return Ok(None);
+    }
+    let width = image1.width.max(image2.width);
+    let height = image1.height.max(image2.height);
+    check_pixel_count(width, height)?;
+    let pad = |image: &Image| image.resize_canvas(width, height, [0; 4]).image;
+    Ok(Some((pad(image1), pad(image2))))
+}
 
-                    if next_changed < changed_blocks.len() {
-                        let (csx, csy, _, _) = changed_blocks[next_changed];
-                        if csx == start_x && csy == start_y {
-                            next_changed += 1;
-                            continue;
-                        }
-                    }
-                    fill_block_gray_optimized(
-                        image1,
-                        out,
-                        options.alpha,
-                        start_x,
-                        start_y,
-                        end_x,
-                        end_y,
-                    );
-                }
+pub(crate) fn check_same_size(image1: &Image, image2: &Image) -> Result<(), DiffError> {
+    if image1.width != image2.width || image1.height != image2.height {
+        return Err(DiffError::SizeMismatch {
+            img1_width: image1.width,
+            img1_height: image1.height,
+            img2_width: image2.width,
+            img2_height: image2.height,
+        });
+    }
+    check_pixel_count(image1.width, image1.height)
+}
+
+/// Rejects images past `u32::MAX` pixels, whose counts [`DiffResult`]
+/// cannot hold. Below that, pixel indices are still computed in `usize` so
+/// `y * width + x` never wraps.
+fn check_pixel_count(width: u32, height: u32) -> Result<(), DiffError> {
+    if width as u64 * height as u64 > u32::MAX as u64 {
+        return Err(DiffError::TooManyPixels { width, height });
+    }
+    Ok(())
+}
+
+/// [`diff_with_painter`], leaving the changed blocks in `changed_blocks`.
+fn diff_into<P: Fn(f32, u32, u32) -> u32>(
+    image1: &Image,
+    image2: &Image,
+    output: Option<&mut Image>,
+    options: &DiffOptions,
+    painter: P,
+    changed_blocks: &mut Vec<(u32, u32, u32, u32)>,
+) -> Result<DiffResult, DiffError> {
+    if let Some((overlap1, overlap2, margin)) = overlap(image1, image2, options) {
+        let output = output.map(|out| {
+            if (out.width, out.height) != (overlap1.width, overlap1.height) {
+                *out = Image::new(overlap1.width, overlap1.height);
+            }
+            out
+        });
+        let mut result = diff_into(
+            &overlap1,
+            &overlap2,
+            output,
+            options,
+            painter,
+            changed_blocks,
+        )?;
+        result.overlap_margin = Some(margin);
+        count_margin(&mut result, image1, image2, &overlap1, options)?;
+        return Ok(result);
+    }
+    if let Some((padded1, padded2)) = padded(image1, image2, options)? {
+        let output = output.map(|out| {
+            if (out.width, out.height) != (padded1.width, padded1.height) {
+                *out = Image::new(padded1.width, padded1.height);
             }
+            out
+        });
+        return diff_into(&padded1, &padded2, output, options, painter, changed_blocks);
+    }
+    check_same_size(image1, image2)?;
+
+    // Empty images have nothing to compare.
+    if image1.width == 0 || image1.height == 0 {
+        return Ok(DiffResult::exact(0));
+    }
+
+    let mut output = output;
+    let mut result = match options.roi {
+        Some(roi) => diff_roi(
+            image1,
+            image2,
+            output.as_deref_mut(),
+            options,
+            painter,
+            changed_blocks,
+            roi,
+        )?,
+        None => diff_pixels(
+            image1.pixels(),
+            image2.pixels(),
+            output.as_deref_mut().map(|out| out.as_u32_mut()),
+            options,
+            painter,
+            changed_blocks,
+        )?,
+    };
+    record_block_and_row_stats(
+        &mut result,
+        changed_blocks,
+        image1.width,
+        image1.height,
+        options,
+    );
+
+    if let (Some(spacing), Some(out)) = (options.grid_overlay, output.as_deref_mut()) {
+        if !changed_blocks.is_empty() {
+            draw_grid(out, spacing, &options.grid_color);
         }
     }
+    if let (Some(padding), Some(out)) = (options.crop_output_to_changes, output) {
+        if !result.identical {
+            let (x, y, width, height) =
+                padded_block_bounds(changed_blocks, padding, image1.width, image1.height);
+            *out = out.crop(x, y, width, height);
+            result.crop_offset = Some((x, y));
+        }
+    }
+    Ok(result)
+}
 
-    // Hot pass: process changed blocks with SIMD
-    #[cfg(target_arch = "x86_64")]
-    let diff_count: u32 = if let Some(ref mut out) = output {
-        let out32 = out.as_u32_mut();
-        changed_blocks
-            .iter()
-            .map(|&(start_x, start_y, end_x, end_y)| {
-                process_hot_block_with_features(
-                    a32,
-                    b32,
-                    Some(out32),
-                    width,
-                    start_x,
-                    start_y,
-                    end_x,
-                    end_y,
-                    max_delta,
-                    include_aa,
-                    draw_background,
-                    diff_color,
-                    diff_color_alt,
-                    aa_color,
-                    alpha_f32,
-                    image1,
-                    image2,
-                    features,
-                )
-            })
-            .sum()
-    } else {
-        changed_blocks
+/// [`diff_pixels`] over the `roi` rectangle alone, with the changed blocks
+/// and the result's positions moved back into image coordinates. Where the
+/// rectangle is drawn, the rest of `output` gets the unchanged background.
+#[allow(clippy::too_many_arguments)]
+fn diff_roi<P: Fn(f32, u32, u32) -> u32>(
+    image1: &Image,
+    image2: &Image,
+    output: Option<&mut Image>,
+    options: &DiffOptions,
+    painter: P,
+    changed_blocks: &mut Vec<(u32, u32, u32, u32)>,
+    (x, y, width, height): (u32, u32, u32, u32),
+) -> Result<DiffResult, DiffError> {
+    for (start, size, limit) in [(x, width, image1.width), (y, height, image1.height)] {
+        let end = start as u64 + size as u64;
+        if end > limit as u64 {
+            return Err(DiffError::InvalidDataSize {
+                expected: limit as usize,
+                actual: end as usize,
+            });
+        }
+    }
+    if width == 0 || height == 0 {
+        changed_blocks.clear();
+        return Ok(DiffResult::exact(0));
+    }
+
+    if let Some(mask) = &options.mask {
+        check_mask_size(mask, image1.width, image1.height)?;
+    }
+
+    // The rectangle is drawn into a buffer of its own, which is not cleared.
+    // Ignored rectangles and the mask move into its coordinates.
+    let roi_options = DiffOptions {
+        output_cleared: false,
+        ignore_regions: options
+            .ignore_regions
             .iter()
-            .map(|&(start_x, start_y, end_x, end_y)| {
-                process_hot_block_with_features(
-                    a32,
-                    b32,
-                    None,
-                    width,
-                    start_x,
-                    start_y,
-                    end_x,
-                    end_y,
-                    max_delta,
-                    include_aa,
-                    draw_background,
-                    diff_color,
-                    diff_color_alt,
-                    aa_color,
-                    alpha_f32,
-                    image1,
-                    image2,
-                    features,
+            .map(|&(rx, ry, rw, rh)| {
+                let (start_x, start_y) = (rx.max(x), ry.max(y));
+                let end_x = rx.saturating_add(rw).min(x + width);
+                let end_y = ry.saturating_add(rh).min(y + height);
+                (
+                    start_x - x,
+                    start_y - y,
+                    end_x.saturating_sub(start_x),
+                    end_y.saturating_sub(start_y),
                 )
             })
-            .sum()
+            .filter(|&(_, _, w, h)| w > 0 && h > 0)
+            .collect(),
+        mask: options
+            .mask
+            .as_ref()
+            .map(|mask| Arc::new(mask.crop(x, y, width, height))),
+        ..options.clone()
     };
+    let mut roi_output = output.is_some().then(|| Image::new_uninit(width, height));
+    let mut result = diff_pixels(
+        image1.crop(x, y, width, height).pixels(),
+        image2.crop(x, y, width, height).pixels(),
+        roi_output.as_mut().map(|out| out.as_u32_mut()),
+        &roi_options,
+        painter,
+        changed_blocks,
+    )?;
+    for block in changed_blocks.iter_mut() {
+        *block = (block.0 + x, block.1 + y, block.2 + x, block.3 + y);
+    }
+    if let Some((cx, cy)) = result.centroid.as_mut() {
+        *cx += x as f32;
+        *cy += y as f32;
+    }
+    if let Some(rows) = result.row_diff_counts.as_mut() {
+        let mut full = vec![0; image1.height as usize];
+        full[y as usize..(y + height) as usize].copy_from_slice(rows);
+        *rows = full;
+    }
+    if let Some(mask) = result.pixel_mask.as_mut() {
+        let mut full = vec![false; image1.width as usize * image1.height as usize];
+        for (row, chunk) in mask.chunks_exact(width as usize).enumerate() {
+            let start = (y as usize + row) * image1.width as usize + x as usize;
+            full[start..start + width as usize].copy_from_slice(chunk);
+        }
+        *mask = full;
+    }
+    if let Some(ratios) = result.block_ratios.as_mut() {
+        for ((block_x, block_y, _, _), _) in ratios.iter_mut() {
+            *block_x += x;
+            *block_y += y;
+        }
+    }
 
-    #[cfg(not(target_arch = "x86_64"))]
-    let diff_count: u32 = if let Some(ref mut out) = output {
-        let out32 = out.as_u32_mut();
-        changed_blocks
-            .iter()
-            .map(|&(start_x, start_y, end_x, end_y)| {
-                process_hot_block(
-                    a32,
-                    b32,
-                    Some(out32),
-                    width,
-                    start_x,
-                    start_y,
-                    end_x,
-                    end_y,
-                    max_delta,
-                    include_aa,
-                    draw_background,
-                    diff_color,
-                    diff_color_alt,
-                    aa_color,
-                    alpha_f32,
-                    image1,
-                    image2,
-                )
-            })
-            .sum()
+    let Some(out) = output else {
+        return Ok(result);
+    };
+    let candidate_on_changed = options.output_mode == OutputMode::CandidateOnChanged;
+    // As `diff` leaves a skipped identical result's output; otherwise the
+    // rectangle, identical or not, is drawn over the unchanged background.
+    if changed_blocks.is_empty() && options.skip_output_when_identical && !candidate_on_changed {
+        return Ok(result);
+    }
+    if candidate_on_changed {
+        out.data.copy_from_slice(&image1.data);
+    } else if options.diff_mask {
+        out.data.fill(0);
     } else {
-        changed_blocks
-            .iter()
-            .map(|&(start_x, start_y, end_x, end_y)| {
-                process_hot_block(
-                    a32,
-                    b32,
-                    None,
-                    width,
-                    start_x,
-                    start_y,
-                    end_x,
-                    end_y,
+        let background = match options.background_source {
+            BackgroundSource::Baseline => image1,
+            BackgroundSource::Candidate => image2,
+        };
+        // The passes' own gray fill, which `fill_gray` can differ from by a
+        // level.
+        fill_block_gray_u32(
+            background.as_u32(),
+            image1.width,
+            out.as_u32_mut(),
+            options.alpha,
+            0,
+            0,
+            image1.width,
+            image1.height,
+        );
+        if let OutputMode::Posterized { levels } = options.output_mode {
+            posterize_bytes(&mut out.data, levels);
+        }
+    }
+    if let Some(roi_output) = roi_output {
+        let (out_width, row_bytes) = (out.width as usize, width as usize * 4);
+        for (row, chunk) in roi_output.data.chunks_exact(row_bytes).enumerate() {
+            let start = ((y as usize + row) * out_width + x as usize) * 4;
+            out.data[start..start + row_bytes].copy_from_slice(chunk);
+        }
+    }
+    Ok(result)
+}
+
+/// Fill the `record_changed_blocks`, `track_row_counts`,
+/// `track_block_ratios` and `track_pixel_mask` fields the passes leave to
+/// the caller: block ids from `changed_blocks`, and all-zero row counts, no
+/// block ratios or an all-false mask for an identical result, which never
+/// reaches the hot pass.
+fn record_block_and_row_stats(
+    result: &mut DiffResult,
+    changed_blocks: &[(u32, u32, u32, u32)],
+    width: u32,
+    height: u32,
+    options: &DiffOptions,
+) {
+    if options.record_changed_blocks {
+        result.changed_block_ids = Some(changed_block_ids(result, changed_blocks, width, height));
+    }
+    if options.track_row_counts && result.identical && result.row_diff_counts.is_none() {
+        result.row_diff_counts = Some(vec![0; height as usize]);
+    }
+    if options.track_block_ratios && result.identical && result.block_ratios.is_none() {
+        result.block_ratios = Some(Vec::new());
+    }
+    if options.track_pixel_mask && result.identical && result.pixel_mask.is_none() {
+        result.pixel_mask = Some(vec![false; width as usize * height as usize]);
+    }
+}
+
+/// Grid indices of every block in the [`block_grid`] that `blocks` (as
+/// `(start_x, start_y, end_x, end_y)`) cover, for
+/// [`DiffResult::changed_block_ids`].
+fn changed_block_ids(
+    result: &DiffResult,
+    blocks: &[(u32, u32, u32, u32)],
+    width: u32,
+    height: u32,
+) -> Vec<u32> {
+    if result.identical {
+        return Vec::new();
+    }
+    let (block_size, blocks_x, _) = block_grid(width, height);
+    let mut ids = Vec::with_capacity(blocks.len());
+    for &(start_x, start_y, end_x, end_y) in blocks {
+        for by in start_y / block_size..end_y.div_ceil(block_size) {
+            ids.extend(
+                (start_x / block_size..end_x.div_ceil(block_size)).map(|bx| by * blocks_x + bx),
+            );
+        }
+    }
+    ids
+}
+
+/// `(x, y, width, height)` of the union of `blocks`, grown by `padding` on
+/// every side and clipped to the image.
+fn padded_block_bounds(
+    blocks: &[(u32, u32, u32, u32)],
+    padding: u32,
+    width: u32,
+    height: u32,
+) -> (u32, u32, u32, u32) {
+    let (min_x, min_y, max_x, max_y) = blocks.iter().fold(
+        (width, height, 0, 0),
+        |(min_x, min_y, max_x, max_y), &(sx, sy, ex, ey)| {
+            (min_x.min(sx), min_y.min(sy), max_x.max(ex), max_y.max(ey))
+        },
+    );
+    let x = min_x.saturating_sub(padding);
+    let y = min_y.saturating_sub(padding);
+    let end_x = max_x.saturating_add(padding).min(width);
+    let end_y = max_y.saturating_add(padding).min(height);
+    (x, y, end_x - x, end_y - y)
+}
+
+/// Option handling shared by [`diff_with_painter`] and [`diff_u32`], for
+/// inputs already known to be the same size.
+fn diff_pixels<P: Fn(f32, u32, u32) -> u32>(
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
+    output: Option<&mut [u32]>,
+    options: &DiffOptions,
+    painter: P,
+    scratch: &mut Vec<(u32, u32, u32, u32)>,
+) -> Result<DiffResult, DiffError> {
+    if image1.width == 0 || image1.height == 0 {
+        return Ok(DiffResult::exact(0));
+    }
+
+    // Masked-out pixels are skipped by the passes' gate like ignored
+    // rectangles, and leave the percentage's denominator.
+    let compared = match &options.mask {
+        Some(mask) => {
+            if options.mask_channel > 3 {
+                return Err(DiffError::InvalidOption {
+                    name: "mask_channel",
+                    detail: format!("{} is above 3", options.mask_channel),
+                });
+            }
+            check_mask_size(mask, image1.width, image1.height)?;
+            let shift = 8 * options.mask_channel as u32;
+            Some(
+                mask.as_u32()
+                    .iter()
+                    .filter(|&&m| (m >> shift) & 0xFF != 0)
+                    .count() as u32,
+            )
+        }
+        None => None,
+    };
+
+    let mut result = diff_transformed(image1, image2, output, options, painter, scratch)?;
+    // Transformed copies can come out equal where the inputs are not, so
+    // exactness is judged once, on the originals.
+    if options.cvd_simulation.is_some()
+        || options.jpeg_artifact_tolerance
+        || options.blur_radius.is_some()
+    {
+        result.exact_match = image1.data == image2.data;
+    }
+    if let Some(compared) = compared {
+        result.diff_percentage = match compared {
+            0 => 0.0,
+            compared => 100.0 * result.diff_count as f64 / compared as f64,
+        };
+    }
+    Ok(result)
+}
+
+/// [`diff_pixels`] once the options are checked: the inputs replaced by
+/// their transformed copies, the coarse early exit, and the orientation
+/// for the background source.
+fn diff_transformed<P: Fn(f32, u32, u32) -> u32>(
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
+    output: Option<&mut [u32]>,
+    options: &DiffOptions,
+    painter: P,
+    scratch: &mut Vec<(u32, u32, u32, u32)>,
+) -> Result<DiffResult, DiffError> {
+    // Simulated copies stand in for the inputs like blurred ones do below.
+    if let Some(kind) = options.cvd_simulation {
+        let simulated1 = image1.simulate_cvd(kind);
+        let simulated2 = image2.simulate_cvd(kind);
+        let options = DiffOptions {
+            cvd_simulation: None,
+            ..options.clone()
+        };
+        return diff_transformed(
+            simulated1.pixels(),
+            simulated2.pixels(),
+            output,
+            &options,
+            painter,
+            scratch,
+        );
+    }
+
+    // Deblocked copies stand in for the inputs like blurred ones do below.
+    if options.jpeg_artifact_tolerance {
+        let deblocked1 = image1.deblock_jpeg();
+        let deblocked2 = image2.deblock_jpeg();
+        let options = DiffOptions {
+            jpeg_artifact_tolerance: false,
+            ..options.clone()
+        };
+        return diff_transformed(
+            deblocked1.pixels(),
+            deblocked2.pixels(),
+            output,
+            &options,
+            painter,
+            scratch,
+        );
+    }
+
+    // Blurred copies stand in for the inputs everywhere, including the gray
+    // background, which is drawn from the blurred baseline.
+    if let Some(radius) = options.blur_radius {
+        let blurred1 = image1.gaussian_blur(radius);
+        let blurred2 = image2.gaussian_blur(radius);
+        let options = DiffOptions {
+            blur_radius: None,
+            ..options.clone()
+        };
+        return diff_transformed(
+            blurred1.pixels(),
+            blurred2.pixels(),
+            output,
+            &options,
+            painter,
+            scratch,
+        );
+    }
+
+    // The coarse pass only short-circuits the count; a diff image always
+    // needs the full-resolution pass.
+    let total_pixels = image1.width * image1.height;
+    if options.progressive_reject
+        && !options.record_changed_blocks
+        && options.mask.is_none()
+        && output.is_none()
+        && total_pixels >= PROGRESSIVE_REJECT_MIN_PIXELS
+    {
+        // A coarse pixel touching an ignored rectangle is ignored whole.
+        let factor = PROGRESSIVE_REJECT_FACTOR;
+        let coarse_options = DiffOptions {
+            progressive_reject: false,
+            ignore_regions: options
+                .ignore_regions
+                .iter()
+                .map(|&(x, y, w, h)| {
+                    let (end_x, end_y) = (x.saturating_add(w), y.saturating_add(h));
+                    let (x, y) = (x / factor, y / factor);
+                    (x, y, end_x.div_ceil(factor) - x, end_y.div_ceil(factor) - y)
+                })
+                .collect(),
+            ..options.clone()
+        };
+        let coarse = diff_oriented(
+            image1.box_downsample(PROGRESSIVE_REJECT_FACTOR).pixels(),
+            image2.box_downsample(PROGRESSIVE_REJECT_FACTOR).pixels(),
+            None,
+            &coarse_options,
+            &painter,
+            scratch,
+        )?;
+        if coarse.diff_percentage > PROGRESSIVE_REJECT_PERCENTAGE {
+            let scale = (PROGRESSIVE_REJECT_FACTOR * PROGRESSIVE_REJECT_FACTOR) as u64;
+            let estimate = (coarse.diff_count as u64 * scale).min(total_pixels as u64);
+            return Ok(DiffResult {
+                estimated: true,
+                ..DiffResult::new(estimate as u32, total_pixels)
+            });
+        }
+    }
+
+    // Unchanged pixels keep the baseline, copied in up front; the mask-mode
+    // hot pass then only overwrites counted pixels, with the candidate's.
+    if options.output_mode == OutputMode::CandidateOnChanged {
+        if let Some(out) = output {
+            out.copy_from_slice(image1.data);
+            let options = DiffOptions {
+                diff_mask: true,
+                output_cleared: true,
+                output_mode: OutputMode::Full,
+                ..options.clone()
+            };
+            return diff_oriented(image1, image2, Some(out), &options, &|_, _, b| b, scratch);
+        }
+    }
+
+    // Background gray is always taken from `image1` below. To dim the
+    // candidate instead, swap the inputs: the YIQ delta is antisymmetric, so
+    // the same pixels are flagged, and flipping the painter's arguments back
+    // keeps lightening/darkening highlights pointing the same way.
+    if options.background_source == BackgroundSource::Candidate {
+        diff_oriented(
+            image2,
+            image1,
+            output,
+            options,
+            &|delta, a, b| painter(-delta, b, a),
+            scratch,
+        )
+    } else {
+        diff_oriented(image1, image2, output, options, &painter, scratch)
+    }
+}
+
+fn check_mask_size(mask: &Image, width: u32, height: u32) -> Result<(), DiffError> {
+    if (mask.width, mask.height) != (width, height) {
+        return Err(DiffError::SizeMismatch {
+            img1_width: width,
+            img1_height: height,
+            img2_width: mask.width,
+            img2_height: mask.height,
+        });
+    }
+    Ok(())
+}
+
+/// Magnitude-weighted severity in 0..=1: the sum of every counted pixel's
+/// `|delta| / MAX_YIQ_DELTA`, divided by the total pixel count.
+///
+/// Uses the same threshold and anti-aliasing rules as [`diff`], so it is
+/// zero exactly when `diff` reports identical, but a black/white flip
+/// weighs far more than a pixel just over the threshold.
+pub fn weighted_diff_score(
+    image1: &Image,
+    image2: &Image,
+    options: &DiffOptions,
+) -> Result<f64, DiffError> {
+    // The painter only runs when there is an output to paint; a pre-cleared
+    // mask buffer keeps that scratch output as cheap as possible. Output
+    // modes that bring their own painter would bypass the sum, so the
+    // scratch is always painted as `Full`, with nothing drawn over it.
+    let mut scratch = Image::new_transparent(image1.width, image1.height);
+    let mask_options = DiffOptions {
+        diff_mask: true,
+        output_cleared: true,
+        output_mode: OutputMode::Full,
+        crop_output_to_changes: None,
+        grid_overlay: None,
+        ..options.clone()
+    };
+    let sum = std::cell::Cell::new(0.0f64);
+    let result = diff_with_painter(
+        image1,
+        image2,
+        Some(&mut scratch),
+        &mask_options,
+        |delta, _, _| {
+            sum.set(sum.get() + (delta.abs() as f64 / MAX_YIQ_DELTA).min(1.0));
+            0
+        },
+    )?;
+
+    let total_pixels = (image1.width as f64) * (image1.height as f64);
+    Ok(if result.identical || total_pixels == 0.0 {
+        0.0
+    } else {
+        sum.get() / total_pixels
+    })
+}
+
+/// Whether `result` is no worse than a previously recorded measurement: its
+/// changed fraction (`diff_percentage / 100`) is at most `recorded_ratio +
+/// slack`. For suites that tolerate known drift but fail when it grows.
+/// An [`estimated`](DiffResult::estimated) result carries no full count to
+/// measure, so diff without `stop_at_first_diff` or `progressive_reject`.
+pub fn compare_against_recorded(result: &DiffResult, recorded_ratio: f64, slack: f64) -> bool {
+    result.diff_percentage / 100.0 <= recorded_ratio + slack
+}
+
+/// Whether the diff image [`diff_to_image`] draws for `image1` and `image2`
+/// is byte-identical to `expected_diff`, for golden tests of the
+/// visualization itself. Identical inputs are still drawn, ignoring
+/// `skip_output_when_identical`, and a size mismatch is `false`.
+pub fn assert_diff_matches(
+    image1: &Image,
+    image2: &Image,
+    expected_diff: &Image,
+    options: &DiffOptions,
+) -> Result<bool, DiffError> {
+    let options = DiffOptions {
+        skip_output_when_identical: false,
+        ..options.clone()
+    };
+    let (_, output) = diff_to_image(image1, image2, &options)?;
+    Ok(output.is_some_and(|output| {
+        (output.width, output.height) == (expected_diff.width, expected_diff.height)
+            && output.data == expected_diff.data
+    }))
+}
+
+/// Diff body shared by every orientation; `image1` supplies the background.
+fn diff_oriented<P: Fn(f32, u32, u32) -> u32>(
+    image1: Pixels<'_>,
+    image2: Pixels<'_>,
+    mut output: Option<&mut [u32]>,
+    options: &DiffOptions,
+    painter: &P,
+    changed_blocks: &mut Vec<(u32, u32, u32, u32)>,
+) -> Result<DiffResult, DiffError> {
+    let (width, height) = (image1.width, image1.height);
+    let total_pixels = width * height;
+    changed_blocks.clear();
+
+    // Resolve the kernels once (x86_64 detects at runtime), rejecting a
+    // forced backend this CPU or build cannot run.
+    #[cfg(target_arch = "x86_64")]
+    let features = X86Features::select(options.force_backend, options.compat_mode)?;
+    #[cfg(all(target_arch = "x86_64", feature = "tracing"))]
+    let backend = features.backend();
+    #[cfg(not(target_arch = "x86_64"))]
+    let backend = native_backend();
+    #[cfg(target_arch = "aarch64")]
+    if options.compat_mode == CompatMode::Wasm && options.force_backend == Some(backend) {
+        return Err(DiffError::UnsupportedBackend(backend));
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    if let Some(force) = options.force_backend.filter(|&force| force != backend) {
+        return Err(DiffError::UnsupportedBackend(force));
+    }
+
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("diff", width, height, backend = backend.as_str()).entered();
+
+    let clear_mask = options.diff_mask && !options.output_cleared;
+    if let Some(ref mut out) = output {
+        if clear_mask && !options.skip_output_when_identical {
+            out.fill(0);
+        }
+    }
+
+    let a32 = image1.as_u32();
+    let b32 = image2.as_u32();
+
+    // Equal pixel buffers, the common "snapshot unchanged" case, skip the
+    // block passes entirely: one vectorized sweep over both buffers, with
+    // an early exit at the first differing chunk, then the gray background.
+    // The same buffer passed twice needs no sweep at all.
+    if a32.as_ptr() == b32.as_ptr()
+        || are_identical_simd(
+            a32,
+            b32,
+            #[cfg(target_arch = "x86_64")]
+            features,
+        )
+    {
+        if let Some(ref mut out) = output {
+            draw_identical_output(
+                a32,
+                out,
+                options,
+                #[cfg(target_arch = "x86_64")]
+                features,
+            );
+        }
+        return Ok(DiffResult::exact(total_pixels));
+    }
+
+    let (block_size, blocks_x, blocks_y) = block_grid(width, height);
+
+    let max_delta = threshold_to_max_delta_f32(options.threshold);
+    let draw_background = output.is_some() && !options.diff_mask;
+    // Text mode always excludes anti-aliased pixels; a silhouette has no
+    // anti-aliasing to exclude.
+    let include_aa = options.silhouette_only || (options.include_aa && !options.text_mode);
+    let alpha_f32 = options.alpha as f32;
+    // The NEON kernels fuse multiply-adds, so WASM compatibility takes the
+    // scalar kernels, which active gates already route to.
+    let scalar = cfg!(target_arch = "aarch64") && options.compat_mode == CompatMode::Wasm;
+    let gate = PixelGate::new(options, max_delta).or(scalar.then(PixelGate::default));
+
+    let aa_color = pack_color_pixel(&options.aa_color);
+
+    // Two solid images differ by the same delta everywhere, and no pixel has
+    // a differing neighbor to be anti-aliased against, so every pixel counts
+    // or none does. Gates, spatial stats and row counts need the per-pixel
+    // passes.
+    if gate.is_none()
+        && !options.track_spatial_stats
+        && !options.track_row_counts
+        && !options.track_block_ratios
+        && !options.track_pixel_mask
+        && !options.block_granularity
+    {
+        if let (Some(pa), Some(pb)) = (image1.solid_color(), image2.solid_color()) {
+            let delta = color_delta_f32(pa, pb);
+            let counted = delta.abs() > max_delta;
+            if let Some(ref mut out) = output {
+                let drawn = if counted {
+                    // Painters may accumulate per pixel, so each one is asked.
+                    for pixel in out.iter_mut() {
+                        *pixel = painter(delta, pa, pb);
+                    }
+                    true
+                } else if !options.diff_mask && !options.skip_output_when_identical {
+                    out.fill(pack_gray_pixel(compute_gray_pixel_f32(
+                        pa,
+                        alpha_f32 / 255.0,
+                    )));
+                    true
+                } else {
+                    false
+                };
+                if let (true, OutputMode::Posterized { levels }) = (drawn, options.output_mode) {
+                    posterize_bytes(bytemuck::cast_slice_mut(out), levels);
+                }
+            }
+            if counted {
+                changed_blocks.push((0, 0, width, height));
+            }
+            let diff_count = if counted { total_pixels } else { 0 };
+            return Ok(DiffResult::new(diff_count, total_pixels));
+        }
+    }
+
+    // Only a yes or no is wanted: interleave the passes and stop at the
+    // first block the hot pass counts, instead of flagging every block first.
+    if options.stop_at_first_diff
+        && output.is_none()
+        && !options.block_granularity
+        && !options.track_spatial_stats
+        && !options.track_row_counts
+        && !options.track_block_ratios
+        && !options.track_pixel_mask
+        && !options.record_changed_blocks
+    {
+        let cold_gate = gate.filter(|gate| scalar || gate.reweighs());
+        for by in 0..blocks_y {
+            for bx in 0..blocks_x {
+                let start_x = bx * block_size;
+                let start_y = by * block_size;
+                let end_x = (start_x + block_size).min(width);
+                let end_y = (start_y + block_size).min(height);
+                let changed = match cold_gate {
+                    Some(gate) => block_changed_gated(
+                        a32, b32, width, start_x, start_y, end_x, end_y, max_delta, gate,
+                    ),
+                    None => block_changed(
+                        a32,
+                        b32,
+                        width,
+                        start_x,
+                        start_y,
+                        end_x,
+                        end_y,
+                        max_delta,
+                        #[cfg(target_arch = "x86_64")]
+                        features,
+                    ),
+                };
+                if !changed {
+                    continue;
+                }
+                #[cfg(target_arch = "x86_64")]
+                let count = process_hot_block_with_features(
+                    a32, b32, None, width, start_x, start_y, end_x, end_y, max_delta, include_aa,
+                    false, painter, aa_color, alpha_f32, image1, image2, gate, features,
+                );
+                #[cfg(not(target_arch = "x86_64"))]
+                let count = process_hot_block(
+                    a32, b32, None, width, start_x, start_y, end_x, end_y, max_delta, include_aa,
+                    false, painter, aa_color, alpha_f32, image1, image2, gate,
+                );
+                if count > 0 {
+                    // Later blocks may count more: a lower bound only.
+                    changed_blocks.push((start_x, start_y, end_x, end_y));
+                    return Ok(DiffResult {
+                        estimated: true,
+                        ..DiffResult::new(count, total_pixels)
+                    });
+                }
+            }
+        }
+        return Ok(DiffResult::new(0, total_pixels));
+    }
+
+    let estimated_changed_blocks = ((blocks_x * blocks_y) as usize / 8).max(16);
+    changed_blocks.reserve(estimated_changed_blocks);
+
+    // Cold pass: identify changed blocks. We deliberately *do not* fill the
+    // output's unchanged blocks with gray here — that fill is purely
+    // cosmetic (it visualizes "this pixel did not change") and is wasted
+    // work whenever the image turns out identical: callers skip saving the
+    // diff PNG in that case. Deferring the fill into a second pass means an
+    // identical-image diff finishes after just the cold-pass equality scan,
+    // with no extra writes to the 4×W×H output buffer.
+    //
+    // Cache cost of the deferred fill on non-identical images: each block's
+    // input data has to be reloaded in pass 2. For full-image-fit-in-L2
+    // inputs that's free; for very large screenshots the extra read is a
+    // small percentage of total bandwidth (and is dwarfed by PNG IO).
+    #[cfg(feature = "tracing")]
+    let cold_span = tracing::debug_span!(
+        "cold_pass",
+        blocks = blocks_x * blocks_y,
+        changed_blocks = tracing::field::Empty
+    )
+    .entered();
+    if options.single_pass {
+        // One block spanning the whole image: no cold pass and no gray
+        // back-fill, the hot pass visits (and draws) every pixel itself.
+        changed_blocks.push((0, 0, width, height));
+    } else if let Some(gate) = gate.filter(|gate| scalar || gate.reweighs()) {
+        find_changed_blocks_gated(
+            a32,
+            b32,
+            width,
+            height,
+            block_size,
+            max_delta,
+            gate,
+            changed_blocks,
+        );
+    } else {
+        find_changed_blocks(
+            a32,
+            b32,
+            width,
+            height,
+            block_size,
+            max_delta,
+            #[cfg(target_arch = "x86_64")]
+            features,
+            total_pixels >= PARALLEL_COLD_PASS_MIN_PIXELS,
+            changed_blocks,
+        );
+        // Blocks flagged only by excluded pixels are not changed.
+        if let Some(gate) = gate.filter(PixelGate::excludes_any) {
+            changed_blocks.retain(|&(start_x, start_y, end_x, end_y)| {
+                block_changed_gated(
+                    a32, b32, width, start_x, start_y, end_x, end_y, max_delta, gate,
+                )
+            });
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    {
+        cold_span.record("changed_blocks", changed_blocks.len());
+        drop(cold_span);
+    }
+
+    if changed_blocks.is_empty() {
+        // Identical within the threshold: drawn like byte-equal inputs above,
+        // the gray background (or the mask cleared up front), so no pixel of
+        // an `Image::new_uninit` output is left unwritten.
+        if let Some(ref mut out) = output {
+            draw_identical_output(
+                a32,
+                out,
+                options,
+                #[cfg(target_arch = "x86_64")]
+                features,
+            );
+        }
+        return Ok(DiffResult::new(0, total_pixels));
+    }
+
+    if let Some(ref mut out) = output {
+        if clear_mask && options.skip_output_when_identical {
+            out.fill(0);
+        }
+    }
+
+    if options.block_granularity {
+        // A non-identical result must leave no pixel of the output unwritten,
+        // which may come from `Image::new_uninit`.
+        if let Some(ref mut out) = output {
+            if !options.diff_mask {
+                fill_gray_identical(
+                    a32,
+                    out,
+                    alpha_f32,
+                    #[cfg(target_arch = "x86_64")]
+                    features,
+                );
+            }
+            let diff_color = pack_color_pixel(&options.diff_color);
+            for &(start_x, start_y, end_x, end_y) in changed_blocks.iter() {
+                for y in start_y..end_y {
+                    let row = y as usize * width as usize;
+                    out[row + start_x as usize..row + end_x as usize].fill(diff_color);
+                }
+            }
+        }
+        let area: u64 = changed_blocks
+            .iter()
+            .map(|&(sx, sy, ex, ey)| (ex - sx) as u64 * (ey - sy) as u64)
+            .sum();
+        return Ok(DiffResult {
+            changed_blocks: Some(changed_blocks.len() as u32),
+            ..DiffResult::new(area as u32, total_pixels)
+        });
+    }
+
+    // Second pass: fill unchanged blocks with gray. We re-walk every block
+    // in the same row-major order pass 1 used and skip indexes that ended
+    // up in `changed_blocks` (which is naturally row-major), so the skip
+    // check is a single comparison per block — no hash set needed.
+    if let Some(ref mut out) = output {
+        if !options.diff_mask && !options.single_pass {
+            let mut next_changed = 0usize;
+            for by in 0..blocks_y {
+                for bx in 0..blocks_x {
+                    let start_x = bx * block_size;
+                    let start_y = by * block_size;
+                    let end_x = (start_x + block_size).min(width);
+                    let end_y = (start_y + block_size).min(height);
+
+                    if next_changed < changed_blocks.len() {
+                        let (csx, csy, _, _) = changed_blocks[next_changed];
+                        if csx == start_x && csy == start_y {
+                            next_changed += 1;
+                            continue;
+                        }
+                    }
+                    fill_block_gray_u32(
+                        image1.as_u32(),
+                        width,
+                        out,
+                        options.alpha,
+                        start_x,
+                        start_y,
+                        end_x,
+                        end_y,
+                    );
+                }
+            }
+        }
+    }
+
+    // Hot pass: process changed blocks with SIMD
+    #[cfg(feature = "tracing")]
+    let _hot_span =
+        tracing::debug_span!("hot_pass", changed_blocks = changed_blocks.len()).entered();
+    // Spatial stats, row counts and the pixel mask need each counted pixel's
+    // coordinates, which only the scalar kernel reports.
+    let mut spatial = (options.track_spatial_stats
+        || options.track_row_counts
+        || options.track_pixel_mask)
+        .then(|| SpatialSums {
+            width,
+            rows: options.track_row_counts.then(|| vec![0; height as usize]),
+            mask: options
+                .track_pixel_mask
+                .then(|| vec![false; total_pixels as usize]),
+            ..Default::default()
+        });
+    let mut block_counts = options
+        .track_block_ratios
+        .then(|| Vec::with_capacity(changed_blocks.len()));
+    #[cfg(target_arch = "x86_64")]
+    let diff_count: u32 = if let Some(ref mut sums) = spatial {
+        let mut out32 = output.as_deref_mut();
+        changed_blocks
+            .iter()
+            .map(|&(start_x, start_y, end_x, end_y)| {
+                process_hot_block_scalar(
+                    a32,
+                    b32,
+                    out32.as_deref_mut(),
+                    width,
+                    start_x,
+                    start_y,
+                    end_x,
+                    end_y,
                     max_delta,
                     include_aa,
-                    draw_background,
-                    diff_color,
-                    diff_color_alt,
-                    aa_color,
-                    alpha_f32,
-                    image1,
-                    image2,
-                )
+                    draw_background,
+                    painter,
+                    aa_color,
+                    alpha_f32,
+                    image1,
+                    image2,
+                    gate.unwrap_or_default(),
+                    Some(sums),
+                )
+            })
+            .inspect(|&count| push_block_count(&mut block_counts, count))
+            .sum()
+    } else if let Some(ref mut out) = output {
+        let out32 = &mut **out;
+        changed_blocks
+            .iter()
+            .map(|&(start_x, start_y, end_x, end_y)| {
+                process_hot_block_with_features(
+                    a32,
+                    b32,
+                    Some(out32),
+                    width,
+                    start_x,
+                    start_y,
+                    end_x,
+                    end_y,
+                    max_delta,
+                    include_aa,
+                    draw_background,
+                    painter,
+                    aa_color,
+                    alpha_f32,
+                    image1,
+                    image2,
+                    gate,
+                    features,
+                )
+            })
+            .inspect(|&count| push_block_count(&mut block_counts, count))
+            .sum()
+    } else {
+        changed_blocks
+            .iter()
+            .map(|&(start_x, start_y, end_x, end_y)| {
+                process_hot_block_with_features(
+                    a32,
+                    b32,
+                    None,
+                    width,
+                    start_x,
+                    start_y,
+                    end_x,
+                    end_y,
+                    max_delta,
+                    include_aa,
+                    draw_background,
+                    painter,
+                    aa_color,
+                    alpha_f32,
+                    image1,
+                    image2,
+                    gate,
+                    features,
+                )
+            })
+            .inspect(|&count| push_block_count(&mut block_counts, count))
+            .sum()
+    };
+
+    #[cfg(not(target_arch = "x86_64"))]
+    let diff_count: u32 = if let Some(ref mut sums) = spatial {
+        let mut out32 = output.as_deref_mut();
+        changed_blocks
+            .iter()
+            .map(|&(start_x, start_y, end_x, end_y)| {
+                process_hot_block_scalar(
+                    a32,
+                    b32,
+                    out32.as_deref_mut(),
+                    width,
+                    start_x,
+                    start_y,
+                    end_x,
+                    end_y,
+                    max_delta,
+                    include_aa,
+                    draw_background,
+                    painter,
+                    aa_color,
+                    alpha_f32,
+                    image1,
+                    image2,
+                    gate.unwrap_or_default(),
+                    Some(sums),
+                )
+            })
+            .inspect(|&count| push_block_count(&mut block_counts, count))
+            .sum()
+    } else if let Some(ref mut out) = output {
+        let out32 = &mut **out;
+        changed_blocks
+            .iter()
+            .map(|&(start_x, start_y, end_x, end_y)| {
+                process_hot_block(
+                    a32,
+                    b32,
+                    Some(out32),
+                    width,
+                    start_x,
+                    start_y,
+                    end_x,
+                    end_y,
+                    max_delta,
+                    include_aa,
+                    draw_background,
+                    painter,
+                    aa_color,
+                    alpha_f32,
+                    image1,
+                    image2,
+                    gate,
+                )
+            })
+            .inspect(|&count| push_block_count(&mut block_counts, count))
+            .sum()
+    } else {
+        changed_blocks
+            .iter()
+            .map(|&(start_x, start_y, end_x, end_y)| {
+                process_hot_block(
+                    a32,
+                    b32,
+                    None,
+                    width,
+                    start_x,
+                    start_y,
+                    end_x,
+                    end_y,
+                    max_delta,
+                    include_aa,
+                    draw_background,
+                    painter,
+                    aa_color,
+                    alpha_f32,
+                    image1,
+                    image2,
+                    gate,
+                )
+            })
+            .inspect(|&count| push_block_count(&mut block_counts, count))
+            .sum()
+    };
+
+    if let (Some(out), OutputMode::Posterized { levels }) = (output, options.output_mode) {
+        posterize_bytes(bytemuck::cast_slice_mut(out), levels);
+    }
+
+    let mut result = DiffResult::new(diff_count, total_pixels);
+    if let Some(sums) = spatial {
+        if let Some(((centroid_x, centroid_y), spread)) = sums.finish() {
+            if options.track_spatial_stats {
+                result.centroid = Some((centroid_x, centroid_y));
+                result.spread = Some(spread);
+            }
+        }
+        result.row_diff_counts = sums.rows;
+        result.pixel_mask = sums.mask;
+    }
+    if let Some(counts) = block_counts {
+        result.block_ratios = Some(
+            changed_blocks
+                .iter()
+                .zip(counts)
+                .map(|(&(start_x, start_y, end_x, end_y), count)| {
+                    let (block_width, block_height) = (end_x - start_x, end_y - start_y);
+                    let area = (block_width * block_height) as f32;
+                    (
+                        (start_x, start_y, block_width, block_height),
+                        count as f32 / area,
+                    )
+                })
+                .collect(),
+        );
+    }
+    Ok(result)
+}
+
+/// Keeps a changed block's hot-pass count, with `track_block_ratios`.
+#[inline]
+fn push_block_count(block_counts: &mut Option<Vec<u32>>, count: u32) {
+    if let Some(counts) = block_counts {
+        counts.push(count);
+    }
+}
+
+/// `text_mode` multiplies `max_delta` by this on edge pixels.
+const TEXT_EDGE_DELTA_SCALE: f32 = 2.0;
+/// Local Y contrast (0-255) at which `text_mode` treats a pixel as an edge.
+const TEXT_EDGE_CONTRAST: f32 = 48.0;
+
+/// Per-pixel conditions on top of the perceptual threshold. Any active gate
+/// routes the hot pass through the scalar kernel; the default passes all.
+#[derive(Clone, Copy, Default, PartialEq)]
+struct PixelGate<'a> {
+    /// The largest channel difference must exceed this (0 disables).
+    min_channel_delta: u8,
+    /// On edge pixels the delta must exceed this instead of `max_delta`.
+    edge_max_delta: Option<f32>,
+    /// Pixels near this key color in either image never count.
+    chroma_key: Option<([u8; 3], u8)>,
+    /// Pixels below this alpha in both images never count (0 disables).
+    min_alpha: u8,
+    /// Weight of the squared alpha difference added to the delta.
+    alpha_weight: f32,
+    /// The delta is the full range where visibility flips, zero elsewhere.
+    silhouette: bool,
+    /// The delta is the CIE76 Delta E, rescaled so `threshold * 100` lands
+    /// on `max_delta`.
+    delta_e76: bool,
+    /// Pixels inside these `(x, y, width, height)` rectangles never count.
+    ignore_regions: &'a [(u32, u32, u32, u32)],
+    /// Mask pixels, row width and channel shift: pixels whose mask channel
+    /// is zero never count.
+    mask: Option<(&'a [u32], u32, u32)>,
+}
+
+impl<'a> PixelGate<'a> {
+    fn new(options: &'a DiffOptions, max_delta: f32) -> Option<Self> {
+        let gate = Self {
+            min_channel_delta: options.min_channel_delta.unwrap_or(0),
+            edge_max_delta: options
+                .text_mode
+                .then_some(max_delta * TEXT_EDGE_DELTA_SCALE),
+            chroma_key: options
+                .chroma_key
+                .map(|key| (key, options.chroma_tolerance)),
+            min_alpha: options.min_alpha,
+            alpha_weight: options.alpha_weight as f32,
+            silhouette: options.silhouette_only,
+            delta_e76: options.color_metric == ColorMetric::DeltaE76,
+            ignore_regions: &options.ignore_regions,
+            mask: options
+                .mask
+                .as_deref()
+                .map(|mask| (mask.as_u32(), mask.width, 8 * options.mask_channel as u32)),
+        };
+        (gate != Self::default()).then_some(gate)
+    }
+
+    /// Whether some pixels are left out of the comparison altogether, which
+    /// the SIMD cold pass cannot see either.
+    fn excludes_any(&self) -> bool {
+        !self.ignore_regions.is_empty() || self.mask.is_some()
+    }
+
+    /// Whether the pixel at `(x, y)` is left out of the comparison.
+    #[inline(always)]
+    fn excludes(&self, x: u32, y: u32) -> bool {
+        if let Some((mask, width, shift)) = self.mask {
+            if (mask[y as usize * width as usize + x as usize] >> shift) & 0xFF == 0 {
+                return true;
+            }
+        }
+        self.ignore_regions
+            .iter()
+            .any(|&(rx, ry, rw, rh)| x >= rx && y >= ry && x - rx < rw && y - ry < rh)
+    }
+
+    /// Whether [`weigh`](Self::weigh) changes deltas, which the SIMD cold
+    /// pass cannot see.
+    fn reweighs(&self) -> bool {
+        self.alpha_weight > 0.0 || self.silhouette || self.delta_e76
+    }
+
+    /// `delta` grown by the weighted squared alpha difference, keeping its
+    /// sign, or replaced by the visibility flip for a silhouette. Under
+    /// Delta E the YIQ delta only lends its sign.
+    #[inline(always)]
+    fn weigh(&self, delta: f32, pa: u32, pb: u32) -> f32 {
+        if self.silhouette {
+            let flipped = ((pa >> 24) == 0) != ((pb >> 24) == 0);
+            return if flipped { MAX_YIQ_DELTA_F32 } else { 0.0 };
+        }
+        let delta = if self.delta_e76 {
+            // `max_delta` is `MAX_YIQ_DELTA * threshold^2`.
+            let fraction = delta_e76(pa, pb) / 100.0;
+            (MAX_YIQ_DELTA_F32 * fraction * fraction).copysign(delta)
+        } else {
+            delta
+        };
+        if self.alpha_weight == 0.0 {
+            return delta;
+        }
+        let da = (pa >> 24) as f32 - (pb >> 24) as f32;
+        let extra = self.alpha_weight * da * da;
+        if delta < 0.0 {
+            delta - extra
+        } else {
+            delta + extra
+        }
+    }
+
+    /// Whether a pixel already over `max_delta` still counts.
+    #[allow(clippy::too_many_arguments)]
+    #[inline(always)]
+    fn passes(
+        &self,
+        delta: f32,
+        pa: u32,
+        pb: u32,
+        x: u32,
+        y: u32,
+        image1: Pixels<'_>,
+        image2: Pixels<'_>,
+    ) -> bool {
+        if self.excludes(x, y) {
+            return false;
+        }
+        if (pa >> 24) < self.min_alpha as u32 && (pb >> 24) < self.min_alpha as u32 {
+            return false;
+        }
+        if self.min_channel_delta > 0 && max_channel_delta(pa, pb) <= self.min_channel_delta {
+            return false;
+        }
+        if let Some((key, tolerance)) = self.chroma_key {
+            if matches_key(pa, key, tolerance) || matches_key(pb, key, tolerance) {
+                return false;
+            }
+        }
+        match self.edge_max_delta {
+            Some(edge_max_delta) if delta.abs() <= edge_max_delta => {
+                local_contrast_pixels(image1, x, y) < TEXT_EDGE_CONTRAST
+                    && local_contrast_pixels(image2, x, y) < TEXT_EDGE_CONTRAST
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Every RGB channel of `pixel` is within `tolerance` of `key`.
+#[inline(always)]
+fn matches_key(pixel: u32, key: [u8; 3], tolerance: u8) -> bool {
+    let rgba = pixel.to_le_bytes();
+    (0..3).all(|c| rgba[c].abs_diff(key[c]) <= tolerance)
+}
+
+/// Running coordinate sums over counted pixels, for `track_spatial_stats`,
+/// the per-row counts for `track_row_counts`, and the counted pixels for
+/// `track_pixel_mask`.
+#[derive(Default)]
+struct SpatialSums {
+    width: u32,
+    count: u64,
+    sum_x: f64,
+    sum_y: f64,
+    sum_sq: f64,
+    rows: Option<Vec<u32>>,
+    mask: Option<Vec<bool>>,
+}
+
+impl SpatialSums {
+    #[inline(always)]
+    fn add(&mut self, x: u32, y: u32) {
+        if let Some(ref mut rows) = self.rows {
+            rows[y as usize] += 1;
+        }
+        if let Some(ref mut mask) = self.mask {
+            mask[y as usize * self.width as usize + x as usize] = true;
+        }
+        let (x, y) = (x as f64, y as f64);
+        self.count += 1;
+        self.sum_x += x;
+        self.sum_y += y;
+        self.sum_sq += x * x + y * y;
+    }
+
+    /// Centroid and spread (RMS distance from the centroid), or `None`
+    /// when nothing was counted.
+    fn finish(&self) -> Option<((f32, f32), f32)> {
+        if self.count == 0 {
+            return None;
+        }
+        let n = self.count as f64;
+        let (mean_x, mean_y) = (self.sum_x / n, self.sum_y / n);
+        let variance = (self.sum_sq / n - mean_x * mean_x - mean_y * mean_y).max(0.0);
+        Some(((mean_x as f32, mean_y as f32), variance.sqrt() as f32))
+    }
+}
+
+/// Largest absolute difference across the four RGBA channels.
+#[inline(always)]
+fn max_channel_delta(pixel_a: u32, pixel_b: u32) -> u8 {
+    let (a, b) = (pixel_a.to_le_bytes(), pixel_b.to_le_bytes());
+    (0..4).map(|c| a[c].abs_diff(b[c])).max().unwrap_or(0)
+}
+
+#[inline(always)]
+fn compute_gray_pixel_f32(pixel: u32, alpha_scaled: f32) -> u8 {
+    let r = (pixel & 0xFF) as f32;
+    let g = ((pixel >> 8) & 0xFF) as f32;
+    let b = ((pixel >> 16) & 0xFF) as f32;
+    let a = ((pixel >> 24) & 0xFF) as f32;
+
+    let luminance = r * YIQ_Y_F32[0] + g * YIQ_Y_F32[1] + b * YIQ_Y_F32[2];
+    let value = 255.0f32 + (luminance - 255.0f32) * alpha_scaled * a;
+
+    value.clamp(0.0f32, 255.0f32) as u8
+}
+
+/// An opaque pixel with every color channel at `gray`.
+#[inline(always)]
+pub(crate) fn pack_gray_pixel(gray: u8) -> u32 {
+    (gray as u32) | ((gray as u32) << 8) | ((gray as u32) << 16) | 0xFF000000
+}
+
+#[inline(always)]
+fn pack_color_pixel(color: &[u8; 3]) -> u32 {
+    (color[0] as u32) | ((color[1] as u32) << 8) | ((color[2] as u32) << 16) | 0xFF000000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aligned::AlignedBytes;
+    use crate::colormap::Colormap;
+    use crate::cvd::CvdType;
+    use crate::types::UNINIT_POISON;
+    use crate::yiq::pack_pixel;
+
+    fn create_solid_image(width: u32, height: u32, color: u32) -> Image {
+        let mut img = Image::new(width, height);
+        for pixel in img.as_u32_mut() {
+            *pixel = color;
+        }
+        img
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let img1 = create_solid_image(100, 100, pack_pixel(255, 255, 255, 255));
+        let img2 = create_solid_image(100, 100, pack_pixel(255, 255, 255, 255));
+        let result = diff(&img1, &img2, None, &DiffOptions::default()).unwrap();
+        assert_eq!(result.diff_count, 0);
+        assert!(result.identical);
+    }
+
+    #[test]
+    fn test_completely_different() {
+        let img1 = create_solid_image(100, 100, pack_pixel(0, 0, 0, 255));
+        let img2 = create_solid_image(100, 100, pack_pixel(255, 255, 255, 255));
+        let options = DiffOptions {
+            include_aa: true,
+            ..Default::default()
+        };
+        let result = diff(&img1, &img2, None, &options).unwrap();
+        assert_eq!(result.diff_count, 10000);
+        assert!(!result.identical);
+    }
+
+    #[test]
+    fn test_size_mismatch() {
+        let img1 = create_solid_image(100, 100, pack_pixel(0, 0, 0, 255));
+        let img2 = create_solid_image(50, 50, pack_pixel(0, 0, 0, 255));
+        let result = diff(&img1, &img2, None, &DiffOptions::default());
+        assert!(matches!(result, Err(DiffError::SizeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_aa_excluded_from_count() {
+        let img1 = create_solid_image(10, 10, pack_pixel(100, 100, 100, 255));
+        let mut img2 = create_solid_image(10, 10, pack_pixel(100, 100, 100, 255));
+
+        img2.as_u32_mut()[0] = pack_pixel(100, 100, 104, 255);
+        img2.as_u32_mut()[1] = pack_pixel(100, 101, 100, 255);
+
+        let options_no_aa = DiffOptions {
+            include_aa: false,
+            threshold: 0.1,
+            ..Default::default()
+        };
+
+        let result = diff(&img1, &img2, None, &options_no_aa).unwrap();
+        assert!(
+            result.diff_count < 2,
+            "AA pixels should be excluded from count, got {}",
+            result.diff_count
+        );
+    }
+
+    #[test]
+    fn test_no_aa_vs_aa_difference() {
+        let img1 = create_solid_image(10, 10, pack_pixel(0, 0, 0, 255));
+        let img2 = create_solid_image(10, 10, pack_pixel(255, 255, 255, 255));
+
+        let options_with_aa = DiffOptions {
+            include_aa: true,
+            threshold: 0.1,
+            ..Default::default()
+        };
+
+        let options_without_aa = DiffOptions {
+            include_aa: false,
+            threshold: 0.1,
+            ..Default::default()
+        };
+
+        let result_with = diff(&img1, &img2, None, &options_with_aa).unwrap();
+        let result_without = diff(&img1, &img2, None, &options_without_aa).unwrap();
+
+        assert_eq!(
+            result_with.diff_count,
+            result_without.diff_count,
+            "With same threshold, include_aa should not affect diff count (it only affects output coloring)"
+        );
+    }
+
+    #[test]
+    fn test_background_source_selects_gray_input() {
+        // 100 vs 120 gray is below the default threshold, so pixel (0, 0) is
+        // drawn as background; pixel (5, 5) is a real change.
+        let img1 = create_solid_image(10, 10, pack_pixel(100, 100, 100, 255));
+        let mut img2 = create_solid_image(10, 10, pack_pixel(120, 120, 120, 255));
+        img2.set_pixel(5, 5, pack_pixel(255, 0, 0, 255));
+
+        let background_gray = |source: BackgroundSource| {
+            let mut output = Image::new(10, 10);
+            let options = DiffOptions {
+                alpha: 1.0,
+                background_source: source,
+                ..Default::default()
+            };
+            let result = diff(&img1, &img2, Some(&mut output), &options).unwrap();
+            assert_eq!(result.diff_count, 1);
+            assert_eq!(output.get_pixel(5, 5), pack_pixel(255, 0, 0, 255));
+            output.data[0] as i32
+        };
+
+        assert!((background_gray(BackgroundSource::Baseline) - 100).abs() <= 1);
+        assert!((background_gray(BackgroundSource::Candidate) - 120).abs() <= 1);
+    }
+
+    #[test]
+    fn test_diff_with_painter_sees_caller_orientation() {
+        let gray = pack_pixel(128, 128, 128, 255);
+        let black = pack_pixel(0, 0, 0, 255);
+        let white = pack_pixel(255, 255, 255, 255);
+        let img1 = create_solid_image(10, 10, gray);
+        let mut img2 = create_solid_image(10, 10, gray);
+        img2.set_pixel(2, 2, black);
+        img2.set_pixel(7, 7, white);
+
+        for source in [BackgroundSource::Baseline, BackgroundSource::Candidate] {
+            let mut output = Image::new(10, 10);
+            let options = DiffOptions {
+                background_source: source,
+                ..Default::default()
+            };
+            let result = diff_with_painter(
+                &img1,
+                &img2,
+                Some(&mut output),
+                &options,
+                |delta, pixel_a, pixel_b| {
+                    assert_eq!(pixel_a, gray);
+                    assert_ne!(pixel_b, gray);
+                    if delta < 0.0 {
+                        pack_pixel(0, 0, 255, 255)
+                    } else {
+                        pack_pixel(0, 255, 0, 255)
+                    }
+                },
+            )
+            .unwrap();
+
+            assert_eq!(result.diff_count, 2);
+            assert_eq!(output.get_pixel(2, 2), pack_pixel(0, 0, 255, 255));
+            assert_eq!(output.get_pixel(7, 7), pack_pixel(0, 255, 0, 255));
+        }
+    }
+
+    #[test]
+    fn test_weighted_diff_score() {
+        let black = pack_pixel(0, 0, 0, 255);
+        let white = pack_pixel(255, 255, 255, 255);
+        let img1 = create_solid_image(10, 10, black);
+        let options = DiffOptions {
+            include_aa: true,
+            ..Default::default()
+        };
+
+        assert_eq!(weighted_diff_score(&img1, &img1, &options).unwrap(), 0.0);
+
+        let all_white = create_solid_image(10, 10, white);
+        let full = weighted_diff_score(&img1, &all_white, &options).unwrap();
+        // Black/white is the largest luma-only delta, just under the overall
+        // YIQ maximum that chroma contributes to.
+        assert!(full > 0.9 && full <= 1.0, "{full}");
+
+        // Same changed-pixel count, smaller deltas, lower score.
+        let dark_gray = create_solid_image(10, 10, pack_pixel(60, 60, 60, 255));
+        let partial = weighted_diff_score(&img1, &dark_gray, &options).unwrap();
+        assert_eq!(
+            diff(&img1, &dark_gray, None, &options).unwrap().diff_count,
+            100
+        );
+        assert!(partial > 0.0 && partial < full / 4.0, "{partial}");
+
+        // How the diff image would be drawn does not change the score.
+        for output_mode in [
+            OutputMode::CandidateOnChanged,
+            OutputMode::Posterized { levels: 4 },
+        ] {
+            let drawn = DiffOptions {
+                output_mode,
+                crop_output_to_changes: Some(2),
+                ..options.clone()
+            };
+            assert_eq!(
+                weighted_diff_score(&img1, &all_white, &drawn).unwrap(),
+                full
+            );
+        }
+    }
+
+    #[test]
+    fn test_blur_ignores_salt_and_pepper_noise() {
+        let gray = pack_pixel(128, 128, 128, 255);
+        let img1 = create_solid_image(64, 64, gray);
+        let mut noisy = create_solid_image(64, 64, gray);
+        let mut state = 7u32;
+        for _ in 0..80 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let (x, y) = (state % 64, (state >> 8) % 64);
+            let value = if state & 1 == 0 { 0 } else { 255 };
+            noisy.set_pixel(x, y, pack_pixel(value, value, value, 255));
+        }
+        let blurred = DiffOptions {
+            blur_radius: Some(2.0),
+            ..Default::default()
+        };
+
+        assert!(
+            diff(&img1, &noisy, None, &DiffOptions::default())
+                .unwrap()
+                .diff_count
+                > 0
+        );
+        assert!(diff(&img1, &noisy, None, &blurred).unwrap().identical);
+
+        let mut block = create_solid_image(64, 64, gray);
+        for y in 20..44 {
+            for x in 20..44 {
+                block.set_pixel(x, y, pack_pixel(255, 255, 255, 255));
+            }
+        }
+        assert!(diff(&img1, &block, None, &blurred).unwrap().diff_count > 100);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_cold_pass_matches_serial() {
+        let mut fixtures = diff_fixtures();
+        // Tall enough for many block rows, sparse enough to leave gaps.
+        let tall = noise_image(40, 900, 11, true);
+        let mut tall_changed = noise_image(40, 900, 11, true);
+        for y in (0..900).step_by(37) {
+            tall_changed.set_pixel(y % 40, y, 0xFF00_00FF);
+        }
+        fixtures.push((tall, tall_changed));
+
+        for (img1, img2) in &fixtures {
+            let (block_size, _, _) = block_grid(img1.width, img1.height);
+            let scan = |parallel: bool| {
+                let mut blocks = Vec::new();
+                find_changed_blocks(
+                    img1.as_u32(),
+                    img2.as_u32(),
+                    img1.width,
+                    img1.height,
+                    block_size,
+                    threshold_to_max_delta_f32(0.1),
+                    #[cfg(target_arch = "x86_64")]
+                    X86Features::detect(),
+                    parallel,
+                    &mut blocks,
+                );
+                blocks
+            };
+            assert_eq!(scan(true), scan(false));
+        }
+    }
+
+    /// Deterministic xorshift noise, so fixtures don't need a rand dependency.
+    fn noise_image(width: u32, height: u32, seed: u32, opaque: bool) -> Image {
+        let mut state = seed.max(1);
+        let mut img = Image::new(width, height);
+        for pixel in img.as_u32_mut() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            *pixel = if opaque { state | 0xFF000000 } else { state };
+        }
+        img
+    }
+
+    /// Pairs exercising solid regions, sparse noise, transparency and sizes
+    /// that don't divide evenly into blocks.
+    fn diff_fixtures() -> Vec<(Image, Image)> {
+        let mut fixtures = Vec::new();
+
+        let solid = create_solid_image(64, 64, pack_pixel(200, 200, 200, 255));
+        let mut rect = create_solid_image(64, 64, pack_pixel(200, 200, 200, 255));
+        for y in 10..20 {
+            for x in 30..50 {
+                rect.set_pixel(x, y, pack_pixel(20, 40, 200, 255));
+            }
+        }
+        fixtures.push((solid, rect));
+
+        let base = noise_image(97, 61, 7, true);
+        let mut sparse = noise_image(97, 61, 7, true);
+        for (i, pixel) in sparse.as_u32_mut().iter_mut().enumerate() {
+            if i % 53 == 0 {
+                *pixel ^= 0x00FF00FF;
+            }
+        }
+        fixtures.push((base, sparse));
+
+        fixtures.push((noise_image(33, 17, 3, false), noise_image(33, 17, 4, false)));
+        fixtures.push((noise_image(256, 9, 11, true), noise_image(256, 9, 12, true)));
+
+        fixtures
+    }
+
+    #[test]
+    fn test_single_pass_matches_two_pass() {
+        for (img1, img2) in diff_fixtures() {
+            for include_aa in [false, true] {
+                let two_pass = DiffOptions {
+                    include_aa,
+                    ..Default::default()
+                };
+                let single_pass = DiffOptions {
+                    single_pass: true,
+                    ..two_pass.clone()
+                };
+                let expected = diff(&img1, &img2, None, &two_pass).unwrap();
+                let mut output = Image::new(img1.width, img1.height);
+                let actual = diff(&img1, &img2, Some(&mut output), &single_pass).unwrap();
+                assert_eq!(
+                    actual.diff_count, expected.diff_count,
+                    "{}x{} include_aa={}",
+                    img1.width, img1.height, include_aa
+                );
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_scalar_delta_matches_sse_kernel_on_transparent_pixels() {
+        use std::arch::x86_64::*;
+
+        if !is_x86_feature_detected!("sse4.1") {
+            return;
+        }
+        let img1 = noise_image(64, 64, 21, false);
+        let img2 = noise_image(64, 64, 22, false);
+        for (pa, pb) in img1
+            .as_u32()
+            .chunks_exact(4)
+            .zip(img2.as_u32().chunks_exact(4))
+        {
+            let mut lanes = [0.0f32; 4];
+            unsafe {
+                let va = _mm_loadu_si128(pa.as_ptr() as *const __m128i);
+                let vb = _mm_loadu_si128(pb.as_ptr() as *const __m128i);
+                let deltas = yiq_delta_4_sse_signed(va, vb, _mm_set1_epi32(0xFF), _mm_setzero_ps());
+                _mm_storeu_ps(lanes.as_mut_ptr(), deltas);
+            }
+            for i in 0..4 {
+                assert_eq!(
+                    lanes[i].to_bits(),
+                    color_delta_f32(pa[i], pb[i]).to_bits(),
+                    "{:#010x} vs {:#010x}",
+                    pa[i],
+                    pb[i]
+                );
+            }
+        }
+    }
+
+    /// Dark anti-aliased discs on white, centered `offset` pixels from the
+    /// top-left of a `size`x`size` canvas.
+    fn aa_discs(size: u32, offset: f32) -> Image {
+        let mut img = Image::new(size, size);
+        for y in 0..size {
+            for x in 0..size {
+                let mut coverage = 0.0f32;
+                for (cx, cy, r) in [(20.5, 22.0, 13.3), (47.0, 41.7, 17.9), (30.2, 60.1, 6.4)] {
+                    let dx = x as f32 + 0.5 - (cx + offset);
+                    let dy = y as f32 + 0.5 - (cy + offset);
+                    let edge = r - (dx * dx + dy * dy).sqrt() + 0.5;
+                    coverage = coverage.max(edge.clamp(0.0, 1.0));
+                }
+                let v = (255.0 - coverage * 215.0).round() as u32;
+                img.set_pixel(x, y, 0xFF00_0000 | v << 16 | (v / 2 + 100) << 8 | v);
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn test_aa_classification_ignores_block_partitioning() {
+        // Half-pixel shifted AA edges cross many block seams. The AA check
+        // reads neighbors from the whole image, so neither a single block
+        // nor a grid shifted by padding the canvas may change the count.
+        let base1 = aa_discs(80, 0.0);
+        let base2 = aa_discs(80, 0.5);
+        let shifted1 = aa_discs(91, 7.0);
+        let shifted2 = aa_discs(91, 7.5);
+        for include_aa in [false, true] {
+            let two_pass = DiffOptions {
+                include_aa,
+                ..Default::default()
+            };
+            let single_pass = DiffOptions {
+                single_pass: true,
+                ..two_pass.clone()
+            };
+            let mut blocked_out = Image::new(80, 80);
+            let blocked = diff(&base1, &base2, Some(&mut blocked_out), &two_pass).unwrap();
+            let mut whole_out = Image::new(80, 80);
+            let whole = diff(&base1, &base2, Some(&mut whole_out), &single_pass).unwrap();
+            let shifted = diff(&shifted1, &shifted2, None, &two_pass).unwrap();
+
+            assert!(blocked.diff_count > 0);
+            assert_eq!(whole.diff_count, blocked.diff_count);
+            assert_eq!(shifted.diff_count, blocked.diff_count);
+            assert!(whole_out.data == blocked_out.data);
+        }
+        let without_aa = diff(&base1, &base2, None, &DiffOptions::default()).unwrap();
+        let all = DiffOptions {
+            include_aa: true,
+            ..Default::default()
+        };
+        assert!(without_aa.diff_count < diff(&base1, &base2, None, &all).unwrap().diff_count);
+    }
+
+    #[test]
+    fn test_passes_agree_on_semi_transparent_content() {
+        // Semi-transparent noise at a width where the two-pass block grid and
+        // the single whole-image block split rows into SIMD lanes and scalar
+        // remainders differently, across a sweep of thresholds so some
+        // pixels sit right at the cutoff.
+        let img1 = noise_image(61, 23, 5, false);
+        let mut img2 = noise_image(61, 23, 5, false);
+        for (i, pixel) in img2.as_u32_mut().iter_mut().enumerate() {
+            *pixel = pixel.wrapping_add((i as u32 % 7) * 0x0101_0101);
+        }
+        for step in 1..40 {
+            let two_pass = DiffOptions {
+                threshold: step as f64 * 0.005,
+                include_aa: true,
+                ..Default::default()
+            };
+            let single_pass = DiffOptions {
+                single_pass: true,
+                ..two_pass.clone()
+            };
+            let expected = diff(&img1, &img2, None, &two_pass).unwrap();
+            let actual = diff(&img1, &img2, None, &single_pass).unwrap();
+            assert_eq!(
+                actual.diff_count, expected.diff_count,
+                "threshold {}",
+                two_pass.threshold
+            );
+        }
+    }
+
+    #[test]
+    fn test_zero_sized_images() {
+        for (width, height) in [(0, 7), (7, 0), (0, 0)] {
+            let img1 = Image::new(width, height);
+            let img2 = Image::new(width, height);
+            let mut output = Image::new(width, height);
+            let options = DiffOptions {
+                blur_radius: Some(1.0),
+                ..Default::default()
+            };
+
+            let result = diff(&img1, &img2, Some(&mut output), &options).unwrap();
+            assert_eq!(result.diff_count, 0);
+            assert_eq!(result.diff_percentage, 0.0);
+            assert!(result.identical);
+            assert_eq!(weighted_diff_score(&img1, &img2, &options).unwrap(), 0.0);
+            assert_eq!(calculate_block_size(width, height), 8);
+        }
+
+        // A zero dimension on one side only is still a size mismatch.
+        assert!(matches!(
+            diff(
+                &Image::new(0, 7),
+                &Image::new(7, 0),
+                None,
+                &DiffOptions::default()
+            ),
+            Err(DiffError::SizeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_candidate_on_changed_output() {
+        let img1 = noise_image(64, 48, 9, true);
+        let mut img2 = Image::new(64, 48);
+        img2.data.copy_from_slice(&img1.data);
+        for y in 10..20 {
+            for x in 30..40 {
+                img2.set_pixel(x, y, pack_pixel(255, 0, (x * 5) as u8, 255));
+            }
+        }
+
+        // A mask-mode diff marks exactly the counted pixels.
+        let mask_options = DiffOptions {
+            diff_mask: true,
+            include_aa: true,
+            ..Default::default()
+        };
+        let mut mask = Image::new_transparent(64, 48);
+        let expected = diff(&img1, &img2, Some(&mut mask), &mask_options).unwrap();
+        assert!(expected.diff_count > 90);
+
+        for background_source in [BackgroundSource::Baseline, BackgroundSource::Candidate] {
+            let options = DiffOptions {
+                output_mode: OutputMode::CandidateOnChanged,
+                include_aa: true,
+                background_source,
+                ..Default::default()
+            };
+            let mut output = Image::new_uninit(64, 48);
+            let result = diff(&img1, &img2, Some(&mut output), &options).unwrap();
+            assert_eq!(result.diff_count, expected.diff_count);
+            for y in 0..48 {
+                for x in 0..64 {
+                    let source = if mask.get_pixel(x, y) != 0 {
+                        &img2
+                    } else {
+                        &img1
+                    };
+                    assert_eq!(output.get_pixel(x, y), source.get_pixel(x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_mode_ignores_edge_jitter() {
+        // A black stroke with 2px gray anti-aliasing bands on white. The
+        // bands are wide enough that the AA detector does not claim them.
+        let stroke = |edge_gray: u8, extra_stroke: bool| {
+            let mut img = create_solid_image(48, 24, pack_pixel(255, 255, 255, 255));
+            for y in 4..20 {
+                for x in 8..16 {
+                    let v = if (10..14).contains(&x) { 0 } else { edge_gray };
+                    img.set_pixel(x, y, pack_pixel(v, v, v, 255));
+                }
+                if extra_stroke {
+                    for x in 30..34 {
+                        img.set_pixel(x, y, pack_pixel(0, 0, 0, 255));
+                    }
+                }
+            }
+            img
+        };
+        let img1 = stroke(128, false);
+        let jittered = stroke(158, false);
+        let changed = stroke(158, true);
+
+        let strict = diff(&img1, &jittered, None, &DiffOptions::default()).unwrap();
+        assert_eq!(strict.diff_count, 64);
+
+        let text = DiffOptions {
+            text_mode: true,
+            ..Default::default()
+        };
+        assert!(diff(&img1, &jittered, None, &text).unwrap().identical);
+        assert_eq!(diff(&img1, &changed, None, &text).unwrap().diff_count, 64);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_force_backend() {
+        let img1 = noise_image(83, 57, 11, false);
+        let img2 = noise_image(83, 57, 12, false);
+        let mut expected_out = Image::new(83, 57);
+        let expected = diff(
+            &img1,
+            &img2,
+            Some(&mut expected_out),
+            &DiffOptions::default(),
+        )
+        .unwrap();
+
+        let detected = X86Features::detect();
+        let mut backends = vec![SimdBackend::Scalar];
+        if detected.has_sse41 {
+            backends.push(SimdBackend::Sse41);
+        }
+        if detected.has_avx2_fma {
+            backends.push(SimdBackend::Avx2);
+        }
+        assert_eq!(backends.last(), Some(&detected_backend()));
+        for backend in backends {
+            assert_eq!(
+                X86Features::select(Some(backend), CompatMode::Native)
+                    .unwrap()
+                    .backend(),
+                backend
+            );
+            let options = DiffOptions {
+                force_backend: Some(backend),
+                ..Default::default()
+            };
+            let mut out = Image::new(83, 57);
+            let result = diff(&img1, &img2, Some(&mut out), &options).unwrap();
+            assert_eq!(result.diff_count, expected.diff_count, "{backend:?}");
+            assert!(out.data == expected_out.data, "{backend:?}");
+        }
+
+        for backend in [SimdBackend::Avx512, SimdBackend::Neon] {
+            let options = DiffOptions {
+                force_backend: Some(backend),
+                ..Default::default()
+            };
+            assert!(matches!(
+                diff(&img1, &img2, None, &options),
+                Err(DiffError::UnsupportedBackend(b)) if b == backend
+            ));
+        }
+    }
+
+    #[test]
+    fn test_diff_u32_matches_diff_and_reuses_scratch() {
+        let mut scratch = Vec::new();
+        for seed in [13, 15] {
+            let img1 = noise_image(71, 45, seed, false);
+            let img2 = noise_image(71, 45, seed + 1, false);
+            let mut expected_out = Image::new(71, 45);
+            let expected = diff(
+                &img1,
+                &img2,
+                Some(&mut expected_out),
+                &DiffOptions::default(),
+            )
+            .unwrap();
+
+            let mut out = vec![0u32; 71 * 45];
+            let result = diff_u32(
+                img1.as_u32(),
+                img2.as_u32(),
+                71,
+                45,
+                Some(&mut out),
+                &mut scratch,
+                &DiffOptions::default(),
+            )
+            .unwrap();
+            assert_eq!(result.diff_count, expected.diff_count);
+            assert!(out == expected_out.as_u32());
+        }
+        assert!(!scratch.is_empty());
+
+        let short = vec![0u32; 10];
+        assert!(matches!(
+            diff_u32(
+                &short,
+                &short,
+                4,
+                3,
+                None,
+                &mut scratch,
+                &DiffOptions::default()
+            ),
+            Err(DiffError::InvalidDataSize {
+                expected: 48,
+                actual: 40
             })
-            .sum()
-    };
+        ));
+    }
+
+    #[test]
+    fn test_progressive_reject() {
+        let progressive = DiffOptions {
+            progressive_reject: true,
+            ..Default::default()
+        };
+
+        // Close pair: the coarse pass passes it on, so the count is exact.
+        let img1 = noise_image(512, 300, 7, true);
+        let mut img2 = Image::new(512, 300);
+        img2.data.copy_from_slice(&img1.data);
+        for y in 100..120 {
+            for x in 200..230 {
+                img2.set_pixel(x, y, pack_pixel(255, 0, 0, 255));
+            }
+        }
+        let exact = diff(&img1, &img2, None, &DiffOptions::default()).unwrap();
+        let result = diff(&img1, &img2, None, &progressive).unwrap();
+        assert!(exact.diff_count > 0);
+        assert_eq!(result.diff_count, exact.diff_count);
+        assert!(!result.estimated);
+
+        // Clearly different pair: rejected from the coarse pass alone.
+        let black = create_solid_image(512, 300, pack_pixel(0, 0, 0, 255));
+        let white = create_solid_image(512, 300, pack_pixel(255, 255, 255, 255));
+        let result = diff(&black, &white, None, &progressive).unwrap();
+        assert!(!result.identical);
+        assert!(result.estimated);
+        assert_eq!(result.diff_count, 512 * 300);
+        assert!(
+            !diff(&black, &white, None, &DiffOptions::default())
+                .unwrap()
+                .estimated
+        );
+    }
+
+    #[test]
+    fn test_min_channel_delta_ignores_one_lsb_change() {
+        let mut img1 = Image::new(40, 40);
+        for px in img1.data.chunks_exact_mut(4) {
+            px.copy_from_slice(&[128, 128, 128, 255]);
+        }
+        let mut img2 = Image::new(40, 40);
+        img2.data.copy_from_slice(&img1.data);
+        img2.data[(5 * 40 + 5) * 4] = 129;
+        img2.data[(30 * 40 + 30) * 4 + 1] = 126;
+
+        let perceptual = DiffOptions {
+            threshold: 0.0,
+            include_aa: true,
+            ..Default::default()
+        };
+        assert_eq!(diff(&img1, &img2, None, &perceptual).unwrap().diff_count, 2);
+
+        let both = DiffOptions {
+            min_channel_delta: Some(1),
+            ..perceptual.clone()
+        };
+        let mut output = Image::new(40, 40);
+        let result = diff(&img1, &img2, Some(&mut output), &both).unwrap();
+        assert_eq!(result.diff_count, 1);
+        assert_eq!(output.get_pixel(5, 5), output.get_pixel(0, 0));
+        assert_eq!(output.get_pixel(30, 30), pack_color_pixel(&both.diff_color));
+
+        // The scalar fallback with a zero floor agrees with the SIMD kernels.
+        let img1 = noise_image(97, 61, 5, false);
+        let img2 = noise_image(97, 61, 6, false);
+        let zero_floor = DiffOptions {
+            min_channel_delta: Some(0),
+            ..Default::default()
+        };
+        let mut simd_out = Image::new(97, 61);
+        let mut scalar_out = Image::new(97, 61);
+        let simd = diff(&img1, &img2, Some(&mut simd_out), &DiffOptions::default()).unwrap();
+        let scalar = diff(&img1, &img2, Some(&mut scalar_out), &zero_floor).unwrap();
+        assert_eq!(simd.diff_count, scalar.diff_count);
+        assert!(simd_out.data == scalar_out.data);
+    }
+
+    #[test]
+    fn test_block_grid() {
+        assert_eq!(block_grid(100, 100), (16, 7, 7));
+        assert_eq!(block_grid(1920, 1080), (64, 30, 17));
+        assert_eq!(block_grid(1, 1), (8, 1, 1));
+        assert_eq!(block_grid(0, 0), (8, 0, 0));
+        assert_eq!(calculate_block_size(100_000, 100_000), 128);
+    }
+
+    #[test]
+    fn test_identical_fast_path_fills_gray() {
+        // Odd dimensions so every SIMD width leaves a scalar tail.
+        let mut img = Image::new(37, 13);
+        for (i, pixel) in img.as_u32_mut().iter_mut().enumerate() {
+            *pixel = pack_pixel((i * 7) as u8, (i * 13) as u8, (i * 29) as u8, (i * 3) as u8);
+        }
+        let mut output = Image::new(37, 13);
+        let options = DiffOptions::default();
+
+        let result = diff(&img, &img, Some(&mut output), &options).unwrap();
+        assert!(result.identical);
+
+        let alpha_scaled = options.alpha as f32 / 255.0;
+        for (&src, &out) in img.as_u32().iter().zip(output.as_u32()) {
+            let expected = compute_gray_pixel_f32(src, alpha_scaled);
+            assert_eq!(out, pack_gray_pixel(expected));
+        }
+
+        // A separate buffer with the same pixels takes the same path.
+        let copy = img.crop(0, 0, 37, 13);
+        let mut copy_output = Image::new(37, 13);
+        let result = diff(&img, &copy, Some(&mut copy_output), &options).unwrap();
+        assert!(result.exact_match);
+        assert!(copy_output.data == output.data);
+
+        // Below the threshold the cold pass finds no block, and the output
+        // is drawn the same way rather than left as it was.
+        let mut near = img.crop(0, 0, 37, 13);
+        let pixel = near.get_pixel(20, 6);
+        near.set_pixel(20, 6, pixel ^ 1);
+        let mut near_output = Image::new_uninit(37, 13);
+        let result = diff(&img, &near, Some(&mut near_output), &options).unwrap();
+        assert!(result.identical && !result.exact_match);
+        assert!(near_output.data == output.data);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_are_identical_simd() {
+        let a: Vec<u32> = (0..203u32).map(|i| i.wrapping_mul(0x9E37_79B9)).collect();
+        let detected = X86Features::detect();
+        let mut backends = vec![SimdBackend::Scalar];
+        if detected.has_sse41 {
+            backends.push(SimdBackend::Sse41);
+        }
+        if detected.has_avx2_fma {
+            backends.push(SimdBackend::Avx2);
+        }
+        for backend in backends {
+            let features = X86Features::select(Some(backend), CompatMode::Native).unwrap();
+            assert!(are_identical_simd(&a, &a.clone(), features), "{backend:?}");
+            assert!(!are_identical_simd(&a, &a[1..], features), "{backend:?}");
+            // Inside the first vector, a later one, and the scalar tail.
+            for i in [0, 7, 45, 200, 202] {
+                let mut b = a.clone();
+                b[i] ^= 0x0100_0000;
+                assert!(!are_identical_simd(&a, &b, features), "{backend:?} {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_mask_output_cleared_matches_explicit_clear() {
+        let img1 = noise_image(97, 61, 3, false);
+        let img2 = noise_image(97, 61, 4, false);
+        let options = DiffOptions {
+            diff_mask: true,
+            ..Default::default()
+        };
+
+        let mut cleared_by_diff = Image::new(97, 61);
+        cleared_by_diff.data.fill(0xAB);
+        let expected = diff(&img1, &img2, Some(&mut cleared_by_diff), &options).unwrap();
+
+        let mut fresh = Image::new_transparent(97, 61);
+        let fresh_options = DiffOptions {
+            output_cleared: true,
+            ..options
+        };
+        let actual = diff(&img1, &img2, Some(&mut fresh), &fresh_options).unwrap();
+
+        assert_eq!(actual.diff_count, expected.diff_count);
+        assert!(fresh.data == cleared_by_diff.data);
+    }
+
+    #[test]
+    fn test_exact_match_separates_sub_threshold_changes() {
+        let img1 = noise_image(32, 32, 21, true);
+        let options = DiffOptions::default();
+
+        let mut img2 = noise_image(32, 32, 21, true);
+        let result = diff(&img1, &img2, None, &options).unwrap();
+        assert!(result.identical && result.exact_match);
+
+        img2.data[0] ^= 1;
+        let result = diff(&img1, &img2, None, &options).unwrap();
+        assert!(result.identical);
+        assert!(!result.exact_match);
+
+        let blurred = DiffOptions {
+            blur_radius: Some(1.0),
+            ..options
+        };
+        let result = diff(&img1, &img2, None, &blurred).unwrap();
+        assert!(result.identical);
+        assert!(!result.exact_match);
+    }
+
+    #[test]
+    fn test_crop_output_to_changes() {
+        let img1 = noise_image(300, 200, 31, true);
+        let mut img2 = noise_image(300, 200, 31, true);
+        for y in 120..124 {
+            for x in 150..156 {
+                img2.set_pixel(x, y, !img2.get_pixel(x, y) | 0xff000000);
+            }
+        }
+
+        let mut full = Image::new(300, 200);
+        let expected = diff(&img1, &img2, Some(&mut full), &DiffOptions::default()).unwrap();
+
+        let options = DiffOptions {
+            crop_output_to_changes: Some(10),
+            ..Default::default()
+        };
+        let mut out = Image::new(300, 200);
+        let result = diff(&img1, &img2, Some(&mut out), &options).unwrap();
+        assert_eq!(result.diff_count, expected.diff_count);
+
+        let (block_size, _, _) = block_grid(300, 200);
+        let (x, y) = result.crop_offset.unwrap();
+        assert_eq!(x, (150 / block_size * block_size).saturating_sub(10));
+        assert_eq!(y, (120 / block_size * block_size).saturating_sub(10));
+        assert!(out.width < 300 && out.height < 200);
+        assert!(out.data == full.crop(x, y, out.width, out.height).data);
+
+        let mut out = Image::new(300, 200);
+        let result = diff(&img1, &img1, Some(&mut out), &options).unwrap();
+        assert!(result.crop_offset.is_none());
+        assert_eq!((out.width, out.height), (300, 200));
+    }
+
+    #[test]
+    fn test_skip_output_when_identical() {
+        let img1 = noise_image(40, 30, 41, true);
+        let mut near = noise_image(40, 30, 41, true);
+        near.data[0] ^= 1;
+        let mut changed = noise_image(40, 30, 41, true);
+        changed.set_pixel(5, 5, !changed.get_pixel(5, 5) | 0xFF00_0000);
+
+        for diff_mask in [false, true] {
+            let options = DiffOptions {
+                diff_mask,
+                skip_output_when_identical: true,
+                ..Default::default()
+            };
+            for img2 in [&img1, &near] {
+                let mut out = noise_image(40, 30, 99, true);
+                let result = diff(&img1, img2, Some(&mut out), &options).unwrap();
+                assert!(result.identical);
+                assert!(out.data == noise_image(40, 30, 99, true).data);
+            }
+
+            let mut expected = Image::new(40, 30);
+            let plain = DiffOptions {
+                skip_output_when_identical: false,
+                ..options.clone()
+            };
+            diff(&img1, &changed, Some(&mut expected), &plain).unwrap();
+            let mut out = noise_image(40, 30, 99, true);
+            diff(&img1, &changed, Some(&mut out), &options).unwrap();
+            assert!(out.data == expected.data);
+        }
+    }
+
+    #[test]
+    fn test_spatial_stats_locate_changes() {
+        let img1 = noise_image(120, 80, 51, true);
+        let mut clustered = noise_image(120, 80, 51, true);
+        for y in 30..40 {
+            for x in 60..70 {
+                clustered.set_pixel(x, y, !clustered.get_pixel(x, y) | 0xFF00_0000);
+            }
+        }
+        let mut diffuse = noise_image(120, 80, 51, true);
+        for (x, y) in [(2, 3), (117, 5), (4, 76), (115, 78), (60, 40)] {
+            diffuse.set_pixel(x, y, !diffuse.get_pixel(x, y) | 0xFF00_0000);
+        }
+
+        let options = DiffOptions {
+            track_spatial_stats: true,
+            include_aa: true,
+            ..Default::default()
+        };
+        let plain = diff(&img1, &clustered, None, &DiffOptions::default()).unwrap();
+        assert!(plain.centroid.is_none() && plain.spread.is_none());
+
+        let mut out = Image::new(120, 80);
+        let tight = diff(&img1, &clustered, Some(&mut out), &options).unwrap();
+        let (cx, cy) = tight.centroid.unwrap();
+        assert!((cx - 64.5).abs() < 0.5 && (cy - 34.5).abs() < 0.5);
+        assert!(tight.spread.unwrap() < 5.0);
+
+        let spread_out = diff(&img1, &diffuse, None, &options).unwrap();
+        assert!(spread_out.spread.unwrap() > 30.0);
+
+        let identical = diff(&img1, &img1, None, &options).unwrap();
+        assert!(identical.centroid.is_none());
+    }
+
+    #[test]
+    fn test_chroma_key_masks_placeholder_region() {
+        const MAGENTA: u32 = 0xFFFF_00FF;
+        let mut img1 = noise_image(64, 48, 61, true);
+        let mut img2 = noise_image(64, 48, 62, true);
+        // The baseline marks the dynamic area with a slightly off magenta;
+        // outside it, copy the baseline so only one pixel really changed.
+        for y in 0..48 {
+            for x in 0..64 {
+                if (10..30).contains(&x) && (8..24).contains(&y) {
+                    img1.set_pixel(x, y, MAGENTA - 0x0000_0002);
+                } else {
+                    img2.set_pixel(x, y, img1.get_pixel(x, y));
+                }
+            }
+        }
+        img2.set_pixel(50, 40, !img1.get_pixel(50, 40) | 0xFF00_0000);
+
+        let plain = diff(&img1, &img2, None, &DiffOptions::default()).unwrap();
+        assert!(plain.diff_count > 1);
+
+        let options = DiffOptions {
+            chroma_key: Some([255, 0, 255]),
+            chroma_tolerance: 4,
+            include_aa: true,
+            ..Default::default()
+        };
+        let mut out = Image::new(64, 48);
+        let result = diff(&img1, &img2, Some(&mut out), &options).unwrap();
+        assert_eq!(result.diff_count, 1);
+        let diff_color = pack_color_pixel(&options.diff_color);
+        assert_ne!(out.get_pixel(15, 15), diff_color);
+        assert_eq!(out.get_pixel(50, 40), diff_color);
+
+        let strict = DiffOptions {
+            chroma_tolerance: 0,
+            ..options
+        };
+        assert!(diff(&img1, &img2, None, &strict).unwrap().diff_count > 1);
+    }
+
+    #[test]
+    fn test_min_alpha_skips_invisible_pixels() {
+        let mut img1 = Image::new_transparent(32, 32);
+        let mut img2 = Image::new_transparent(32, 32);
+        for y in 4..12 {
+            for x in 4..12 {
+                // Fading out: red at alpha 8 becomes blue at alpha 3.
+                img1.set_pixel(x, y, 0x0800_00FF);
+                img2.set_pixel(x, y, 0x03FF_0000);
+            }
+        }
+        for x in 20..24 {
+            img2.set_pixel(x, 20, 0xFF00_00FF);
+        }
+
+        let options = DiffOptions {
+            threshold: 0.0,
+            include_aa: true,
+            ..Default::default()
+        };
+        let plain = diff(&img1, &img2, None, &options).unwrap();
+        assert!(plain.diff_count > 4);
+
+        let floored = DiffOptions {
+            min_alpha: 16,
+            ..options
+        };
+        let result = diff(&img1, &img2, None, &floored).unwrap();
+        assert_eq!(result.diff_count, 4);
+    }
+
+    #[test]
+    fn test_heatmap_colors_by_magnitude() {
+        let mut img1 = Image::new(16, 16);
+        img1.data.fill(255);
+        let mut img2 = Image::new(16, 16);
+        img2.data.fill(255);
+        img2.set_pixel(3, 3, 0xFF00_0000); // white to black: the largest delta
+        img2.set_pixel(12, 12, 0xFF90_9090); // white to light gray
+
+        for colormap in [Colormap::Viridis, Colormap::Grayscale] {
+            let options = DiffOptions {
+                output_mode: OutputMode::Heatmap { colormap },
+                include_aa: true,
+                ..Default::default()
+            };
+            let mut out = Image::new(16, 16);
+            let result = diff(&img1, &img2, Some(&mut out), &options).unwrap();
+            assert_eq!(result.diff_count, 2);
+            let (big, small) = (out.get_pixel(3, 3), out.get_pixel(12, 12));
+            assert_ne!(big, small);
+            assert_ne!(small, pack_color_pixel(&colormap.rgb(0.0)));
+            if colormap == Colormap::Grayscale {
+                assert!(big & 0xFF > small & 0xFF);
+            }
+        }
+    }
+
+    #[test]
+    fn test_diff_to_image_matches_caller_buffer() {
+        let img1 = noise_image(40, 30, 7, true);
+        let mut img2 = noise_image(40, 30, 7, true);
+        img2.set_pixel(5, 5, 0xFF00_0000);
+        img2.set_pixel(33, 21, 0xFFFF_FFFF);
+
+        for diff_mask in [false, true] {
+            let options = DiffOptions {
+                diff_mask,
+                ..Default::default()
+            };
+            let mut expected = Image::new(40, 30);
+            let want = diff(&img1, &img2, Some(&mut expected), &options).unwrap();
+            let (result, out) = diff_to_image(&img1, &img2, &options).unwrap();
+            assert_eq!(result.diff_count, want.diff_count);
+            assert!(out.unwrap().data == expected.data);
+        }
+
+        // Nothing differs: the buffer holds what `diff` draws, in every mode.
+        let same = noise_image(40, 30, 7, true);
+        for options in [
+            DiffOptions::default(),
+            DiffOptions {
+                output_mode: OutputMode::Posterized { levels: 4 },
+                ..Default::default()
+            },
+            DiffOptions {
+                background_source: BackgroundSource::Candidate,
+                ..Default::default()
+            },
+            DiffOptions {
+                roi: Some((8, 4, 16, 12)),
+                ..Default::default()
+            },
+        ] {
+            let mut expected = Image::new(40, 30);
+            diff(&img1, &same, Some(&mut expected), &options).unwrap();
+            let (result, out) = diff_to_image(&img1, &same, &options).unwrap();
+            assert!(result.identical);
+            assert!(out.unwrap().data == expected.data);
+        }
+
+        let skip = DiffOptions {
+            skip_output_when_identical: true,
+            ..Default::default()
+        };
+        assert!(diff_to_image(&img1, &same, &skip).unwrap().1.is_none());
+        assert!(diff_to_image(&img1, &img2, &skip).unwrap().1.is_some());
+        assert!(matches!(
+            diff_to_image(&img1, &Image::new(40, 31), &skip),
+            Err(DiffError::SizeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_block_granularity_estimate() {
+        let img1 = noise_image(100, 70, 11, true);
+        let mut img2 = noise_image(100, 70, 11, true);
+        img2.set_pixel(3, 3, img1.get_pixel(3, 3) ^ 0x00FF_FFFF);
+        img2.set_pixel(99, 69, img1.get_pixel(99, 69) ^ 0x00FF_FFFF);
+
+        let options = DiffOptions {
+            block_granularity: true,
+            ..Default::default()
+        };
+        let mut out = Image::new(100, 70);
+        let result = diff(&img1, &img2, Some(&mut out), &options).unwrap();
+        let (block_size, _, _) = block_grid(100, 70);
+        let last = (100 - (99 / block_size) * block_size) * (70 - (69 / block_size) * block_size);
+        assert_eq!(result.changed_blocks, Some(2));
+        assert_eq!(result.diff_count, block_size * block_size + last);
+        let diff_color = pack_color_pixel(&options.diff_color);
+        assert_eq!(out.get_pixel(0, 0), diff_color, "changed block filled");
+        assert_eq!(out.get_pixel(99, 69), diff_color);
+        let gray = out.get_pixel(50, 35);
+        assert_eq!((gray >> 24, gray & 0xFF), (0xFF, (gray >> 8) & 0xFF));
+
+        let exact = diff(&img1, &img2, None, &DiffOptions::default()).unwrap();
+        assert_eq!((exact.diff_count, exact.changed_blocks), (2, None));
+    }
+
+    #[test]
+    fn test_alpha_weight_counts_alpha_only_changes() {
+        let mut img1 = Image::new(16, 16);
+        img1.as_u32_mut().fill(0xFF64_6464);
+        let mut img2 = Image::new(16, 16);
+        img2.as_u32_mut().fill(0xFF64_6464);
+        for y in 5..8 {
+            for x in 5..8 {
+                img2.set_pixel(x, y, 0xE664_6464); // 10% more transparent
+            }
+        }
+
+        let count = |alpha_weight| {
+            let options = DiffOptions {
+                alpha_weight,
+                include_aa: true,
+                ..Default::default()
+            };
+            diff(&img1, &img2, None, &options).unwrap().diff_count
+        };
+        assert_eq!(count(0.0), 0, "the blended luminance change is too small");
+        assert_eq!(count(0.5053), 9);
+    }
+
+    #[test]
+    fn test_diff_normalized_matches_densities() {
+        let small = noise_image(12, 8, 3, true);
+        let large = small.upscale_nearest(2);
+        let result = diff_normalized(&large, &small, &DiffOptions::default()).unwrap();
+        assert!(result.exact_match);
+        assert_eq!(result.normalized_scale, Some(2.0));
+
+        let mut changed = small.upscale_nearest(2);
+        changed.as_u32_mut()[..24].fill(0xFF00_0000);
+        let result = diff_normalized(&small, &changed, &DiffOptions::default()).unwrap();
+        assert!((1..=12).contains(&result.diff_count), "counted at 1x");
+        assert_eq!(result.normalized_scale, Some(2.0));
+
+        let mut flat = Image::new(4, 6);
+        flat.as_u32_mut().fill(0xFF33_6699);
+        let mut flat_large = Image::new(6, 9);
+        flat_large.as_u32_mut().fill(0xFF33_6699);
+        let result = diff_normalized(&flat, &flat_large, &DiffOptions::default()).unwrap();
+        assert_eq!(result.normalized_scale, Some(1.5));
+        assert!(result.identical);
+
+        let same = diff_normalized(&small, &small, &DiffOptions::default()).unwrap();
+        assert_eq!(same.normalized_scale, None);
+        assert!(matches!(
+            diff_normalized(&flat, &Image::new(7, 6), &DiffOptions::default()),
+            Err(DiffError::SizeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_compare_overlap() {
+        let base = noise_image(30, 20, 5, true);
+        let grown = {
+            let mut img = Image::new(30, 28);
+            img.data[..base.data.len()].copy_from_slice(&base.data);
+            img
+        };
+        assert!(matches!(
+            diff(&base, &grown, None, &DiffOptions::default()),
+            Err(DiffError::SizeMismatch { .. })
+        ));
+
+        let options = DiffOptions {
+            compare_overlap: true,
+            ..Default::default()
+        };
+        let mut out = Image::new(30, 28);
+        let result = diff(&base, &grown, Some(&mut out), &options).unwrap();
+        assert!(result.exact_match);
+        assert_eq!(result.overlap_margin, Some((0, 8)));
+        assert_eq!((out.width, out.height), (30, 20));
+
+        let narrow = base.crop(0, 0, 25, 20);
+        let (result, out) = diff_to_image(&narrow, &grown, &options).unwrap();
+        assert_eq!(result.overlap_margin, Some((5, 8)));
+        assert_eq!(result.diff_count, 0);
+        let out = out.unwrap();
+        assert_eq!((out.width, out.height), (25, 20));
+
+        let same = diff(&base, &base, None, &options).unwrap();
+        assert_eq!(same.overlap_margin, None);
+    }
+
+    #[test]
+    fn test_size_mismatch_policy() {
+        let base = noise_image(100, 100, 9, true);
+        let mut tall = Image::new(100, 101);
+        tall.data[..base.data.len()].copy_from_slice(&base.data);
+        for x in 0..100 {
+            let color = if x < 40 { 0xFF00_0000 } else { 0xFFFF_FFFF };
+            tall.set_pixel(x, 100, color);
+        }
+
+        assert!(matches!(
+            diff(&base, &tall, None, &DiffOptions::default()),
+            Err(DiffError::SizeMismatch { .. })
+        ));
+
+        let crop = |count_margin| DiffOptions {
+            size_mismatch: SizeMismatchPolicy::CropToSmaller { count_margin },
+            ..Default::default()
+        };
+        let result = diff(&base, &tall, None, &crop(false)).unwrap();
+        assert_eq!(result.diff_count, 0);
+        assert_eq!(result.overlap_margin, Some((0, 1)));
+
+        let result = diff(&base, &tall, None, &crop(true)).unwrap();
+        assert_eq!(result.diff_count, 100);
+        assert!((result.diff_percentage - 100.0 * 100.0 / 10100.0).abs() < 1e-9);
+        assert!(!result.identical);
+        let (result, _) = diff_to_image(&tall, &base, &crop(true)).unwrap();
+        assert_eq!(result.diff_count, 100);
+
+        // Padding adds a transparent row, which only differs where the
+        // taller image's last row is not white.
+        let options = DiffOptions {
+            size_mismatch: SizeMismatchPolicy::PadToLarger,
+            ..Default::default()
+        };
+        let mut out = Image::new(100, 100);
+        let result = diff(&base, &tall, Some(&mut out), &options).unwrap();
+        assert_eq!(result.diff_count, 40);
+        assert!((result.diff_percentage - 100.0 * 40.0 / 10100.0).abs() < 1e-9);
+        assert_eq!(result.overlap_margin, None);
+        assert_eq!((out.width, out.height), (100, 101));
+        let (result, out) = diff_to_image(&tall, &base, &options).unwrap();
+        assert_eq!(result.diff_count, 40);
+        assert_eq!(out.map(|out| out.height), Some(101));
+    }
+
+    #[test]
+    fn test_compare_against_recorded() {
+        let result = DiffResult::new(30, 1000); // 3% changed
+        assert!(compare_against_recorded(&result, 0.03, 0.0));
+        assert!(compare_against_recorded(&result, 0.02, 0.01));
+        assert!(!compare_against_recorded(&result, 0.02, 0.005));
+        assert!(compare_against_recorded(&DiffResult::exact(1000), 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_assert_diff_matches() {
+        let a = noise_image(40, 30, 1, true);
+        let b = noise_image(40, 30, 2, true);
+        let options = DiffOptions {
+            skip_output_when_identical: true,
+            ..Default::default()
+        };
+        let (_, expected) = diff_to_image(&a, &b, &options).unwrap();
+        let mut expected = expected.unwrap();
+        assert!(assert_diff_matches(&a, &b, &expected, &options).unwrap());
+
+        let (_, gray) = diff_to_image(&a, &a, &DiffOptions::default()).unwrap();
+        assert!(assert_diff_matches(&a, &a, &gray.unwrap(), &options).unwrap());
+
+        expected.data[5] ^= 1;
+        assert!(!assert_diff_matches(&a, &b, &expected, &options).unwrap());
+        assert!(!assert_diff_matches(&a, &b, &Image::new(30, 40), &options).unwrap());
+    }
+
+    #[test]
+    fn test_aa_only_hides_solid_changes() {
+        // A black bar whose right edge gains a half-covered column (edge
+        // jitter), plus an unrelated solid red square.
+        let white = pack_pixel(255, 255, 255, 255);
+        let mut img1 = create_solid_image(30, 20, white);
+        for y in 3..17 {
+            for x in 5..10 {
+                img1.set_pixel(x, y, pack_pixel(0, 0, 0, 255));
+            }
+        }
+        let mut img2 = Image::new(30, 20);
+        img2.data.copy_from_slice(&img1.data);
+        for y in 3..17 {
+            img2.set_pixel(10, y, pack_pixel(128, 128, 128, 255));
+        }
+        for y in 5..9 {
+            for x in 20..24 {
+                img2.set_pixel(x, y, pack_pixel(255, 0, 0, 255));
+            }
+        }
+
+        let full = DiffOptions::default();
+        let aa_only = DiffOptions {
+            output_mode: OutputMode::AaOnly,
+            ..Default::default()
+        };
+        let mut full_out = Image::new(30, 20);
+        let mut aa_out = Image::new(30, 20);
+        let full_result = diff(&img1, &img2, Some(&mut full_out), &full).unwrap();
+        let aa_result = diff(&img1, &img2, Some(&mut aa_out), &aa_only).unwrap();
+        assert_eq!(full_result.diff_count, aa_result.diff_count);
+
+        let aa_color = pack_color_pixel(&full.aa_color);
+        let diff_color = pack_color_pixel(&full.diff_color);
+        assert_eq!(
+            aa_out.get_pixel(10, 10),
+            aa_color,
+            "edge jitter highlighted"
+        );
+        assert_eq!(full_out.get_pixel(21, 6), diff_color);
+        let background = aa_out.get_pixel(21, 6);
+        assert_ne!(background, diff_color, "solid change suppressed");
+        assert_eq!(background & 0xFF, (background >> 8) & 0xFF, "drawn as gray");
+        for (i, (&f, &a)) in full_out.as_u32().iter().zip(aa_out.as_u32()).enumerate() {
+            if f != diff_color {
+                assert_eq!(f, a, "pixel {i} unchanged outside solid changes");
+            }
+        }
+    }
+
+    #[test]
+    fn test_new_uninit_output_fully_written() {
+        let img1 = noise_image(90, 70, 21, true);
+        let mut img2 = noise_image(90, 70, 21, true);
+        for x in 10..30 {
+            img2.set_pixel(x, 40, img1.get_pixel(x, 40) ^ 0x00FF_FFFF);
+        }
+        let poison = u32::from_ne_bytes([UNINIT_POISON; 4]);
+
+        let cases = [
+            DiffOptions::default(),
+            DiffOptions {
+                block_granularity: true,
+                ..Default::default()
+            },
+            DiffOptions {
+                single_pass: true,
+                ..Default::default()
+            },
+            DiffOptions {
+                background_source: BackgroundSource::Candidate,
+                ..Default::default()
+            },
+            DiffOptions {
+                output_mode: OutputMode::CandidateOnChanged,
+                ..Default::default()
+            },
+            DiffOptions {
+                output_mode: OutputMode::AaOnly,
+                ..Default::default()
+            },
+            DiffOptions {
+                diff_mask: true,
+                ..Default::default()
+            },
+            DiffOptions {
+                diff_mask: true,
+                skip_output_when_identical: true,
+                ..Default::default()
+            },
+            DiffOptions {
+                track_spatial_stats: true,
+                ..Default::default()
+            },
+            DiffOptions {
+                min_alpha: 1,
+                ..Default::default()
+            },
+            DiffOptions {
+                blur_radius: Some(1.0),
+                ..Default::default()
+            },
+            DiffOptions {
+                crop_output_to_changes: Some(4),
+                ..Default::default()
+            },
+        ];
+        for options in &cases {
+            let mut out = Image::new(90, 70);
+            out.data.fill(UNINIT_POISON);
+            let result = diff(&img1, &img2, Some(&mut out), options).unwrap();
+            assert!(!result.identical);
+            assert!(
+                out.as_u32().iter().all(|&p| p != poison),
+                "unwritten output pixels with {options:?}"
+            );
+        }
+    }
 
-    Ok(DiffResult::new(diff_count, total_pixels))
-}
+    #[test]
+    fn test_solid_images_fast_path() {
+        let white = pack_pixel(255, 255, 255, 255);
+        let max_delta = threshold_to_max_delta_f32(0.1);
+        let (mut below, mut above) = (false, false);
+        for v in 220..=235u8 {
+            let img1 = create_solid_image(40, 30, white);
+            let img2 = create_solid_image(40, 30, pack_pixel(v, v, v, 255));
+            assert!(img1.is_solid() && img2.is_solid());
+
+            let mut out = Image::new(40, 30);
+            let fast = diff(&img1, &img2, Some(&mut out), &DiffOptions::default()).unwrap();
+            // Spatial stats need the per-pixel passes, so they skip the fast path.
+            let slow_options = DiffOptions {
+                track_spatial_stats: true,
+                ..Default::default()
+            };
+            let mut slow_out = Image::new(40, 30);
+            let slow = diff(&img1, &img2, Some(&mut slow_out), &slow_options).unwrap();
+            assert_eq!(fast.diff_count, slow.diff_count, "gray {v}");
+            let counted =
+                color_delta_f32(img1.get_pixel(0, 0), img2.get_pixel(0, 0)).abs() > max_delta;
+            assert!(out.data == slow_out.data, "gray {v}");
+            if !counted {
+                let gray = out.get_pixel(0, 0);
+                assert_eq!(gray >> 24, 0xFF);
+                assert!(out.as_u32().iter().all(|&p| p == gray));
+            }
+            assert_eq!(fast.diff_count, if counted { 1200 } else { 0 });
+            below |= !counted;
+            above |= counted;
+        }
+        assert!(below && above, "the range straddles the threshold");
+
+        // Posterizing applies to the fast path's output as to the passes'.
+        let img1 = create_solid_image(40, 30, white);
+        let img2 = create_solid_image(40, 30, pack_pixel(100, 100, 100, 255));
+        let posterized = DiffOptions {
+            output_mode: OutputMode::Posterized { levels: 3 },
+            diff_color: [200, 30, 30],
+            ..Default::default()
+        };
+        let mut fast_out = Image::new(40, 30);
+        diff(&img1, &img2, Some(&mut fast_out), &posterized).unwrap();
+        let mut slow_out = Image::new(40, 30);
+        let slow_options = DiffOptions {
+            track_spatial_stats: true,
+            ..posterized.clone()
+        };
+        diff(&img1, &img2, Some(&mut slow_out), &slow_options).unwrap();
+        assert_eq!(fast_out.get_pixel(0, 0), pack_pixel(255, 0, 0, 255));
+        assert!(fast_out.data == slow_out.data);
 
-#[inline(always)]
-fn compute_gray_pixel_f32(pixel: u32, alpha_scaled: f32) -> u8 {
-    let r = (pixel & 0xFF) as f32;
-    let g = ((pixel >> 8) & 0xFF) as f32;
-    let b = ((pixel >> 16) & 0xFF) as f32;
-    let a = ((pixel >> 24) & 0xFF) as f32;
+        assert!(!noise_image(4, 4, 3, true).is_solid());
+        assert!(!Image::new(0, 0).is_solid());
+    }
 
-    let luminance = r * YIQ_Y_F32[0] + g * YIQ_Y_F32[1] + b * YIQ_Y_F32[2];
-    let value = 255.0f32 + (luminance - 255.0f32) * alpha_scaled * a;
+    #[test]
+    fn test_record_changed_blocks() {
+        let img1 = noise_image(100, 70, 5, true);
+        let mut img2 = noise_image(100, 70, 5, true);
+        img2.set_pixel(3, 3, img1.get_pixel(3, 3) ^ 0x00FF_FFFF);
+        img2.set_pixel(99, 69, img1.get_pixel(99, 69) ^ 0x00FF_FFFF);
+        let (_, blocks_x, blocks_y) = block_grid(100, 70);
 
-    value.clamp(0.0f32, 255.0f32) as u8
-}
+        let options = DiffOptions {
+            record_changed_blocks: true,
+            ..Default::default()
+        };
+        let result = diff(&img1, &img2, None, &options).unwrap();
+        assert_eq!(
+            result.changed_block_ids,
+            Some(vec![0, blocks_x * blocks_y - 1])
+        );
+        assert_eq!(
+            diff(&img1, &img2, None, &DiffOptions::default())
+                .unwrap()
+                .changed_block_ids,
+            None
+        );
+        assert_eq!(
+            diff(&img1, &img1, None, &options)
+                .unwrap()
+                .changed_block_ids,
+            Some(vec![])
+        );
 
-#[inline(always)]
-fn pack_gray_pixel(gray: u8) -> u32 {
-    (gray as u32) | ((gray as u32) << 8) | ((gray as u32) << 16) | 0xFF000000
-}
+        let single_pass = DiffOptions {
+            single_pass: true,
+            ..options
+        };
+        let all = diff(&img1, &img2, None, &single_pass).unwrap();
+        assert_eq!(
+            all.changed_block_ids,
+            Some((0..blocks_x * blocks_y).collect())
+        );
+    }
 
-#[inline(always)]
-fn pack_color_pixel(color: &[u8; 3]) -> u32 {
-    (color[0] as u32) | ((color[1] as u32) << 8) | ((color[2] as u32) << 16) | 0xFF000000
-}
+    #[test]
+    fn test_jpeg_artifact_tolerance() {
+        // A smooth reference, and a candidate whose pixels either side of
+        // every 8-pixel seam overshoot in opposite directions.
+        let (width, height) = (64u32, 48u32);
+        let mut reference = Image::new(width, height);
+        let mut candidate = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = 60 + x * 2 + y;
+                reference.set_pixel(x, y, pack_gray_pixel(v as u8));
+                let ring: i32 = match x % 8 {
+                    7 => 36,
+                    0 if x > 0 => -36,
+                    _ => 0,
+                };
+                let c = (v as i32 + ring).min(255) as u8;
+                candidate.set_pixel(x, y, pack_gray_pixel(c));
+            }
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::yiq::pack_pixel;
+        let strict = diff(&reference, &candidate, None, &DiffOptions::default()).unwrap();
+        let tolerant_options = DiffOptions {
+            jpeg_artifact_tolerance: true,
+            ..Default::default()
+        };
+        let tolerant = diff(&reference, &candidate, None, &tolerant_options).unwrap();
+        assert!(strict.diff_count > 0);
+        assert!(tolerant.diff_count < strict.diff_count / 4, "{tolerant:?}");
+        assert!(!tolerant.exact_match);
+    }
 
-    fn create_solid_image(width: u32, height: u32, color: u32) -> Image {
-        let mut img = Image::new(width, height);
-        for pixel in img.as_u32_mut() {
-            *pixel = color;
+    #[test]
+    fn test_row_diff_counts() {
+        let img1 = noise_image(50, 40, 9, true);
+        let mut img2 = noise_image(50, 40, 9, true);
+        for x in 5..25 {
+            img2.set_pixel(x, 12, img1.get_pixel(x, 12) ^ 0x00FF_FFFF);
         }
-        img
+        for y in 30..33 {
+            img2.set_pixel(40, y, img1.get_pixel(40, y) ^ 0x00FF_FFFF);
+        }
+        let options = DiffOptions {
+            track_row_counts: true,
+            include_aa: true,
+            ..Default::default()
+        };
+        let result = diff(&img1, &img2, None, &options).unwrap();
+        let rows = result.row_diff_counts.unwrap();
+        assert_eq!(rows.len(), 40);
+        assert_eq!(rows.iter().sum::<u32>(), result.diff_count);
+        assert_eq!((rows[12], rows[30], rows[32], rows[0]), (20, 1, 1, 0));
+        assert!(result.centroid.is_none(), "spatial stats stay off");
+
+        let identical = diff(&img1, &img1, None, &options).unwrap();
+        assert_eq!(identical.row_diff_counts, Some(vec![0; 40]));
+        let plain = diff(&img1, &img2, None, &DiffOptions::default()).unwrap();
+        assert!(plain.row_diff_counts.is_none());
     }
 
     #[test]
-    fn test_identical_images() {
-        let img1 = create_solid_image(100, 100, pack_pixel(255, 255, 255, 255));
-        let img2 = create_solid_image(100, 100, pack_pixel(255, 255, 255, 255));
-        let result = diff(&img1, &img2, None, &DiffOptions::default()).unwrap();
-        assert_eq!(result.diff_count, 0);
-        assert!(result.identical);
+    fn test_cvd_simulation_merges_confusable_colors() {
+        // Orange against green: obvious normally and to a tritanope, nearly
+        // the same color to a protanope.
+        let img1 = create_solid_image(8, 8, pack_pixel(200, 120, 60, 255));
+        let mut img2 = create_solid_image(8, 8, pack_pixel(200, 120, 60, 255));
+        for x in 0..4 {
+            img2.set_pixel(x, 4, pack_pixel(0, 160, 60, 255));
+        }
+        let count = |cvd_simulation| {
+            let options = DiffOptions {
+                cvd_simulation,
+                include_aa: true,
+                ..Default::default()
+            };
+            diff(&img1, &img2, None, &options).unwrap().diff_count
+        };
+        assert_eq!(count(None), 4);
+        assert_eq!(count(Some(CvdType::Protanopia)), 0);
+        assert_eq!(count(Some(CvdType::Tritanopia)), 4);
     }
 
     #[test]
-    fn test_completely_different() {
-        let img1 = create_solid_image(100, 100, pack_pixel(0, 0, 0, 255));
-        let img2 = create_solid_image(100, 100, pack_pixel(255, 255, 255, 255));
+    fn test_silhouette_only_ignores_colors() {
+        // A round-ish icon on a transparent background.
+        let icon = |color: u32, radius: i32| {
+            let mut img = Image::new(16, 16);
+            for y in 0..16i32 {
+                for x in 0..16i32 {
+                    let inside = (x - 8).pow(2) + (y - 8).pow(2) <= radius * radius;
+                    img.set_pixel(x as u32, y as u32, if inside { color } else { 0 });
+                }
+            }
+            img
+        };
+        let red = icon(pack_pixel(220, 30, 30, 255), 5);
+        let blue = icon(pack_pixel(30, 30, 220, 128), 5);
+        let larger = icon(pack_pixel(220, 30, 30, 255), 6);
+
         let options = DiffOptions {
-            include_aa: true,
+            silhouette_only: true,
             ..Default::default()
         };
-        let result = diff(&img1, &img2, None, &options).unwrap();
-        assert_eq!(result.diff_count, 10000);
-        assert!(!result.identical);
+        assert!(
+            diff(&red, &blue, None, &DiffOptions::default())
+                .unwrap()
+                .diff_count
+                > 0
+        );
+        assert_eq!(diff(&red, &blue, None, &options).unwrap().diff_count, 0);
+
+        let ring = (0..16 * 16)
+            .filter(|&i| (red.as_u32()[i] >> 24 == 0) != (larger.as_u32()[i] >> 24 == 0))
+            .count() as u32;
+        assert!(ring > 0);
+        assert_eq!(
+            diff(&red, &larger, None, &options).unwrap().diff_count,
+            ring
+        );
     }
 
     #[test]
-    fn test_size_mismatch() {
-        let img1 = create_solid_image(100, 100, pack_pixel(0, 0, 0, 255));
-        let img2 = create_solid_image(50, 50, pack_pixel(0, 0, 0, 255));
-        let result = diff(&img1, &img2, None, &DiffOptions::default());
-        assert!(matches!(result, Err(DiffError::SizeMismatch { .. })));
+    fn test_compat_mode_wasm_matches_scalar_rounding() {
+        // The wasm32 build rounds like `color_delta_f32`, so counting with
+        // it is the cross-check. Translucent noise and thresholds spread
+        // over the delta range put many pixels near the FMA rounding edge.
+        let img1 = noise_image(97, 61, 21, false);
+        let img2 = noise_image(97, 61, 22, false);
+        for threshold in [0.01, 0.05, 0.1, 0.3, 0.6] {
+            let max_delta = threshold_to_max_delta_f32(threshold);
+            let reference = img1
+                .as_u32()
+                .iter()
+                .zip(img2.as_u32())
+                .filter(|&(&pa, &pb)| pa != pb && color_delta_f32(pa, pb).abs() > max_delta)
+                .count() as u32;
+            let options = DiffOptions {
+                threshold,
+                include_aa: true,
+                compat_mode: CompatMode::Wasm,
+                ..Default::default()
+            };
+            let mut out = Image::new(97, 61);
+            let result = diff(&img1, &img2, Some(&mut out), &options).unwrap();
+            assert_eq!(result.diff_count, reference, "threshold {threshold}");
+        }
+
+        let fma = DiffOptions {
+            compat_mode: CompatMode::Wasm,
+            force_backend: Some(detected_backend()),
+            ..Default::default()
+        };
+        if matches!(detected_backend(), SimdBackend::Avx2 | SimdBackend::Neon) {
+            assert!(matches!(
+                diff(&img1, &img2, None, &fma),
+                Err(DiffError::UnsupportedBackend(_))
+            ));
+        }
     }
 
     #[test]
-    fn test_aa_excluded_from_count() {
-        let img1 = create_solid_image(10, 10, pack_pixel(100, 100, 100, 255));
-        let mut img2 = create_solid_image(10, 10, pack_pixel(100, 100, 100, 255));
+    fn test_grid_overlay_draws_over_diff() {
+        let img1 = noise_image(40, 30, 31, true);
+        let mut img2 = noise_image(40, 30, 31, true);
+        img2.set_pixel(12, 7, 0xFF00_0000);
+        let mut expected = Image::new(40, 30);
+        diff(&img1, &img2, Some(&mut expected), &DiffOptions::default()).unwrap();
+        draw_grid(&mut expected, 8, &[0, 128, 255]);
 
-        img2.as_u32_mut()[0] = pack_pixel(100, 100, 104, 255);
-        img2.as_u32_mut()[1] = pack_pixel(100, 101, 100, 255);
+        let options = DiffOptions {
+            grid_overlay: Some(8),
+            ..Default::default()
+        };
+        let mut out = Image::new(40, 30);
+        diff(&img1, &img2, Some(&mut out), &options).unwrap();
+        assert!(out.data == expected.data);
+
+        // No block of an identical pair changed, so neither entry point
+        // draws a grid over its gray background.
+        let mut gray = Image::new(40, 30);
+        diff(&img1, &img1, Some(&mut gray), &DiffOptions::default()).unwrap();
+        let mut plain = Image::new(40, 30);
+        diff(&img1, &img1, Some(&mut plain), &options).unwrap();
+        assert!(plain.data == gray.data);
+        let (_, identical) = diff_to_image(&img1, &img1, &options).unwrap();
+        assert!(identical.unwrap().data == gray.data);
+    }
 
-        let options_no_aa = DiffOptions {
-            include_aa: false,
-            threshold: 0.1,
+    #[test]
+    fn test_track_block_ratios() {
+        // 100x100 has 16 px blocks: fill the first block fully and a quarter
+        // of a block on the bottom edge, which is clipped to 4 rows.
+        let img1 = create_solid_image(100, 100, 0xFFFF_FFFF);
+        let mut img2 = create_solid_image(100, 100, 0xFFFF_FFFF);
+        for y in 0..16 {
+            for x in 0..16 {
+                img2.set_pixel(x, y, 0xFF00_0000);
+            }
+        }
+        for x in 32..40 {
+            for y in 96..98 {
+                img2.set_pixel(x, y, 0xFF00_0000);
+            }
+        }
+        let options = DiffOptions {
+            track_block_ratios: true,
             ..Default::default()
         };
+        let expected = vec![((0, 0, 16, 16), 1.0), ((32, 96, 16, 4), 0.25)];
+        let mut out = Image::new(100, 100);
+        let result = diff(&img1, &img2, Some(&mut out), &options).unwrap();
+        assert_eq!(result.block_ratios, Some(expected.clone()));
+        let counted = diff(&img1, &img2, None, &options).unwrap();
+        assert_eq!(counted.block_ratios, Some(expected));
+
+        let identical = diff(&img1, &img1, None, &options).unwrap();
+        assert_eq!(identical.block_ratios, Some(Vec::new()));
+        let plain = diff(&img1, &img2, None, &DiffOptions::default()).unwrap();
+        assert!(plain.block_ratios.is_none());
+    }
 
-        let result = diff(&img1, &img2, None, &options_no_aa).unwrap();
-        assert!(
-            result.diff_count < 2,
-            "AA pixels should be excluded from count, got {}",
+    #[test]
+    fn test_color_metric_delta_e76_threshold() {
+        let img1 = create_solid_image(8, 8, pack_pixel(128, 128, 128, 255));
+        let mut img2 = create_solid_image(8, 8, pack_pixel(128, 128, 128, 255));
+        for x in 0..5 {
+            img2.set_pixel(x, 3, pack_pixel(140, 140, 140, 255));
+        }
+        let delta_e = delta_e76(img1.get_pixel(0, 3), img2.get_pixel(0, 3));
+        assert!((4.0..6.0).contains(&delta_e), "{delta_e}");
+
+        let count = |threshold| {
+            let options = DiffOptions {
+                threshold,
+                color_metric: ColorMetric::DeltaE76,
+                include_aa: true,
+                ..Default::default()
+            };
+            diff(&img1, &img2, None, &options).unwrap().diff_count
+        };
+        assert_eq!(count(0.04), 5);
+        assert_eq!(count(0.06), 0);
+        assert_eq!(count(0.0), 5);
+    }
+
+    #[test]
+    fn test_too_many_pixels() {
+        // The dimensions alone are rejected, before any pixel is read.
+        let huge = |width, height| Image {
+            data: AlignedBytes::new(),
+            width,
+            height,
+        };
+        let (width, height) = (65_536, 65_537);
+        for result in [
+            diff(
+                &huge(width, height),
+                &huge(width, height),
+                None,
+                &DiffOptions::default(),
+            ),
+            diff_u32(
+                &[],
+                &[],
+                width,
+                height,
+                None,
+                &mut Vec::new(),
+                &DiffOptions::default(),
+            ),
+        ] {
+            assert!(matches!(
+                result,
+                Err(DiffError::TooManyPixels {
+                    width: 65_536,
+                    height: 65_537
+                })
+            ));
+        }
+        assert!(diff_to_image(
+            &huge(width, height),
+            &huge(width, height),
+            &DiffOptions::default()
+        )
+        .is_err());
+        assert!(check_pixel_count(65_536, 65_535).is_ok());
+        assert!(check_pixel_count(u32::MAX, 1).is_ok());
+        assert!(check_pixel_count(u32::MAX, 2).is_err());
+    }
+
+    #[test]
+    fn test_stop_at_first_diff() {
+        let mut pairs = diff_fixtures();
+        // Only anti-aliasing differs: blocks are flagged but nothing counts.
+        pairs.push((aa_discs(80, 0.0), aa_discs(80, 0.5)));
+        let base = noise_image(300, 200, 7, true);
+        let mut last = base.crop(0, 0, 300, 200);
+        last.set_pixel(299, 199, !base.get_pixel(299, 199) | 0xFF00_0000);
+        pairs.push((base, last));
+
+        let stop = DiffOptions {
+            stop_at_first_diff: true,
+            ..Default::default()
+        };
+        for (a, b) in &pairs {
+            for include_aa in [false, true] {
+                let full = diff(
+                    a,
+                    b,
+                    None,
+                    &DiffOptions {
+                        include_aa,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                let early = diff(
+                    a,
+                    b,
+                    None,
+                    &DiffOptions {
+                        include_aa,
+                        ..stop.clone()
+                    },
+                )
+                .unwrap();
+                assert_eq!(early.identical, full.identical);
+                assert!(early.diff_count <= full.diff_count);
+                assert_eq!(early.estimated, !early.identical);
+            }
+        }
+        let (a, b) = pairs.last().unwrap();
+        assert_eq!(diff(a, b, None, &stop).unwrap().diff_count, 1);
+
+        // Two changed patches: only the first is counted, so the result
+        // cannot pass for the full count against a recorded ratio.
+        let a = create_solid_image(256, 256, 0xFFFF_FFFF);
+        let mut b = a.crop(0, 0, 256, 256);
+        for (x0, y0) in [(8, 8), (200, 200)] {
+            for y in y0..y0 + 10 {
+                for x in x0..x0 + 10 {
+                    b.set_pixel(x, y, 0xFF00_0000);
+                }
+            }
+        }
+        let full = diff(&a, &b, None, &DiffOptions::default()).unwrap();
+        let early = diff(&a, &b, None, &stop).unwrap();
+        assert_eq!((full.diff_count, full.estimated), (200, false));
+        assert_eq!((early.diff_count, early.estimated), (100, true));
+
+        // A drawn output needs every block, so the option is ignored.
+        let (a, b) = &pairs[0];
+        let mut output = Image::new(a.width, a.height);
+        let drawn = diff(a, b, Some(&mut output), &stop).unwrap();
+        let full = diff(a, b, None, &DiffOptions::default()).unwrap();
+        assert_eq!(drawn.diff_count, full.diff_count);
+    }
+
+    #[test]
+    fn test_diff_slices_matches_diff() {
+        let (a, b) = diff_fixtures().swap_remove(0);
+        let (width, height) = (a.width, a.height);
+        let mut expected = Image::new(width, height);
+        let full = diff(&a, &b, Some(&mut expected), &DiffOptions::default()).unwrap();
+
+        let mut out = vec![0u8; a.data.len()];
+        let result = diff_slices(
+            &a.data,
+            &b.data,
+            Some(&mut out),
+            width,
+            height,
+            &DiffOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(result.diff_count, full.diff_count);
+        assert!(out == expected.data);
+
+        // One byte into a larger buffer: no longer 4-byte aligned.
+        let shifted = |data: &[u8]| {
+            let mut buffer = vec![0u8; data.len() + 1];
+            buffer[1..].copy_from_slice(data);
+            buffer
+        };
+        let (a1, b1) = (shifted(&a.data), shifted(&b.data));
+        let mut out1 = vec![0u8; a.data.len() + 1];
+        let result = diff_slices(
+            &a1[1..],
+            &b1[1..],
+            Some(&mut out1[1..]),
+            width,
+            height,
+            &DiffOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(result.diff_count, full.diff_count);
+        assert!(out1[1..] == expected.data[..]);
+
+        assert!(matches!(
+            diff_slices(&a.data[4..], &b.data, None, width, height, &DiffOptions::default()),
+            Err(DiffError::InvalidDataSize { expected, actual })
+                if expected == a.data.len() && actual == a.data.len() - 4
+        ));
+    }
+
+    #[test]
+    fn test_track_pixel_mask() {
+        let img1 = noise_image(60, 40, 3, true);
+        let mut img2 = noise_image(60, 40, 3, true);
+        for (x0, y0, w, h) in [(2, 3, 10, 4), (45, 30, 3, 3)] {
+            for y in y0..y0 + h {
+                for x in x0..x0 + w {
+                    img2.set_pixel(x, y, img1.get_pixel(x, y) ^ 0x00FF_FFFF);
+                }
+            }
+        }
+        let options = DiffOptions {
+            track_pixel_mask: true,
+            include_aa: true,
+            ..Default::default()
+        };
+        let result = diff(&img1, &img2, None, &options).unwrap();
+        let mask = result.pixel_mask.unwrap();
+        assert_eq!(
+            mask.iter().filter(|&&m| m).count() as u32,
             result.diff_count
         );
+        assert_eq!(
+            crate::diff_regions(&mask, 60, 40).unwrap(),
+            vec![(2, 3, 10, 4), (45, 30, 3, 3)]
+        );
+
+        let identical = diff(&img1, &img1, None, &options).unwrap();
+        assert_eq!(identical.pixel_mask, Some(vec![false; 60 * 40]));
+        let plain = diff(&img1, &img2, None, &DiffOptions::default()).unwrap();
+        assert!(plain.pixel_mask.is_none());
     }
 
     #[test]
-    fn test_no_aa_vs_aa_difference() {
-        let img1 = create_solid_image(10, 10, pack_pixel(0, 0, 0, 255));
-        let img2 = create_solid_image(10, 10, pack_pixel(255, 255, 255, 255));
+    fn test_ignore_regions() {
+        let img1 = noise_image(64, 48, 5, true);
+        let mut img2 = noise_image(64, 48, 5, true);
+        for y in 20..30 {
+            for x in 30..40 {
+                // Black on light pixels, white on dark ones.
+                let [r, g, b, _] = img1.get_pixel(x, y).to_le_bytes();
+                let light = r as u32 + g as u32 + b as u32 > 382;
+                img2.set_pixel(x, y, if light { 0xFF00_0000 } else { 0xFFFF_FFFF });
+            }
+        }
+        let ignore = |ignore_regions| DiffOptions {
+            ignore_regions,
+            include_aa: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            diff(&img1, &img2, None, &ignore(vec![]))
+                .unwrap()
+                .diff_count,
+            100
+        );
 
-        let options_with_aa = DiffOptions {
+        let options = ignore(vec![(0, 0, 8, 8), (28, 18, 20, 15)]);
+        let (result, out) = diff_to_image(&img1, &img2, &options).unwrap();
+        assert_eq!(result.diff_count, 0);
+        assert!(result.identical && !result.exact_match);
+        // Drawn like an identical pair.
+        let (_, expected) = diff_to_image(&img1, &img1, &DiffOptions::default()).unwrap();
+        assert!(out.unwrap().data == expected.unwrap().data);
+        let mask = diff_to_image(
+            &img1,
+            &img2,
+            &DiffOptions {
+                diff_mask: true,
+                ..options.clone()
+            },
+        )
+        .unwrap()
+        .1
+        .unwrap();
+        assert!(mask.data.iter().all(|&b| b == 0));
+
+        // Only the part inside the rectangle is ignored, and rectangles may
+        // run off the image.
+        let partial = ignore(vec![(35, 0, 100, 100)]);
+        assert_eq!(diff(&img1, &img2, None, &partial).unwrap().diff_count, 50);
+        let candidate = DiffOptions {
+            background_source: BackgroundSource::Candidate,
+            ..partial
+        };
+        assert_eq!(diff(&img1, &img2, None, &candidate).unwrap().diff_count, 50);
+
+        // Rectangles stay in image coordinates under `roi`.
+        let roi = DiffOptions {
+            roi: Some((24, 16, 24, 20)),
+            ..ignore(vec![(35, 0, 100, 100)])
+        };
+        assert_eq!(diff(&img1, &img2, None, &roi).unwrap().diff_count, 50);
+    }
+
+    #[test]
+    fn test_excluded_pixels_leave_neighbors_unchanged() {
+        // A gray pixel right of an excluded black square on white: against
+        // the candidate's real pixels it sits on an anti-aliased edge, so
+        // it never counts, with or without the square excluded.
+        let img1 = create_solid_image(64, 48, 0xFFFF_FFFF);
+        let mut img2 = img1.crop(0, 0, 64, 48);
+        for y in 20..30 {
+            for x in 30..40 {
+                img2.set_pixel(x, y, pack_pixel(0, 0, 0, 255));
+            }
+        }
+        img2.set_pixel(40, 25, pack_pixel(128, 128, 128, 255));
+        let tracked = DiffOptions {
+            track_pixel_mask: true,
+            ..Default::default()
+        };
+        let full = diff(&img1, &img2, None, &tracked).unwrap();
+        let mut expected = full.pixel_mask.unwrap();
+        assert!(!expected[25 * 64 + 40]);
+        for y in 20..30 {
+            for x in 30..40 {
+                expected[y * 64 + x] = false;
+            }
+        }
+        // The same square masked out instead.
+        let mut mask = create_solid_image(64, 48, 0xFFFF_FFFF);
+        for y in 20..30 {
+            for x in 30..40 {
+                mask.set_pixel(x, y, 0);
+            }
+        }
+        let mask = Arc::new(mask);
+        for background_source in [BackgroundSource::Baseline, BackgroundSource::Candidate] {
+            for (ignore_regions, mask) in
+                [(vec![(30, 20, 10, 10)], None), (vec![], Some(mask.clone()))]
+            {
+                let options = DiffOptions {
+                    ignore_regions,
+                    mask,
+                    background_source,
+                    ..tracked.clone()
+                };
+                let result = diff(&img1, &img2, None, &options).unwrap();
+                assert_eq!(result.diff_count, 0);
+                assert!(!result.exact_match);
+                assert!(result.pixel_mask.unwrap() == expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_roi() {
+        let img1 = noise_image(64, 48, 5, true);
+        let mut img2 = noise_image(64, 48, 5, true);
+        let invert = |img: &mut Image, (x0, y0, w, h): (u32, u32, u32, u32)| {
+            for y in y0..y0 + h {
+                for x in x0..x0 + w {
+                    // Black on light pixels, white on dark ones.
+                    let [r, g, b, _] = img1.get_pixel(x, y).to_le_bytes();
+                    let light = r as u32 + g as u32 + b as u32 > 382;
+                    img.set_pixel(x, y, if light { 0xFF00_0000 } else { 0xFFFF_FFFF });
+                }
+            }
+        };
+        invert(&mut img2, (30, 20, 10, 10));
+        invert(&mut img2, (0, 0, 5, 5));
+
+        let options = DiffOptions {
+            roi: Some((24, 16, 24, 20)),
+            include_aa: true,
+            track_spatial_stats: true,
+            track_row_counts: true,
+            ..Default::default()
+        };
+        let (result, out) = diff_to_image(&img1, &img2, &options).unwrap();
+        assert_eq!(result.diff_count, 100);
+        assert!((result.diff_percentage - 100.0 * 100.0 / 480.0).abs() < 1e-9);
+        assert_eq!(result.centroid, Some((34.5, 24.5)));
+        let rows = result.row_diff_counts.unwrap();
+        assert_eq!(rows.len(), 48);
+        assert_eq!((rows[19], rows[20], rows[29], rows[30]), (0, 10, 10, 0));
+
+        // Outside the rectangle is drawn as unchanged; every kernel draws the
+        // same gray, so the two renders match exactly.
+        let ignored = DiffOptions {
+            ignore_regions: vec![(0, 0, 5, 5)],
             include_aa: true,
-            threshold: 0.1,
             ..Default::default()
         };
+        let (_, expected) = diff_to_image(&img1, &img2, &ignored).unwrap();
+        let (out, expected) = (out.unwrap(), expected.unwrap());
+        for y in 0..48 {
+            for x in 0..64 {
+                let (a, b) = (out.get_pixel(x, y), expected.get_pixel(x, y));
+                assert_eq!(a, b, "({x}, {y}): {a:08x} {b:08x}");
+            }
+        }
 
-        let options_without_aa = DiffOptions {
-            include_aa: false,
-            threshold: 0.1,
+        let outside = DiffOptions {
+            roi: Some((0, 20, 20, 20)),
             ..Default::default()
         };
+        assert!(diff(&img1, &img2, None, &outside).unwrap().identical);
 
-        let result_with = diff(&img1, &img2, None, &options_with_aa).unwrap();
-        let result_without = diff(&img1, &img2, None, &options_without_aa).unwrap();
+        for (roi, limit, end) in [((60, 0, 10, 10), 64, 70), ((0, 40, 10, 9), 48, 49)] {
+            let options = DiffOptions {
+                roi: Some(roi),
+                ..Default::default()
+            };
+            assert!(matches!(
+                diff(&img1, &img2, None, &options),
+                Err(DiffError::InvalidDataSize { expected, actual })
+                    if (expected, actual) == (limit, end)
+            ));
+        }
 
-        assert_eq!(
-            result_with.diff_count,
-            result_without.diff_count,
-            "With same threshold, include_aa should not affect diff count (it only affects output coloring)"
-        );
+        // The entry points without an owned output cannot honor it.
+        let options = DiffOptions {
+            roi: Some((0, 0, 8, 8)),
+            ..Default::default()
+        };
+        let rejected = |result: Result<DiffResult, DiffError>| {
+            matches!(result, Err(DiffError::InvalidOption { name: "roi", .. }))
+        };
+        let (a, b) = (img1.as_u32(), img2.as_u32());
+        assert!(rejected(diff_u32(
+            a,
+            b,
+            64,
+            48,
+            None,
+            &mut Vec::new(),
+            &options
+        )));
+        assert!(rejected(diff_slices(
+            &img1.data, &img2.data, None, 64, 48, &options
+        )));
+        assert!(rejected(Differ::new().diff_incremental(
+            &img1,
+            &img2,
+            &[],
+            None,
+            &options
+        )));
+    }
+
+    #[test]
+    fn test_mask_restricts_comparison() {
+        let img1 = create_solid_image(40, 20, pack_pixel(200, 200, 200, 255));
+        let mut img2 = create_solid_image(40, 20, pack_pixel(200, 200, 200, 255));
+        for y in 5..10 {
+            for x in 5..9 {
+                img2.set_pixel(x, y, pack_pixel(0, 0, 0, 255));
+                img2.set_pixel(x + 25, y, pack_pixel(0, 0, 0, 255));
+            }
+        }
+        // White on the left half, black on the right.
+        let mut mask = create_solid_image(40, 20, pack_pixel(0, 0, 0, 255));
+        for y in 0..20 {
+            for x in 0..20 {
+                mask.set_pixel(x, y, pack_pixel(255, 255, 255, 255));
+            }
+        }
+        let options = DiffOptions {
+            mask: Some(Arc::new(mask)),
+            include_aa: true,
+            ..Default::default()
+        };
+        let (result, out) = diff_to_image(&img1, &img2, &options).unwrap();
+        assert_eq!(result.diff_count, 20);
+        assert!((result.diff_percentage - 100.0 * 20.0 / 400.0).abs() < 1e-9);
+        let out = out.unwrap();
+        assert_eq!(out.get_pixel(5, 5), pack_pixel(255, 0, 0, 255));
+        assert_eq!(out.get_pixel(30, 5), out.get_pixel(30, 15), "masked out");
+
+        // Alpha is 255 everywhere, so the whole image is compared.
+        let alpha = DiffOptions {
+            mask_channel: 3,
+            ..options.clone()
+        };
+        assert_eq!(diff(&img1, &img2, None, &alpha).unwrap().diff_count, 40);
+        let bad_channel = DiffOptions {
+            mask_channel: 4,
+            ..options.clone()
+        };
+        assert!(matches!(
+            diff(&img1, &img2, None, &bad_channel),
+            Err(DiffError::InvalidOption {
+                name: "mask_channel",
+                ..
+            })
+        ));
+
+        let small = DiffOptions {
+            mask: Some(Arc::new(Image::new(20, 20))),
+            ..Default::default()
+        };
+        assert!(matches!(
+            diff(&img1, &img2, None, &small),
+            Err(DiffError::SizeMismatch {
+                img2_width: 20,
+                img2_height: 20,
+                ..
+            })
+        ));
     }
 }
@@ -0,0 +1,389 @@
+//! Stateful re-diffing for live previews, where the caller knows which
+//! parts of the candidate changed since the last render.
+
+use super::{
+    block_grid, check_same_size, default_painter, pack_color_pixel, process_hot_block_scalar,
+    reject_roi, PixelGate,
+};
+use crate::types::{DiffError, DiffOptions, DiffResult, Image, OutputMode};
+use crate::yiq::threshold_to_max_delta_f32;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// How far around a changed pixel the anti-aliasing check can look.
+const AA_REACH: u32 = 2;
+
+/// Remembers each block's count from the previous diff so the next one only
+/// re-evaluates blocks the caller marks dirty.
+///
+/// Blocks run on the scalar hot pass with the per-pixel options (threshold,
+/// anti-aliasing, colors, `diff_mask`, the pixel gates). Options that work
+/// on whole images (`roi`, `blur_radius`, a non-`Full` `output_mode`,
+/// `ignore_regions`, `mask`, `crop_output_to_changes`,
+/// `progressive_reject`) fail with [`DiffError::InvalidOption`]. Call
+/// [`reset`](Differ::reset) after changing options.
+#[derive(Debug, Default)]
+pub struct Differ {
+    width: u32,
+    height: u32,
+    block_counts: Vec<u32>,
+}
+
+impl Differ {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget the cached counts, so the next diff evaluates every block.
+    pub fn reset(&mut self) {
+        self.block_counts.clear();
+    }
+
+    /// Diff `image1` against `image2`, re-evaluating only the blocks that
+    /// come within two pixels of a `(x, y, width, height)` rectangle in
+    /// `dirty_rects`, and reusing the cached counts everywhere else.
+    ///
+    /// The first call, and any call after [`reset`](Differ::reset) or a
+    /// size change, evaluates every block. `output` is only drawn where
+    /// blocks are re-evaluated, so pass the same buffer on every call. The
+    /// result never reports [`DiffResult::exact_match`].
+    pub fn diff_incremental(
+        &mut self,
+        image1: &Image,
+        image2: &Image,
+        dirty_rects: &[(u32, u32, u32, u32)],
+        mut output: Option<&mut Image>,
+        options: &DiffOptions,
+    ) -> Result<DiffResult, DiffError> {
+        check_same_size(image1, image2)?;
+        reject_roi(options, "Differ::diff_incremental")?;
+        reject_whole_image_options(options)?;
+        let (width, height) = (image1.width, image1.height);
+        if width == 0 || height == 0 {
+            self.reset();
+            return Ok(DiffResult::exact(0));
+        }
+
+        let (block_size, blocks_x, blocks_y) = block_grid(width, height);
+        let full = (self.width, self.height) != (width, height)
+            || self.block_counts.len() != (blocks_x * blocks_y) as usize;
+        if full {
+            self.width = width;
+            self.height = height;
+            // Keeps the allocation from before a `reset`.
+            self.block_counts.clear();
+            self.block_counts.resize((blocks_x * blocks_y) as usize, 0);
+        }
+
+        let max_delta = threshold_to_max_delta_f32(options.threshold);
+        let include_aa = options.silhouette_only || (options.include_aa && !options.text_mode);
+        let gate = PixelGate::new(options, max_delta).unwrap_or_default();
+        let painter = default_painter(options);
+        let aa_color = pack_color_pixel(&options.aa_color);
+        let draw_background = output.is_some() && !options.diff_mask;
+
+        for by in 0..blocks_y {
+            for bx in 0..blocks_x {
+                let start_x = bx * block_size;
+                let start_y = by * block_size;
+                let end_x = (start_x + block_size).min(width);
+                let end_y = (start_y + block_size).min(height);
+                // Anti-aliasing detection reads up to two pixels around a
+                // change, so a rectangle dirties blocks that close to it too.
+                let dirty = dirty_rects.iter().any(|&(x, y, w, h)| {
+                    x.saturating_sub(AA_REACH) < end_x
+                        && y.saturating_sub(AA_REACH) < end_y
+                        && x.saturating_add(w).saturating_add(AA_REACH) > start_x
+                        && y.saturating_add(h).saturating_add(AA_REACH) > start_y
+                });
+                if !full && !dirty {
+                    continue;
+                }
+
+                let mut out32 = output.as_deref_mut().map(|out| out.as_u32_mut());
+                if options.diff_mask {
+                    if let Some(ref mut out) = out32 {
+                        for y in start_y..end_y {
+                            let row = y as usize * width as usize;
+                            out[row + start_x as usize..row + end_x as usize].fill(0);
+                        }
+                    }
+                }
+                self.block_counts[(by * blocks_x + bx) as usize] = process_hot_block_scalar(
+                    image1.as_u32(),
+                    image2.as_u32(),
+                    out32,
+                    width,
+                    start_x,
+                    start_y,
+                    end_x,
+                    end_y,
+                    max_delta,
+                    include_aa,
+                    draw_background,
+                    &painter,
+                    aa_color,
+                    options.alpha as f32,
+                    image1.pixels(),
+                    image2.pixels(),
+                    gate,
+                    None,
+                );
+            }
+        }
+
+        Ok(DiffResult::new(
+            self.block_counts.iter().sum(),
+            width * height,
+        ))
+    }
+}
+
+/// Shares [`Differ`]s across threads, so concurrent requests reuse their
+/// buffers instead of allocating per request.
+///
+/// [`get`](DifferPool::get) hands out an idle `Differ`, or a new one when all
+/// are in use; dropping the guard resets it and returns it to the pool.
+#[derive(Debug, Default)]
+pub struct DifferPool {
+    idle: Mutex<Vec<Differ>>,
+}
+
+impl DifferPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self) -> PooledDiffer<'_> {
+        let differ = self.lock().pop().unwrap_or_default();
+        PooledDiffer {
+            pool: self,
+            differ: Some(differ),
+        }
+    }
+
+    /// Number of `Differ`s waiting to be reused.
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// The free list holds no invariant a panicking holder could break.
+    fn lock(&self) -> MutexGuard<'_, Vec<Differ>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A [`Differ`] borrowed from a [`DifferPool`]. Starts with no cached
+/// counts, like [`Differ::new`].
+#[derive(Debug)]
+pub struct PooledDiffer<'a> {
+    pool: &'a DifferPool,
+    differ: Option<Differ>,
+}
+
+impl Deref for PooledDiffer<'_> {
+    type Target = Differ;
+
+    fn deref(&self) -> &Differ {
+        self.differ.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledDiffer<'_> {
+    fn deref_mut(&mut self) -> &mut Differ {
+        self.differ.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledDiffer<'_> {
+    fn drop(&mut self) {
+        if let Some(mut differ) = self.differ.take() {
+            // Counts cached for one caller's images are meaningless to the next.
+            differ.reset();
+            self.pool.lock().push(differ);
+        }
+    }
+}
+
+/// Fail on the first option that needs the whole image at once, which a
+/// block-by-block re-diff cannot honor.
+fn reject_whole_image_options(options: &DiffOptions) -> Result<(), DiffError> {
+    let name = if options.blur_radius.is_some() {
+        "blur_radius"
+    } else if options.output_mode != OutputMode::Full {
+        "output_mode"
+    } else if !options.ignore_regions.is_empty() {
+        "ignore_regions"
+    } else if options.mask.is_some() {
+        "mask"
+    } else if options.crop_output_to_changes.is_some() {
+        "crop_output_to_changes"
+    } else if options.progressive_reject {
+        "progressive_reject"
+    } else {
+        return Ok(());
+    };
+    Err(DiffError::InvalidOption {
+        name,
+        detail: "not supported by Differ::diff_incremental; use diff".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{diff, pack_gray_pixel};
+
+    fn gradient(width: u32, height: u32) -> Image {
+        let mut img = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                img.set_pixel(x, y, pack_gray_pixel(((x * 3 + y * 5) % 256) as u8));
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn test_incremental_matches_full_diff() {
+        let base = gradient(150, 90);
+        let mut candidate = gradient(150, 90);
+        for x in 10..20 {
+            candidate.set_pixel(x, 10, 0xFF00_00FF);
+        }
+
+        let options = DiffOptions::default();
+        let mut differ = Differ::new();
+        let mut out = Image::new(150, 90);
+        let first = differ
+            .diff_incremental(&base, &candidate, &[], Some(&mut out), &options)
+            .unwrap();
+        let full = diff(&base, &candidate, None, &options).unwrap();
+        assert!(first.diff_count > 0);
+        assert_eq!(first.diff_count, full.diff_count);
+
+        // Change a second region and fix the first; only those are dirty.
+        for x in 10..20 {
+            candidate.set_pixel(x, 10, base.get_pixel(x, 10));
+        }
+        for y in 60..80 {
+            candidate.set_pixel(120, y, 0xFF00_FF00);
+        }
+        let dirty = [(10, 10, 10, 1), (120, 60, 1, 20)];
+        let second = differ
+            .diff_incremental(&base, &candidate, &dirty, Some(&mut out), &options)
+            .unwrap();
+        let full = diff(&base, &candidate, None, &options).unwrap();
+        assert_eq!(second.diff_count, full.diff_count);
+
+        let mut fresh = Image::new(150, 90);
+        Differ::new()
+            .diff_incremental(&base, &candidate, &[], Some(&mut fresh), &options)
+            .unwrap();
+        assert!(out.data == fresh.data, "dirty blocks are redrawn");
+
+        // Blocks outside the dirty rectangles keep their cached counts.
+        candidate.set_pixel(0, 89, 0xFF00_00FF);
+        let stale = differ
+            .diff_incremental(&base, &candidate, &[], None, &options)
+            .unwrap();
+        assert_eq!(stale.diff_count, second.diff_count);
+    }
+
+    #[test]
+    fn test_incremental_rejects_whole_image_options() {
+        let base = gradient(32, 32);
+        let cases = [
+            (
+                "blur_radius",
+                DiffOptions {
+                    blur_radius: Some(1.0),
+                    ..Default::default()
+                },
+            ),
+            (
+                "output_mode",
+                DiffOptions {
+                    output_mode: OutputMode::CandidateOnChanged,
+                    ..Default::default()
+                },
+            ),
+            (
+                "ignore_regions",
+                DiffOptions {
+                    ignore_regions: vec![(0, 0, 4, 4)],
+                    ..Default::default()
+                },
+            ),
+            (
+                "mask",
+                DiffOptions {
+                    mask: Some(std::sync::Arc::new(Image::new(32, 32))),
+                    ..Default::default()
+                },
+            ),
+            (
+                "crop_output_to_changes",
+                DiffOptions {
+                    crop_output_to_changes: Some(2),
+                    ..Default::default()
+                },
+            ),
+            (
+                "progressive_reject",
+                DiffOptions {
+                    progressive_reject: true,
+                    ..Default::default()
+                },
+            ),
+        ];
+        for (option, options) in cases {
+            let result = Differ::new().diff_incremental(&base, &base, &[], None, &options);
+            assert!(
+                matches!(result, Err(DiffError::InvalidOption { name, .. }) if name == option),
+                "{option} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_pool_shared_across_threads() {
+        let base = gradient(64, 48);
+        let mut candidate = gradient(64, 48);
+        for x in 5..30 {
+            candidate.set_pixel(x, 20, 0xFF00_00FF);
+        }
+        let options = DiffOptions::default();
+        let expected = diff(&base, &candidate, None, &options).unwrap().diff_count;
+
+        let pool = DifferPool::new();
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        let mut differ = pool.get();
+                        let result = differ
+                            .diff_incremental(&base, &candidate, &[], None, &options)
+                            .unwrap();
+                        assert_eq!(result.diff_count, expected);
+                    }
+                });
+            }
+        });
+        assert!((1..=8).contains(&pool.idle()));
+
+        // A returned Differ forgets its counts: with no dirty rectangles the
+        // next holder still evaluates everything.
+        {
+            let mut differ = pool.get();
+            differ
+                .diff_incremental(&base, &base, &[], None, &options)
+                .unwrap();
+        }
+        let result = pool
+            .get()
+            .diff_incremental(&base, &candidate, &[], None, &options)
+            .unwrap();
+        assert_eq!(result.diff_count, expected);
+    }
+}
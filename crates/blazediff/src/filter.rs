@@ -0,0 +1,381 @@
+//! Image preprocessing filters.
+
+use crate::aligned::AlignedBytes;
+use crate::types::{Image, Pixels};
+
+impl Image {
+    /// Separable Gaussian blur with standard deviation `radius`, applied to
+    /// each RGBA channel independently (straight alpha, edges clamped).
+    ///
+    /// Costs one f32 scratch buffer of 16 bytes per pixel plus the returned
+    /// image, and two passes of `2 * ceil(3 * radius) + 1` taps per pixel.
+    /// A non-positive or non-finite `radius` returns an unchanged copy.
+    pub fn gaussian_blur(&self, radius: f32) -> Image {
+        self.pixels().gaussian_blur(radius)
+    }
+
+    /// Shrink by `factor` in each dimension, averaging every `factor` x
+    /// `factor` box per channel (rounded). Edge boxes that run past the image
+    /// average only the pixels they cover, so the result is
+    /// `ceil(width / factor)` x `ceil(height / factor)`. A `factor` of 0 or 1
+    /// returns an unchanged copy.
+    pub fn box_downsample(&self, factor: u32) -> Image {
+        self.pixels().box_downsample(factor)
+    }
+
+    /// Soften the seams between JPEG's 8x8 DCT blocks: the two pixels either
+    /// side of every 8-pixel boundary, across it, get a 1-2-1 blend with
+    /// their neighbors (RGB only, vertical seams then horizontal ones).
+    /// Linear gradients pass through unchanged.
+    pub fn deblock_jpeg(&self) -> Image {
+        self.pixels().deblock_jpeg()
+    }
+
+    /// Grow by `factor` in each dimension, repeating every pixel as a
+    /// `factor` x `factor` block so single-pixel highlights stay crisp on
+    /// HiDPI displays. A `factor` of 0 or 1 returns an unchanged copy.
+    pub fn upscale_nearest(&self, factor: u32) -> Image {
+        if factor <= 1 {
+            return Image {
+                data: self.data.clone(),
+                width: self.width,
+                height: self.height,
+            };
+        }
+
+        let f = factor as usize;
+        let row_bytes = self.width as usize * 4 * f;
+        let mut data = AlignedBytes::with_capacity(row_bytes * self.height as usize * f);
+        for row in self.data.chunks_exact(self.width as usize * 4) {
+            let start = data.len();
+            for px in row.chunks_exact(4) {
+                for _ in 0..f {
+                    data.extend_from_slice(px);
+                }
+            }
+            for _ in 1..f {
+                data.extend_from_within(start..start + row_bytes);
+            }
+        }
+        Image {
+            data,
+            width: self.width * factor,
+            height: self.height * factor,
+        }
+    }
+}
+
+impl Pixels<'_> {
+    pub(crate) fn gaussian_blur(self, radius: f32) -> Image {
+        let src = self.bytes();
+        let (width, height) = (self.width as usize, self.height as usize);
+        if !(radius > 0.0 && radius.is_finite()) || width == 0 || height == 0 {
+            return Image {
+                data: AlignedBytes::from_slice(src),
+                width: self.width,
+                height: self.height,
+            };
+        }
+
+        let kernel = gaussian_kernel(radius);
+        let half = kernel.len() / 2;
+
+        // Horizontal pass: u8 rows into an f32 scratch buffer.
+        let mut scratch = vec![0.0f32; width * height * 4];
+        for y in 0..height {
+            let row = &src[y * width * 4..(y + 1) * width * 4];
+            let out = &mut scratch[y * width * 4..(y + 1) * width * 4];
+            for x in 0..width {
+                let mut acc = [0.0f32; 4];
+                for (k, &weight) in kernel.iter().enumerate() {
+                    let sx = (x + k).saturating_sub(half).min(width - 1);
+                    for c in 0..4 {
+                        acc[c] += row[sx * 4 + c] as f32 * weight;
+                    }
+                }
+                out[x * 4..x * 4 + 4].copy_from_slice(&acc);
+            }
+        }
+
+        // Vertical pass: scratch columns back to rounded u8.
+        let mut data = AlignedBytes::zeroed(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let mut acc = [0.0f32; 4];
+                for (k, &weight) in kernel.iter().enumerate() {
+                    let sy = (y + k).saturating_sub(half).min(height - 1);
+                    let idx = (sy * width + x) * 4;
+                    for c in 0..4 {
+                        acc[c] += scratch[idx + c] * weight;
+                    }
+                }
+                let idx = (y * width + x) * 4;
+                for c in 0..4 {
+                    data[idx + c] = acc[c].round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+
+        Image {
+            data,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    pub(crate) fn deblock_jpeg(self) -> Image {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut data = AlignedBytes::from_slice(self.bytes());
+        smooth_seams(&mut data, width, height, 4, width * 4);
+        smooth_seams(&mut data, height, width, width * 4, 4);
+
+        Image {
+            data,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    pub(crate) fn box_downsample(self, factor: u32) -> Image {
+        let src = self.bytes();
+        if factor <= 1 {
+            return Image {
+                data: AlignedBytes::from_slice(src),
+                width: self.width,
+                height: self.height,
+            };
+        }
+
+        let (width, height) = (self.width as usize, self.height as usize);
+        let f = factor as usize;
+        let (out_width, out_height) = (width.div_ceil(f), height.div_ceil(f));
+        let mut data = AlignedBytes::zeroed(out_width * out_height * 4);
+        for oy in 0..out_height {
+            let rows = oy * f..((oy + 1) * f).min(height);
+            for ox in 0..out_width {
+                let cols = ox * f..((ox + 1) * f).min(width);
+                let mut acc = [0u32; 4];
+                for y in rows.clone() {
+                    for x in cols.clone() {
+                        let idx = (y * width + x) * 4;
+                        for (sum, &value) in acc.iter_mut().zip(&src[idx..idx + 4]) {
+                            *sum += value as u32;
+                        }
+                    }
+                }
+                let count = (rows.len() * cols.len()) as u32;
+                let idx = (oy * out_width + ox) * 4;
+                for c in 0..4 {
+                    data[idx + c] = ((acc[c] + count / 2) / count) as u8;
+                }
+            }
+        }
+
+        Image {
+            data,
+            width: out_width as u32,
+            height: out_height as u32,
+        }
+    }
+
+    /// Shrink by `p / q` in each dimension (`p >= q`), averaging per channel
+    /// (rounded) the source area under each output pixel, with source pixels
+    /// cut by an output edge weighted by the fraction they cover. The same
+    /// result as a nearest upscale by `q` then a `p` x `p` box, without the
+    /// `q`² larger image in between. Both dimensions must be multiples of
+    /// `p / q`, so every output pixel covers the same area.
+    pub(crate) fn box_resample(self, p: u32, q: u32) -> Image {
+        let src = self.bytes();
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (out_width, out_height) = (
+            width * q as usize / p as usize,
+            height * q as usize / p as usize,
+        );
+        let xs = box_weights(out_width, p, q);
+        let ys = box_weights(out_height, p, q);
+        let area = p as u64 * p as u64;
+        let mut data = AlignedBytes::zeroed(out_width * out_height * 4);
+        for (oy, rows) in ys.iter().enumerate() {
+            for (ox, cols) in xs.iter().enumerate() {
+                let mut acc = [0u64; 4];
+                for &(y, wy) in rows {
+                    for &(x, wx) in cols {
+                        let weight = wy as u64 * wx as u64;
+                        let idx = (y * width + x) * 4;
+                        for (sum, &value) in acc.iter_mut().zip(&src[idx..idx + 4]) {
+                            *sum += value as u64 * weight;
+                        }
+                    }
+                }
+                let idx = (oy * out_width + ox) * 4;
+                for c in 0..4 {
+                    data[idx + c] = ((acc[c] + area / 2) / area) as u8;
+                }
+            }
+        }
+
+        Image {
+            data,
+            width: out_width as u32,
+            height: out_height as u32,
+        }
+    }
+}
+
+/// For each of `len` output pixels along one axis of
+/// [`Pixels::box_resample`], the source pixels it covers and by how much,
+/// in units of `1 / q` of a source pixel: output `o` spans `[o * p,
+/// (o + 1) * p)` and source `s` spans `[s * q, (s + 1) * q)`.
+fn box_weights(len: usize, p: u32, q: u32) -> Vec<Vec<(usize, u32)>> {
+    let (p, q) = (p as usize, q as usize);
+    (0..len)
+        .map(|o| {
+            let (start, end) = (o * p, (o + 1) * p);
+            (start / q..end.div_ceil(q))
+                .map(|s| {
+                    let covered = end.min((s + 1) * q) - start.max(s * q);
+                    (s, covered as u32)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Edge length of the DCT blocks JPEG codes independently.
+const JPEG_BLOCK: usize = 8;
+
+/// One direction of [`Image::deblock_jpeg`] over `lines` lines of `len`
+/// pixels, `step` bytes apart along a line and `line_step` bytes between
+/// lines. Each seam's pair is blended from the values before the blend.
+fn smooth_seams(data: &mut [u8], len: usize, lines: usize, step: usize, line_step: usize) {
+    for line in 0..lines {
+        let at = |i: usize| line * line_step + i * step;
+        for seam in (JPEG_BLOCK..len).step_by(JPEG_BLOCK) {
+            let (p1, p0) = (at(seam.saturating_sub(2)), at(seam - 1));
+            let (q0, q1) = (at(seam), at((seam + 1).min(len - 1)));
+            for c in 0..3 {
+                let [p1v, p0v, q0v, q1v] = [p1, p0, q0, q1].map(|idx| data[idx + c] as u32);
+                data[p0 + c] = ((p1v + 2 * p0v + q0v + 2) / 4) as u8;
+                data[q0 + c] = ((p0v + 2 * q0v + q1v + 2) / 4) as u8;
+            }
+        }
+    }
+}
+
+/// Normalized 1D Gaussian taps covering +/- 3 standard deviations.
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let half = (3.0 * sigma).ceil() as i32;
+    let denom = 2.0 * sigma * sigma;
+    let mut kernel: Vec<f32> = (-half..=half)
+        .map(|i| (-((i * i) as f32) / denom).exp())
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    for weight in &mut kernel {
+        *weight /= sum;
+    }
+    kernel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::pack_gray_pixel;
+    use crate::yiq::pack_pixel;
+
+    #[test]
+    fn test_upscale_nearest_repeats_pixels_as_blocks() {
+        let mut image = Image::new(3, 2);
+        for (i, px) in image.as_u32_mut().iter_mut().enumerate() {
+            *px = 0x0102_0304 * (i as u32 + 1);
+        }
+        let scaled = image.upscale_nearest(3);
+        assert_eq!((scaled.width, scaled.height), (9, 6));
+        for y in 0..6 {
+            for x in 0..9 {
+                assert_eq!(scaled.get_pixel(x, y), image.get_pixel(x / 3, y / 3));
+            }
+        }
+        assert!(image.upscale_nearest(1).data == image.data);
+    }
+
+    #[test]
+    fn test_gaussian_kernel_is_normalized() {
+        let kernel = gaussian_kernel(2.0);
+        assert_eq!(kernel.len(), 13);
+        assert!((kernel.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(kernel[6] > kernel[5] && kernel[5] == kernel[7]);
+    }
+
+    #[test]
+    fn test_blur_keeps_flat_image() {
+        let mut img = Image::new(9, 5);
+        for px in img.data.chunks_exact_mut(4) {
+            px.copy_from_slice(&[40, 120, 200, 255]);
+        }
+        assert!(img.gaussian_blur(1.5).data == img.data);
+        assert!(img.gaussian_blur(0.0).data == img.data);
+    }
+
+    #[test]
+    fn test_blur_spreads_a_single_pixel() {
+        let mut img = Image::new(15, 15);
+        img.data[(7 * 15 + 7) * 4] = 255;
+        let blurred = img.gaussian_blur(2.0);
+        let red = |x: usize, y: usize| blurred.data[(y * 15 + x) * 4];
+        assert!(red(7, 7) < 20);
+        assert!(red(7, 7) > red(8, 7) && red(8, 7) > red(10, 7));
+        assert_eq!(red(8, 7), red(6, 7));
+    }
+
+    #[test]
+    fn test_box_downsample_averages_boxes() {
+        let mut img = Image::new(5, 3);
+        for (i, px) in img.data.chunks_exact_mut(4).enumerate() {
+            px.copy_from_slice(&[(i * 10) as u8, 0, 255, 255]);
+        }
+        let small = img.box_downsample(2);
+        assert_eq!((small.width, small.height), (3, 2));
+        // Top-left box covers red 0, 10, 50, 60.
+        assert_eq!(&small.data[0..4], &[30, 0, 255, 255]);
+        // Bottom-right box is the lone pixel (4, 2).
+        assert_eq!(small.data[5 * 4], 140);
+        assert!(img.box_downsample(1).data == img.data);
+    }
+
+    #[test]
+    fn test_box_resample_matches_upscale_then_box() {
+        let mut img = Image::new(20, 10);
+        for (i, px) in img.data.chunks_exact_mut(4).enumerate() {
+            px.copy_from_slice(&[(i * 37 % 251) as u8, (i * 11) as u8, 200, (i * 5) as u8]);
+        }
+        for (p, q) in [(5, 4), (5, 3), (2, 1), (5, 2)] {
+            let resampled = img.pixels().box_resample(p, q);
+            let expected = img.upscale_nearest(q).box_downsample(p);
+            assert_eq!(
+                (resampled.width, resampled.height),
+                (expected.width, expected.height)
+            );
+            assert!(resampled.data == expected.data, "{p}/{q}");
+        }
+    }
+
+    #[test]
+    fn test_deblock_jpeg_softens_seams() {
+        // A linear ramp passes through; a step on a block seam is halved.
+        let mut ramp = Image::new(20, 20);
+        let mut step = Image::new(20, 20);
+        for y in 0..20 {
+            for x in 0..20 {
+                let v = (x * 6 + y * 3) as u8;
+                ramp.set_pixel(x, y, pack_pixel(v, v, v, 0x80));
+                step.set_pixel(x, y, pack_gray_pixel(if x < 8 { 100 } else { 140 }));
+            }
+        }
+        assert!(ramp.deblock_jpeg().data == ramp.data);
+
+        let smoothed = step.deblock_jpeg();
+        let red = |x: u32| smoothed.get_pixel(x, 4) & 0xFF;
+        assert_eq!((red(6), red(7), red(8), red(9)), (100, 110, 130, 140));
+        assert_eq!(smoothed.get_pixel(7, 4) >> 24, 0xFF, "alpha untouched");
+    }
+}
@@ -0,0 +1,235 @@
+//! Format-agnostic image I/O: pick PNG, JPEG or QOI from a path's extension
+//! or a buffer's magic bytes, plus the one-shot [`compare_files`].
+
+use crate::diff::diff;
+use crate::io::{decode_png, load_png, load_pngs, save_png_indexed, save_png_with_compression};
+use crate::jpeg_io::{decode_jpeg, load_jpeg, load_jpegs, save_jpeg};
+use crate::qoi_io::{decode_qoi, load_qoi, load_qois, save_qoi};
+use crate::types::{DiffError, DiffOptions, DiffResult, Image};
+use rayon::prelude::*;
+use std::path::Path;
+
+/// JPEG quality used when [`compare_files`] writes a `.jpg` diff image.
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Supported image formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Qoi,
+}
+
+impl ImageFormat {
+    /// Detect the format from a file extension (case-insensitive).
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "qoi" => Some(ImageFormat::Qoi),
+            _ => None,
+        }
+    }
+
+    /// Detect the format from the leading magic bytes of an encoded buffer.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            return Some(ImageFormat::Png);
+        }
+        if data.starts_with(&[0xff, 0xd8, 0xff]) {
+            return Some(ImageFormat::Jpeg);
+        }
+        if data.starts_with(b"qoif") {
+            return Some(ImageFormat::Qoi);
+        }
+        None
+    }
+}
+
+fn format_of<P: AsRef<Path>>(path: P) -> Result<ImageFormat, DiffError> {
+    ImageFormat::from_path(&path).ok_or_else(|| {
+        DiffError::UnsupportedFormat(format!("Unsupported format: {}", path.as_ref().display()))
+    })
+}
+
+/// Load a single image, auto-detecting format from extension
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<Image, DiffError> {
+    match format_of(&path)? {
+        ImageFormat::Png => load_png(path),
+        ImageFormat::Jpeg => load_jpeg(path),
+        ImageFormat::Qoi => load_qoi(path),
+    }
+}
+
+/// Load two images in parallel, auto-detecting format from extension
+pub fn load_images<P1: AsRef<Path> + Sync, P2: AsRef<Path> + Sync>(
+    path1: P1,
+    path2: P2,
+) -> Result<(Image, Image), DiffError> {
+    let fmt1 = format_of(&path1)?;
+    let fmt2 = format_of(&path2)?;
+
+    // If both are same format, use optimized parallel loader
+    if fmt1 == fmt2 {
+        return match fmt1 {
+            ImageFormat::Png => load_pngs(&path1, &path2),
+            ImageFormat::Jpeg => load_jpegs(&path1, &path2),
+            ImageFormat::Qoi => load_qois(&path1, &path2),
+        };
+    }
+
+    // Mixed formats: load in parallel anyway
+    let results: Vec<Result<Image, DiffError>> = [path1.as_ref(), path2.as_ref()]
+        .par_iter()
+        .map(load_image)
+        .collect();
+
+    let mut iter = results.into_iter();
+    Ok((iter.next().unwrap()?, iter.next().unwrap()?))
+}
+
+/// Decode an in-memory image, auto-detecting format from its magic bytes
+pub fn decode_image(data: &[u8]) -> Result<Image, DiffError> {
+    match ImageFormat::from_bytes(data) {
+        Some(ImageFormat::Png) => decode_png(data),
+        Some(ImageFormat::Jpeg) => decode_jpeg(data),
+        Some(ImageFormat::Qoi) => decode_qoi(data),
+        None => Err(DiffError::UnsupportedFormat(
+            "Unsupported image buffer format".to_string(),
+        )),
+    }
+}
+
+/// Save an image, auto-detecting format from extension. `compression` only
+/// applies to PNG and `quality` only to JPEG.
+pub fn save_image<P: AsRef<Path>>(
+    image: &Image,
+    path: P,
+    compression: u8,
+    quality: u8,
+) -> Result<(), DiffError> {
+    match format_of(&path)? {
+        ImageFormat::Png => save_png_with_compression(image, path, compression),
+        ImageFormat::Jpeg => save_jpeg(image, path, quality),
+        ImageFormat::Qoi => save_qoi(image, path),
+    }
+}
+
+/// Load two images, diff them, and write the diff image to `diff_out` only
+/// when they differ. Formats are detected from the extensions; the diff is
+/// written by [`diff_and_save`] with a JPEG quality of
+/// [`DEFAULT_JPEG_QUALITY`].
+///
+/// ```no_run
+/// use blazediff::{compare_files, DiffOptions};
+/// use std::path::Path;
+///
+/// let result = compare_files(
+///     "a.png",
+///     "b.png",
+///     Some(Path::new("diff.png")),
+///     &DiffOptions::default(),
+/// )
+/// .unwrap();
+/// println!("{} pixels differ", result.diff_count);
+/// ```
+pub fn compare_files<P1: AsRef<Path> + Sync, P2: AsRef<Path> + Sync>(
+    path1: P1,
+    path2: P2,
+    diff_out: Option<&Path>,
+    options: &DiffOptions,
+) -> Result<DiffResult, DiffError> {
+    // Reject an unwritable extension before paying for the decode and diff.
+    diff_out.map(format_of).transpose()?;
+    let (image1, image2) = load_images(path1, path2)?;
+    diff_and_save(&image1, &image2, diff_out, options, DEFAULT_JPEG_QUALITY)
+}
+
+/// Diff two loaded images into an [`Image::new_output`] buffer and write it
+/// to `diff_out` with [`save_diff_image`] only when they differ.
+pub fn diff_and_save<P: AsRef<Path>>(
+    image1: &Image,
+    image2: &Image,
+    diff_out: Option<P>,
+    options: &DiffOptions,
+    quality: u8,
+) -> Result<DiffResult, DiffError> {
+    let Some(out) = diff_out else {
+        return diff(image1, image2, None, options);
+    };
+
+    let options = DiffOptions {
+        output_cleared: options.diff_mask,
+        ..options.clone()
+    };
+    let mut output = Image::new_output(image1, options.diff_mask);
+    let result = diff(image1, image2, Some(&mut output), &options)?;
+    if !result.identical {
+        save_diff_image(&output, out, &options, quality)?;
+    }
+    Ok(result)
+}
+
+/// Save a diff image like [`save_image`], except that a `diff_mask` PNG is
+/// palette-encoded with [`save_png_indexed`]: a mask is a handful of colors,
+/// which a palette PNG stores at one byte per pixel.
+pub fn save_diff_image<P: AsRef<Path>>(
+    output: &Image,
+    path: P,
+    options: &DiffOptions,
+    quality: u8,
+) -> Result<(), DiffError> {
+    if options.diff_mask && format_of(&path)? == ImageFormat::Png {
+        save_png_indexed(output, path, options.compression)
+    } else {
+        save_image(output, path, options.compression, quality)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_format_detection() {
+        assert_eq!(ImageFormat::from_path("a/B.JPEG"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_path("x.qoi"), Some(ImageFormat::Qoi));
+        assert_eq!(ImageFormat::from_path("x.gif"), None);
+        assert_eq!(ImageFormat::from_path("noext"), None);
+        assert_eq!(
+            ImageFormat::from_bytes(b"\x89PNG\r\n\x1a\n...."),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(ImageFormat::from_bytes(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn test_compare_files_saves_only_on_difference() {
+        let dir = std::env::temp_dir();
+        let pid = std::process::id();
+        let path1 = dir.join(format!("blazediff_compare_{pid}_1.png"));
+        let path2 = dir.join(format!("blazediff_compare_{pid}_2.qoi"));
+        let out = dir.join(format!("blazediff_compare_{pid}_diff.png"));
+
+        let mut img = Image::new(16, 16);
+        img.data.fill(255);
+        crate::save_png(&img, &path1).unwrap();
+        save_qoi(&img, &path2).unwrap();
+
+        let options = DiffOptions::default();
+        let result = compare_files(&path1, &path2, Some(&out), &options).unwrap();
+        assert!(result.identical);
+        assert!(!out.exists());
+
+        img.data[0] = 0;
+        save_qoi(&img, &path2).unwrap();
+        let result = compare_files(&path1, &path2, Some(&out), &options).unwrap();
+        assert_eq!(result.diff_count, 1);
+        assert_eq!(load_image(&out).unwrap().width, 16);
+
+        for path in [&path1, &path2, &out] {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Zero-decode conversions to and from the `image` crate, for callers that
+//! already hold decoded pixels. Both sides store RGBA8 row-major, so each
+//! conversion is a single buffer copy.
+
+use crate::types::Image;
+use image::{DynamicImage, RgbaImage};
+
+impl From<RgbaImage> for Image {
+    fn from(image: RgbaImage) -> Self {
+        let (width, height) = image.dimensions();
+        Image {
+            data: image.into_raw().into(),
+            width,
+            height,
+        }
+    }
+}
+
+impl From<DynamicImage> for Image {
+    /// Converts to RGBA8 first; a no-op move when it already is.
+    fn from(image: DynamicImage) -> Self {
+        image.into_rgba8().into()
+    }
+}
+
+impl From<&Image> for RgbaImage {
+    fn from(image: &Image) -> Self {
+        RgbaImage::from_raw(image.width, image.height, image.data.to_vec())
+            .expect("Image data length matches its dimensions")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rgba_image_round_trip() {
+        let mut rgba = RgbaImage::new(3, 2);
+        rgba.put_pixel(2, 1, image::Rgba([10, 20, 30, 40]));
+
+        let image = Image::from(rgba.clone());
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(&image.data[20..24], &[10, 20, 30, 40]);
+        assert_eq!(RgbaImage::from(&image), rgba);
+
+        let gray = DynamicImage::ImageLuma8(image::GrayImage::from_pixel(1, 1, image::Luma([7])));
+        assert_eq!(Image::from(gray).data, vec![7, 7, 7, 255]);
+    }
+}
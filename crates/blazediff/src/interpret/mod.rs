@@ -205,6 +205,8 @@ pub fn interpret_with_output(
         aa_color: MASK_AA_COLOR,
         diff_color: MASK_DIFF_COLOR,
         diff_color_alt: Some(MASK_DIFF_COLOR_ALT),
+        // The internal scratch buffer is never pre-cleared.
+        output_cleared: options.output_cleared && retain_output,
         ..options.clone()
     };
     let diff_result = diff(img1, img2, Some(diff_output), &mask_options)?;
@@ -2,47 +2,10 @@ mod label_extract;
 mod morphology;
 
 use super::types::BoundingBox;
+use crate::regions::label_connected_components;
 use label_extract::extract_labeled_regions;
 use morphology::morph_close;
 
-struct UnionFind {
-    parent: Vec<u32>,
-    rank: Vec<u8>,
-}
-
-impl UnionFind {
-    fn new(size: usize) -> Self {
-        Self {
-            parent: (0..size as u32).collect(),
-            rank: vec![0; size],
-        }
-    }
-
-    fn find(&mut self, mut x: u32) -> u32 {
-        while self.parent[x as usize] != x {
-            self.parent[x as usize] = self.parent[self.parent[x as usize] as usize];
-            x = self.parent[x as usize];
-        }
-        x
-    }
-
-    fn union(&mut self, a: u32, b: u32) {
-        let ra = self.find(a);
-        let rb = self.find(b);
-        if ra == rb {
-            return;
-        }
-        match self.rank[ra as usize].cmp(&self.rank[rb as usize]) {
-            std::cmp::Ordering::Less => self.parent[ra as usize] = rb,
-            std::cmp::Ordering::Greater => self.parent[rb as usize] = ra,
-            std::cmp::Ordering::Equal => {
-                self.parent[rb as usize] = ra;
-                self.rank[ra as usize] += 1;
-            }
-        }
-    }
-}
-
 pub struct ComponentInfo {
     pub bbox: BoundingBox,
     pub pixel_count: u32,
@@ -121,55 +84,6 @@ fn find_connected_components(mask: &[bool], width: u32, height: u32) -> Vec<Comp
     extract_labeled_regions(&labels, mask, width)
 }
 
-/// Label each foreground pixel with its connected component ID (4-connectivity).
-/// Returns a label map: 0 = background, >0 = component label.
-fn label_connected_components(mask: &[bool], width: u32, height: u32) -> Vec<i32> {
-    let w = width as usize;
-    let h = height as usize;
-    let total = w * h;
-    let mut labels = vec![0i32; total];
-
-    if total == 0 {
-        return labels;
-    }
-
-    let mut uf = UnionFind::new(total);
-
-    for y in 0..h {
-        for x in 0..w {
-            let idx = y * w + x;
-            if !mask[idx] {
-                continue;
-            }
-            if x > 0 && mask[idx - 1] {
-                uf.union(idx as u32, (idx - 1) as u32);
-            }
-            if y > 0 && mask[idx - w] {
-                uf.union(idx as u32, (idx - w) as u32);
-            }
-        }
-    }
-
-    // Map roots to sequential labels
-    let mut root_to_label: std::collections::HashMap<u32, i32> = std::collections::HashMap::new();
-    let mut next_label = 1i32;
-
-    for i in 0..total {
-        if !mask[i] {
-            continue;
-        }
-        let root = uf.find(i as u32);
-        let label = *root_to_label.entry(root).or_insert_with(|| {
-            let l = next_label;
-            next_label += 1;
-            l
-        });
-        labels[i] = label;
-    }
-
-    labels
-}
-
 /// Full region detection pipeline:
 /// binary mask → morph close → connected components → extract with original mask
 pub fn detect_regions(mask: &[bool], width: u32, height: u32) -> Vec<ComponentInfo> {
@@ -3,8 +3,9 @@
 //! when enabled it takes the decode and stored-block (level 0) encode paths,
 //! with spng staying as the defensive decode fallback.
 
+use crate::aligned::AlignedBytes;
 use crate::spng_ffi::*;
-use crate::types::{DiffError, Image};
+use crate::types::{DecodeFailure, DecodeLimits, DiffError, Image, LoadOptions};
 use memmap2::Mmap;
 use std::fs::File;
 use std::io::Write;
@@ -37,33 +38,106 @@ impl Drop for CtxGuard {
 }
 
 pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Image, DiffError> {
+    load_png_with_limits(path, &DecodeLimits::default())
+}
+
+/// [`load_png`] for untrusted input: fails with [`DiffError::TooLarge`]
+/// before allocating if the header declares an image beyond `limits`.
+pub fn load_png_with_limits<P: AsRef<Path>>(
+    path: P,
+    limits: &DecodeLimits,
+) -> Result<Image, DiffError> {
+    let file = File::open(path.as_ref())?;
+    let file_data = unsafe { Mmap::map(&file)? };
+    decode_png_with_limits(&file_data, limits)
+}
+
+/// [`load_png`] with bounds on the decode and optional alpha
+/// unpremultiplication of the result.
+pub fn load_png_with<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Result<Image, DiffError> {
+    let mut image = load_png_with_limits(path, &options.limits)?;
+    if options.unpremultiply {
+        image.unpremultiply();
+    }
+    Ok(image)
+}
+
+/// How a PNG declares its colors, carried from an input to the saved diff
+/// so color-managed viewers show both the same way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColorProfile {
+    /// An embedded ICC profile (iCCP chunk), decompressed.
+    Icc { name: String, data: Vec<u8> },
+    /// The sRGB chunk with its rendering intent (0-3).
+    Srgb { rendering_intent: u8 },
+}
+
+/// Ancillary PNG data [`load_png_with_metadata`] reads besides the pixels.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PngMetadata {
+    /// The iCCP profile, else the sRGB chunk; `None` when neither is present.
+    pub color_profile: Option<ColorProfile>,
+}
+
+/// [`load_png_with`] that also returns the file's [`PngMetadata`]. Always
+/// decodes through spng, which parses the ancillary chunks.
+pub fn load_png_with_metadata<P: AsRef<Path>>(
+    path: P,
+    options: &LoadOptions,
+) -> Result<(Image, PngMetadata), DiffError> {
     let file = File::open(path.as_ref())?;
     let file_data = unsafe { Mmap::map(&file)? };
-    decode_png(&file_data)
+    let mut metadata = PngMetadata::default();
+    let mut image = decode_spng_with(&file_data, &options.limits, Some(&mut metadata))?;
+    if options.unpremultiply {
+        image.unpremultiply();
+    }
+    Ok((image, metadata))
 }
 
 pub(crate) fn decode_png(file_data: &[u8]) -> Result<Image, DiffError> {
+    decode_png_with_limits(file_data, &DecodeLimits::default())
+}
+
+pub(crate) fn decode_png_with_limits(
+    file_data: &[u8],
+    limits: &DecodeLimits,
+) -> Result<Image, DiffError> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("decode_png", bytes = file_data.len()).entered();
     // When enabled, blazediff_png decodes every format spng accepts,
     // byte-identically, and is substantially faster (whole-buffer libdeflate
     // inflate, SIMD defilter); spng stays as a defensive fallback should the
     // codec ever reject an input spng would have taken. Off by default while
-    // the codec is experimental.
-    if blazediff_png_enabled() {
+    // the codec is experimental. Bounded decodes always take spng, which
+    // reports the decoded size before anything is allocated.
+    if blazediff_png_enabled() && *limits == DecodeLimits::default() {
         if let Ok(img) = blazediff_png::decode(file_data) {
+            // The codec returns a plain `Vec<u8>`, adopted without a copy
+            // when its allocation is already 16-byte aligned.
             return Ok(Image {
-                data: img.data,
+                data: img.data.into(),
                 width: img.width,
                 height: img.height,
             });
         }
     }
-    decode_spng(file_data)
+    decode_spng(file_data, limits)
 }
 
 /// Decode a PNG byte buffer through spng (FMT_RGBA8 + tRNS). The decode
 /// fallback, and the reference oracle `blazediff_png` is verified against
 /// byte-for-byte (see that crate's differential tests).
-pub(crate) fn decode_spng(file_data: &[u8]) -> Result<Image, DiffError> {
+pub(crate) fn decode_spng(file_data: &[u8], limits: &DecodeLimits) -> Result<Image, DiffError> {
+    decode_spng_with(file_data, limits, None)
+}
+
+/// [`decode_spng`], filling `metadata` from the ancillary chunks when given.
+fn decode_spng_with(
+    file_data: &[u8],
+    limits: &DecodeLimits,
+    metadata: Option<&mut PngMetadata>,
+) -> Result<Image, DiffError> {
     unsafe {
         // Keep Adler32 verification on: `load_png` is public API decoding
         // arbitrary, possibly untrusted PNGs (CLI + napi/python bindings), so a
@@ -101,22 +175,35 @@ pub(crate) fn decode_spng(file_data: &[u8]) -> Result<Image, DiffError> {
                 "Failed to get decoded image size".into(),
             ));
         }
+        limits.check("PNG", width, height, out_size)?;
 
-        // Allocate without zero-initialization (spng will overwrite)
-        let mut data: Vec<u8> = Vec::with_capacity(out_size);
-        data.set_len(out_size);
+        // calloc: large buffers get cleared pages without a memset, and spng
+        // overwrites every byte.
+        let mut data = AlignedBytes::zeroed(out_size);
 
-        if spng_decode_image(
+        // Without SPNG_DECODE_PROGRESSIVE this decodes the whole image in
+        // one call, and spng de-interlaces Adam7 input into row-major RGBA8
+        // itself; no per-pass loop is needed here.
+        let ret = spng_decode_image(
             ctx,
             data.as_mut_ptr() as *mut _,
             out_size,
             spng_format_SPNG_FMT_RGBA8 as c_int,
             spng_decode_flags_SPNG_DECODE_TRNS as c_int,
-        ) != 0
-        {
-            return Err(DiffError::PngError("Failed to decode image".into()));
+        );
+        if ret != 0 {
+            return Err(DiffError::DecodeError {
+                format: "PNG",
+                width,
+                height,
+                kind: classify_spng_error(ret),
+                detail: spng_error_string(ret),
+            });
         }
 
+        if let Some(metadata) = metadata {
+            metadata.color_profile = read_color_profile(ctx);
+        }
         Ok(Image {
             data,
             width,
@@ -125,6 +212,61 @@ pub(crate) fn decode_spng(file_data: &[u8]) -> Result<Image, DiffError> {
     }
 }
 
+/// The iCCP profile of a decoded PNG, else its sRGB chunk.
+unsafe fn read_color_profile(ctx: *mut spng_ctx) -> Option<ColorProfile> {
+    let mut iccp: spng_iccp = std::mem::zeroed();
+    if spng_get_iccp(ctx, &mut iccp) == 0 && !iccp.profile.is_null() {
+        let name = std::ffi::CStr::from_ptr(iccp.profile_name.as_ptr())
+            .to_string_lossy()
+            .into_owned();
+        let data = std::slice::from_raw_parts(iccp.profile as *const u8, iccp.profile_len).to_vec();
+        return Some(ColorProfile::Icc { name, data });
+    }
+    let mut rendering_intent = 0u8;
+    if spng_get_srgb(ctx, &mut rendering_intent) == 0 {
+        return Some(ColorProfile::Srgb { rendering_intent });
+    }
+    None
+}
+
+/// Map an spng error code raised after IHDR to a broad failure cause.
+fn classify_spng_error(code: c_int) -> DecodeFailure {
+    const TRUNCATED: [spng_errno; 3] = [
+        spng_errno_SPNG_EIO,
+        spng_errno_SPNG_EOF,
+        spng_errno_SPNG_EIDAT_TOO_SHORT,
+    ];
+    const UNSUPPORTED: [spng_errno; 8] = [
+        spng_errno_SPNG_EBIT_DEPTH,
+        spng_errno_SPNG_ECOLOR_TYPE,
+        spng_errno_SPNG_ECOMPRESSION_METHOD,
+        spng_errno_SPNG_EFILTER_METHOD,
+        spng_errno_SPNG_EINTERLACE_METHOD,
+        spng_errno_SPNG_ECHUNK_UNKNOWN_CRITICAL,
+        spng_errno_SPNG_ECHUNK_LIMITS,
+        spng_errno_SPNG_EFMT,
+    ];
+    let code = code as spng_errno;
+    if TRUNCATED.contains(&code) {
+        DecodeFailure::Truncated
+    } else if UNSUPPORTED.contains(&code) {
+        DecodeFailure::Unsupported
+    } else {
+        DecodeFailure::Corrupt
+    }
+}
+
+fn spng_error_string(code: c_int) -> String {
+    unsafe {
+        let msg = spng_strerror(code);
+        if msg.is_null() {
+            format!("spng error {code}")
+        } else {
+            std::ffi::CStr::from_ptr(msg).to_string_lossy().into_owned()
+        }
+    }
+}
+
 /// Differential-oracle hook: decode through spng at an arbitrary
 /// `SPNG_FMT_*` + decode-flags combination, returning the raw output bytes
 /// plus the image dimensions and source color type / bit depth. Used by
@@ -200,18 +342,180 @@ pub fn save_png<P: AsRef<Path>>(image: &Image, path: P) -> Result<(), DiffError>
     save_png_with_compression(image, path, 0)
 }
 
+/// Images of at least this many pixels (64 MiB of RGBA) are saved through
+/// [`write_png`], so the encoded file is never held in memory alongside
+/// them.
+const STREAMING_SAVE_MIN_PIXELS: usize = 16 * 1024 * 1024;
+
+/// Encode and write a PNG. Small images are encoded to a buffer and written
+/// at once; large ones stream rows to the file through [`write_png`].
 pub fn save_png_with_compression<P: AsRef<Path>>(
     image: &Image,
     path: P,
     compression: u8,
 ) -> Result<(), DiffError> {
-    let png_data = encode_png(image, compression as i32)?;
-    let mut file = File::create(path.as_ref())?;
+    save_png_to(image, path.as_ref(), compression, None)
+}
+
+/// The saving behind [`save_png_with_compression`] and
+/// [`save_png_with_profile`].
+fn save_png_to(
+    image: &Image,
+    path: &Path,
+    compression: u8,
+    profile: Option<&ColorProfile>,
+) -> Result<(), DiffError> {
+    if image.as_u32().len() >= STREAMING_SAVE_MIN_PIXELS {
+        return write_replacing(path, |file| {
+            stream_png(image, file, compression as i32, profile)
+        });
+    }
+    let png_data = match profile {
+        None => encode_png(image, compression as i32)?,
+        Some(_) => spng_encode(
+            &image.data,
+            image.width,
+            image.height,
+            spng_color_type_SPNG_COLOR_TYPE_TRUECOLOR_ALPHA as u8,
+            None,
+            profile,
+            compression as i32,
+        )?,
+    };
+    let mut file = File::create(path)?;
     file.write_all(&png_data)?;
     Ok(())
 }
 
+/// Run `write` against a temporary file beside `path` and rename it over
+/// `path` once everything is flushed. On any error the temporary file is
+/// removed, so a save that fails partway never leaves a truncated PNG at the
+/// destination.
+fn write_replacing(
+    path: &Path,
+    write: impl FnOnce(&mut std::io::BufWriter<File>) -> Result<(), DiffError>,
+) -> Result<(), DiffError> {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(name);
+    let written = File::create(&temp)
+        .map_err(DiffError::from)
+        .and_then(|file| {
+            let mut file = std::io::BufWriter::new(file);
+            write(&mut file)?;
+            file.flush()?;
+            Ok(())
+        })
+        .and_then(|()| std::fs::rename(&temp, path).map_err(DiffError::from));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    written
+}
+
+/// Encode as [`encode_png`] does, but feed spng one row at a time and hand
+/// each compressed chunk to `writer` as it is produced, so memory stays at
+/// one row plus the deflate state however large the image. Always uses
+/// spng, even with the experimental codec enabled.
+pub fn write_png<W: Write>(
+    image: &Image,
+    writer: &mut W,
+    compression_level: i32,
+) -> Result<(), DiffError> {
+    stream_png(image, writer, compression_level, None)
+}
+
+/// [`write_png`] with the color chunk for `profile`.
+fn stream_png<W: Write>(
+    image: &Image,
+    writer: &mut W,
+    compression_level: i32,
+    profile: Option<&ColorProfile>,
+) -> Result<(), DiffError> {
+    #[cfg(feature = "tracing")]
+    let _span =
+        tracing::debug_span!("write_png", width = image.width, height = image.height).entered();
+    let mut sink = StreamSink {
+        writer,
+        error: None,
+    };
+    unsafe {
+        let guard = spng_encoder(
+            image.width,
+            image.height,
+            spng_color_type_SPNG_COLOR_TYPE_TRUECOLOR_ALPHA as u8,
+            None,
+            compression_level,
+        )?;
+        let ctx = guard.0;
+        set_color_profile(ctx, profile)?;
+        spng_set_png_stream(
+            ctx,
+            Some(write_to_sink::<W>),
+            &mut sink as *mut StreamSink<'_, W> as *mut _,
+        );
+
+        let flags = (spng_encode_flags_SPNG_ENCODE_PROGRESSIVE
+            | spng_encode_flags_SPNG_ENCODE_FINALIZE) as c_int;
+        let mut ret = spng_encode_image(
+            ctx,
+            std::ptr::null(),
+            0,
+            spng_format_SPNG_FMT_PNG as c_int,
+            flags,
+        );
+        let row_bytes = image.width as usize * 4;
+        if ret == 0 && row_bytes > 0 {
+            for row in image.data.chunks_exact(row_bytes) {
+                ret = spng_encode_row(ctx, row.as_ptr() as *const _, row.len());
+                if ret != 0 {
+                    break;
+                }
+            }
+        }
+        // The last row finalizes the stream and reports end of image.
+        if ret != 0 && ret != spng_errno_SPNG_EOI {
+            if let Some(error) = sink.error.take() {
+                return Err(DiffError::IoError(error));
+            }
+            return Err(DiffError::PngError(format!(
+                "Failed to encode image: {}",
+                ret
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Where [`write_png`]'s spng stream callback sends the encoded bytes, and
+/// the first write error, which spng itself only sees as an error code.
+struct StreamSink<'a, W> {
+    writer: &'a mut W,
+    error: Option<std::io::Error>,
+}
+
+unsafe extern "C" fn write_to_sink<W: Write>(
+    _ctx: *mut spng_ctx,
+    user: *mut std::os::raw::c_void,
+    src: *mut std::os::raw::c_void,
+    length: usize,
+) -> c_int {
+    let sink = &mut *(user as *mut StreamSink<'_, W>);
+    let bytes = std::slice::from_raw_parts(src as *const u8, length);
+    match sink.writer.write_all(bytes) {
+        Ok(()) => 0,
+        Err(error) => {
+            sink.error = Some(error);
+            spng_errno_SPNG_IO_ERROR as c_int
+        }
+    }
+}
+
 pub fn encode_png(image: &Image, compression_level: i32) -> Result<Vec<u8>, DiffError> {
+    #[cfg(feature = "tracing")]
+    let _span =
+        tracing::debug_span!("encode_png", width = image.width, height = image.height).entered();
     // Level 0 means stored (uncompressed) deflate blocks; when the
     // experimental codec is enabled, blazediff_png writes them directly
     // instead of going through zlib. RGBA8 + filter none matches the
@@ -233,52 +537,127 @@ pub fn encode_png(image: &Image, compression_level: i32) -> Result<Vec<u8>, Diff
         return blazediff_png::encode_ref(png, &options)
             .map_err(|e| DiffError::PngError(format!("blazediff_png encode failed: {e}")));
     }
-    unsafe {
-        let ctx = spng_ctx_new(spng_ctx_flags_SPNG_CTX_ENCODER as c_int);
-        if ctx.is_null() {
-            return Err(DiffError::PngError(
-                "Failed to create spng encoder context".into(),
-            ));
-        }
+    spng_encode(
+        &image.data,
+        image.width,
+        image.height,
+        spng_color_type_SPNG_COLOR_TYPE_TRUECOLOR_ALPHA as u8,
+        None,
+        None,
+        compression_level,
+    )
+}
 
-        let mut ihdr = spng_ihdr {
-            width: image.width,
-            height: image.height,
-            bit_depth: 8,
-            color_type: spng_color_type_SPNG_COLOR_TYPE_TRUECOLOR_ALPHA as u8,
-            compression_method: 0,
-            filter_method: spng_filter_SPNG_FILTER_NONE as u8,
-            interlace_method: spng_interlace_method_SPNG_INTERLACE_NONE as u8,
-        };
+/// [`save_png_with_compression`] that embeds `profile`, e.g. the input's
+/// from [`load_png_with_metadata`], so the diff displays in the same color
+/// space. `None` writes no color chunk, as the other savers do.
+pub fn save_png_with_profile<P: AsRef<Path>>(
+    image: &Image,
+    path: P,
+    compression: u8,
+    profile: Option<&ColorProfile>,
+) -> Result<(), DiffError> {
+    save_png_to(image, path.as_ref(), compression, profile)
+}
 
-        if spng_set_ihdr(ctx, &mut ihdr) != 0 {
-            spng_ctx_free(ctx);
-            return Err(DiffError::PngError("Failed to set IHDR".into()));
-        }
+pub fn save_png_indexed<P: AsRef<Path>>(
+    image: &Image,
+    path: P,
+    compression: u8,
+) -> Result<(), DiffError> {
+    let png_data = encode_png_indexed(image, compression as i32)?;
+    let mut file = File::create(path.as_ref())?;
+    file.write_all(&png_data)?;
+    Ok(())
+}
+
+/// Encode as an 8-bit palette PNG when the image has at most 256 distinct
+/// RGBA colors, as `diff_mask` outputs usually do: one byte per pixel
+/// instead of four. Busier images fall back to [`encode_png`].
+pub fn encode_png_indexed(image: &Image, compression_level: i32) -> Result<Vec<u8>, DiffError> {
+    let Some((palette, indices)) = build_palette(image) else {
+        return encode_png(image, compression_level);
+    };
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!(
+        "encode_png_indexed",
+        width = image.width,
+        height = image.height,
+        colors = palette.len()
+    )
+    .entered();
+    spng_encode(
+        &indices,
+        image.width,
+        image.height,
+        spng_color_type_SPNG_COLOR_TYPE_INDEXED as u8,
+        Some(&palette),
+        None,
+        compression_level,
+    )
+}
+
+/// Distinct packed RGBA colors in first-seen order plus each pixel's index
+/// into them, or `None` past 256 colors.
+fn build_palette(image: &Image) -> Option<(Vec<u32>, Vec<u8>)> {
+    let mut palette: Vec<u32> = Vec::new();
+    let mut lookup = std::collections::HashMap::new();
+    let mut indices = Vec::with_capacity(image.as_u32().len());
+    // Masks are long runs of one color; skip the map while the run lasts.
+    let mut last: Option<(u32, u8)> = None;
+    for &pixel in image.as_u32() {
+        let index = match last {
+            Some((color, index)) if color == pixel => index,
+            _ => {
+                let index = match lookup.get(&pixel) {
+                    Some(&index) => index,
+                    None => {
+                        if palette.len() == 256 {
+                            return None;
+                        }
+                        let index = palette.len() as u8;
+                        palette.push(pixel);
+                        lookup.insert(pixel, index);
+                        index
+                    }
+                };
+                last = Some((pixel, index));
+                index
+            }
+        };
+        indices.push(index);
+    }
+    Some((palette, indices))
+}
 
+/// Encode 8-bit samples of `color_type` through spng, with a PLTE (and
+/// tRNS, when any entry is translucent) from `palette` for indexed images
+/// and an iCCP or sRGB chunk from `profile`.
+fn spng_encode(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    color_type: u8,
+    palette: Option<&[u32]>,
+    profile: Option<&ColorProfile>,
+    compression_level: i32,
+) -> Result<Vec<u8>, DiffError> {
+    unsafe {
+        let guard = spng_encoder(width, height, color_type, palette, compression_level)?;
+        let ctx = guard.0;
+        set_color_profile(ctx, profile)?;
         spng_set_option(ctx, spng_option_SPNG_ENCODE_TO_BUFFER, 1);
-        spng_set_option(
-            ctx,
-            spng_option_SPNG_FILTER_CHOICE,
-            spng_filter_choice_SPNG_DISABLE_FILTERING as c_int,
-        );
-        spng_set_option(
-            ctx,
-            spng_option_SPNG_IMG_COMPRESSION_LEVEL,
-            compression_level,
-        );
 
         let flags = spng_encode_flags_SPNG_ENCODE_FINALIZE as c_int;
         let ret = spng_encode_image(
             ctx,
-            image.data.as_ptr() as *const _,
-            image.data.len(),
+            data.as_ptr() as *const _,
+            data.len(),
             spng_format_SPNG_FMT_PNG as c_int,
             flags,
         );
 
         if ret != 0 {
-            spng_ctx_free(ctx);
             return Err(DiffError::PngError(format!(
                 "Failed to encode image: {}",
                 ret
@@ -290,7 +669,6 @@ pub fn encode_png(image: &Image, compression_level: i32) -> Result<Vec<u8>, Diff
         let buf = spng_get_png_buffer(ctx, &mut len, &mut error);
 
         if buf.is_null() || error != 0 {
-            spng_ctx_free(ctx);
             return Err(DiffError::PngError(format!(
                 "Failed to get PNG buffer: {}",
                 error
@@ -300,12 +678,125 @@ pub fn encode_png(image: &Image, compression_level: i32) -> Result<Vec<u8>, Diff
         let result = std::slice::from_raw_parts(buf as *const u8, len).to_vec();
 
         libc::free(buf);
-        spng_ctx_free(ctx);
 
         Ok(result)
     }
 }
 
+/// Set the iCCP or sRGB chunk for `profile` on an encoder context; `None`
+/// sets nothing. spng keeps pointers into `profile`, which must outlive the
+/// encode.
+unsafe fn set_color_profile(
+    ctx: *mut spng_ctx,
+    profile: Option<&ColorProfile>,
+) -> Result<(), DiffError> {
+    let set = match profile {
+        Some(ColorProfile::Icc {
+            name,
+            data: icc_data,
+        }) => {
+            let mut iccp: spng_iccp = std::mem::zeroed();
+            // The name is 1-79 bytes; the zeroed tail terminates it.
+            let name = if name.is_empty() { "ICC profile" } else { name };
+            for (dst, &src) in iccp.profile_name[..79].iter_mut().zip(name.as_bytes()) {
+                *dst = src as std::os::raw::c_char;
+            }
+            iccp.profile_len = icc_data.len();
+            iccp.profile = icc_data.as_ptr() as *mut _;
+            spng_set_iccp(ctx, &mut iccp)
+        }
+        Some(&ColorProfile::Srgb { rendering_intent }) => spng_set_srgb(ctx, rendering_intent),
+        None => 0,
+    };
+    if set != 0 {
+        return Err(DiffError::PngError(
+            "Failed to set the color profile".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// An spng encoder context with the header, palette and unfiltered
+/// compression settings [`spng_encode`] and [`write_png`] share; the caller
+/// picks the output target.
+unsafe fn spng_encoder(
+    width: u32,
+    height: u32,
+    color_type: u8,
+    palette: Option<&[u32]>,
+    compression_level: i32,
+) -> Result<CtxGuard, DiffError> {
+    let ctx = spng_ctx_new(spng_ctx_flags_SPNG_CTX_ENCODER as c_int);
+    if ctx.is_null() {
+        return Err(DiffError::PngError(
+            "Failed to create spng encoder context".into(),
+        ));
+    }
+    let guard = CtxGuard(ctx);
+
+    let mut ihdr = spng_ihdr {
+        width,
+        height,
+        bit_depth: 8,
+        color_type,
+        compression_method: 0,
+        filter_method: spng_filter_SPNG_FILTER_NONE as u8,
+        interlace_method: spng_interlace_method_SPNG_INTERLACE_NONE as u8,
+    };
+
+    if spng_set_ihdr(ctx, &mut ihdr) != 0 {
+        return Err(DiffError::PngError("Failed to set IHDR".into()));
+    }
+
+    if let Some(palette) = palette {
+        let mut plte = spng_plte {
+            n_entries: palette.len() as u32,
+            entries: [spng_plte_entry {
+                red: 0,
+                green: 0,
+                blue: 0,
+                alpha: 0,
+            }; 256],
+        };
+        let mut trns = spng_trns {
+            gray: 0,
+            red: 0,
+            green: 0,
+            blue: 0,
+            n_type3_entries: palette.len() as u32,
+            type3_alpha: [255; 256],
+        };
+        for (i, &color) in palette.iter().enumerate() {
+            let [red, green, blue, alpha] = color.to_le_bytes();
+            plte.entries[i] = spng_plte_entry {
+                red,
+                green,
+                blue,
+                alpha,
+            };
+            trns.type3_alpha[i] = alpha;
+        }
+        if spng_set_plte(ctx, &mut plte) != 0 {
+            return Err(DiffError::PngError("Failed to set PLTE".into()));
+        }
+        if palette.iter().any(|&color| color >> 24 != 0xFF) && spng_set_trns(ctx, &mut trns) != 0 {
+            return Err(DiffError::PngError("Failed to set tRNS".into()));
+        }
+    }
+
+    spng_set_option(
+        ctx,
+        spng_option_SPNG_FILTER_CHOICE,
+        spng_filter_choice_SPNG_DISABLE_FILTERING as c_int,
+    );
+    spng_set_option(
+        ctx,
+        spng_option_SPNG_IMG_COMPRESSION_LEVEL,
+        compression_level,
+    );
+    Ok(guard)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -317,4 +808,197 @@ mod tests {
         assert_eq!(img.height, 100);
         assert_eq!(img.data.len(), 100 * 100 * 4);
     }
+
+    #[test]
+    fn test_interlaced_png_decodes_row_major() {
+        // Odd dimensions leave some Adam7 passes partial or empty.
+        let (width, height) = (37u32, 11u32);
+        let data: Vec<u8> = (0..width * height * 4)
+            .map(|i| (i * 37 % 251) as u8)
+            .collect();
+        let png = blazediff_png::encode(
+            &blazediff_png::Image {
+                data: data.clone(),
+                width,
+                height,
+            },
+            &blazediff_png::EncodeOptions {
+                interlace: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(png[28], 1, "fixture should use Adam7 interlacing");
+
+        let decoded = decode_spng(&png, &DecodeLimits::default()).unwrap();
+        assert_eq!((decoded.width, decoded.height), (width, height));
+        assert!(decoded.data == data);
+    }
+
+    #[test]
+    fn test_indexed_png_round_trips_masks() {
+        let mut mask = Image::new_transparent(64, 48);
+        for y in 10..20 {
+            for x in 5..40 {
+                mask.set_pixel(x, y, if x % 2 == 0 { 0xFF00_00FF } else { 0xFF00_FFFF });
+            }
+        }
+        let indexed = encode_png_indexed(&mask, 6).unwrap();
+        assert!(indexed.len() < encode_png(&mask, 6).unwrap().len());
+        assert!(decode_png(&indexed).unwrap().data == mask.data);
+
+        let mut busy = Image::new(32, 32);
+        for (i, px) in busy.as_u32_mut().iter_mut().enumerate() {
+            *px = 0xFF00_0000 | i as u32;
+        }
+        assert!(build_palette(&busy).is_none());
+        assert_eq!(
+            encode_png_indexed(&busy, 6).unwrap(),
+            encode_png(&busy, 6).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_classify_spng_error() {
+        let classify = |code: spng_errno| classify_spng_error(code as c_int);
+        assert_eq!(classify(spng_errno_SPNG_EOF), DecodeFailure::Truncated);
+        assert_eq!(
+            classify(spng_errno_SPNG_EIDAT_TOO_SHORT),
+            DecodeFailure::Truncated
+        );
+        assert_eq!(
+            classify(spng_errno_SPNG_EINTERLACE_METHOD),
+            DecodeFailure::Unsupported
+        );
+        assert_eq!(classify(spng_errno_SPNG_EZLIB), DecodeFailure::Corrupt);
+        assert_eq!(classify(spng_errno_SPNG_EFILTER), DecodeFailure::Corrupt);
+    }
+
+    #[test]
+    fn test_decode_limits_reject_before_decoding() {
+        let png = encode_png(&Image::new(64, 32), 6).unwrap();
+        let fits = DecodeLimits {
+            max_decoded_bytes: Some(64 * 32 * 4),
+            max_dimension: Some(64),
+        };
+        assert!(decode_png_with_limits(&png, &fits).is_ok());
+
+        for limits in [
+            DecodeLimits {
+                max_dimension: Some(63),
+                ..Default::default()
+            },
+            DecodeLimits {
+                max_decoded_bytes: Some(64 * 32 * 4 - 1),
+                ..Default::default()
+            },
+        ] {
+            assert!(matches!(
+                decode_png_with_limits(&png, &limits),
+                Err(DiffError::TooLarge {
+                    format: "PNG",
+                    width: 64,
+                    height: 32,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_write_png_streams_rows() {
+        let mut img = Image::new(67, 45);
+        for (i, px) in img.as_u32_mut().iter_mut().enumerate() {
+            *px = (i as u32).wrapping_mul(2_654_435_761) | 0x8000_0000;
+        }
+        for level in [0, 6] {
+            let mut streamed = Vec::new();
+            write_png(&img, &mut streamed, level).unwrap();
+            assert!(
+                decode_png(&streamed).unwrap().data == img.data,
+                "level {level}"
+            );
+        }
+
+        struct Full;
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::StorageFull.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        assert!(matches!(
+            write_png(&img, &mut Full, 6),
+            Err(DiffError::IoError(e)) if e.kind() == std::io::ErrorKind::StorageFull
+        ));
+    }
+
+    #[test]
+    fn test_failed_streaming_save_leaves_no_file() {
+        let dir = std::env::temp_dir().join(format!("blazediff-replace-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.png");
+        let fail = |file: &mut std::io::BufWriter<File>| -> Result<(), DiffError> {
+            file.write_all(b"\x89PNG partial")?;
+            Err(DiffError::IoError(std::io::ErrorKind::StorageFull.into()))
+        };
+        assert!(matches!(
+            write_replacing(&path, fail),
+            Err(DiffError::IoError(_))
+        ));
+        let left = std::fs::read_dir(&dir).unwrap().count();
+
+        // An earlier save at the destination survives a failed one.
+        write_replacing(&path, |file| Ok(file.write_all(b"saved")?)).unwrap();
+        assert!(write_replacing(&path, fail).is_err());
+        let kept = std::fs::read(&path).unwrap();
+        let entries = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(left, 0);
+        assert_eq!(kept, b"saved");
+        assert_eq!(entries, 1);
+    }
+
+    #[test]
+    fn test_color_profile_round_trips() {
+        let img = Image::new(8, 4);
+        let dir = std::env::temp_dir();
+        for (i, profile) in [
+            ColorProfile::Icc {
+                name: "Display P3".into(),
+                data: (0..=255).collect(),
+            },
+            ColorProfile::Srgb {
+                rendering_intent: 1,
+            },
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let path = dir.join(format!("blazediff-profile-{}-{i}.png", std::process::id()));
+            save_png_with_profile(&img, &path, 6, Some(&profile)).unwrap();
+            let (loaded, metadata) =
+                load_png_with_metadata(&path, &LoadOptions::default()).unwrap();
+            assert!(loaded.data == img.data);
+            assert_eq!(metadata.color_profile.as_ref(), Some(&profile));
+
+            // The streaming path large images take writes the same chunk.
+            let mut file = File::create(&path).unwrap();
+            stream_png(&img, &mut file, 6, Some(&profile)).unwrap();
+            drop(file);
+            let (loaded, metadata) =
+                load_png_with_metadata(&path, &LoadOptions::default()).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert!(loaded.data == img.data);
+            assert_eq!(metadata.color_profile, Some(profile));
+        }
+
+        let path = dir.join(format!("blazediff-profile-none-{}.png", std::process::id()));
+        save_png(&img, &path).unwrap();
+        let (_, metadata) = load_png_with_metadata(&path, &LoadOptions::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(metadata, PngMetadata::default());
+    }
 }
@@ -1,7 +1,8 @@
 //! JPEG I/O via libjpeg-turbo (TurboJPEG API).
 
+use crate::aligned::AlignedBytes;
 use crate::turbojpeg_ffi::*;
-use crate::types::{DiffError, Image};
+use crate::types::{DecodeFailure, DecodeLimits, DiffError, Image};
 use memmap2::Mmap;
 use std::ffi::CStr;
 use std::fs::File;
@@ -30,14 +31,46 @@ unsafe fn get_tj_error(handle: tjhandle) -> String {
     }
 }
 
+/// Map a libjpeg-turbo error message to a broad failure cause. TurboJPEG
+/// only reports warning/fatal severity, so the libjpeg message text is the
+/// one place the cause is spelled out.
+fn classify_tj_error(message: &str) -> DecodeFailure {
+    let message = message.to_ascii_lowercase();
+    if message.contains("premature end") || message.contains("empty input") {
+        DecodeFailure::Truncated
+    } else if message.contains("unsupported") || message.contains("not supported") {
+        DecodeFailure::Unsupported
+    } else {
+        DecodeFailure::Corrupt
+    }
+}
+
 /// Load a JPEG image from file into RGBA format
 pub fn load_jpeg<P: AsRef<Path>>(path: P) -> Result<Image, DiffError> {
+    load_jpeg_with_limits(path, &DecodeLimits::default())
+}
+
+/// [`load_jpeg`] for untrusted input: fails with [`DiffError::TooLarge`]
+/// before allocating if the header declares an image beyond `limits`.
+pub fn load_jpeg_with_limits<P: AsRef<Path>>(
+    path: P,
+    limits: &DecodeLimits,
+) -> Result<Image, DiffError> {
     let file = File::open(path.as_ref())?;
     let file_data = unsafe { Mmap::map(&file)? };
-    decode_jpeg(&file_data)
+    decode_jpeg_with_limits(&file_data, limits)
 }
 
 pub(crate) fn decode_jpeg(file_data: &[u8]) -> Result<Image, DiffError> {
+    decode_jpeg_with_limits(file_data, &DecodeLimits::default())
+}
+
+pub(crate) fn decode_jpeg_with_limits(
+    file_data: &[u8],
+    limits: &DecodeLimits,
+) -> Result<Image, DiffError> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("decode_jpeg", bytes = file_data.len()).entered();
     unsafe {
         // Initialize decompressor
         let handle = tj3Init(TJINIT_TJINIT_DECOMPRESS as i32);
@@ -62,10 +95,10 @@ pub(crate) fn decode_jpeg(file_data: &[u8]) -> Result<Image, DiffError> {
         }
 
         // Allocate output buffer (RGBA = 4 bytes per pixel)
+        let buf_size = width as usize * height as usize * 4;
+        limits.check("JPEG", width, height, buf_size)?;
         let stride = (width * 4) as i32;
-        let buf_size = (stride as u32 * height) as usize;
-        let mut data: Vec<u8> = Vec::with_capacity(buf_size);
-        data.set_len(buf_size);
+        let mut data = AlignedBytes::zeroed(buf_size);
 
         // Decompress to RGBA
         if tj3Decompress8(
@@ -77,7 +110,14 @@ pub(crate) fn decode_jpeg(file_data: &[u8]) -> Result<Image, DiffError> {
             TJPF_TJPF_RGBA,
         ) != 0
         {
-            return Err(DiffError::JpegError(get_tj_error(handle)));
+            let detail = get_tj_error(handle);
+            return Err(DiffError::DecodeError {
+                format: "JPEG",
+                width,
+                height,
+                kind: classify_tj_error(&detail),
+                detail,
+            });
         }
 
         Ok(Image {
@@ -99,6 +139,9 @@ pub fn load_jpegs<P1: AsRef<Path> + Sync, P2: AsRef<Path> + Sync>(
 
 /// Save an RGBA image as JPEG with specified quality
 pub fn save_jpeg<P: AsRef<Path>>(image: &Image, path: P, quality: u8) -> Result<(), DiffError> {
+    #[cfg(feature = "tracing")]
+    let _span =
+        tracing::debug_span!("encode_jpeg", width = image.width, height = image.height).entered();
     unsafe {
         // Initialize compressor
         let handle = tj3Init(TJINIT_TJINIT_COMPRESS as i32);
@@ -181,4 +224,30 @@ mod tests {
         // Clean up
         std::fs::remove_file(temp_path).ok();
     }
+
+    #[test]
+    fn test_truncated_jpeg_reports_dimensions() {
+        let mut img = Image::new(64, 48);
+        for (i, byte) in img.data.iter_mut().enumerate() {
+            *byte = (i * 7) as u8;
+        }
+        let temp_path = "/tmp/blazediff_test_truncated.jpg";
+        save_jpeg(&img, temp_path, 95).expect("Failed to save JPEG");
+        let bytes = std::fs::read(temp_path).unwrap();
+        std::fs::remove_file(temp_path).ok();
+
+        match decode_jpeg(&bytes[..bytes.len() / 2]) {
+            Err(DiffError::DecodeError {
+                format,
+                width,
+                height,
+                kind,
+                ..
+            }) => {
+                assert_eq!((format, width, height), ("JPEG", 64, 48));
+                assert_eq!(kind, DecodeFailure::Truncated);
+            }
+            other => panic!("expected DecodeError, got {:?}", other.map(|_| ())),
+        }
+    }
 }
@@ -0,0 +1,90 @@
+//! CIE L*a*b* conversion and the CIE76 color difference.
+//!
+//! Colors are blended over white first, as the YIQ delta does, then taken
+//! from sRGB through linear RGB and XYZ under the D65 white point.
+
+use crate::yiq::unpack_pixel;
+
+/// Linear sRGB to XYZ, D65.
+const SRGB_TO_XYZ: [[f32; 3]; 3] = [
+    [0.412_456_4, 0.357_576_1, 0.180_437_5],
+    [0.212_672_9, 0.715_152_2, 0.072_175],
+    [0.019_333_9, 0.119_192, 0.950_304_1],
+];
+/// D65 reference white, with Y normalized to 1.
+const WHITE_XYZ: [f32; 3] = [0.950_47, 1.0, 1.088_83];
+/// Where the Lab companding function switches to its linear segment.
+const LAB_EPSILON: f32 = 216.0 / 24389.0;
+const LAB_KAPPA: f32 = 24389.0 / 27.0;
+
+/// `(L*, a*, b*)` of a packed pixel composited over white. L* runs 0 to 100.
+pub fn pixel_to_lab(pixel: u32) -> [f32; 3] {
+    let (r, g, b, a) = unpack_pixel(pixel);
+    let alpha = a as f32 / 255.0;
+    let linear = [r, g, b].map(|c| srgb_to_linear(255.0 + (c as f32 - 255.0) * alpha));
+    let [x, y, z] =
+        SRGB_TO_XYZ.map(|row| row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2]);
+    let [fx, fy, fz] = [x / WHITE_XYZ[0], y / WHITE_XYZ[1], z / WHITE_XYZ[2]].map(lab_f);
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// CIE76 Delta E: the Euclidean distance between the two pixels in L*a*b*.
+/// About 2.3 is a just-noticeable difference; black to white is 100.
+pub fn delta_e76(pixel_a: u32, pixel_b: u32) -> f32 {
+    if pixel_a == pixel_b {
+        return 0.0;
+    }
+    let [l1, a1, b1] = pixel_to_lab(pixel_a);
+    let [l2, a2, b2] = pixel_to_lab(pixel_b);
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+/// An sRGB channel value in 0.0..=255.0 to linear light in 0.0..=1.0.
+fn srgb_to_linear(value: f32) -> f32 {
+    let c = value / 255.0;
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn lab_f(t: f32) -> f32 {
+    if t > LAB_EPSILON {
+        t.cbrt()
+    } else {
+        (LAB_KAPPA * t + 16.0) / 116.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yiq::pack_pixel;
+
+    #[test]
+    fn test_pixel_to_lab() {
+        let white = pixel_to_lab(pack_pixel(255, 255, 255, 255));
+        assert!((white[0] - 100.0).abs() < 0.01 && white[1].abs() < 0.01 && white[2].abs() < 0.01);
+        let black = pixel_to_lab(pack_pixel(0, 0, 0, 255));
+        assert!(black.iter().all(|c| c.abs() < 0.01));
+        // Reference values for sRGB red.
+        let [l, a, b] = pixel_to_lab(pack_pixel(255, 0, 0, 255));
+        assert!((l - 53.24).abs() < 0.05, "{l}");
+        assert!((a - 80.09).abs() < 0.1, "{a}");
+        assert!((b - 67.20).abs() < 0.1, "{b}");
+        // Transparent is white.
+        assert!(delta_e76(0, pack_pixel(255, 255, 255, 255)) < 0.01);
+    }
+
+    #[test]
+    fn test_delta_e76() {
+        let black = pack_pixel(0, 0, 0, 255);
+        let white = pack_pixel(255, 255, 255, 255);
+        assert!((delta_e76(black, white) - 100.0).abs() < 0.01);
+        assert_eq!(delta_e76(black, black), 0.0);
+        let gray1 = pack_pixel(128, 128, 128, 255);
+        let gray2 = pack_pixel(129, 129, 129, 255);
+        assert!((0.2..1.0).contains(&delta_e76(gray1, gray2)));
+    }
+}
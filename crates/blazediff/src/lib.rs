@@ -17,14 +17,25 @@
 //! println!("{} pixels differ", result.diff_count);
 //! ```
 
+pub mod align;
+pub mod aligned;
 pub mod antialiasing;
+pub mod colormap;
+pub mod cvd;
 pub mod diff;
+pub mod filter;
+#[cfg(feature = "io")]
+pub mod format;
+#[cfg(feature = "image-interop")]
+mod image_interop;
 #[cfg(feature = "interpret")]
 pub mod interpret;
 #[cfg(feature = "io")]
 pub mod io;
 #[cfg(feature = "io")]
 pub mod jpeg_io;
+pub mod lab;
+pub mod metrics;
 #[cfg(feature = "napi")]
 mod napi;
 pub mod output;
@@ -32,6 +43,7 @@ pub mod output;
 mod python;
 #[cfg(feature = "io")]
 pub mod qoi_io;
+pub mod regions;
 pub mod simd;
 #[cfg(feature = "io")]
 #[allow(
@@ -55,21 +67,48 @@ mod wasm;
 pub mod yiq;
 
 // Re-export main types and functions
-pub use diff::diff;
+pub use align::estimate_shift;
+pub use aligned::AlignedBytes;
+pub use colormap::Colormap;
+pub use cvd::CvdType;
+pub use diff::{
+    assert_diff_matches, block_grid, compare_against_recorded, detected_backend, diff,
+    diff_normalized, diff_slices, diff_to_image, diff_u32, diff_with_painter, weighted_diff_score,
+    DiffContext, Differ, DifferPool, PooledDiffer,
+};
+#[cfg(feature = "io")]
+pub use format::{
+    compare_files, decode_image, diff_and_save, load_image, load_images, save_diff_image,
+    save_image, ImageFormat,
+};
 #[cfg(feature = "io")]
-pub use io::{encode_png, load_png, load_pngs, save_png, save_png_with_compression};
+pub use io::{
+    encode_png, encode_png_indexed, load_png, load_png_with, load_png_with_limits,
+    load_png_with_metadata, load_pngs, save_png, save_png_indexed, save_png_with_compression,
+    save_png_with_profile, write_png, ColorProfile, PngMetadata,
+};
 #[cfg(feature = "io")]
-pub use jpeg_io::{load_jpeg, load_jpegs, save_jpeg};
+pub use jpeg_io::{load_jpeg, load_jpeg_with_limits, load_jpegs, save_jpeg};
+pub use metrics::{mse, psnr, ssim, ssim_map};
 #[cfg(feature = "io")]
 pub use qoi_io::{load_qoi, load_qois, save_qoi};
-pub use types::{DiffError, DiffOptions, DiffResult, Image};
+pub use regions::diff_regions;
+pub use types::{
+    BackgroundSource, BlockRect, CanvasResize, ColorMetric, CompatMode, DecodeFailure,
+    DecodeLimits, DiffError, DiffOptions, DiffOptionsBuilder, DiffResult, Image, LoadOptions,
+    OutputMode, SimdBackend, SizeMismatchPolicy, JSON_SCHEMA_VERSION,
+};
+pub use yiq::{
+    color_delta, pack_pixel, pack_rgba, threshold_to_max_delta, unpack_pixel, unpack_rgba,
+    MAX_YIQ_DELTA,
+};
 
 /// Fuzzing-only oracle: exposes the pub(crate) spng reference decoder so the
 /// `blazediff_png` differential tests can check their decode against it.
 #[cfg(all(feature = "io", feature = "fuzzing"))]
 #[doc(hidden)]
 pub fn decode_spng_reference(data: &[u8]) -> Result<Image, DiffError> {
-    io::decode_spng(data)
+    io::decode_spng(data, &types::DecodeLimits::default())
 }
 
 /// Fuzzing-only oracle: decode through spng at an arbitrary `SPNG_FMT_*` and
@@ -84,3 +123,11 @@ pub fn decode_spng_reference_fmt(
 ) -> Result<(u32, u32, u8, u8, Vec<u8>), DiffError> {
     io::decode_spng_fmt(data, fmt, flags)
 }
+
+/// Bench-only: the serial cold pass on its own, returning the changed
+/// blocks as `(start_x, start_y, end_x, end_y)`, for `benches/diff.rs`.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub fn cold_pass(image1: &Image, image2: &Image, threshold: f64) -> Vec<(u32, u32, u32, u32)> {
+    diff::cold_pass(image1, image2, threshold)
+}
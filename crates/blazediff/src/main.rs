@@ -2,20 +2,24 @@
 //!
 //! Usage:
 //!   blazediff <image1> <image2> [diff] [options]
+//!   blazediff snapshot <candidate> --baseline-dir <dir> --id <name> [--update]
+//!   blazediff --capabilities
 //!
 //! Supports PNG, JPEG, and QOI formats (auto-detected by extension).
 //!
 //! Exit codes:
-//!   0 - Images identical (within threshold)
+//!   0 - Images identical (within threshold), no worse than --recorded-ratio,
+//!       or snapshot baseline written
 //!   1 - Images differ
 //!   2 - Error
 
 use blazediff::{
-    diff, interpret::interpret_with_output, load_jpeg, load_jpegs, load_png, load_pngs, load_qoi,
-    load_qois, save_jpeg, save_png_with_compression, save_qoi, DiffError, DiffOptions, Image,
+    compare_against_recorded, detected_backend, diff, interpret::interpret_with_output, load_image,
+    load_images, load_png, load_png_with_metadata, save_diff_image, save_png_with_compression,
+    save_png_with_profile, ColorProfile, DiffError, DiffOptions, Image, ImageFormat, LoadOptions,
+    JSON_SCHEMA_VERSION,
 };
 use clap::Parser;
-use rayon::prelude::*;
 use serde::Serialize;
 use std::path::Path;
 use std::process::ExitCode;
@@ -69,6 +73,99 @@ struct Args {
     /// Run structured interpretation after raw pixel diff
     #[arg(long)]
     interpret: bool,
+
+    /// Upscale the diff image by this integer factor (nearest-neighbor)
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    scale: u32,
+
+    /// Decimal places to round the diff percentage to, in text and JSON
+    /// output (default: 4 in text, unrounded in JSON)
+    #[arg(long)]
+    precision: Option<u8>,
+
+    /// Indent JSON output for reading in a terminal
+    #[arg(long)]
+    json_pretty: bool,
+
+    /// Changed fraction of pixels (0.0-1.0) last measured for this pair;
+    /// differing images still exit 0 while they change no more than this
+    #[arg(long)]
+    recorded_ratio: Option<f64>,
+
+    /// Extra changed fraction tolerated on top of --recorded-ratio
+    #[arg(long, requires = "recorded_ratio")]
+    max_regression: Option<f64>,
+
+    /// In text output, add an ASCII sparkline of changed pixels per row,
+    /// top of the image on the left
+    #[arg(long)]
+    row_profile: bool,
+
+    /// Draw faint gridlines on the diff image every N pixels, brighter
+    /// every 10th, for measuring displacement
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    grid: Option<u32>,
+
+    /// RGB color of the --grid lines (r,g,b)
+    #[arg(long, value_parser = parse_rgb, requires = "grid")]
+    grid_color: Option<[u8; 3]>,
+
+    /// Leave a rectangle (x,y,width,height) out of the comparison, such as
+    /// a timestamp or an ad slot; repeat for several
+    #[arg(long = "ignore", value_parser = parse_rect)]
+    ignore_regions: Vec<(u32, u32, u32, u32)>,
+
+    /// Copy image1's ICC profile or sRGB chunk into a PNG diff image, so
+    /// color-managed viewers render it like the inputs
+    #[arg(long)]
+    keep_color_profile: bool,
+}
+
+/// `blazediff snapshot`: compare against `<baseline-dir>/<id>.png`.
+#[derive(Parser, Debug)]
+#[command(name = "blazediff snapshot")]
+#[command(about = "Compare an image against a stored baseline, writing it with --update")]
+struct SnapshotArgs {
+    /// Candidate image path
+    #[arg(index = 1)]
+    candidate: String,
+
+    /// Directory holding the baseline PNGs
+    #[arg(long)]
+    baseline_dir: String,
+
+    /// Snapshot name; the baseline lives at <baseline-dir>/<id>.png
+    #[arg(long)]
+    id: String,
+
+    /// Write the candidate as the baseline, creating or replacing it
+    #[arg(short, long)]
+    update: bool,
+
+    /// PNG diff image to write when the snapshot does not match
+    #[arg(long)]
+    diff: Option<String>,
+
+    /// Color difference threshold (0.0-1.0)
+    #[arg(short, long, default_value = "0.1")]
+    threshold: f64,
+
+    /// Enable anti-aliasing detection
+    #[arg(short, long)]
+    antialiasing: bool,
+
+    /// Output format (json or text)
+    #[arg(long, default_value = "json")]
+    output_format: String,
+
+    /// Decimal places to round the diff percentage to, in text and JSON
+    /// output (default: 4 in text, unrounded in JSON)
+    #[arg(long)]
+    precision: Option<u8>,
+
+    /// Indent JSON output for reading in a terminal
+    #[arg(long)]
+    json_pretty: bool,
 }
 
 fn parse_rgb(value: &str) -> Result<[u8; 3], String> {
@@ -87,105 +184,108 @@ fn parse_rgb(value: &str) -> Result<[u8; 3], String> {
         .map_err(|_| "RGB color must contain exactly three channels".to_string())
 }
 
-/// Supported image formats
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum ImageFormat {
-    Png,
-    Jpeg,
-    Qoi,
-}
-
-impl ImageFormat {
-    fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
-        let ext = path.as_ref().extension()?.to_str()?.to_lowercase();
-        match ext.as_str() {
-            "png" => Some(ImageFormat::Png),
-            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
-            "qoi" => Some(ImageFormat::Qoi),
-            _ => None,
-        }
-    }
-}
-
-/// Load a single image, auto-detecting format
-#[allow(dead_code)]
-fn load_image<P: AsRef<Path>>(path: P) -> Result<Image, DiffError> {
-    let format = ImageFormat::from_path(&path).ok_or_else(|| {
-        DiffError::UnsupportedFormat(format!("Unsupported format: {}", path.as_ref().display()))
-    })?;
-    match format {
-        ImageFormat::Png => load_png(path),
-        ImageFormat::Jpeg => load_jpeg(path),
-        ImageFormat::Qoi => load_qoi(path),
-    }
-}
-
-/// Load two images in parallel, auto-detecting format
-fn load_images<P1: AsRef<Path> + Sync, P2: AsRef<Path> + Sync>(
-    path1: P1,
-    path2: P2,
-) -> Result<(Image, Image), DiffError> {
-    let fmt1 = ImageFormat::from_path(&path1).ok_or_else(|| {
-        DiffError::UnsupportedFormat(format!("Unsupported format: {}", path1.as_ref().display()))
-    })?;
-    let fmt2 = ImageFormat::from_path(&path2).ok_or_else(|| {
-        DiffError::UnsupportedFormat(format!("Unsupported format: {}", path2.as_ref().display()))
-    })?;
-
-    // If both are same format, use optimized parallel loader
-    if fmt1 == fmt2 {
-        return match fmt1 {
-            ImageFormat::Png => load_pngs(&path1, &path2),
-            ImageFormat::Jpeg => load_jpegs(&path1, &path2),
-            ImageFormat::Qoi => load_qois(&path1, &path2),
-        };
-    }
-
-    // Mixed formats: load in parallel anyway
-    let results: Vec<Result<Image, DiffError>> = [
-        (path1.as_ref().to_path_buf(), fmt1),
-        (path2.as_ref().to_path_buf(), fmt2),
-    ]
-    .par_iter()
-    .map(|(path, fmt)| match fmt {
-        ImageFormat::Png => load_png(path),
-        ImageFormat::Jpeg => load_jpeg(path),
-        ImageFormat::Qoi => load_qoi(path),
-    })
-    .collect();
-
-    let mut iter = results.into_iter();
-    Ok((iter.next().unwrap()?, iter.next().unwrap()?))
-}
+fn parse_rect(value: &str) -> Result<(u32, u32, u32, u32), String> {
+    let parts = value
+        .split(',')
+        .map(str::trim)
+        .map(|part| {
+            part.parse::<u32>()
+                .map_err(|_| format!("invalid rectangle value: {part}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
-/// Save an image, auto-detecting format from extension
-fn save_image<P: AsRef<Path>>(image: &Image, path: P, args: &Args) -> Result<(), DiffError> {
-    let format = ImageFormat::from_path(&path).ok_or_else(|| {
-        DiffError::UnsupportedFormat(format!("Unsupported format: {}", path.as_ref().display()))
-    })?;
-    match format {
-        ImageFormat::Png => save_png_with_compression(image, path, args.compression),
-        ImageFormat::Jpeg => save_jpeg(image, path, args.quality),
-        ImageFormat::Qoi => save_qoi(image, path),
+    match parts[..] {
+        [x, y, width, height] => Ok((x, y, width, height)),
+        _ => Err("rectangle must be x,y,width,height".to_string()),
     }
 }
 
 #[derive(Serialize)]
 struct JsonOutput {
+    #[serde(rename = "schemaVersion")]
+    schema_version: u32,
+    version: &'static str,
     #[serde(rename = "diffCount")]
     diff_count: u32,
     #[serde(rename = "diffPercentage")]
     diff_percentage: f64,
     identical: bool,
+    #[serde(rename = "exactMatch")]
+    exact_match: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SnapshotOutput {
+    #[serde(rename = "schemaVersion")]
+    schema_version: u32,
+    version: &'static str,
+    /// "match", "mismatch", "created", "updated" or "error"
+    status: &'static str,
+    baseline: String,
+    #[serde(rename = "diffCount", skip_serializing_if = "Option::is_none")]
+    diff_count: Option<u32>,
+    #[serde(rename = "diffPercentage", skip_serializing_if = "Option::is_none")]
+    diff_percentage: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     error: Option<String>,
 }
 
+/// `blazediff --capabilities`: what this binary was built with and the
+/// kernels it picks on this CPU.
+#[derive(Serialize)]
+struct Capabilities {
+    version: &'static str,
+    /// Image formats the CLI can read and write.
+    formats: Vec<&'static str>,
+    /// Optional cargo features compiled in.
+    features: Vec<&'static str>,
+    #[serde(rename = "simdBackend")]
+    simd_backend: &'static str,
+}
+
+impl Capabilities {
+    fn current() -> Self {
+        let features = [
+            ("parallel", cfg!(feature = "parallel")),
+            ("interpret", cfg!(feature = "interpret")),
+            ("tracing", cfg!(feature = "tracing")),
+            ("image-interop", cfg!(feature = "image-interop")),
+        ];
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            formats: vec!["png", "jpeg", "qoi"],
+            features: features
+                .into_iter()
+                .filter_map(|(name, enabled)| enabled.then_some(name))
+                .collect(),
+            simd_backend: detected_backend().as_str(),
+        }
+    }
+}
+
 fn main() -> ExitCode {
+    // `snapshot` can't be a clap subcommand without breaking the positional
+    // `blazediff <image1> <image2>` form, so it is dispatched by hand.
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|arg| arg == "snapshot")
+    {
+        return run_snapshot(&SnapshotArgs::parse_from(std::env::args_os().skip(1)));
+    }
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|arg| arg == "--capabilities")
+    {
+        println!("{}", to_json(&Capabilities::current(), true));
+        return ExitCode::SUCCESS;
+    }
+
     let args = Args::parse();
 
-    let (img1, img2) = match load_images(&args.image1, &args.image2) {
-        Ok(imgs) => imgs,
+    let (img1, img2, color_profile) = match load_inputs(&args) {
+        Ok(loaded) => loaded,
         Err(e) => {
             output_error(&args, &format!("Failed to load images: {}", e));
             return ExitCode::from(2);
@@ -210,15 +310,20 @@ fn main() -> ExitCode {
         diff_mask: args.diff_mask,
         diff_color_alt: args.diff_color_alt,
         compression: args.compression,
+        output_cleared: args.diff_mask,
+        track_row_counts: args.row_profile && args.output_format != "json",
+        grid_overlay: args.grid,
+        grid_color: args.grid_color.unwrap_or(DiffOptions::default().grid_color),
+        ignore_regions: args.ignore_regions.clone(),
         ..Default::default()
     };
 
     if args.interpret {
-        return run_interpret(&args, &img1, &img2, &options);
+        return run_interpret(&args, &img1, &img2, &options, color_profile.as_ref());
     }
 
     let mut output_image = if args.output.is_some() {
-        Some(Image::new_uninit(img1.width, img1.height))
+        Some(Image::new_output(&img1, options.diff_mask))
     } else {
         None
     };
@@ -233,7 +338,9 @@ fn main() -> ExitCode {
 
     if !result.identical {
         if let (Some(ref output_path), Some(ref output)) = (&args.output, &output_image) {
-            if let Err(e) = save_image(output, output_path, &args) {
+            if let Err(e) =
+                save_scaled_diff(&args, output, output_path, &options, color_profile.as_ref())
+            {
                 output_error(&args, &format!("Failed to save {}: {}", output_path, e));
                 return ExitCode::from(2);
             }
@@ -242,16 +349,72 @@ fn main() -> ExitCode {
 
     output_result(&args, &result);
 
-    if result.identical {
+    let within_recorded = args.recorded_ratio.is_some_and(|recorded| {
+        compare_against_recorded(&result, recorded, args.max_regression.unwrap_or(0.0))
+    });
+    if result.identical || within_recorded {
         ExitCode::from(0)
     } else {
         ExitCode::from(1)
     }
 }
 
-fn run_interpret(args: &Args, img1: &Image, img2: &Image, options: &DiffOptions) -> ExitCode {
+/// Load both inputs in parallel. With `--keep-color-profile`, a PNG image1
+/// also yields its color chunks from the same decode, for a PNG diff image.
+fn load_inputs(args: &Args) -> Result<(Image, Image, Option<ColorProfile>), DiffError> {
+    let png_out = args
+        .output
+        .as_ref()
+        .is_some_and(|path| ImageFormat::from_path(path) == Some(ImageFormat::Png));
+    if !(args.keep_color_profile
+        && png_out
+        && ImageFormat::from_path(&args.image1) == Some(ImageFormat::Png))
+    {
+        let (img1, img2) = load_images(&args.image1, &args.image2)?;
+        return Ok((img1, img2, None));
+    }
+    let (first, second) = rayon::join(
+        || load_png_with_metadata(&args.image1, &LoadOptions::default()),
+        || load_image(&args.image2),
+    );
+    let (img1, metadata) = first?;
+    Ok((img1, second?, metadata.color_profile))
+}
+
+/// Save the diff image, upscaled first when `--scale` asks for it, through
+/// [`save_diff_image`] unless `color_profile`, read from image1 by
+/// `--keep-color-profile`, needs the truecolor path.
+fn save_scaled_diff(
+    args: &Args,
+    output: &Image,
+    path: &str,
+    options: &DiffOptions,
+    color_profile: Option<&ColorProfile>,
+) -> Result<(), DiffError> {
+    let scaled;
+    let output = if args.scale > 1 {
+        scaled = output.upscale_nearest(args.scale);
+        &scaled
+    } else {
+        output
+    };
+    let png_out = ImageFormat::from_path(path) == Some(ImageFormat::Png);
+    if let (true, Some(profile)) = (png_out, color_profile) {
+        save_png_with_profile(output, path, args.compression, Some(profile))
+    } else {
+        save_diff_image(output, path, options, args.quality)
+    }
+}
+
+fn run_interpret(
+    args: &Args,
+    img1: &Image,
+    img2: &Image,
+    options: &DiffOptions,
+    color_profile: Option<&ColorProfile>,
+) -> ExitCode {
     let mut output_image = if args.output.is_some() {
-        Some(Image::new_uninit(img1.width, img1.height))
+        Some(Image::new_output(img1, options.diff_mask))
     } else {
         None
     };
@@ -265,7 +428,7 @@ fn run_interpret(args: &Args, img1: &Image, img2: &Image, options: &DiffOptions)
 
     if result.diff_count > 0 {
         if let (Some(ref output_path), Some(ref output)) = (&args.output, &output_image) {
-            if let Err(e) = save_image(output, output_path, args) {
+            if let Err(e) = save_scaled_diff(args, output, output_path, options, color_profile) {
                 output_error(args, &format!("Failed to save {output_path}: {e}"));
                 return ExitCode::from(2);
             }
@@ -285,32 +448,228 @@ fn run_interpret(args: &Args, img1: &Image, img2: &Image, options: &DiffOptions)
     }
 }
 
+fn run_snapshot(args: &SnapshotArgs) -> ExitCode {
+    let baseline_path = Path::new(&args.baseline_dir).join(format!("{}.png", args.id));
+    let baseline = baseline_path.display().to_string();
+    let report = |status: &'static str, result: Option<&blazediff::DiffResult>| {
+        output_snapshot(args, status, &baseline, result, None)
+    };
+    let fail = |message: String| {
+        output_snapshot(args, "error", &baseline, None, Some(message));
+        ExitCode::from(2)
+    };
+
+    let candidate = match load_image(&args.candidate) {
+        Ok(img) => img,
+        Err(e) => return fail(format!("Failed to load {}: {}", args.candidate, e)),
+    };
+
+    let exists = baseline_path.exists();
+    if args.update {
+        if let Err(e) = write_baseline(&args.candidate, &candidate, &baseline_path) {
+            return fail(format!("Failed to write baseline: {}", e));
+        }
+        report(if exists { "updated" } else { "created" }, None);
+        return ExitCode::from(0);
+    }
+    if !exists {
+        return fail("Baseline not found; rerun with --update to create it".to_string());
+    }
+
+    let base = match load_png(&baseline_path) {
+        Ok(img) => img,
+        Err(e) => return fail(format!("Failed to load baseline: {}", e)),
+    };
+    if base.width != candidate.width || base.height != candidate.height {
+        output_snapshot(
+            args,
+            "mismatch",
+            &baseline,
+            None,
+            Some(format!(
+                "Layout differs: {}x{} vs {}x{}",
+                base.width, base.height, candidate.width, candidate.height
+            )),
+        );
+        return ExitCode::from(1);
+    }
+
+    let options = DiffOptions {
+        threshold: args.threshold,
+        include_aa: !args.antialiasing,
+        ..Default::default()
+    };
+    let mut output_image = args
+        .diff
+        .as_ref()
+        .map(|_| Image::new_uninit(base.width, base.height));
+    let result = match diff(&base, &candidate, output_image.as_mut(), &options) {
+        Ok(r) => r,
+        Err(e) => return fail(format!("Diff failed: {}", e)),
+    };
+
+    if result.identical {
+        report("match", Some(&result));
+        return ExitCode::from(0);
+    }
+    if let (Some(path), Some(output)) = (&args.diff, &output_image) {
+        if let Err(e) = save_png_with_compression(output, path, 0) {
+            return fail(format!("Failed to save {}: {}", path, e));
+        }
+    }
+    report("mismatch", Some(&result));
+    ExitCode::from(1)
+}
+
+/// PNG candidates are copied byte-for-byte; other formats are re-encoded.
+fn write_baseline(source: &str, image: &Image, baseline: &Path) -> Result<(), DiffError> {
+    if let Some(dir) = baseline.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    if ImageFormat::from_path(source) == Some(ImageFormat::Png) {
+        std::fs::copy(source, baseline)?;
+        Ok(())
+    } else {
+        save_png_with_compression(image, baseline, 9)
+    }
+}
+
+fn output_snapshot(
+    args: &SnapshotArgs,
+    status: &'static str,
+    baseline: &str,
+    result: Option<&blazediff::DiffResult>,
+    error: Option<String>,
+) {
+    if args.output_format == "json" {
+        let json = SnapshotOutput {
+            schema_version: JSON_SCHEMA_VERSION,
+            version: env!("CARGO_PKG_VERSION"),
+            status,
+            baseline: baseline.to_string(),
+            diff_count: result.map(|r| r.diff_count),
+            diff_percentage: result.map(|r| round_percentage(r.diff_percentage, args.precision)),
+            error,
+        };
+        let line = to_json(&json, args.json_pretty);
+        if status == "error" {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    } else {
+        println!("Snapshot {}: {}", status, baseline);
+        if let Some(result) = result {
+            println!("Diff count: {}", result.diff_count);
+            println!(
+                "Diff percentage: {:.*}%",
+                args.precision.map_or(4, usize::from),
+                round_percentage(result.diff_percentage, args.precision)
+            );
+        }
+        if let Some(error) = error {
+            eprintln!("Error: {}", error);
+        }
+    }
+}
+
 fn output_result(args: &Args, result: &blazediff::DiffResult) {
     if args.output_format == "json" {
         let json = JsonOutput {
+            schema_version: JSON_SCHEMA_VERSION,
+            version: env!("CARGO_PKG_VERSION"),
             diff_count: result.diff_count,
-            diff_percentage: result.diff_percentage,
+            diff_percentage: round_percentage(result.diff_percentage, args.precision),
             identical: result.identical,
+            exact_match: result.exact_match,
             error: None,
         };
-        println!("{}", serde_json::to_string(&json).unwrap());
+        println!("{}", to_json(&json, args.json_pretty));
     } else {
         println!("Diff count: {}", result.diff_count);
-        println!("Diff percentage: {:.4}%", result.diff_percentage);
+        println!(
+            "Diff percentage: {:.*}%",
+            args.precision.map_or(4, usize::from),
+            round_percentage(result.diff_percentage, args.precision)
+        );
         println!("Identical: {}", result.identical);
+        println!("Exact match: {}", result.exact_match);
+        if let Some(rows) = &result.row_diff_counts {
+            println!("Rows: [{}]", sparkline(rows, SPARKLINE_WIDTH));
+        }
+    }
+}
+
+/// Most characters `--row-profile` draws; taller images sum adjacent rows.
+const SPARKLINE_WIDTH: usize = 64;
+
+/// `counts` squeezed into at most `width` buckets, each drawn by its share of
+/// the largest bucket. Empty buckets are blank, so any change stays visible.
+fn sparkline(counts: &[u32], width: usize) -> String {
+    const LEVELS: &[u8] = b".:-=+*#%@";
+    let per_bucket = counts.len().div_ceil(width).max(1);
+    let buckets: Vec<u64> = counts
+        .chunks(per_bucket)
+        .map(|chunk| chunk.iter().map(|&c| c as u64).sum())
+        .collect();
+    let max = buckets.iter().copied().max().unwrap_or(0).max(1);
+    buckets
+        .iter()
+        .map(|&sum| match sum {
+            0 => ' ',
+            _ => LEVELS[((sum * LEVELS.len() as u64 - 1) / max) as usize] as char,
+        })
+        .collect()
+}
+
+fn to_json<T: Serialize>(value: &T, pretty: bool) -> String {
+    if pretty {
+        serde_json::to_string_pretty(value).unwrap()
+    } else {
+        serde_json::to_string(value).unwrap()
+    }
+}
+
+/// Round to `precision` decimal places, so JSON carries the same value the
+/// text output shows instead of trailing float noise.
+fn round_percentage(value: f64, precision: Option<u8>) -> f64 {
+    match precision {
+        Some(places) => {
+            let scale = 10f64.powi(places.into());
+            (value * scale).round() / scale
+        }
+        None => value,
     }
 }
 
 fn output_error(args: &Args, message: &str) {
     if args.output_format == "json" {
         let json = JsonOutput {
+            schema_version: JSON_SCHEMA_VERSION,
+            version: env!("CARGO_PKG_VERSION"),
             diff_count: 0,
             diff_percentage: 0.0,
             identical: false,
+            exact_match: false,
             error: Some(message.to_string()),
         };
-        eprintln!("{}", serde_json::to_string(&json).unwrap());
+        eprintln!("{}", to_json(&json, args.json_pretty));
     } else {
         eprintln!("Error: {}", message);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_percentage() {
+        assert_eq!(round_percentage(12.345678, None), 12.345678);
+        assert_eq!(round_percentage(12.345678, Some(2)), 12.35);
+        assert_eq!(round_percentage(12.345678, Some(0)), 12.0);
+        assert_eq!(round_percentage(0.00004, Some(4)), 0.0);
+        assert_eq!(round_percentage(100.0, Some(3)), 100.0);
+        assert_eq!(round_percentage(12.5, Some(255)), 12.5);
+    }
+}
@@ -0,0 +1,197 @@
+//! Image-quality metrics: mean squared error and peak signal-to-noise ratio
+//! over the raw RGBA channels, and structural similarity over luminance.
+//!
+//! Unlike [`diff`](crate::diff), nothing is thresholded. MSE and PSNR count
+//! every channel, alpha included, as stored.
+
+use crate::diff::check_same_size;
+use crate::types::{DiffError, Image};
+use crate::yiq::luminance_over_white;
+
+/// SSIM's stabilizing constants, `(0.01 * 255)^2` and `(0.03 * 255)^2`.
+const SSIM_C1: f64 = 6.5025;
+const SSIM_C2: f64 = 58.5225;
+
+/// Bytes summed into a `u32` before it is folded into the `u64` total:
+/// 4096 squares of at most 255^2 stay below `u32::MAX`, and the short inner
+/// loop over `u32` lanes is one the compiler vectorizes.
+const CHUNK_BYTES: usize = 4096;
+
+/// Mean squared error per channel over all four RGBA channels, from 0.0
+/// (identical) to 65025.0 (every channel at opposite extremes). Images of
+/// different sizes fail with [`DiffError::SizeMismatch`]; empty images give
+/// 0.0.
+pub fn mse(a: &Image, b: &Image) -> Result<f64, DiffError> {
+    check_same_size(a, b)?;
+    if a.data.is_empty() {
+        return Ok(0.0);
+    }
+    Ok(squared_error(&a.data, &b.data) as f64 / a.data.len() as f64)
+}
+
+/// Peak signal-to-noise ratio in decibels, `20 * log10(255 / sqrt(mse))`,
+/// from [`mse`]. Identical images give `f64::INFINITY`.
+pub fn psnr(a: &Image, b: &Image) -> Result<f64, DiffError> {
+    let mse = mse(a, b)?;
+    if mse == 0.0 {
+        return Ok(f64::INFINITY);
+    }
+    Ok(20.0 * (255.0 / mse.sqrt()).log10())
+}
+
+/// Mean structural similarity (SSIM) of the two images' luminance, from
+/// 1.0 for identical structure down towards 0.0 (or below, for inverted
+/// structure). The mean of [`ssim_map`]; empty images give 1.0.
+pub fn ssim(a: &Image, b: &Image, window: u32) -> Result<f64, DiffError> {
+    let map = ssim_map(a, b, window)?;
+    if map.is_empty() {
+        return Ok(1.0);
+    }
+    Ok(map.iter().map(|&s| s as f64).sum::<f64>() / map.len() as f64)
+}
+
+/// SSIM of each `window` x `window` tile (at least 1x1), row by row, with
+/// `width.div_ceil(window)` tiles per row. Tiles on the right and bottom
+/// edges are clamped to the image, so they may be smaller.
+///
+/// Luminance is the YIQ Y channel of each pixel blended over white, as the
+/// diff's color delta sees it. Images of different sizes fail with
+/// [`DiffError::SizeMismatch`].
+pub fn ssim_map(a: &Image, b: &Image, window: u32) -> Result<Vec<f32>, DiffError> {
+    check_same_size(a, b)?;
+    let window = window.max(1);
+    let (width, height) = (a.width, a.height);
+    let luma_a: Vec<f64> = a
+        .as_u32()
+        .iter()
+        .map(|&p| luminance_over_white(p))
+        .collect();
+    let luma_b: Vec<f64> = b
+        .as_u32()
+        .iter()
+        .map(|&p| luminance_over_white(p))
+        .collect();
+
+    let mut map = Vec::with_capacity((width.div_ceil(window) * height.div_ceil(window)) as usize);
+    for y0 in (0..height).step_by(window as usize) {
+        let y1 = (y0 + window).min(height);
+        for x0 in (0..width).step_by(window as usize) {
+            let x1 = (x0 + window).min(width);
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in y0..y1 {
+                let row = y as usize * width as usize;
+                for i in row + x0 as usize..row + x1 as usize {
+                    let (la, lb) = (luma_a[i], luma_b[i]);
+                    sum_a += la;
+                    sum_b += lb;
+                    sum_aa += la * la;
+                    sum_bb += lb * lb;
+                    sum_ab += la * lb;
+                }
+            }
+            let n = ((x1 - x0) * (y1 - y0)) as f64;
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+            let score = ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+            map.push(score as f32);
+        }
+    }
+    Ok(map)
+}
+
+/// Sum of squared byte differences.
+fn squared_error(a: &[u8], b: &[u8]) -> u64 {
+    a.chunks(CHUNK_BYTES)
+        .zip(b.chunks(CHUNK_BYTES))
+        .map(|(a, b)| {
+            let chunk: u32 = a
+                .iter()
+                .zip(b)
+                .map(|(&x, &y)| {
+                    let d = x.abs_diff(y) as u32;
+                    d * d
+                })
+                .sum();
+            chunk as u64
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::yiq::pack_pixel;
+
+    fn solid(width: u32, height: u32, pixel: u32) -> Image {
+        let mut img = Image::new(width, height);
+        img.as_u32_mut().fill(pixel);
+        img
+    }
+
+    #[test]
+    fn test_mse_and_psnr_of_shifted_copy() {
+        let base = solid(33, 17, pack_pixel(100, 150, 200, 250));
+        let shifted = solid(33, 17, pack_pixel(101, 151, 201, 251));
+
+        assert_eq!(mse(&base, &shifted).unwrap(), 1.0);
+        // 20 * log10(255) for an MSE of 1.
+        let expected = 20.0 * 255f64.log10();
+        assert!((psnr(&base, &shifted).unwrap() - expected).abs() < 1e-12);
+        assert!((expected - 48.1308).abs() < 1e-4);
+
+        assert_eq!(mse(&base, &base).unwrap(), 0.0);
+        assert_eq!(psnr(&base, &base).unwrap(), f64::INFINITY);
+
+        // One channel of one pixel at the extremes.
+        let mut one = solid(2, 2, 0);
+        one.data[0] = 255;
+        assert_eq!(mse(&solid(2, 2, 0), &one).unwrap(), 65025.0 / 16.0);
+
+        assert!(matches!(
+            mse(&base, &solid(33, 18, 0)),
+            Err(DiffError::SizeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ssim_of_blurred_copy() {
+        // A fine checkerboard of gray levels: structure that blurring erases.
+        let mut img = Image::new(64, 48);
+        for y in 0..48 {
+            for x in 0..64 {
+                let v = if (x / 2 + y / 2) % 2 == 0 { 40 } else { 210 };
+                img.set_pixel(x, y, pack_pixel(v, v, v, 255));
+            }
+        }
+        let same = img.crop(0, 0, 64, 48);
+        assert!((ssim(&img, &same, 8).unwrap() - 1.0).abs() < 1e-9);
+
+        let blurred = img.gaussian_blur(2.0);
+        let score = ssim(&img, &blurred, 8).unwrap();
+        assert!(score < 0.5, "{score}");
+
+        // 64 / 7 and 48 / 7 leave clamped edge tiles.
+        let map = ssim_map(&img, &blurred, 7).unwrap();
+        assert_eq!(map.len(), 10 * 7);
+        assert!(map.iter().all(|&s| s < 0.9));
+        let mean = map.iter().map(|&s| s as f64).sum::<f64>() / map.len() as f64;
+        assert!((ssim(&img, &blurred, 7).unwrap() - mean).abs() < 1e-12);
+
+        assert_eq!(ssim(&Image::new(0, 0), &Image::new(0, 0), 8).unwrap(), 1.0);
+        assert!(matches!(
+            ssim_map(&img, &Image::new(64, 47), 8),
+            Err(DiffError::SizeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_squared_error_across_chunks() {
+        let a = vec![0u8; CHUNK_BYTES * 3 + 5];
+        let b = vec![255u8; CHUNK_BYTES * 3 + 5];
+        assert_eq!(squared_error(&a, &b), 65025 * a.len() as u64);
+    }
+}
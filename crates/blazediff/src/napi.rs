@@ -4,125 +4,22 @@
 //! without spawning child processes.
 
 use crate::{
-    diff,
+    decode_image, diff_and_save,
     interpret::types as itypes,
     interpret::{interpret, interpret_with_output},
-    load_jpeg, load_jpegs, load_png, load_pngs, save_jpeg, save_png_with_compression, DiffError,
-    DiffOptions, Image,
+    load_images, save_diff_image, DiffError, DiffOptions, Image, JSON_SCHEMA_VERSION,
 };
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
-use rayon::prelude::*;
-use std::path::Path;
-
-/// Supported image formats
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum ImageFormat {
-    Png,
-    Jpeg,
-    Qoi,
-}
-
-impl ImageFormat {
-    fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
-        let ext = path.as_ref().extension()?.to_str()?.to_lowercase();
-        match ext.as_str() {
-            "png" => Some(ImageFormat::Png),
-            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
-            "qoi" => Some(ImageFormat::Qoi),
-            _ => None,
-        }
-    }
-
-    fn from_bytes(data: &[u8]) -> Option<Self> {
-        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
-            return Some(ImageFormat::Png);
-        }
-        if data.starts_with(&[0xff, 0xd8, 0xff]) {
-            return Some(ImageFormat::Jpeg);
-        }
-        if data.starts_with(b"qoif") {
-            return Some(ImageFormat::Qoi);
-        }
-        None
-    }
-}
-
-/// Load two images in parallel, auto-detecting format
-fn load_images<P1: AsRef<Path> + Sync, P2: AsRef<Path> + Sync>(
-    path1: P1,
-    path2: P2,
-) -> std::result::Result<(Image, Image), DiffError> {
-    let fmt1 = ImageFormat::from_path(&path1).ok_or_else(|| {
-        DiffError::UnsupportedFormat(format!("Unsupported format: {}", path1.as_ref().display()))
-    })?;
-    let fmt2 = ImageFormat::from_path(&path2).ok_or_else(|| {
-        DiffError::UnsupportedFormat(format!("Unsupported format: {}", path2.as_ref().display()))
-    })?;
-
-    if fmt1 == fmt2 {
-        return match fmt1 {
-            ImageFormat::Png => load_pngs(&path1, &path2),
-            ImageFormat::Jpeg => load_jpegs(&path1, &path2),
-            ImageFormat::Qoi => crate::load_qois(&path1, &path2),
-        };
-    }
-
-    let results: Vec<std::result::Result<Image, DiffError>> = [
-        (path1.as_ref().to_path_buf(), fmt1),
-        (path2.as_ref().to_path_buf(), fmt2),
-    ]
-    .par_iter()
-    .map(|(path, fmt)| match fmt {
-        ImageFormat::Png => load_png(path),
-        ImageFormat::Jpeg => load_jpeg(path),
-        ImageFormat::Qoi => crate::load_qoi(path),
-    })
-    .collect();
-
-    let mut iter = results.into_iter();
-    Ok((iter.next().unwrap()?, iter.next().unwrap()?))
-}
-
-fn decode_image_buffer(data: &[u8]) -> std::result::Result<Image, DiffError> {
-    match ImageFormat::from_bytes(data) {
-        Some(ImageFormat::Png) => crate::io::decode_png(data),
-        Some(ImageFormat::Jpeg) => crate::jpeg_io::decode_jpeg(data),
-        Some(ImageFormat::Qoi) => crate::qoi_io::decode_qoi(data),
-        None => Err(DiffError::UnsupportedFormat(
-            "Unsupported image buffer format".to_string(),
-        )),
-    }
-}
 
 fn load_image_buffers(
     image1: &[u8],
     image2: &[u8],
 ) -> std::result::Result<(Image, Image), DiffError> {
-    let (result1, result2) = rayon::join(
-        || decode_image_buffer(image1),
-        || decode_image_buffer(image2),
-    );
+    let (result1, result2) = rayon::join(|| decode_image(image1), || decode_image(image2));
     Ok((result1?, result2?))
 }
 
-/// Save an image, auto-detecting format from extension
-fn save_image<P: AsRef<Path>>(
-    image: &Image,
-    path: P,
-    compression: u8,
-    quality: u8,
-) -> std::result::Result<(), DiffError> {
-    let format = ImageFormat::from_path(&path).ok_or_else(|| {
-        DiffError::UnsupportedFormat(format!("Unsupported format: {}", path.as_ref().display()))
-    })?;
-    match format {
-        ImageFormat::Png => save_png_with_compression(image, path, compression),
-        ImageFormat::Jpeg => save_jpeg(image, path, quality),
-        ImageFormat::Qoi => crate::save_qoi(image, path),
-    }
-}
-
 /// Options for image comparison
 #[napi(object)]
 pub struct NapiDiffOptions {
@@ -145,8 +42,14 @@ pub struct NapiDiffOptions {
 /// Result of image comparison
 #[napi(object)]
 pub struct NapiDiffResult {
+    /// Version of this result shape, see `JSON_SCHEMA_VERSION`
+    pub schema_version: u32,
+    /// blazediff crate version that produced the result
+    pub version: String,
     /// Whether the images match (identical within threshold)
     pub match_result: bool,
+    /// Whether the decoded pixels are byte-for-byte equal
+    pub exact_match: bool,
     /// Reason for mismatch: "pixel-diff", "layout-diff", or null if matched
     pub reason: Option<String>,
     /// Number of different pixels (only for pixel-diff)
@@ -197,7 +100,10 @@ fn compare_images(
     // Check for size mismatch - can't diff images of different sizes
     if img1.width != img2.width || img1.height != img2.height {
         return Ok(NapiDiffResult {
+            schema_version: JSON_SCHEMA_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_string(),
             match_result: false,
+            exact_match: false,
             reason: Some("layout-diff".to_string()),
             diff_count: None,
             diff_percentage: None,
@@ -211,23 +117,22 @@ fn compare_images(
         diff_mask,
         diff_color_alt,
         compression,
+        output_cleared: diff_mask,
         ..Default::default()
     };
 
     // Interpret mode: generate the visualization and structured analysis in one pass.
     if run_interpret {
-        let mut output_image = if diff_output.is_some() {
-            Some(Image::new_uninit(img1.width, img1.height))
-        } else {
-            None
-        };
+        let mut output_image = diff_output
+            .as_ref()
+            .map(|_| Image::new_output(&img1, diff_mask));
         let result = interpret_with_output(&img1, &img2, output_image.as_mut(), &diff_options)
             .map_err(|e| Error::new(Status::GenericFailure, format!("Interpret failed: {}", e)))?;
 
         let is_identical = result.diff_count == 0;
         if !is_identical {
             if let (Some(output_path), Some(output)) = (&diff_output, &output_image) {
-                save_image(output, output_path, compression, quality).map_err(|e| {
+                save_diff_image(output, output_path, &diff_options, quality).map_err(|e| {
                     Error::new(
                         Status::GenericFailure,
                         format!("Failed to save diff: {}", e),
@@ -241,7 +146,10 @@ fn compare_images(
         let regions: Vec<NapiChangeRegion> = result.regions.iter().map(convert_region).collect();
 
         return Ok(NapiDiffResult {
+            schema_version: JSON_SCHEMA_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_string(),
             match_result: is_identical,
+            exact_match: is_identical && img1.data == img2.data,
             reason: if is_identical {
                 None
             } else {
@@ -262,30 +170,15 @@ fn compare_images(
         });
     }
 
-    let mut output_image = if diff_output.is_some() {
-        Some(Image::new_uninit(img1.width, img1.height))
-    } else {
-        None
-    };
-
-    let result = diff(&img1, &img2, output_image.as_mut(), &diff_options)
+    let result = diff_and_save(&img1, &img2, diff_output, &diff_options, quality)
         .map_err(|e| Error::new(Status::GenericFailure, format!("Diff failed: {}", e)))?;
 
-    // Save diff image if requested and images differ
-    if !result.identical {
-        if let (Some(output_path), Some(output)) = (&diff_output, &output_image) {
-            save_image(output, output_path, compression, quality).map_err(|e| {
-                Error::new(
-                    Status::GenericFailure,
-                    format!("Failed to save diff: {}", e),
-                )
-            })?;
-        }
-    }
-
     if result.identical {
         Ok(NapiDiffResult {
+            schema_version: JSON_SCHEMA_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_string(),
             match_result: true,
+            exact_match: result.exact_match,
             reason: None,
             diff_count: None,
             diff_percentage: None,
@@ -293,7 +186,10 @@ fn compare_images(
         })
     } else {
         Ok(NapiDiffResult {
+            schema_version: JSON_SCHEMA_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_string(),
             match_result: false,
+            exact_match: false,
             reason: Some("pixel-diff".to_string()),
             diff_count: Some(result.diff_count),
             diff_percentage: Some(result.diff_percentage),
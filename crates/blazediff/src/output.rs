@@ -1,5 +1,11 @@
 //! Diff visualization output.
 
+#[cfg(target_arch = "aarch64")]
+use crate::diff::compute_gray_4_neon;
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+use crate::diff::compute_gray_4_wasm;
+#[cfg(target_arch = "x86_64")]
+use crate::diff::{compute_gray_4_sse, compute_gray_8_avx2};
 use crate::types::Image;
 use crate::yiq::{pack_pixel, YIQ_Y};
 
@@ -59,13 +65,14 @@ pub fn draw_gray_pixel_u32(source: &Image, pixel_index: usize, alpha: f64, outpu
     output.as_u32_mut()[pixel_index] = pixel;
 }
 
+/// Draw every pixel of `source` as the dimmed gray background, using the
+/// same vectorized kernels as [`fill_block_gray`].
 pub fn fill_gray(source: &Image, alpha: f64, output: &mut Image) {
-    let len = (source.width * source.height) as usize;
-    for i in 0..len {
-        draw_gray_pixel_u32(source, i, alpha, output);
-    }
+    fill_block_gray(source, output, alpha, 0, 0, source.width, source.height);
 }
 
+/// Draw the `start..end` rectangle of `source` as the gray background,
+/// 4 or 8 pixels at a time with NEON, SSE4.1, AVX2 or wasm SIMD.
 pub fn fill_block_gray(
     source: &Image,
     output: &mut Image,
@@ -75,15 +82,19 @@ pub fn fill_block_gray(
     end_x: u32,
     end_y: u32,
 ) {
-    let width = source.width;
-    for y in start_y..end_y {
-        for x in start_x..end_x {
-            let pixel_index = (y * width + x) as usize;
-            draw_gray_pixel_u32(source, pixel_index, alpha, output);
-        }
-    }
+    fill_block_gray_u32(
+        source.as_u32(),
+        source.width,
+        output.as_u32_mut(),
+        alpha,
+        start_x,
+        start_y,
+        end_x,
+        end_y,
+    );
 }
 
+/// Alias of [`fill_block_gray`], kept for existing callers.
 pub fn fill_block_gray_optimized(
     source: &Image,
     output: &mut Image,
@@ -92,41 +103,70 @@ pub fn fill_block_gray_optimized(
     start_y: u32,
     end_x: u32,
     end_y: u32,
+) {
+    fill_block_gray(source, output, alpha, start_x, start_y, end_x, end_y);
+}
+
+/// [`fill_block_gray_optimized`] over packed pixel slices `width` wide.
+///
+/// Vectorized with the hot pass's gray kernels, so unchanged blocks come out
+/// exactly as the hot pass draws unchanged pixels.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fill_block_gray_u32(
+    source: &[u32],
+    width: u32,
+    output: &mut [u32],
+    alpha: f64,
+    start_x: u32,
+    start_y: u32,
+    end_x: u32,
+    end_y: u32,
 ) {
     #[cfg(target_arch = "aarch64")]
     {
-        fill_block_gray_neon(source, output, alpha, start_x, start_y, end_x, end_y);
+        fill_block_gray_neon(source, width, output, alpha, start_x, start_y, end_x, end_y);
     }
 
     #[cfg(target_arch = "x86_64")]
     {
         if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
             unsafe {
-                fill_block_gray_avx2(source, output, alpha, start_x, start_y, end_x, end_y);
+                fill_block_gray_avx2(source, width, output, alpha, start_x, start_y, end_x, end_y);
             }
         } else if is_x86_feature_detected!("sse4.1") {
             unsafe {
-                fill_block_gray_sse(source, output, alpha, start_x, start_y, end_x, end_y);
+                fill_block_gray_sse(source, width, output, alpha, start_x, start_y, end_x, end_y);
             }
         } else {
-            fill_block_gray_scalar(source, output, alpha, start_x, start_y, end_x, end_y);
+            fill_block_gray_scalar(source, width, output, alpha, start_x, start_y, end_x, end_y);
         }
     }
 
-    #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
+    #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+    {
+        fill_block_gray_wasm(source, width, output, alpha, start_x, start_y, end_x, end_y);
+    }
+
+    #[cfg(not(any(
+        target_arch = "aarch64",
+        target_arch = "x86_64",
+        all(target_arch = "wasm32", target_feature = "simd128"),
+    )))]
     {
-        fill_block_gray_scalar(source, output, alpha, start_x, start_y, end_x, end_y);
+        fill_block_gray_scalar(source, width, output, alpha, start_x, start_y, end_x, end_y);
     }
 }
 
 const YIQ_Y_F32: [f32; 3] = [0.29889531, 0.58662247, 0.11448223];
 
 /// NEON implementation - processes 4 pixels at a time
+#[allow(clippy::too_many_arguments)]
 #[cfg(target_arch = "aarch64")]
 #[inline]
 fn fill_block_gray_neon(
-    source: &Image,
-    output: &mut Image,
+    source_pixels: &[u32],
+    width: u32,
+    output_pixels: &mut [u32],
     alpha: f64,
     start_x: u32,
     start_y: u32,
@@ -135,16 +175,12 @@ fn fill_block_gray_neon(
 ) {
     use std::arch::aarch64::*;
 
-    let width = source.width;
-    let source_pixels = source.as_u32();
-    let output_pixels = output.as_u32_mut();
     let alpha_scaled = (alpha / 255.0) as f32;
 
     unsafe {
         let mask_ff = vdupq_n_u32(0xFF);
         let v255 = vdupq_n_f32(255.0);
         let alpha_vec = vdupq_n_f32(alpha_scaled);
-        let alpha_byte = vdupq_n_u32(0xFF000000);
 
         for y in start_y..end_y {
             let row_start = (y * width + start_x) as usize;
@@ -157,57 +193,32 @@ fn fill_block_gray_neon(
             // Process 4 pixels at a time
             while offset + 4 <= row_width {
                 let pixels = vld1q_u32(src_ptr.add(offset));
-
-                // Extract RGBA
-                let r = vcvtq_f32_u32(vandq_u32(pixels, mask_ff));
-                let g = vcvtq_f32_u32(vandq_u32(vshrq_n_u32(pixels, 8), mask_ff));
-                let b = vcvtq_f32_u32(vandq_u32(vshrq_n_u32(pixels, 16), mask_ff));
-                let a = vcvtq_f32_u32(vshrq_n_u32(pixels, 24));
-
-                // Luminance: Y = 0.299*R + 0.587*G + 0.114*B
-                let luminance = vfmaq_n_f32(
-                    vfmaq_n_f32(vmulq_n_f32(r, YIQ_Y_F32[0]), g, YIQ_Y_F32[1]),
-                    b,
-                    YIQ_Y_F32[2],
-                );
-
-                // Gray = 255 + (luminance - 255) * alpha_scaled * pixel_alpha
-                let gray_f = vfmaq_f32(v255, vsubq_f32(luminance, v255), vmulq_f32(alpha_vec, a));
-                let gray_clamped = vminq_f32(vmaxq_f32(gray_f, vdupq_n_f32(0.0)), v255);
-                let gray_u32 = vcvtq_u32_f32(gray_clamped);
-
-                // Pack as RGBA (gray, gray, gray, 255)
-                let result = vorrq_u32(
-                    vorrq_u32(
-                        vorrq_u32(gray_u32, vshlq_n_u32(gray_u32, 8)),
-                        vshlq_n_u32(gray_u32, 16),
-                    ),
-                    alpha_byte,
-                );
-
-                vst1q_u32(dst_ptr.add(offset), result);
+                let grays = compute_gray_4_neon(pixels, alpha_vec, mask_ff, v255);
+                vst1q_u32(dst_ptr.add(offset), grays);
                 offset += 4;
             }
 
-            // Scalar remainder
-            while offset < row_width {
-                let idx = row_start + offset;
-                let pixel = source_pixels[idx];
-                let gray = compute_gray_f32_fast(pixel, alpha_scaled);
-                output_pixels[idx] = pack_gray_u32(gray);
-                offset += 1;
-            }
+            fill_row_gray_scalar(
+                source_pixels,
+                output_pixels,
+                row_start,
+                offset,
+                row_width,
+                alpha_scaled,
+            );
         }
     }
 }
 
 /// AVX2 implementation - processes 8 pixels at a time
+#[allow(clippy::too_many_arguments)]
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2", enable = "fma")]
 #[inline]
 unsafe fn fill_block_gray_avx2(
-    source: &Image,
-    output: &mut Image,
+    source_pixels: &[u32],
+    width: u32,
+    output_pixels: &mut [u32],
     alpha: f64,
     start_x: u32,
     start_y: u32,
@@ -216,19 +227,12 @@ unsafe fn fill_block_gray_avx2(
 ) {
     use std::arch::x86_64::*;
 
-    let width = source.width;
-    let source_pixels = source.as_u32();
-    let output_pixels = output.as_u32_mut();
     let alpha_scaled = (alpha / 255.0) as f32;
 
     let mask_ff = _mm256_set1_epi32(0xFF);
     let v255 = _mm256_set1_ps(255.0);
     let zero = _mm256_setzero_ps();
     let alpha_vec = _mm256_set1_ps(alpha_scaled);
-    let alpha_byte = _mm256_set1_epi32(0xFF000000u32 as i32);
-    let y_r = _mm256_set1_ps(YIQ_Y_F32[0]);
-    let y_g = _mm256_set1_ps(YIQ_Y_F32[1]);
-    let y_b = _mm256_set1_ps(YIQ_Y_F32[2]);
 
     for y in start_y..end_y {
         let row_start = (y * width + start_x) as usize;
@@ -241,35 +245,8 @@ unsafe fn fill_block_gray_avx2(
         // Process 8 pixels at a time
         while offset + 8 <= row_width {
             let pixels = _mm256_loadu_si256(src_ptr.add(offset) as *const __m256i);
-
-            // Extract RGBA
-            let r = _mm256_cvtepi32_ps(_mm256_and_si256(pixels, mask_ff));
-            let g = _mm256_cvtepi32_ps(_mm256_and_si256(_mm256_srli_epi32(pixels, 8), mask_ff));
-            let b = _mm256_cvtepi32_ps(_mm256_and_si256(_mm256_srli_epi32(pixels, 16), mask_ff));
-            let a = _mm256_cvtepi32_ps(_mm256_srli_epi32(pixels, 24));
-
-            // Luminance using FMA
-            let luminance = _mm256_fmadd_ps(r, y_r, _mm256_fmadd_ps(g, y_g, _mm256_mul_ps(b, y_b)));
-
-            // Gray = 255 + (luminance - 255) * alpha_scaled * pixel_alpha
-            let gray_f = _mm256_fmadd_ps(
-                _mm256_sub_ps(luminance, v255),
-                _mm256_mul_ps(alpha_vec, a),
-                v255,
-            );
-            let gray_clamped = _mm256_min_ps(_mm256_max_ps(gray_f, zero), v255);
-            let gray_u32 = _mm256_cvtps_epi32(gray_clamped);
-
-            // Pack as RGBA
-            let result = _mm256_or_si256(
-                _mm256_or_si256(
-                    _mm256_or_si256(gray_u32, _mm256_slli_epi32(gray_u32, 8)),
-                    _mm256_slli_epi32(gray_u32, 16),
-                ),
-                alpha_byte,
-            );
-
-            _mm256_storeu_si256(dst_ptr.add(offset) as *mut __m256i, result);
+            let grays = compute_gray_8_avx2(pixels, alpha_vec, mask_ff, v255, zero);
+            _mm256_storeu_si256(dst_ptr.add(offset) as *mut __m256i, grays);
             offset += 8;
         }
 
@@ -285,14 +262,14 @@ unsafe fn fill_block_gray_avx2(
             offset += 4;
         }
 
-        // Scalar remainder
-        while offset < row_width {
-            let idx = row_start + offset;
-            let pixel = source_pixels[idx];
-            let gray = compute_gray_f32_fast(pixel, alpha_scaled);
-            output_pixels[idx] = pack_gray_u32(gray);
-            offset += 1;
-        }
+        fill_row_gray_scalar(
+            source_pixels,
+            output_pixels,
+            row_start,
+            offset,
+            row_width,
+            alpha_scaled,
+        );
     }
 }
 
@@ -309,63 +286,35 @@ unsafe fn fill_row_gray_sse4(
 ) {
     use std::arch::x86_64::*;
 
-    let mask_ff = _mm_set1_epi32(0xFF);
-    let v255 = _mm_set1_ps(255.0);
-    let zero = _mm_setzero_ps();
-    let alpha_vec = _mm_set1_ps(alpha_scaled);
-    let alpha_byte = _mm_set1_epi32(0xFF000000u32 as i32);
-    let y_r = _mm_set1_ps(YIQ_Y_F32[0]);
-    let y_g = _mm_set1_ps(YIQ_Y_F32[1]);
-    let y_b = _mm_set1_ps(YIQ_Y_F32[2]);
-
     let src_ptr = source_pixels.as_ptr().add(row_start + offset);
     let dst_ptr = output_pixels.as_mut_ptr().add(row_start + offset);
 
     let pixels = _mm_loadu_si128(src_ptr as *const __m128i);
-
-    let r = _mm_cvtepi32_ps(_mm_and_si128(pixels, mask_ff));
-    let g = _mm_cvtepi32_ps(_mm_and_si128(_mm_srli_epi32(pixels, 8), mask_ff));
-    let b = _mm_cvtepi32_ps(_mm_and_si128(_mm_srli_epi32(pixels, 16), mask_ff));
-    let a = _mm_cvtepi32_ps(_mm_srli_epi32(pixels, 24));
-
-    let luminance = _mm_add_ps(
-        _mm_add_ps(_mm_mul_ps(r, y_r), _mm_mul_ps(g, y_g)),
-        _mm_mul_ps(b, y_b),
-    );
-    let gray_f = _mm_add_ps(
-        v255,
-        _mm_mul_ps(_mm_sub_ps(luminance, v255), _mm_mul_ps(alpha_vec, a)),
+    let grays = compute_gray_4_sse(
+        pixels,
+        _mm_set1_ps(alpha_scaled),
+        _mm_set1_epi32(0xFF),
+        _mm_set1_ps(255.0),
+        _mm_setzero_ps(),
     );
-    let gray_clamped = _mm_min_ps(_mm_max_ps(gray_f, zero), v255);
-    let gray_u32 = _mm_cvtps_epi32(gray_clamped);
-
-    let result = _mm_or_si128(
-        _mm_or_si128(
-            _mm_or_si128(gray_u32, _mm_slli_epi32(gray_u32, 8)),
-            _mm_slli_epi32(gray_u32, 16),
-        ),
-        alpha_byte,
-    );
-
-    _mm_storeu_si128(dst_ptr as *mut __m128i, result);
+    _mm_storeu_si128(dst_ptr as *mut __m128i, grays);
 }
 
 /// SSE implementation - processes 4 pixels at a time
+#[allow(clippy::too_many_arguments)]
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "sse4.1")]
 #[inline]
 unsafe fn fill_block_gray_sse(
-    source: &Image,
-    output: &mut Image,
+    source_pixels: &[u32],
+    width: u32,
+    output_pixels: &mut [u32],
     alpha: f64,
     start_x: u32,
     start_y: u32,
     end_x: u32,
     end_y: u32,
 ) {
-    let width = source.width;
-    let source_pixels = source.as_u32();
-    let output_pixels = output.as_u32_mut();
     let alpha_scaled = (alpha / 255.0) as f32;
 
     for y in start_y..end_y {
@@ -384,39 +333,111 @@ unsafe fn fill_block_gray_sse(
             offset += 4;
         }
 
-        while offset < row_width {
-            let idx = row_start + offset;
-            let pixel = source_pixels[idx];
-            let gray = compute_gray_f32_fast(pixel, alpha_scaled);
-            output_pixels[idx] = pack_gray_u32(gray);
-            offset += 1;
+        fill_row_gray_scalar(
+            source_pixels,
+            output_pixels,
+            row_start,
+            offset,
+            row_width,
+            alpha_scaled,
+        );
+    }
+}
+
+/// wasm v128 implementation - processes 4 pixels at a time
+#[allow(clippy::too_many_arguments)]
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+#[inline]
+fn fill_block_gray_wasm(
+    source_pixels: &[u32],
+    width: u32,
+    output_pixels: &mut [u32],
+    alpha: f64,
+    start_x: u32,
+    start_y: u32,
+    end_x: u32,
+    end_y: u32,
+) {
+    use std::arch::wasm32::*;
+
+    let alpha_scaled = (alpha / 255.0) as f32;
+
+    unsafe {
+        let mask_ff = u32x4_splat(0xFF);
+        let v255 = f32x4_splat(255.0);
+        let alpha_vec = f32x4_splat(alpha_scaled);
+
+        for y in start_y..end_y {
+            let row_start = (y * width + start_x) as usize;
+            let row_width = (end_x - start_x) as usize;
+            let mut offset = 0usize;
+
+            let src_ptr = source_pixels.as_ptr().add(row_start);
+            let dst_ptr = output_pixels.as_mut_ptr().add(row_start);
+
+            while offset + 4 <= row_width {
+                let pixels = v128_load(src_ptr.add(offset) as *const v128);
+                let grays = compute_gray_4_wasm(pixels, alpha_vec, mask_ff, v255);
+                v128_store(dst_ptr.add(offset) as *mut v128, grays);
+                offset += 4;
+            }
+
+            fill_row_gray_scalar(
+                source_pixels,
+                output_pixels,
+                row_start,
+                offset,
+                row_width,
+                alpha_scaled,
+            );
         }
     }
 }
 
+/// Scalar remainder of a row, from `offset` to `row_width`
+#[inline(always)]
+fn fill_row_gray_scalar(
+    source_pixels: &[u32],
+    output_pixels: &mut [u32],
+    row_start: usize,
+    offset: usize,
+    row_width: usize,
+    alpha_scaled: f32,
+) {
+    for idx in row_start + offset..row_start + row_width {
+        let gray = compute_gray_f32_fast(source_pixels[idx], alpha_scaled);
+        output_pixels[idx] = pack_gray_u32(gray);
+    }
+}
+
 /// Scalar fallback
-#[cfg(any(target_arch = "x86_64", not(target_arch = "aarch64")))]
+#[allow(clippy::too_many_arguments)]
+#[cfg(not(any(
+    target_arch = "aarch64",
+    all(target_arch = "wasm32", target_feature = "simd128"),
+)))]
 fn fill_block_gray_scalar(
-    source: &Image,
-    output: &mut Image,
+    source_pixels: &[u32],
+    width: u32,
+    output_pixels: &mut [u32],
     alpha: f64,
     start_x: u32,
     start_y: u32,
     end_x: u32,
     end_y: u32,
 ) {
-    let width = source.width;
-    let source_pixels = source.as_u32();
-    let output_pixels = output.as_u32_mut();
     let alpha_scaled = (alpha / 255.0) as f32;
 
     for y in start_y..end_y {
-        for x in start_x..end_x {
-            let idx = (y * width + x) as usize;
-            let pixel = source_pixels[idx];
-            let gray = compute_gray_f32_fast(pixel, alpha_scaled);
-            output_pixels[idx] = pack_gray_u32(gray);
-        }
+        let row_start = (y * width + start_x) as usize;
+        fill_row_gray_scalar(
+            source_pixels,
+            output_pixels,
+            row_start,
+            0,
+            (end_x - start_x) as usize,
+            alpha_scaled,
+        );
     }
 }
 
@@ -441,6 +462,72 @@ pub fn clear_transparent(output: &mut Image) {
     output.data.fill(0);
 }
 
+/// Quantize every RGB channel to `levels` evenly spaced values (clamped to
+/// at least 2). Alpha is untouched so diff_mask transparency survives.
+pub fn posterize(output: &mut Image, levels: u8) {
+    posterize_bytes(&mut output.data, levels);
+}
+
+/// [`posterize`] over raw RGBA bytes.
+pub(crate) fn posterize_bytes(data: &mut [u8], levels: u8) {
+    let steps = levels.max(2) as u32 - 1;
+    let mut lut = [0u8; 256];
+    for (value, entry) in lut.iter_mut().enumerate() {
+        let level = (value as u32 * steps + 127) / 255;
+        *entry = ((level * 255 + steps / 2) / steps) as u8;
+    }
+    for px in data.chunks_exact_mut(4) {
+        px[0] = lut[px[0] as usize];
+        px[1] = lut[px[1] as usize];
+        px[2] = lut[px[2] as usize];
+    }
+}
+
+/// Opacity of ordinary [`draw_grid`] lines, out of 255.
+const GRID_MINOR_ALPHA: u32 = 64;
+/// Opacity of every 10th [`draw_grid`] line.
+const GRID_MAJOR_ALPHA: u32 = 128;
+/// Every this many grid lines one is drawn brighter.
+const GRID_MAJOR_EVERY: u32 = 10;
+
+/// Composite translucent `color` lines over the output every `spacing`
+/// pixels (at least 1) from the top-left corner, with every 10th line
+/// brighter, as a ruler for measuring displacement. Crossings take the
+/// brighter line's opacity.
+pub fn draw_grid(output: &mut Image, spacing: u32, color: &[u8; 3]) {
+    let spacing = spacing.max(1);
+    let major = spacing.saturating_mul(GRID_MAJOR_EVERY);
+    let line_alpha = |at: u32| {
+        if at.is_multiple_of(major) {
+            GRID_MAJOR_ALPHA
+        } else if at.is_multiple_of(spacing) {
+            GRID_MINOR_ALPHA
+        } else {
+            0
+        }
+    };
+    let width = output.width as usize;
+    for (y, row) in output.data.chunks_exact_mut(width * 4).enumerate() {
+        let row_alpha = line_alpha(y as u32);
+        for (x, px) in row.chunks_exact_mut(4).enumerate() {
+            let alpha = row_alpha.max(line_alpha(x as u32));
+            if alpha > 0 {
+                blend_over(px, color, alpha);
+            }
+        }
+    }
+}
+
+/// Straight-alpha `color` at `alpha` (0-255) over the RGBA pixel `px`.
+fn blend_over(px: &mut [u8], color: &[u8; 3], alpha: u32) {
+    let below = px[3] as u32 * (255 - alpha) / 255;
+    let total = alpha + below;
+    for (channel, &value) in px[..3].iter_mut().zip(color) {
+        *channel = ((value as u32 * alpha + *channel as u32 * below + total / 2) / total) as u8;
+    }
+    px[3] = total as u8;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -484,4 +571,75 @@ mod tests {
 
         assert!(output.data.iter().all(|&x| x == 0));
     }
+
+    #[test]
+    fn test_posterize() {
+        let mut output = Image::new(2, 1);
+        output
+            .data
+            .copy_from_slice(&[0, 100, 200, 255, 63, 64, 192, 128]);
+
+        posterize(&mut output, 3);
+
+        assert_eq!(output.data, vec![0, 128, 255, 255, 0, 128, 255, 128]);
+
+        let mut two = Image::new(1, 1);
+        two.data.copy_from_slice(&[127, 128, 255, 0]);
+        posterize(&mut two, 0);
+        assert_eq!(two.data, vec![0, 255, 255, 0]);
+    }
+
+    #[test]
+    fn test_draw_grid() {
+        let mut output = Image::new(25, 3);
+        output.as_u32_mut().fill(pack_pixel(100, 100, 100, 255));
+        draw_grid(&mut output, 2, &[200, 0, 0]);
+
+        let minor = pack_pixel(125, 75, 75, 255);
+        let major = pack_pixel(150, 50, 50, 255);
+        let plain = pack_pixel(100, 100, 100, 255);
+        assert_eq!(output.get_pixel(0, 1), major, "column 0");
+        assert_eq!(output.get_pixel(20, 1), major, "column 20");
+        assert_eq!(output.get_pixel(2, 1), minor);
+        assert_eq!(output.get_pixel(3, 1), plain);
+        assert_eq!(output.get_pixel(3, 2), minor, "row 2");
+        assert_eq!(output.get_pixel(3, 0), major, "row 0");
+
+        let mut transparent = Image::new_transparent(1, 1);
+        draw_grid(&mut transparent, 4, &[0, 128, 255]);
+        assert_eq!(transparent.get_pixel(0, 0), pack_pixel(0, 128, 255, 128));
+    }
+
+    #[test]
+    fn test_fill_block_gray() {
+        // 37 wide, so rows end in a 4-pixel step and a scalar tail.
+        let mut source = Image::new(37, 5);
+        for (i, pixel) in source.as_u32_mut().iter_mut().enumerate() {
+            *pixel = pack_pixel((i * 7) as u8, (i * 13) as u8, (i * 29) as u8, (i * 3) as u8);
+        }
+        let mut filled = Image::new(37, 5);
+        fill_gray(&source, 0.1, &mut filled);
+        let mut per_pixel = Image::new(37, 5);
+        for i in 0..37 * 5 {
+            draw_gray_pixel_u32(&source, i, 0.1, &mut per_pixel);
+        }
+        // f32 against the per-pixel f64 math: a level apart at most.
+        for (&a, &b) in filled.as_u32().iter().zip(per_pixel.as_u32()) {
+            let [ga, _, _, alpha] = a.to_le_bytes();
+            assert_eq!(a, u32::from_le_bytes([ga, ga, ga, 255]));
+            assert_eq!(alpha, 255);
+            assert!((ga as i32 - b.to_le_bytes()[0] as i32).abs() <= 1);
+        }
+
+        // Only the rectangle is drawn.
+        let mut block = Image::new(37, 5);
+        fill_block_gray(&source, &mut block, 0.1, 3, 1, 30, 4);
+        for y in 0..5 {
+            for x in 0..37 {
+                let inside = (3..30).contains(&x) && (1..4).contains(&y);
+                let expected = if inside { filled.get_pixel(x, y) } else { 0 };
+                assert_eq!(block.get_pixel(x, y), expected, "({x}, {y})");
+            }
+        }
+    }
 }
@@ -4,84 +4,11 @@
 //! to Python via maturin-built wheels.
 
 use crate::{
-    diff, interpret::interpret as run_interpret_fn, interpret::types as itypes, load_jpeg,
-    load_jpegs, load_png, load_pngs, save_jpeg, save_png_with_compression, DiffError, DiffOptions,
-    Image,
+    diff_and_save, interpret::interpret as run_interpret_fn, interpret::types as itypes,
+    load_images, DiffOptions,
 };
 use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
-use rayon::prelude::*;
-use std::path::Path;
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum ImageFormat {
-    Png,
-    Jpeg,
-    Qoi,
-}
-
-impl ImageFormat {
-    fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
-        let ext = path.as_ref().extension()?.to_str()?.to_lowercase();
-        match ext.as_str() {
-            "png" => Some(ImageFormat::Png),
-            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
-            "qoi" => Some(ImageFormat::Qoi),
-            _ => None,
-        }
-    }
-}
-
-fn load_images<P1: AsRef<Path> + Sync, P2: AsRef<Path> + Sync>(
-    path1: P1,
-    path2: P2,
-) -> std::result::Result<(Image, Image), DiffError> {
-    let fmt1 = ImageFormat::from_path(&path1).ok_or_else(|| {
-        DiffError::UnsupportedFormat(format!("Unsupported format: {}", path1.as_ref().display()))
-    })?;
-    let fmt2 = ImageFormat::from_path(&path2).ok_or_else(|| {
-        DiffError::UnsupportedFormat(format!("Unsupported format: {}", path2.as_ref().display()))
-    })?;
-
-    if fmt1 == fmt2 {
-        return match fmt1 {
-            ImageFormat::Png => load_pngs(&path1, &path2),
-            ImageFormat::Jpeg => load_jpegs(&path1, &path2),
-            ImageFormat::Qoi => crate::load_qois(&path1, &path2),
-        };
-    }
-
-    let results: Vec<std::result::Result<Image, DiffError>> = [
-        (path1.as_ref().to_path_buf(), fmt1),
-        (path2.as_ref().to_path_buf(), fmt2),
-    ]
-    .par_iter()
-    .map(|(path, fmt)| match fmt {
-        ImageFormat::Png => load_png(path),
-        ImageFormat::Jpeg => load_jpeg(path),
-        ImageFormat::Qoi => crate::load_qoi(path),
-    })
-    .collect();
-
-    let mut iter = results.into_iter();
-    Ok((iter.next().unwrap()?, iter.next().unwrap()?))
-}
-
-fn save_image<P: AsRef<Path>>(
-    image: &Image,
-    path: P,
-    compression: u8,
-    quality: u8,
-) -> std::result::Result<(), DiffError> {
-    let format = ImageFormat::from_path(&path).ok_or_else(|| {
-        DiffError::UnsupportedFormat(format!("Unsupported format: {}", path.as_ref().display()))
-    })?;
-    match format {
-        ImageFormat::Png => save_png_with_compression(image, path, compression),
-        ImageFormat::Jpeg => save_jpeg(image, path, quality),
-        ImageFormat::Qoi => crate::save_qoi(image, path),
-    }
-}
 
 // ─── Result types ────────────────────────────────────────────────────────────
 
@@ -300,6 +227,7 @@ fn compare(
         include_aa: !antialiasing,
         diff_mask,
         compression,
+        output_cleared: diff_mask,
         ..Default::default()
     };
 
@@ -334,22 +262,9 @@ fn compare(
         });
     }
 
-    let mut output_image = if diff_output.is_some() {
-        Some(Image::new_uninit(img1.width, img1.height))
-    } else {
-        None
-    };
-
-    let result = diff(&img1, &img2, output_image.as_mut(), &diff_options)
+    let result = diff_and_save(&img1, &img2, diff_output, &diff_options, quality)
         .map_err(|e| PyValueError::new_err(format!("Diff failed: {}", e)))?;
 
-    if !result.identical {
-        if let (Some(output_path), Some(ref output)) = (diff_output, &output_image) {
-            save_image(output, output_path, compression, quality)
-                .map_err(|e| PyValueError::new_err(format!("Failed to save diff: {}", e)))?;
-        }
-    }
-
     if result.identical {
         Ok(PyDiffResult {
             match_result: true,
@@ -1,5 +1,6 @@
 //! QOI I/O via qoi-rust (https://github.com/aldanor/qoi-rust).
 
+use crate::aligned::AlignedBytes;
 use crate::types::{DiffError, Image};
 use memmap2::Mmap;
 use std::fs::File;
@@ -13,28 +14,18 @@ pub fn load_qoi<P: AsRef<Path>>(path: P) -> Result<Image, DiffError> {
 }
 
 pub(crate) fn decode_qoi(file_data: &[u8]) -> Result<Image, DiffError> {
-    let (header, pixels) =
-        qoi::decode_to_vec(file_data).map_err(|e| DiffError::QoiError(e.to_string()))?;
-
-    let width = header.width;
-    let height = header.height;
-
-    let data = match header.channels {
-        qoi::Channels::Rgb => {
-            let mut rgba = Vec::with_capacity((width * height * 4) as usize);
-            for chunk in pixels.chunks_exact(3) {
-                rgba.extend_from_slice(chunk);
-                rgba.push(255);
-            }
-            rgba
-        }
-        qoi::Channels::Rgba => pixels,
-    };
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("decode_qoi", bytes = file_data.len()).entered();
+    // RGB files decode straight to RGBA with opaque alpha.
+    let mut decoder = qoi::Decoder::new(file_data)?.with_channels(qoi::Channels::Rgba);
+    let mut data = AlignedBytes::zeroed(decoder.required_buf_len());
+    decoder.decode_to_buf(&mut *data)?;
 
+    let header = decoder.header();
     Ok(Image {
         data,
-        width,
-        height,
+        width: header.width,
+        height: header.height,
     })
 }
 
@@ -47,8 +38,10 @@ pub fn load_qois<P1: AsRef<Path> + Sync, P2: AsRef<Path> + Sync>(
 }
 
 pub fn save_qoi<P: AsRef<Path>>(image: &Image, path: P) -> Result<(), DiffError> {
-    let encoded = qoi::encode_to_vec(&image.data, image.width, image.height)
-        .map_err(|e| DiffError::QoiError(e.to_string()))?;
+    #[cfg(feature = "tracing")]
+    let _span =
+        tracing::debug_span!("encode_qoi", width = image.width, height = image.height).entered();
+    let encoded = qoi::encode_to_vec(&image.data, image.width, image.height)?;
 
     let mut file = File::create(path.as_ref())?;
     file.write_all(&encoded)?;
@@ -84,4 +77,10 @@ mod tests {
 
         std::fs::remove_file(temp_path).ok();
     }
+
+    #[test]
+    fn test_qoi_decode_error_converts() {
+        let result = decode_qoi(b"not a qoi file");
+        assert!(matches!(result, Err(DiffError::QoiError(_))));
+    }
 }
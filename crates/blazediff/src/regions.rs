@@ -0,0 +1,166 @@
+//! Connected regions of a per-pixel diff mask, such as
+//! [`DiffResult::pixel_mask`](crate::DiffResult::pixel_mask).
+
+use crate::types::DiffError;
+
+struct UnionFind {
+    parent: Vec<u32>,
+    rank: Vec<u8>,
+}
+
+impl UnionFind {
+    fn new(size: usize) -> Self {
+        Self {
+            parent: (0..size as u32).collect(),
+            rank: vec![0; size],
+        }
+    }
+
+    fn find(&mut self, mut x: u32) -> u32 {
+        while self.parent[x as usize] != x {
+            self.parent[x as usize] = self.parent[self.parent[x as usize] as usize];
+            x = self.parent[x as usize];
+        }
+        x
+    }
+
+    fn union(&mut self, a: u32, b: u32) {
+        let ra = self.find(a);
+        let rb = self.find(b);
+        if ra == rb {
+            return;
+        }
+        match self.rank[ra as usize].cmp(&self.rank[rb as usize]) {
+            std::cmp::Ordering::Less => self.parent[ra as usize] = rb,
+            std::cmp::Ordering::Greater => self.parent[rb as usize] = ra,
+            std::cmp::Ordering::Equal => {
+                self.parent[rb as usize] = ra;
+                self.rank[ra as usize] += 1;
+            }
+        }
+    }
+}
+
+/// Label each foreground pixel with its connected component ID (4-connectivity).
+/// Returns a label map: 0 = background, >0 = component label.
+pub(crate) fn label_connected_components(mask: &[bool], width: u32, height: u32) -> Vec<i32> {
+    let w = width as usize;
+    let h = height as usize;
+    let total = w * h;
+    let mut labels = vec![0i32; total];
+
+    if total == 0 {
+        return labels;
+    }
+
+    let mut uf = UnionFind::new(total);
+
+    for y in 0..h {
+        for x in 0..w {
+            let idx = y * w + x;
+            if !mask[idx] {
+                continue;
+            }
+            if x > 0 && mask[idx - 1] {
+                uf.union(idx as u32, (idx - 1) as u32);
+            }
+            if y > 0 && mask[idx - w] {
+                uf.union(idx as u32, (idx - w) as u32);
+            }
+        }
+    }
+
+    // Map roots to sequential labels
+    let mut root_to_label: std::collections::HashMap<u32, i32> = std::collections::HashMap::new();
+    let mut next_label = 1i32;
+
+    for i in 0..total {
+        if !mask[i] {
+            continue;
+        }
+        let root = uf.find(i as u32);
+        let label = *root_to_label.entry(root).or_insert_with(|| {
+            let l = next_label;
+            next_label += 1;
+            l
+        });
+        labels[i] = label;
+    }
+
+    labels
+}
+
+/// Bounding rectangles `(x, y, width, height)` of the 4-connected regions
+/// of `mask` (row-major, `width * height` entries), largest area first.
+/// Regions of equal area keep the order of their first pixel. A mask of
+/// another length fails with [`DiffError::InvalidDataSize`].
+pub fn diff_regions(
+    mask: &[bool],
+    width: u32,
+    height: u32,
+) -> Result<Vec<(u32, u32, u32, u32)>, DiffError> {
+    let expected = width as usize * height as usize;
+    if mask.len() != expected {
+        return Err(DiffError::InvalidDataSize {
+            expected,
+            actual: mask.len(),
+        });
+    }
+    let labels = label_connected_components(mask, width, height);
+    // Per label: min x, min y, max x, max y.
+    let mut bounds: Vec<(u32, u32, u32, u32)> = Vec::new();
+    for (i, &label) in labels.iter().enumerate() {
+        if label == 0 {
+            continue;
+        }
+        let (x, y) = ((i % width as usize) as u32, (i / width as usize) as u32);
+        let label = label as usize - 1;
+        if label == bounds.len() {
+            bounds.push((x, y, x, y));
+        }
+        let b = &mut bounds[label];
+        *b = (b.0.min(x), b.1.min(y), b.2.max(x), b.3.max(y));
+    }
+    let mut regions: Vec<_> = bounds
+        .into_iter()
+        .map(|(x0, y0, x1, y1)| (x0, y0, x1 - x0 + 1, y1 - y0 + 1))
+        .collect();
+    regions.sort_by_key(|&(_, _, w, h)| std::cmp::Reverse(w as u64 * h as u64));
+    Ok(regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_regions() {
+        // 8 x 5: a 3 x 2 block, an L of three pixels, and a lone pixel.
+        #[rustfmt::skip]
+        let mask = [
+            0, 0, 0, 0, 0, 0, 0, 1,
+            0, 1, 1, 1, 0, 0, 0, 0,
+            0, 1, 1, 1, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 1, 0, 0,
+            0, 0, 0, 0, 0, 1, 1, 0,
+        ]
+        .map(|m| m == 1);
+        assert_eq!(
+            diff_regions(&mask, 8, 5).unwrap(),
+            vec![(1, 1, 3, 2), (5, 3, 2, 2), (7, 0, 1, 1)]
+        );
+        // Diagonal neighbors are separate regions.
+        assert_eq!(
+            diff_regions(&[true, false, false, true], 2, 2).unwrap(),
+            vec![(0, 0, 1, 1), (1, 1, 1, 1)]
+        );
+        assert!(diff_regions(&[], 0, 0).unwrap().is_empty());
+        assert!(matches!(
+            diff_regions(&mask, 8, 4),
+            Err(DiffError::InvalidDataSize {
+                expected: 32,
+                actual: 40
+            })
+        ));
+    }
+}
@@ -1,49 +1,95 @@
 //! Core types.
 
+use crate::aligned::AlignedBytes;
+use crate::colormap::Colormap;
+use crate::cvd::CvdType;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Version of the JSON result shape emitted by the CLI and the N-API binding.
+/// Bump whenever a field is added, renamed or removed.
+pub const JSON_SCHEMA_VERSION: u32 = 2;
+
+/// Byte that debug builds fill [`Image::new_uninit`] buffers with.
+#[cfg(any(test, debug_assertions))]
+pub(crate) const UNINIT_POISON: u8 = 0xCD;
 
 pub struct Image {
-    pub data: Vec<u8>, // RGBA, 4 bytes/pixel
+    pub data: AlignedBytes, // RGBA, 4 bytes/pixel, 16-byte aligned
     pub width: u32,
     pub height: u32,
 }
 
+/// Dimensions only: the pixel data would swamp any log.
+impl std::fmt::Debug for Image {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Image")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Image {
     pub fn new(width: u32, height: u32) -> Self {
         Self {
-            data: vec![0u8; (width * height * 4) as usize],
+            data: AlignedBytes::zeroed(width as usize * height as usize * 4),
             width,
             height,
         }
     }
 
+    /// Create an image with every pixel fully transparent (all bytes zero).
+    ///
+    /// This is the buffer `diff_mask` mode wants: pair it with
+    /// [`DiffOptions::output_cleared`] and [`crate::diff`] skips its own
+    /// `clear_transparent` pass. The zeroed allocation goes through
+    /// `calloc`, which for large buffers maps already-cleared pages instead
+    /// of writing every byte, so this is cheaper than `new_uninit` followed
+    /// by a clear.
+    pub fn new_transparent(width: u32, height: u32) -> Self {
+        Self::new(width, height)
+    }
+
+    /// Allocate a diff-output buffer the size of `like`: transparent for a
+    /// `diff_mask` run (set [`DiffOptions::output_cleared`] to match),
+    /// otherwise [`Image::new_uninit`], since every other mode overwrites
+    /// the whole buffer.
+    pub fn new_output(like: &Image, diff_mask: bool) -> Self {
+        if diff_mask {
+            Self::new_transparent(like.width, like.height)
+        } else {
+            Self::new_uninit(like.width, like.height)
+        }
+    }
+
     /// Create an image whose pixel data is left uninitialized.
     ///
     /// Use this for diff-output buffers that the [`crate::diff`] pipeline
     /// fully overwrites — either by `clear_transparent` (diff_mask mode),
-    /// by the deferred gray-fill pass (when at least one block differs and
-    /// diff_mask is off), or by the hot pass writing changed blocks. The
-    /// only call path that does not overwrite is the early "identical"
-    /// short-circuit: in that case `result.identical` is true and every
-    /// shipping front-end (CLI, N-API, Python, the WASM in-place copy)
-    /// already skips reading the output buffer.
+    /// by the gray-fill passes (diff_mask off), or by the hot pass writing
+    /// changed blocks. The only call path that does not overwrite is
+    /// [`DiffOptions::skip_output_when_identical`] on identical inputs: in
+    /// that case `result.identical` is true and the caller should not read
+    /// the output buffer.
+    ///
+    /// Release builds allocate through `calloc`, which for big buffers maps
+    /// already-cleared pages instead of writing every byte, so this still
+    /// saves the 64 MB memset per 4K-image diff that `vec![0u8; ...]` would
+    /// cost. Small buffers come back zeroed rather than holding whatever
+    /// the last call left behind.
     ///
-    /// Avoiding `vec![0u8; ...]` here saves a 64 MB memset per 4K-image
-    /// diff. The OS's allocator typically hands us freshly-cleared pages
-    /// for big allocations anyway, but tiny diffs (a few hundred KB) reuse
-    /// arena memory whose contents are whatever the last call left behind
-    /// — fine here, because the callers above respect the "identical →
-    /// don't read" contract.
+    /// Debug builds fill the buffer with `0xCD` bytes instead, so a pixel
+    /// some path forgot to write is easy to spot.
     pub fn new_uninit(width: u32, height: u32) -> Self {
         let size = (width as usize) * (height as usize) * 4;
-        let mut data: Vec<u8> = Vec::with_capacity(size);
-        // SAFETY: the new length matches the capacity we just reserved, the
-        // element type (`u8`) has no validity requirements, and every byte
-        // of this allocation is overwritten before being read on every
-        // non-identical diff path. Identical-input callers skip the read.
-        unsafe {
-            data.set_len(size);
-        }
+        // Debug builds poison the buffer instead, so a path that leaves
+        // pixels unwritten shows up deterministically rather than leaking
+        // old memory into a saved diff.
+        #[cfg(debug_assertions)]
+        let data = AlignedBytes::filled(size, UNINIT_POISON);
+        #[cfg(not(debug_assertions))]
+        let data = AlignedBytes::zeroed(size);
         Self {
             data,
             width,
@@ -51,27 +97,412 @@ impl Image {
         }
     }
 
+    /// Wrap a raw RGBA buffer whose rows are `stride` bytes apart (`None`
+    /// means tightly packed, `width * 4`), e.g. a GPU readback aligned to
+    /// 256 bytes.
+    ///
+    /// The diff kernels index rows as `y * width`, so the rows are copied
+    /// into the image's aligned buffer without their padding.
+    pub fn from_strided(
+        data: Vec<u8>,
+        width: u32,
+        height: u32,
+        stride: Option<u32>,
+    ) -> Result<Self, DiffError> {
+        let row_bytes = width as usize * 4;
+        let stride = stride.map_or(row_bytes, |s| s as usize);
+        if stride < row_bytes {
+            return Err(DiffError::InvalidDataSize {
+                expected: row_bytes,
+                actual: stride,
+            });
+        }
+        let needed = match height as usize {
+            0 => 0,
+            rows => stride * (rows - 1) + row_bytes,
+        };
+        if data.len() < needed {
+            return Err(DiffError::InvalidDataSize {
+                expected: needed,
+                actual: data.len(),
+            });
+        }
+        let mut pixels = AlignedBytes::with_capacity(row_bytes * height as usize);
+        for y in 0..height as usize {
+            pixels.extend_from_slice(&data[y * stride..y * stride + row_bytes]);
+        }
+        Ok(Self {
+            data: pixels,
+            width,
+            height,
+        })
+    }
+
+    /// Wrap tightly packed RGBA bytes, e.g. an `image::RgbaImage`'s
+    /// `into_raw()`. `data` must hold exactly `width * height * 4` bytes.
+    /// It is taken over without copying when 16-byte aligned, as allocations
+    /// of a few hundred bytes or more are in practice; see
+    /// [`AlignedBytes::from_vec`].
+    pub fn from_rgba(data: Vec<u8>, width: u32, height: u32) -> Result<Self, DiffError> {
+        let expected = width as usize * height as usize * 4;
+        if data.len() != expected {
+            return Err(DiffError::InvalidDataSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(Self {
+            data: data.into(),
+            width,
+            height,
+        })
+    }
+
+    /// Copy tightly packed RGB bytes into a new image with every pixel
+    /// opaque. `data` must hold exactly `width * height * 3` bytes.
+    pub fn from_rgb(data: &[u8], width: u32, height: u32) -> Result<Self, DiffError> {
+        let expected = width as usize * height as usize * 3;
+        if data.len() != expected {
+            return Err(DiffError::InvalidDataSize {
+                expected,
+                actual: data.len(),
+            });
+        }
+        let mut rgba = AlignedBytes::with_capacity(expected / 3 * 4);
+        for rgb in data.chunks_exact(3) {
+            rgba.extend_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+        }
+        Ok(Self {
+            data: rgba,
+            width,
+            height,
+        })
+    }
+
+    /// The raw RGBA bytes, 4 per pixel in row-major order.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Mutable [`as_bytes`](Image::as_bytes).
+    #[inline]
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// The pixels packed as little-endian `u32`s. Never panics on
+    /// alignment: [`AlignedBytes`] starts every buffer on a 16-byte
+    /// boundary, which the SIMD loads rely on too.
     #[inline]
     pub fn as_u32(&self) -> &[u32] {
+        debug_assert_eq!(self.data.as_ptr() as usize % 16, 0);
         bytemuck::cast_slice(&self.data)
     }
 
     #[inline]
     pub fn as_u32_mut(&mut self) -> &mut [u32] {
+        debug_assert_eq!(self.data.as_ptr() as usize % 16, 0);
         bytemuck::cast_slice_mut(&mut self.data)
     }
 
+    /// Borrowed view for the crate's slice-based kernels.
+    #[inline]
+    pub(crate) fn pixels(&self) -> Pixels<'_> {
+        Pixels {
+            data: self.as_u32(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+
     #[inline]
     pub fn get_pixel(&self, x: u32, y: u32) -> u32 {
-        let idx = (y * self.width + x) as usize;
+        let idx = y as usize * self.width as usize + x as usize;
         self.as_u32()[idx]
     }
 
     #[inline]
     pub fn set_pixel(&mut self, x: u32, y: u32, pixel: u32) {
-        let idx = (y * self.width + x) as usize;
+        let idx = y as usize * self.width as usize + x as usize;
         self.as_u32_mut()[idx] = pixel;
     }
+
+    /// Checked [`get_pixel`](Self::get_pixel): `None` unless `x < width`
+    /// and `y < height`, so an out-of-range `x` never reads the next row.
+    #[inline]
+    pub fn try_get_pixel(&self, x: u32, y: u32) -> Option<u32> {
+        if x < self.width && y < self.height {
+            Some(self.get_pixel(x, y))
+        } else {
+            None
+        }
+    }
+
+    /// Checked [`set_pixel`](Self::set_pixel): rejects coordinates outside
+    /// the image instead of panicking or writing into another row.
+    #[inline]
+    pub fn try_set_pixel(&mut self, x: u32, y: u32, pixel: u32) -> Result<(), DiffError> {
+        if x < self.width && y < self.height {
+            self.set_pixel(x, y, pixel);
+            Ok(())
+        } else {
+            Err(DiffError::OutOfBounds {
+                x,
+                y,
+                width: self.width,
+                height: self.height,
+            })
+        }
+    }
+
+    /// Whether every pixel has the same RGBA value. An empty image is not
+    /// solid.
+    pub fn is_solid(&self) -> bool {
+        self.pixels().solid_color().is_some()
+    }
+
+    /// Composite every pixel over a solid `background` (src-over), giving a
+    /// fully opaque image.
+    ///
+    /// Diffing two composited overlays compares what is actually visible on
+    /// the page, rather than relying on the white blend the YIQ delta
+    /// applies to transparent pixels.
+    pub fn blend_over(&self, background: [u8; 3]) -> Image {
+        let mut data = AlignedBytes::with_capacity(self.data.len());
+        for px in self.data.chunks_exact(4) {
+            let a = px[3] as u32;
+            for c in 0..3 {
+                let blended = px[c] as u32 * a + background[c] as u32 * (255 - a);
+                data.push(((blended + 127) / 255) as u8);
+            }
+            data.push(255);
+        }
+        Image {
+            data,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Convert premultiplied alpha to straight alpha in place, dividing each
+    /// color channel by alpha (rounded, clamped to 255). Fully transparent
+    /// and fully opaque pixels are left as they are.
+    ///
+    /// For PNGs whose producer stored premultiplied colors despite the
+    /// spec, so they compare correctly against straight-alpha references.
+    pub fn unpremultiply(&mut self) {
+        for px in self.data.chunks_exact_mut(4) {
+            let a = px[3] as u32;
+            if a == 0 || a == 255 {
+                continue;
+            }
+            for c in &mut px[..3] {
+                *c = ((*c as u32 * 255 + a / 2) / a).min(255) as u8;
+            }
+        }
+    }
+
+    /// Copy out the `width`x`height` rectangle whose top-left corner is
+    /// `(x, y)`. Panics if it does not fit inside the image.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Image {
+        assert!(
+            x + width <= self.width && y + height <= self.height,
+            "crop {}x{}+{}+{} outside {}x{} image",
+            width,
+            height,
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let row_bytes = width as usize * 4;
+        let mut data = AlignedBytes::with_capacity(row_bytes * height as usize);
+        for row in y..y + height {
+            let start = (row as usize * self.width as usize + x as usize) * 4;
+            data.extend_from_slice(&self.data[start..start + row_bytes]);
+        }
+        Image {
+            data,
+            width,
+            height,
+        }
+    }
+
+    /// Change the canvas to `width`x`height`, keeping the content anchored
+    /// at the top-left: new area is painted `fill` (RGBA) and area outside
+    /// the new bounds is cut off. [`CanvasResize::data_lost`] reports
+    /// whether any cut-off pixel differed from `fill`, since diffing a
+    /// silently cropped candidate under-reports the change.
+    pub fn resize_canvas(&self, width: u32, height: u32, fill: [u8; 4]) -> CanvasResize {
+        let fill = u32::from_le_bytes(fill);
+        let mut image = Image::new(width, height);
+        image.as_u32_mut().fill(fill);
+        // A zero-width source has no rows to split.
+        if self.data.is_empty() {
+            return CanvasResize {
+                image,
+                data_lost: false,
+            };
+        }
+
+        let (kept_width, kept_height) = (width.min(self.width), height.min(self.height));
+        let mut data_lost = false;
+        for (y, row) in self.as_u32().chunks_exact(self.width as usize).enumerate() {
+            let (kept, cut) = row.split_at(kept_width as usize);
+            if (y as u32) < kept_height && !kept.is_empty() {
+                let start = y * width as usize;
+                image.as_u32_mut()[start..start + kept.len()].copy_from_slice(kept);
+                data_lost |= cut.iter().any(|&pixel| pixel != fill);
+            } else {
+                data_lost |= row.iter().any(|&pixel| pixel != fill);
+            }
+        }
+        CanvasResize { image, data_lost }
+    }
+}
+
+/// What [`Image::resize_canvas`] produced.
+pub struct CanvasResize {
+    pub image: Image,
+    /// Cropping discarded at least one pixel that was not the fill color.
+    pub data_lost: bool,
+}
+
+/// Borrowed packed pixels with their dimensions: what the diff kernels read,
+/// whether the caller holds an [`Image`] or bare `u32` slices.
+#[derive(Clone, Copy)]
+pub(crate) struct Pixels<'a> {
+    pub data: &'a [u32],
+    pub width: u32,
+    pub height: u32,
+}
+
+impl<'a> Pixels<'a> {
+    #[inline]
+    pub fn as_u32(&self) -> &'a [u32] {
+        self.data
+    }
+
+    #[inline]
+    pub fn bytes(&self) -> &'a [u8] {
+        bytemuck::cast_slice(self.data)
+    }
+
+    /// The one packed color every pixel shares, if there is one.
+    pub fn solid_color(&self) -> Option<u32> {
+        let (&first, rest) = self.data.split_first()?;
+        rest.iter().all(|&p| p == first).then_some(first)
+    }
+}
+
+/// Which input is drawn dimmed underneath the diff highlights.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackgroundSource {
+    /// Gray out `image1`, the baseline.
+    #[default]
+    Baseline,
+    /// Gray out `image2`, the candidate.
+    Candidate,
+}
+
+/// Post-processing applied to the diff image after the hot pass.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Leave the output as drawn.
+    #[default]
+    Full,
+    /// Quantize each RGB channel to `levels` evenly spaced steps (at least
+    /// 2), so small diff previews compress well as indexed PNGs.
+    Posterized { levels: u8 },
+    /// Show real content instead of highlights: `image2`'s own pixel
+    /// wherever a diff is counted and `image1`'s everywhere else, with no
+    /// graying. `alpha`, `diff_color` and `background_source` are ignored;
+    /// anti-aliased pixels still get `aa_color` unless `include_aa` is set.
+    CandidateOnChanged,
+    /// Color each counted pixel by how much it changed instead of with
+    /// `diff_color`: `sqrt(|delta| / MAX_YIQ_DELTA)`, the distance in YIQ
+    /// space as a fraction of black to white, looked up in `colormap`.
+    /// Only [`diff`](crate::diff) applies it; custom painters draw as usual.
+    Heatmap { colormap: Colormap },
+    /// Highlight only the anti-aliased differences, in `aa_color`, and draw
+    /// counted changes as background, to isolate font-rendering changes.
+    /// `diff_count` is unaffected. Needs `include_aa` off, since that skips
+    /// the anti-aliasing check. Only [`diff`](crate::diff) applies it.
+    AaOnly,
+}
+
+/// Kernel family the diff passes run on; see [`DiffOptions::force_backend`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimdBackend {
+    /// Portable scalar code, available on every x86_64 CPU.
+    Scalar,
+    /// x86_64 SSE4.1.
+    Sse41,
+    /// x86_64 AVX2 with FMA.
+    Avx2,
+    /// x86_64 AVX-512. There are no AVX-512 kernels yet, so forcing this
+    /// always fails.
+    Avx512,
+    /// aarch64 NEON.
+    Neon,
+    /// wasm32 SIMD128.
+    Simd128,
+}
+
+/// How the color difference of two pixels is measured; see
+/// [`DiffOptions::color_metric`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMetric {
+    /// Weighted YIQ distance (Kotsarenko & Ramos), on the SIMD kernels.
+    #[default]
+    Yiq,
+    /// CIE76 Delta E: Euclidean distance in L*a*b*
+    /// ([`lab::delta_e76`](crate::lab::delta_e76)).
+    DeltaE76,
+}
+
+/// Whose arithmetic the diff passes reproduce; see
+/// [`DiffOptions::compat_mode`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompatMode {
+    /// The fastest kernels for this CPU, fused multiply-adds included.
+    #[default]
+    Native,
+    /// Round like the wasm32 build, which has no fused multiply-add.
+    Wasm,
+}
+
+/// What [`diff`](crate::diff) does with images of different sizes; see
+/// [`DiffOptions::size_mismatch`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizeMismatchPolicy {
+    /// Fail with [`DiffError::SizeMismatch`].
+    #[default]
+    Error,
+    /// Compare the top-left rectangle both images share. With
+    /// `count_margin`, every pixel of either image outside it also counts,
+    /// and `diff_percentage` is taken over the area the two images cover
+    /// together; without, only the shared rectangle counts and is the
+    /// denominator, as with [`DiffOptions::compare_overlap`].
+    CropToSmaller { count_margin: bool },
+    /// Grow both images to the larger width and height, filling with fully
+    /// transparent pixels, which blend to white like any transparency.
+    /// `diff_percentage` is taken over the grown canvas.
+    PadToLarger,
+}
+
+impl SimdBackend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SimdBackend::Scalar => "scalar",
+            SimdBackend::Sse41 => "sse4.1",
+            SimdBackend::Avx2 => "avx2",
+            SimdBackend::Avx512 => "avx512",
+            SimdBackend::Neon => "neon",
+            SimdBackend::Simd128 => "simd128",
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -84,6 +515,173 @@ pub struct DiffOptions {
     pub diff_color_alt: Option<[u8; 3]>,
     pub diff_mask: bool, // transparent background mode
     pub compression: u8, // PNG compression level 0-9 (0=fastest, 9=smallest)
+    /// [`ColorMetric::DeltaE76`] counts a pixel when the Delta E between
+    /// its two colors exceeds `threshold * 100`, so 0.023 is about one
+    /// just-noticeable difference. The passes then run scalar, and
+    /// anti-aliasing detection still compares neighbors in YIQ.
+    pub color_metric: ColorMetric,
+    /// Which input is drawn dimmed under the highlights (default: baseline).
+    pub background_source: BackgroundSource,
+    /// Skip the block cold pass and run the hot pass over the whole image.
+    pub single_pass: bool,
+    /// The output buffer is already fully transparent (see
+    /// [`Image::new_transparent`]), so `diff_mask` mode skips clearing it.
+    pub output_cleared: bool,
+    /// Post-processing for the diff image (default: none).
+    pub output_mode: OutputMode,
+    /// Gaussian-blur both inputs with this standard deviation before
+    /// diffing, so high-frequency noise (text AA, dithering) is ignored.
+    /// Costs a blurred copy of each input; see [`Image::gaussian_blur`].
+    pub blur_radius: Option<f32>,
+    /// Deblock both inputs with [`Image::deblock_jpeg`] before diffing, so
+    /// the seams JPEG leaves every 8 pixels count less. Meant for comparing a
+    /// lossless reference against a JPEG of it; applied before `blur_radius`.
+    pub jpeg_artifact_tolerance: bool,
+    /// Compare the images as a viewer with this color blindness sees them,
+    /// via [`Image::simulate_cvd`] on both. Applied before
+    /// `jpeg_artifact_tolerance` and `blur_radius`.
+    pub cvd_simulation: Option<CvdType>,
+    /// Also require the largest absolute RGBA channel difference to exceed
+    /// this before a pixel counts, on top of the perceptual threshold.
+    /// Suppresses sub-quantization noise; the hot pass runs scalar when set.
+    pub min_channel_delta: Option<u8>,
+    /// Tuned for anti-aliased text: always excludes AA pixels (overriding
+    /// `include_aa`) and doubles the delta threshold on high-contrast edge
+    /// pixels, keeping flat regions strict. The hot pass runs scalar.
+    pub text_mode: bool,
+    /// When no output image is requested, first diff 1/8-scale copies of
+    /// large inputs and return early if that coarse diff is already large.
+    /// The early `diff_count` is then an estimate scaled up from the coarse
+    /// count, marked by [`DiffResult::estimated`]; pairs that pass the
+    /// coarse check get the exact full diff. Skipped when a `mask` is set.
+    pub progressive_reject: bool,
+    /// Run on this backend instead of the best one detected, e.g. to
+    /// reproduce a scalar-only machine. On x86_64 any detected backend may
+    /// be chosen; elsewhere only the one compiled in. Anything else makes
+    /// `diff` return [`DiffError::UnsupportedBackend`].
+    pub force_backend: Option<SimdBackend>,
+    /// [`CompatMode::Wasm`] keeps the cold and hot passes off the FMA
+    /// kernels (AVX2 on x86_64, which falls back to SSE4.1, and NEON on
+    /// aarch64, which falls back to scalar), so every delta rounds exactly
+    /// as in `@blazediff/core-wasm` and counts match it pixel for pixel.
+    /// Forcing [`SimdBackend::Avx2`] or [`SimdBackend::Neon`] alongside it
+    /// is unsupported. Gray backgrounds may still differ by a level.
+    pub compat_mode: CompatMode,
+    /// When pixels differ, replace the output image with a crop to the
+    /// bounding box of the changed blocks grown by this many pixels, and
+    /// report its top-left corner in [`DiffResult::crop_offset`]. Only
+    /// [`diff`](crate::diff) and [`diff_with_painter`](crate::diff_with_painter)
+    /// own an `Image` they can shrink; [`diff_u32`](crate::diff_u32) ignores it.
+    pub crop_output_to_changes: Option<u32>,
+    /// Draw faint `grid_color` lines over the output every this many pixels,
+    /// brighter every 10th, as a ruler for how far content moved. Lines are
+    /// anchored to the top-left of the full image, before any
+    /// `crop_output_to_changes`. An identical diff, where no block changed,
+    /// gets none; [`diff_u32`](crate::diff_u32) ignores it.
+    pub grid_overlay: Option<u32>,
+    /// Color of the `grid_overlay` lines.
+    pub grid_color: [u8; 3],
+    /// Compare only this rectangle `(x, y, width, height)`: blocks outside
+    /// it are never examined, and the output there is drawn as unchanged
+    /// background. `diff_percentage` is relative to the rectangle's area,
+    /// while positions in the result stay in image coordinates. A rectangle
+    /// past the image fails with [`DiffError::InvalidDataSize`], giving the
+    /// image's width (or height) and the rectangle's right (or bottom) edge.
+    /// [`diff_u32`](crate::diff_u32), [`diff_slices`](crate::diff_slices) and
+    /// [`Differ`](crate::Differ) fail with [`DiffError::InvalidOption`] when
+    /// it is set.
+    pub roi: Option<(u32, u32, u32, u32)>,
+    /// Leave `output` exactly as passed in when the images come out
+    /// identical: no gray fill, and no `diff_mask` clear, which is deferred
+    /// until a changed block is found. Its contents are then whatever the
+    /// caller put there (for `CandidateOnChanged`, the baseline), so check
+    /// [`DiffResult::identical`] before saving it. `single_pass` draws as it
+    /// counts, so it still writes the output unless the inputs are equal.
+    pub skip_output_when_identical: bool,
+    /// Fill [`DiffResult::centroid`] and [`DiffResult::spread`] from the
+    /// counted pixels' positions. The hot pass runs scalar when set, and a
+    /// `progressive_reject` estimate leaves both unset.
+    pub track_spatial_stats: bool,
+    /// Fill [`DiffResult::row_diff_counts`] with the counted pixels per row.
+    /// The hot pass runs scalar when set, and a `progressive_reject` estimate
+    /// or a `block_granularity` diff leaves it unset.
+    pub track_row_counts: bool,
+    /// Fill [`DiffResult::block_ratios`] with the counted fraction of every
+    /// block the cold pass flagged, from the hot pass's per-block counts.
+    /// Left unset by `block_granularity` and an early `progressive_reject`.
+    pub track_block_ratios: bool,
+    /// Fill [`DiffResult::pixel_mask`] with whether each pixel was counted,
+    /// for [`diff_regions`](crate::diff_regions). The hot pass runs scalar
+    /// when set, and a `progressive_reject` estimate or a
+    /// `block_granularity` diff leaves it unset.
+    pub track_pixel_mask: bool,
+    /// Fill [`DiffResult::changed_block_ids`] with the cold pass's changed
+    /// blocks. Costs one allocation, and turns off `progressive_reject`,
+    /// whose coarse pass uses a different grid.
+    pub record_changed_blocks: bool,
+    /// Placeholder color marking content that should not be compared: a
+    /// pixel within `chroma_tolerance` of it (per RGB channel) in either
+    /// image is never counted and is drawn as background. The hot pass runs
+    /// scalar when set.
+    pub chroma_key: Option<[u8; 3]>,
+    /// Largest per-channel distance from `chroma_key` that still matches.
+    pub chroma_tolerance: u8,
+    /// Pixels whose alpha is below this in both images are effectively
+    /// invisible and never count, however their colors differ (0
+    /// disables). The hot pass runs scalar when set.
+    pub min_alpha: u8,
+    /// Stop after the cold pass: `diff_count` becomes the combined area of
+    /// the blocks holding any pixel over the threshold, a coarse upper bound
+    /// rather than a count, and [`DiffResult::changed_blocks`] is set to mark
+    /// it. Anti-aliasing and the per-pixel options above are not applied;
+    /// the output fills each changed block with `diff_color` over the gray
+    /// background (or a cleared mask). Meaningless with `single_pass`, whose
+    /// one block is the whole image.
+    pub block_granularity: bool,
+    /// Only whether the images differ is needed: run the two passes block by
+    /// block and stop at the first block with a counted pixel. `diff_count`
+    /// is then that block's count, a lower bound on the total marked by
+    /// [`DiffResult::estimated`], while [`DiffResult::identical`] stays
+    /// exact. Ignored when an output is
+    /// drawn, and by `block_granularity` and the options that record every
+    /// block (`track_spatial_stats`, `track_row_counts`, `track_block_ratios`,
+    /// `track_pixel_mask`, `record_changed_blocks`).
+    pub stop_at_first_diff: bool,
+    /// Rectangles `(x, y, width, height)` left out of the comparison, such
+    /// as timestamps or ad slots: their pixels never count and are drawn as
+    /// unchanged background. Parts outside the image are ignored.
+    pub ignore_regions: Vec<(u32, u32, u32, u32)>,
+    /// Compare only where this image, the size of the compared inputs, is
+    /// nonzero in `mask_channel`: a black and white PNG marking the stable
+    /// area, say. Masked-out pixels never count, are drawn as unchanged
+    /// background, and are left out of `diff_percentage`'s denominator. A
+    /// mask of another size fails with [`DiffError::SizeMismatch`].
+    pub mask: Option<Arc<Image>>,
+    /// Channel of `mask` that is read: 0 to 3 for red, green, blue, alpha.
+    /// Anything else fails with [`DiffError::InvalidOption`] when a mask is
+    /// set.
+    pub mask_channel: u8,
+    /// Add `alpha_weight * da^2` to each pixel's delta, where `da` is the
+    /// alpha difference on the same 0-255 scale as the color channels, so
+    /// transparency changes count beyond what blending over white shows.
+    /// The luminance weight, 0.5053, makes an alpha step count like an
+    /// equal luminance step. 0 disables; the passes run scalar when set.
+    pub alpha_weight: f64,
+    /// Compare only which pixels are visible: a pixel counts, at the full
+    /// delta, exactly when its alpha is zero in one image and not the other.
+    /// Colors and alpha magnitude are ignored, and so is anti-aliasing
+    /// detection. The passes run scalar when set.
+    pub silhouette_only: bool,
+    /// When the images differ in size, diff the top-left region they share
+    /// instead of failing with [`DiffError::SizeMismatch`], and report the
+    /// rest in [`DiffResult::overlap_margin`]. Counts and percentages cover
+    /// the shared region only, and a caller's output image is replaced by
+    /// one of that size.
+    pub compare_overlap: bool,
+    /// How images of different sizes are compared, when `compare_overlap`
+    /// is off. A caller's output image is replaced by one of the compared
+    /// size.
+    pub size_mismatch: SizeMismatchPolicy,
 }
 
 impl Default for DiffOptions {
@@ -97,15 +695,191 @@ impl Default for DiffOptions {
             diff_color_alt: None,
             diff_mask: false,
             compression: 0, // fastest by default
+            color_metric: ColorMetric::Yiq,
+            background_source: BackgroundSource::Baseline,
+            single_pass: false,
+            output_cleared: false,
+            output_mode: OutputMode::Full,
+            blur_radius: None,
+            jpeg_artifact_tolerance: false,
+            cvd_simulation: None,
+            min_channel_delta: None,
+            text_mode: false,
+            force_backend: None,
+            compat_mode: CompatMode::Native,
+            progressive_reject: false,
+            crop_output_to_changes: None,
+            grid_overlay: None,
+            grid_color: [0, 128, 255],
+            roi: None,
+            skip_output_when_identical: false,
+            track_spatial_stats: false,
+            track_row_counts: false,
+            track_block_ratios: false,
+            track_pixel_mask: false,
+            record_changed_blocks: false,
+            chroma_key: None,
+            chroma_tolerance: 0,
+            min_alpha: 0,
+            block_granularity: false,
+            stop_at_first_diff: false,
+            ignore_regions: Vec::new(),
+            mask: None,
+            mask_channel: 0,
+            alpha_weight: 0.0,
+            silhouette_only: false,
+            compare_overlap: false,
+            size_mismatch: SizeMismatchPolicy::Error,
         }
     }
 }
 
+impl DiffOptions {
+    /// Start a [`DiffOptionsBuilder`] from the defaults.
+    pub fn builder() -> DiffOptionsBuilder {
+        DiffOptionsBuilder::default()
+    }
+}
+
+/// Fluent construction of the common [`DiffOptions`], checked by
+/// [`build`](Self::build). Fields without a method here are set on the
+/// built options directly.
+///
+/// ```
+/// use blazediff::DiffOptions;
+///
+/// let options = DiffOptions::builder()
+///     .threshold(0.05)
+///     .antialiasing(true)
+///     .build()
+///     .unwrap();
+/// assert!(!options.include_aa);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DiffOptionsBuilder {
+    options: DiffOptions,
+}
+
+impl DiffOptionsBuilder {
+    /// Matching threshold, 0.0 (strictest) to 1.0.
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.options.threshold = threshold;
+        self
+    }
+
+    /// Detect anti-aliased pixels and leave them out of the count, as the
+    /// CLI's `--antialiasing` does. Sets `include_aa` to the opposite.
+    pub fn antialiasing(mut self, detect: bool) -> Self {
+        self.options.include_aa = !detect;
+        self
+    }
+
+    pub fn diff_color(mut self, color: [u8; 3]) -> Self {
+        self.options.diff_color = color;
+        self
+    }
+
+    /// Draw the diff over a transparent background instead of the dimmed
+    /// image.
+    pub fn diff_mask(mut self, diff_mask: bool) -> Self {
+        self.options.diff_mask = diff_mask;
+        self
+    }
+
+    /// Opacity of the dimmed image under the highlights.
+    pub fn alpha(mut self, alpha: f64) -> Self {
+        self.options.alpha = alpha;
+        self
+    }
+
+    /// PNG compression level, 0 (fastest) to 9 (smallest).
+    pub fn compression(mut self, level: u8) -> Self {
+        self.options.compression = level;
+        self
+    }
+
+    /// The options, or [`DiffError::InvalidOption`] when `threshold` is
+    /// outside 0.0 to 1.0 (or NaN) or `compression` is above 9.
+    pub fn build(self) -> Result<DiffOptions, DiffError> {
+        let options = self.options;
+        if !(0.0..=1.0).contains(&options.threshold) {
+            return Err(DiffError::InvalidOption {
+                name: "threshold",
+                detail: format!("{} is outside 0.0 to 1.0", options.threshold),
+            });
+        }
+        if options.compression > 9 {
+            return Err(DiffError::InvalidOption {
+                name: "compression",
+                detail: format!("{} is above 9", options.compression),
+            });
+        }
+        Ok(options)
+    }
+}
+
+/// A block of the [`block_grid`](crate::block_grid) as `(x, y, width,
+/// height)`, clipped to the image.
+pub type BlockRect = (u32, u32, u32, u32);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiffResult {
     pub diff_count: u32,
     pub diff_percentage: f64,
+    /// No pixel differs beyond the threshold.
     pub identical: bool,
+    /// The inputs are byte-for-byte equal. Implies `identical`, but
+    /// sub-threshold changes leave `identical` set and this one clear.
+    #[serde(default)]
+    pub exact_match: bool,
+    /// Where the cropped output starts in the full image, set when
+    /// `crop_output_to_changes` cropped it.
+    #[serde(default)]
+    pub crop_offset: Option<(u32, u32)>,
+    /// Mean `(x, y)` of the counted pixels, with `track_spatial_stats`.
+    #[serde(default)]
+    pub centroid: Option<(f32, f32)>,
+    /// Root-mean-square distance of the counted pixels from `centroid`, in
+    /// pixels: small for one localized change, large for a global shift.
+    #[serde(default)]
+    pub spread: Option<f32>,
+    /// Number of changed blocks, set when a `block_granularity` diff finds
+    /// any. `diff_count` is then their combined area, not a pixel count.
+    #[serde(default)]
+    pub changed_blocks: Option<u32>,
+    /// `diff_count` and `diff_percentage` are not the full count: either
+    /// scaled up from a coarse diff that `progressive_reject` found already
+    /// large, or the first counted block's pixels alone under
+    /// `stop_at_first_diff`, a lower bound. `identical` is still exact.
+    #[serde(default)]
+    pub estimated: bool,
+    /// How much [`diff_normalized`](crate::diff_normalized) shrank the
+    /// larger image to match the smaller one, e.g. 2.0 for a 2x screenshot.
+    #[serde(default)]
+    pub normalized_scale: Option<f64>,
+    /// How far the wider image extends right of, and the taller one below,
+    /// the region a `compare_overlap` diff compared.
+    #[serde(default)]
+    pub overlap_margin: Option<(u32, u32)>,
+    /// Row-major [`block_grid`](crate::block_grid) indices of the blocks
+    /// holding a pixel over the threshold, ascending, with
+    /// `record_changed_blocks`. Empty when the result is identical; every
+    /// block with `single_pass`, whose one block is the whole image.
+    #[serde(default)]
+    pub changed_block_ids: Option<Vec<u32>>,
+    /// Counted pixels in each row, top to bottom, with `track_row_counts`:
+    /// a vertical profile of where the image changed.
+    #[serde(default)]
+    pub row_diff_counts: Option<Vec<u32>>,
+    /// Each changed block with the fraction of its pixels counted, in
+    /// row-major order, with `track_block_ratios`: a coarse density map.
+    /// Anti-aliasing can leave a block at 0.0.
+    #[serde(default)]
+    pub block_ratios: Option<Vec<(BlockRect, f32)>>,
+    /// Whether each pixel was counted, row-major, with `track_pixel_mask`.
+    /// Left out of serialized results, which it would dwarf.
+    #[serde(skip)]
+    pub pixel_mask: Option<Vec<bool>>,
 }
 
 impl DiffResult {
@@ -119,6 +893,26 @@ impl DiffResult {
             diff_count,
             diff_percentage,
             identical: diff_count == 0,
+            exact_match: false,
+            crop_offset: None,
+            centroid: None,
+            spread: None,
+            changed_blocks: None,
+            estimated: false,
+            normalized_scale: None,
+            overlap_margin: None,
+            changed_block_ids: None,
+            row_diff_counts: None,
+            block_ratios: None,
+            pixel_mask: None,
+        }
+    }
+
+    /// Result for byte-identical inputs.
+    pub fn exact(total_pixels: u32) -> Self {
+        Self {
+            exact_match: true,
+            ..Self::new(0, total_pixels)
         }
     }
 }
@@ -140,6 +934,114 @@ pub enum DiffError {
     JpegError(String),
     QoiError(String),
     UnsupportedFormat(String),
+    /// The header parsed but pixel decoding failed part-way through.
+    DecodeError {
+        format: &'static str,
+        width: u32,
+        height: u32,
+        kind: DecodeFailure,
+        detail: String,
+    },
+    /// [`DiffOptions::force_backend`] named a backend this CPU or build
+    /// cannot run.
+    UnsupportedBackend(SimdBackend),
+    /// A pixel coordinate fell outside the image.
+    OutOfBounds {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    /// The header declares an image larger than the [`DecodeLimits`] the
+    /// loader was given; nothing was allocated for the pixels.
+    TooLarge {
+        format: &'static str,
+        width: u32,
+        height: u32,
+        decoded_bytes: usize,
+    },
+    /// `width * height` exceeds `u32::MAX`, more pixels than the `u32`
+    /// counts in [`DiffResult`] can hold.
+    TooManyPixels {
+        width: u32,
+        height: u32,
+    },
+    /// The option `name` holds a value it cannot take, as rejected by
+    /// [`DiffOptionsBuilder::build`] or by the diff itself.
+    InvalidOption {
+        name: &'static str,
+        detail: String,
+    },
+}
+
+/// Broad cause of a [`DiffError::DecodeError`], for triaging batch failures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeFailure {
+    /// The stream ended before all pixel data was read.
+    Truncated,
+    /// The pixel data is present but malformed.
+    Corrupt,
+    /// The file uses a feature the decoder does not handle.
+    Unsupported,
+}
+
+impl std::fmt::Display for DecodeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeFailure::Truncated => write!(f, "truncated"),
+            DecodeFailure::Corrupt => write!(f, "corrupt"),
+            DecodeFailure::Unsupported => write!(f, "unsupported"),
+        }
+    }
+}
+
+/// Upper bounds a loader checks against an image's header before allocating
+/// its pixels, so an untrusted upload cannot claim gigabytes of memory. The
+/// default sets no bound.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Largest decoded RGBA8 buffer, in bytes.
+    pub max_decoded_bytes: Option<usize>,
+    /// Largest width or height, in pixels.
+    pub max_dimension: Option<u32>,
+}
+
+/// How [`load_png_with`](crate::load_png_with) treats the decoded pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Bounds checked before the pixels are allocated.
+    pub limits: DecodeLimits,
+    /// Convert premultiplied colors to straight alpha after decoding (see
+    /// [`Image::unpremultiply`]).
+    pub unpremultiply: bool,
+}
+
+impl DecodeLimits {
+    /// Fail with [`DiffError::TooLarge`] if a `width`x`height` image that
+    /// decodes to `decoded_bytes` exceeds either bound.
+    pub fn check(
+        &self,
+        format: &'static str,
+        width: u32,
+        height: u32,
+        decoded_bytes: usize,
+    ) -> Result<(), DiffError> {
+        let too_wide = self
+            .max_dimension
+            .is_some_and(|max| width > max || height > max);
+        let too_big = self
+            .max_decoded_bytes
+            .is_some_and(|max| decoded_bytes > max);
+        if too_wide || too_big {
+            return Err(DiffError::TooLarge {
+                format,
+                width,
+                height,
+                decoded_bytes,
+            });
+        }
+        Ok(())
+    }
 }
 
 impl std::fmt::Display for DiffError {
@@ -169,6 +1071,50 @@ impl std::fmt::Display for DiffError {
             DiffError::JpegError(e) => write!(f, "JPEG error: {}", e),
             DiffError::QoiError(e) => write!(f, "QOI error: {}", e),
             DiffError::UnsupportedFormat(e) => write!(f, "Unsupported format: {}", e),
+            DiffError::DecodeError {
+                format,
+                width,
+                height,
+                kind,
+                detail,
+            } => write!(
+                f,
+                "{} decode failed ({}) for {}x{} image: {}",
+                format, kind, width, height, detail
+            ),
+            DiffError::UnsupportedBackend(backend) => {
+                write!(f, "SIMD backend not available: {}", backend.as_str())
+            }
+            DiffError::OutOfBounds {
+                x,
+                y,
+                width,
+                height,
+            } => write!(
+                f,
+                "Pixel ({}, {}) is outside the {}x{} image",
+                x, y, width, height
+            ),
+            DiffError::TooLarge {
+                format,
+                width,
+                height,
+                decoded_bytes,
+            } => write!(
+                f,
+                "{} image {}x{} exceeds the decode limits ({} bytes decoded)",
+                format, width, height, decoded_bytes
+            ),
+            DiffError::TooManyPixels { width, height } => write!(
+                f,
+                "{}x{} image has more pixels than a diff can count ({})",
+                width,
+                height,
+                u32::MAX
+            ),
+            DiffError::InvalidOption { name, detail } => {
+                write!(f, "Invalid {}: {}", name, detail)
+            }
         }
     }
 }
@@ -180,3 +1126,257 @@ impl From<std::io::Error> for DiffError {
         DiffError::IoError(e)
     }
 }
+
+impl From<qoi::Error> for DiffError {
+    fn from(e: qoi::Error) -> Self {
+        DiffError::QoiError(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_options_builder() {
+        let options = DiffOptions::builder()
+            .threshold(0.2)
+            .antialiasing(false)
+            .diff_color([0, 255, 0])
+            .diff_mask(true)
+            .alpha(0.5)
+            .compression(9)
+            .build()
+            .unwrap();
+        assert_eq!(options.threshold, 0.2);
+        assert!(options.include_aa);
+        assert_eq!(options.diff_color, [0, 255, 0]);
+        assert!(options.diff_mask);
+        assert_eq!(options.alpha, 0.5);
+        assert_eq!(options.compression, 9);
+        assert_eq!(options.aa_color, DiffOptions::default().aa_color);
+
+        assert!(
+            !DiffOptions::builder()
+                .antialiasing(true)
+                .build()
+                .unwrap()
+                .include_aa
+        );
+
+        for threshold in [-0.1, 1.01, f64::NAN] {
+            assert!(matches!(
+                DiffOptions::builder().threshold(threshold).build(),
+                Err(DiffError::InvalidOption {
+                    name: "threshold",
+                    ..
+                })
+            ));
+        }
+        let err = DiffOptions::builder().compression(10).build().unwrap_err();
+        assert!(matches!(
+            err,
+            DiffError::InvalidOption {
+                name: "compression",
+                ..
+            }
+        ));
+        assert_eq!(err.to_string(), "Invalid compression: 10 is above 9");
+    }
+
+    #[test]
+    fn test_from_strided() {
+        // 2x3 image with 4 bytes of padding per row; the last row is unpadded.
+        let mut data = Vec::new();
+        for y in 0..3u8 {
+            for x in 0..2u8 {
+                data.extend_from_slice(&[x, y, 0, 255]);
+            }
+            if y < 2 {
+                data.extend_from_slice(&[0xEE; 4]);
+            }
+        }
+
+        let img = Image::from_strided(data, 2, 3, Some(12)).unwrap();
+        assert_eq!(img.data.len(), 2 * 3 * 4);
+        for y in 0..3u8 {
+            for x in 0..2u8 {
+                let [r, g, _, a] = img.get_pixel(x as u32, y as u32).to_le_bytes();
+                assert_eq!((r, g, a), (x, y, 255));
+            }
+        }
+
+        let tight = Image::from_strided(vec![7; 16], 2, 2, None).unwrap();
+        assert_eq!(tight.data, vec![7; 16]);
+
+        assert!(matches!(
+            Image::from_strided(vec![0; 64], 4, 2, Some(8)),
+            Err(DiffError::InvalidDataSize {
+                expected: 16,
+                actual: 8
+            })
+        ));
+        assert!(matches!(
+            Image::from_strided(vec![0; 19], 2, 2, Some(12)),
+            Err(DiffError::InvalidDataSize {
+                expected: 20,
+                actual: 19
+            })
+        ));
+    }
+
+    #[test]
+    fn test_from_rgba_and_rgb() {
+        let img = Image::from_rgba(vec![1, 2, 3, 4, 5, 6, 7, 8], 2, 1).unwrap();
+        assert_eq!(img.get_pixel(1, 0).to_le_bytes(), [5, 6, 7, 8]);
+        assert!(matches!(
+            Image::from_rgba(vec![0; 7], 2, 1),
+            Err(DiffError::InvalidDataSize {
+                expected: 8,
+                actual: 7
+            })
+        ));
+
+        let img = Image::from_rgb(&[1, 2, 3, 4, 5, 6], 1, 2).unwrap();
+        assert_eq!(img.data, vec![1, 2, 3, 255, 4, 5, 6, 255]);
+        assert!(matches!(
+            Image::from_rgb(&[0; 8], 1, 2),
+            Err(DiffError::InvalidDataSize {
+                expected: 6,
+                actual: 8
+            })
+        ));
+    }
+
+    #[test]
+    fn test_image_data_aligned() {
+        let aligned = |img: &Image| (img.data.as_ptr() as usize).is_multiple_of(16);
+        let img = Image::new(17, 3);
+        assert!(aligned(&img));
+        assert!(aligned(&Image::new_uninit(17, 3)));
+        assert!(aligned(&img.crop(1, 1, 15, 2)));
+        assert!(aligned(&Image::from_rgb(&[0; 17 * 3 * 3], 17, 3).unwrap()));
+        assert!(aligned(
+            &Image::from_strided(vec![0; 72 * 3], 17, 3, Some(72)).unwrap()
+        ));
+        assert!(aligned(&Image::new(0, 0)));
+        assert_eq!(img.as_u32().len(), 51);
+    }
+
+    #[test]
+    fn test_checked_pixel_access() {
+        let mut img = Image::new(3, 2);
+        assert!(img.try_set_pixel(2, 1, 0xFF00_00FF).is_ok());
+        assert_eq!(img.try_get_pixel(2, 1), Some(0xFF00_00FF));
+        // (3, 0) would alias (0, 1) through y * width + x.
+        assert_eq!(img.try_get_pixel(3, 0), None);
+        assert_eq!(img.try_get_pixel(0, 2), None);
+        assert!(matches!(
+            img.try_set_pixel(3, 0, 0),
+            Err(DiffError::OutOfBounds {
+                x: 3,
+                y: 0,
+                width: 3,
+                height: 2
+            })
+        ));
+        assert_eq!(img.get_pixel(0, 1), 0);
+    }
+
+    #[test]
+    fn test_blend_over() {
+        let mut img = Image::new(4, 1);
+        img.data.copy_from_slice(&[
+            10, 20, 30, 255, // opaque: unchanged
+            10, 20, 30, 0, // transparent: background
+            255, 0, 0, 128, // half red over blue
+            0, 0, 0, 64, // quarter black over blue
+        ]);
+
+        let out = img.blend_over([0, 0, 255]);
+        assert_eq!((out.width, out.height), (4, 1));
+        assert_eq!(
+            out.data,
+            vec![10, 20, 30, 255, 0, 0, 255, 255, 128, 0, 127, 255, 0, 0, 191, 255]
+        );
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let unbounded = DecodeLimits::default();
+        assert!(unbounded
+            .check("PNG", u32::MAX, u32::MAX, usize::MAX)
+            .is_ok());
+
+        let limits = DecodeLimits {
+            max_decoded_bytes: Some(4 * 100 * 100),
+            max_dimension: Some(200),
+        };
+        assert!(limits.check("PNG", 100, 100, 4 * 100 * 100).is_ok());
+        assert!(matches!(
+            limits.check("PNG", 201, 1, 4 * 201),
+            Err(DiffError::TooLarge { width: 201, .. })
+        ));
+        assert!(matches!(
+            limits.check("JPEG", 200, 200, 4 * 200 * 200),
+            Err(DiffError::TooLarge {
+                format: "JPEG",
+                decoded_bytes: 160_000,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_unpremultiply() {
+        let mut img = Image::new(4, 1);
+        img.data.copy_from_slice(&[
+            64, 32, 0, 128, // half-transparent (128, 64, 0)
+            200, 0, 0, 100, // out of range: clamps
+            9, 9, 9, 0, // transparent: unchanged
+            10, 20, 30, 255, // opaque: unchanged
+        ]);
+        img.unpremultiply();
+        assert_eq!(
+            img.data,
+            vec![128, 64, 0, 128, 255, 0, 0, 100, 9, 9, 9, 0, 10, 20, 30, 255]
+        );
+    }
+
+    #[test]
+    fn test_resize_canvas_grow() {
+        let mut img = Image::new(2, 2);
+        img.as_u32_mut().copy_from_slice(&[1, 2, 3, 4]);
+        let fill = [9, 9, 9, 255];
+        let grown = img.resize_canvas(3, 3, fill);
+        assert!(!grown.data_lost);
+        let pad = u32::from_le_bytes(fill);
+        assert_eq!(grown.image.as_u32(), &[1, 2, pad, 3, 4, pad, pad, pad, pad]);
+    }
+
+    #[test]
+    fn test_resize_canvas_lossy_shrink() {
+        let fill = [255, 255, 255, 255];
+        let white = u32::from_le_bytes(fill);
+        let mut img = Image::new(3, 3);
+        img.as_u32_mut().fill(white);
+        img.set_pixel(0, 0, 0xFF00_0000);
+
+        // Only background is cut off.
+        let blank_cut = img.resize_canvas(2, 1, fill);
+        assert!(!blank_cut.data_lost);
+        assert_eq!(blank_cut.image.as_u32(), &[0xFF00_0000, white]);
+
+        img.set_pixel(2, 1, 0xFF00_00FF);
+        assert!(img.resize_canvas(2, 3, fill).data_lost, "cut column");
+        assert!(img.resize_canvas(3, 1, fill).data_lost, "cut row");
+        let wider = img.resize_canvas(4, 2, fill);
+        assert!(!wider.data_lost, "grow one side, cut blank rows");
+        assert_eq!(wider.image.get_pixel(2, 1), 0xFF00_00FF);
+        assert!(Image::new(0, 0)
+            .resize_canvas(0, 0, fill)
+            .image
+            .data
+            .is_empty());
+    }
+}
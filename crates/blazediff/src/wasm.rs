@@ -4,6 +4,7 @@
 //! `createImageBitmap`, `ImageDecoder`, etc.) and pass `Uint8Array`s in. No
 //! PNG/JPEG decoders are bundled into the wasm artifact.
 
+use crate::aligned::AlignedBytes;
 use crate::diff::diff;
 use crate::types::{DiffOptions, Image};
 use wasm_bindgen::prelude::*;
@@ -27,7 +28,7 @@ fn image_from_slice(rgba: &[u8], width: u32, height: u32, label: &str) -> Result
         )));
     }
     Ok(Image {
-        data: rgba.to_vec(),
+        data: AlignedBytes::from_slice(rgba),
         width,
         height,
     })
@@ -7,6 +7,8 @@ pub const YIQ_Y: [f64; 3] = [0.29889531, 0.58662247, 0.11448223];
 pub const YIQ_I: [f64; 3] = [0.59597799, -0.2741761, -0.32180189];
 pub const YIQ_Q: [f64; 3] = [0.21147017, -0.52261711, 0.31114694];
 pub const YIQ_WEIGHTS: [f64; 3] = [0.5053, 0.299, 0.1957];
+/// Upper bound of `|`[`color_delta`]`|` between any two 8-bit colors: the
+/// scale every delta threshold is measured against.
 pub const MAX_YIQ_DELTA: f64 = 35215.0;
 pub const MAX_YIQ_DELTA_F32: f32 = 35215.0;
 pub const COLOR_DELTA_SHIFT: u32 = 12;
@@ -20,6 +22,10 @@ const YIQ_I_F32: [f32; 3] = [0.59597799, -0.2741761, -0.32180189];
 const YIQ_Q_F32: [f32; 3] = [0.21147017, -0.52261711, 0.31114694];
 const YIQ_WEIGHTS_F32: [f32; 3] = [0.5053, 0.299, 0.1957];
 
+/// Split a packed pixel (as returned by [`Image::get_pixel`]) into
+/// `(r, g, b, a)`. Red is the low byte, matching little-endian RGBA bytes.
+///
+/// [`Image::get_pixel`]: crate::Image::get_pixel
 #[inline(always)]
 pub fn unpack_pixel(pixel: u32) -> (u8, u8, u8, u8) {
     let r = (pixel & 0xFF) as u8;
@@ -29,11 +35,37 @@ pub fn unpack_pixel(pixel: u32) -> (u8, u8, u8, u8) {
     (r, g, b, a)
 }
 
+/// Pack channels into the `u32` layout used by [`Image::get_pixel`] and
+/// [`Image::set_pixel`].
+///
+/// [`Image::get_pixel`]: crate::Image::get_pixel
+/// [`Image::set_pixel`]: crate::Image::set_pixel
 #[inline(always)]
 pub fn pack_pixel(r: u8, g: u8, b: u8, a: u8) -> u32 {
     (r as u32) | ((g as u32) << 8) | ((b as u32) << 16) | ((a as u32) << 24)
 }
 
+/// [`pack_pixel`] for an `[r, g, b, a]` array.
+#[inline(always)]
+pub fn pack_rgba(rgba: [u8; 4]) -> u32 {
+    u32::from_le_bytes(rgba)
+}
+
+/// [`unpack_pixel`] as an `[r, g, b, a]` array.
+#[inline(always)]
+pub fn unpack_rgba(pixel: u32) -> [u8; 4] {
+    pixel.to_le_bytes()
+}
+
+/// YIQ Y of a packed pixel blended over white, 0.0 to 255.0: the luminance
+/// [`crate::diff`] compares, as a single value per pixel.
+#[inline]
+pub(crate) fn luminance_over_white(pixel: u32) -> f64 {
+    let (r, g, b, a) = unpack_pixel(pixel);
+    let y = YIQ_Y[0] * r as f64 + YIQ_Y[1] * g as f64 + YIQ_Y[2] * b as f64;
+    255.0 + (y - 255.0) * a as f64 / 255.0
+}
+
 /// Fast YIQ delta for opaque pixels (no alpha blending needed)
 #[inline(always)]
 pub fn color_delta_opaque(pixel_a: u32, pixel_b: u32) -> f64 {
@@ -60,8 +92,36 @@ pub fn is_opaque(pixel: u32) -> bool {
     (pixel >> 24) == 0xFF
 }
 
+/// Perceptual distance between two packed RGBA pixels (see [`pack_pixel`]):
+/// the weighted sum of squared YIQ differences, negative when `pixel_b` is
+/// darker. A pixel counts as changed when the magnitude exceeds
+/// [`threshold_to_max_delta`] of the threshold.
+///
+/// Semi-transparent pixels are blended over a checkerboard picked by
+/// `pixel_index`, so pass 0 for a single color pair. `y_only` returns the
+/// signed luminance difference alone instead of the squared sum. Note that
+/// [`crate::diff`] blends over white rather than the checkerboard.
 #[inline]
 pub fn color_delta(pixel_a: u32, pixel_b: u32, pixel_index: usize, y_only: bool) -> f64 {
+    color_delta_seeded(pixel_a, pixel_b, pixel_index, y_only, 0)
+}
+
+/// [`color_delta`] with the transparency checkerboard shifted by `seed`.
+///
+/// Seed 0 is the standard pattern. Any other seed moves the background each
+/// semi-transparent pixel is blended over, so the delta reported for
+/// transparent content changes; opaque pixels are unaffected. Use it to
+/// rule out differences that only appear because content happens to line
+/// up with the fixed pattern. Note that [`crate::diff`] blends transparent
+/// pixels over white rather than this checkerboard.
+#[inline]
+pub fn color_delta_seeded(
+    pixel_a: u32,
+    pixel_b: u32,
+    pixel_index: usize,
+    y_only: bool,
+    seed: u64,
+) -> f64 {
     let (r1, g1, b1, a1) = unpack_pixel(pixel_a);
     let (r2, g2, b2, a2) = unpack_pixel(pixel_b);
 
@@ -78,6 +138,7 @@ pub fn color_delta(pixel_a: u32, pixel_b: u32, pixel_index: usize, y_only: bool)
     // Alpha blending with procedural checkerboard background
     if a1 < 255 || a2 < 255 {
         // Generate checkerboard background color using golden ratio
+        let pixel_index = (pixel_index as u64).wrapping_add(seed) as usize;
         let rb = 48.0 + 159.0 * ((pixel_index % 2) as f64);
         let gb = 48.0 + 159.0 * ((((pixel_index as f64) / PHI) as usize & 1) as f64);
         let bb = 48.0 + 159.0 * ((((pixel_index as f64) / PHI2) as usize & 1) as f64);
@@ -169,6 +230,10 @@ pub fn color_delta_fixed(pixel_a: u32, pixel_b: u32) -> i64 {
     (y * y * W_Y + i * i * W_I + q * q * W_Q) >> (2 * COLOR_DELTA_SHIFT)
 }
 
+/// The largest [`color_delta`] magnitude a pixel may have and still match
+/// at `threshold` (0.0-1.0): `MAX_YIQ_DELTA * threshold^2`. The threshold is
+/// squared because the delta is itself a squared distance, so a threshold
+/// of 0.1 allows a YIQ distance of a tenth of the maximum.
 #[inline]
 pub fn threshold_to_max_delta(threshold: f64) -> f64 {
     MAX_YIQ_DELTA * threshold * threshold
@@ -253,6 +318,13 @@ mod tests {
         assert_eq!(pixel, 0xFF804020);
     }
 
+    #[test]
+    fn test_rgba_array_round_trip() {
+        assert_eq!(pack_rgba([32, 64, 128, 255]), pack_pixel(32, 64, 128, 255));
+        assert_eq!(unpack_rgba(0xFF804020), [0x20, 0x40, 0x80, 0xFF]);
+        assert_eq!(unpack_rgba(pack_rgba([1, 2, 3, 4])), [1, 2, 3, 4]);
+    }
+
     #[test]
     fn test_identical_pixels_zero_delta() {
         let pixel = 0xFF8080FF; // Opaque pixel
@@ -269,6 +341,28 @@ mod tests {
         assert!(delta.abs() > 30000.0);
     }
 
+    #[test]
+    fn test_checkerboard_seed() {
+        let a = pack_pixel(200, 40, 90, 128);
+        let b = pack_pixel(20, 180, 60, 64);
+        for index in [0, 1, 17, 4096] {
+            assert_eq!(
+                color_delta_seeded(a, b, index, false, 0),
+                color_delta(a, b, index, false)
+            );
+        }
+        assert!((0..8).any(|seed| {
+            color_delta_seeded(a, b, 0, false, seed) != color_delta(a, b, 0, false)
+        }));
+
+        let opaque_a = pack_pixel(200, 40, 90, 255);
+        let opaque_b = pack_pixel(20, 180, 60, 255);
+        assert_eq!(
+            color_delta_seeded(opaque_a, opaque_b, 3, false, 12345),
+            color_delta(opaque_a, opaque_b, 3, false)
+        );
+    }
+
     #[test]
     fn test_threshold_conversion() {
         let threshold = 0.1;
@@ -0,0 +1,80 @@
+//! Allocation counts for batch diffing. The counting allocator is global to
+//! this test binary, so the checks live here rather than in the unit tests.
+
+use blazediff::{DiffContext, DiffOptions, Image};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count() {
+    let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+}
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// A gradient with a square of changed pixels at `(offset, offset)`, so
+/// successive pairs flag different blocks.
+fn pair(size: u32, offset: u32) -> (Image, Image) {
+    let mut base = Image::new(size, size);
+    for y in 0..size {
+        for x in 0..size {
+            base.set_pixel(x, y, 0xFF00_0000 | (((x + y) % 256) * 0x0001_0101));
+        }
+    }
+    let mut candidate = base.crop(0, 0, size, size);
+    for y in offset..offset + 20 {
+        for x in offset..offset + 20 {
+            candidate.set_pixel(x, y, 0xFF00_00FF);
+        }
+    }
+    (base, candidate)
+}
+
+#[test]
+fn test_diff_context_reuses_buffers() {
+    let pairs: Vec<_> = (0..4).map(|i| pair(256, i * 50)).collect();
+    let mut output = Image::new(256, 256);
+    let options = DiffOptions::default();
+    let mut context = DiffContext::new();
+    for (base, candidate) in &pairs {
+        context
+            .diff_with(base, candidate, Some(&mut output), &options)
+            .unwrap();
+    }
+
+    let before = allocations();
+    for i in 0..1000 {
+        let (base, candidate) = &pairs[i % pairs.len()];
+        let result = context
+            .diff_with(base, candidate, Some(&mut output), &options)
+            .unwrap();
+        assert_eq!(result.diff_count, 400);
+    }
+    assert_eq!(allocations(), before, "no allocation after warm-up");
+}
@@ -0,0 +1,116 @@
+//! Cross-format consistency: the same `Image` saved as PNG or QOI must load
+//! back to byte-identical RGBA, so a diff count never depends on which
+//! lossless format the inputs happened to be stored in. JPEG is lossy and
+//! drops alpha, so it is only held to a documented tolerance.
+
+#![cfg(feature = "io")]
+
+use blazediff::{
+    diff, load_jpeg, load_png, load_qoi, save_jpeg, save_png, save_qoi, DiffOptions, Image,
+};
+use std::path::PathBuf;
+
+/// Worst per-channel error allowed after a quality-95 JPEG round trip of a
+/// smooth opaque image. 4:2:0 chroma subsampling is the dominant source.
+const JPEG_MAX_CHANNEL_ERROR: u8 = 16;
+/// Mean per-channel error bound for the same round trip.
+const JPEG_MEAN_CHANNEL_ERROR: f64 = 2.0;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("blazediff_formats_{}_{}", std::process::id(), name))
+}
+
+/// Gradients plus a band of partially transparent pixels, at dimensions that
+/// are not a multiple of any codec's block or chunk size.
+fn sample_image(width: u32, height: u32, transparent_band: bool) -> Image {
+    let mut img = Image::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let alpha = if transparent_band && y % 8 < 3 {
+                (x * 255 / width) as u8
+            } else {
+                255
+            };
+            let idx = ((y * width + x) * 4) as usize;
+            img.data[idx] = (x * 255 / width) as u8;
+            img.data[idx + 1] = (y * 255 / height) as u8;
+            img.data[idx + 2] = ((x + y) * 3 % 256) as u8;
+            img.data[idx + 3] = alpha;
+        }
+    }
+    img
+}
+
+#[test]
+fn png_and_qoi_round_trip_identically() {
+    let img = sample_image(67, 45, true);
+    let png_path = temp_path("lossless.png");
+    let qoi_path = temp_path("lossless.qoi");
+
+    save_png(&img, &png_path).expect("Failed to save PNG");
+    save_qoi(&img, &qoi_path).expect("Failed to save QOI");
+    let from_png = load_png(&png_path).expect("Failed to load PNG");
+    let from_qoi = load_qoi(&qoi_path).expect("Failed to load QOI");
+    std::fs::remove_file(&png_path).ok();
+    std::fs::remove_file(&qoi_path).ok();
+
+    for loaded in [&from_png, &from_qoi] {
+        assert_eq!((loaded.width, loaded.height), (img.width, img.height));
+        assert!(loaded.data == img.data);
+    }
+}
+
+#[test]
+fn jpeg_round_trip_stays_within_tolerance() {
+    let img = sample_image(67, 45, false);
+    let path = temp_path("lossy.jpg");
+
+    save_jpeg(&img, &path, 95).expect("Failed to save JPEG");
+    let loaded = load_jpeg(&path).expect("Failed to load JPEG");
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!((loaded.width, loaded.height), (img.width, img.height));
+    let mut max_error = 0u8;
+    let mut total_error = 0u64;
+    for (expected, actual) in img.data.chunks_exact(4).zip(loaded.data.chunks_exact(4)) {
+        assert_eq!(actual[3], 255);
+        for c in 0..3 {
+            let error = expected[c].abs_diff(actual[c]);
+            max_error = max_error.max(error);
+            total_error += error as u64;
+        }
+    }
+    let mean_error = total_error as f64 / (img.width * img.height * 3) as f64;
+    assert!(max_error <= JPEG_MAX_CHANNEL_ERROR, "max error {max_error}");
+    assert!(
+        mean_error <= JPEG_MEAN_CHANNEL_ERROR,
+        "mean error {mean_error}"
+    );
+}
+
+#[test]
+fn jpeg_artifact_tolerance_discounts_block_seams() {
+    // Low quality on smooth content is where JPEG's 8x8 blocking shows.
+    let img = sample_image(96, 64, false);
+    let png_path = temp_path("reference.png");
+    let jpeg_path = temp_path("blocky.jpg");
+    save_png(&img, &png_path).expect("Failed to save PNG");
+    save_jpeg(&img, &jpeg_path, 30).expect("Failed to save JPEG");
+    let reference = load_png(&png_path).expect("Failed to load PNG");
+    let candidate = load_jpeg(&jpeg_path).expect("Failed to load JPEG");
+    std::fs::remove_file(&png_path).ok();
+    std::fs::remove_file(&jpeg_path).ok();
+
+    let strict = diff(&reference, &candidate, None, &DiffOptions::default()).unwrap();
+    let tolerant_options = DiffOptions {
+        jpeg_artifact_tolerance: true,
+        ..Default::default()
+    };
+    let tolerant = diff(&reference, &candidate, None, &tolerant_options).unwrap();
+    assert!(
+        tolerant.diff_count <= strict.diff_count,
+        "{} with the tolerance vs {} without",
+        tolerant.diff_count,
+        strict.diff_count
+    );
+}
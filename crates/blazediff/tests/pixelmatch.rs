@@ -0,0 +1,193 @@
+//! Parity with pixelmatch, whose YIQ delta and anti-aliasing detector
+//! blazediff implements. Each fixture pair in `fixtures/pixelmatch` is
+//! diffed with the options pixelmatch's own test suite uses, and the count
+//! must match the count pixelmatch reports.
+//!
+//! One difference is intentional: recent pixelmatch blends translucent
+//! pixels over a checkered background, blazediff over white as pixelmatch
+//! originally did, so a translucent pixel against a transparent one is only
+//! counted when it is visible on white. Pair 5 pins that.
+//!
+//! The fixtures are decoded with the `png` crate, so this suite runs without
+//! the `io` feature.
+
+use blazediff::{diff, AlignedBytes, DiffOptions, Image};
+use std::path::PathBuf;
+
+struct Reference {
+    pair: &'static str,
+    threshold: f64,
+    include_aa: bool,
+    /// `diff_count` pixelmatch reports with the same options.
+    pixelmatch: u32,
+    /// What blazediff reports where it intentionally differs.
+    expected: Option<u32>,
+}
+
+const REFERENCES: &[Reference] = &[
+    Reference {
+        pair: "1",
+        threshold: 0.05,
+        include_aa: false,
+        pixelmatch: 143,
+        expected: None,
+    },
+    Reference {
+        pair: "1",
+        threshold: 0.05,
+        include_aa: true,
+        pixelmatch: 251,
+        expected: None,
+    },
+    Reference {
+        pair: "1",
+        threshold: 0.1,
+        include_aa: false,
+        pixelmatch: 106,
+        expected: None,
+    },
+    Reference {
+        pair: "2",
+        threshold: 0.05,
+        include_aa: false,
+        pixelmatch: 12437,
+        expected: None,
+    },
+    Reference {
+        pair: "2",
+        threshold: 0.1,
+        include_aa: false,
+        pixelmatch: 9730,
+        expected: None,
+    },
+    Reference {
+        pair: "3",
+        threshold: 0.05,
+        include_aa: false,
+        pixelmatch: 212,
+        expected: None,
+    },
+    Reference {
+        pair: "3",
+        threshold: 0.1,
+        include_aa: false,
+        pixelmatch: 178,
+        expected: None,
+    },
+    Reference {
+        pair: "4",
+        threshold: 0.05,
+        include_aa: false,
+        pixelmatch: 36049,
+        expected: None,
+    },
+    Reference {
+        pair: "4",
+        threshold: 0.05,
+        include_aa: true,
+        pixelmatch: 41623,
+        expected: None,
+    },
+    Reference {
+        pair: "4",
+        threshold: 0.1,
+        include_aa: false,
+        pixelmatch: 6889,
+        expected: None,
+    },
+    Reference {
+        pair: "5",
+        threshold: 0.05,
+        include_aa: false,
+        pixelmatch: 6,
+        // Translucent red over white stays under the threshold.
+        expected: Some(0),
+    },
+    Reference {
+        pair: "6",
+        threshold: 0.05,
+        include_aa: false,
+        pixelmatch: 51,
+        expected: None,
+    },
+    Reference {
+        pair: "7",
+        threshold: 0.05,
+        include_aa: false,
+        pixelmatch: 3016,
+        expected: None,
+    },
+    Reference {
+        pair: "7",
+        threshold: 0.1,
+        include_aa: false,
+        pixelmatch: 2448,
+        expected: None,
+    },
+];
+
+fn fixture(name: &str) -> Image {
+    let path: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "../../fixtures/pixelmatch",
+        name,
+    ]
+    .iter()
+    .collect();
+    let file = std::fs::File::open(&path)
+        .unwrap_or_else(|e| panic!("Failed to open {}: {e}", path.display()));
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::ALPHA);
+    let mut reader = decoder.read_info().expect("Failed to read PNG header");
+    let mut data = AlignedBytes::zeroed(reader.output_buffer_size().expect("PNG too large"));
+    let info = reader.next_frame(&mut data).expect("Failed to decode PNG");
+    assert_eq!(info.color_type, png::ColorType::Rgba, "{name}");
+    assert_eq!(info.bit_depth, png::BitDepth::Eight, "{name}");
+    data.truncate(info.buffer_size());
+    Image {
+        data,
+        width: info.width,
+        height: info.height,
+    }
+}
+
+#[test]
+fn diff_counts_match_pixelmatch() {
+    let mut mismatches = Vec::new();
+    for reference in REFERENCES {
+        let img1 = fixture(&format!("{}a.png", reference.pair));
+        let img2 = fixture(&format!("{}b.png", reference.pair));
+        let options = DiffOptions {
+            threshold: reference.threshold,
+            include_aa: reference.include_aa,
+            ..Default::default()
+        };
+        let count = diff(&img1, &img2, None, &options).unwrap().diff_count;
+        let expected = reference.expected.unwrap_or(reference.pixelmatch);
+        if count != expected {
+            mismatches.push(format!(
+                "{}a/{}b at threshold {}: blazediff {} vs expected {} (pixelmatch {})",
+                reference.pair,
+                reference.pair,
+                reference.threshold,
+                count,
+                expected,
+                reference.pixelmatch
+            ));
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+#[test]
+fn identical_fixtures_have_no_diff() {
+    for pair in ["1", "6"] {
+        let img = fixture(&format!("{pair}a.png"));
+        let copy = fixture(&format!("{pair}a.png"));
+        let options = DiffOptions {
+            threshold: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(diff(&img, &copy, None, &options).unwrap().diff_count, 0);
+    }
+}
@@ -41,15 +41,21 @@ export type BlazeDiffResult =
 	| { match: false; reason: "file-not-exists"; file: string };
 
 interface JsonOutput {
+	schemaVersion: number;
+	version: string;
 	diffCount: number;
 	diffPercentage: number;
 	identical: boolean;
+	exactMatch: boolean;
 	error?: string;
 }
 
 /** N-API binding result structure */
 interface NapiDiffResult {
+	schemaVersion: number;
+	version: string;
 	matchResult: boolean;
+	exactMatch: boolean;
 	reason: string | null;
 	diffCount: number | null;
 	diffPercentage: number | null;